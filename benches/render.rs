@@ -0,0 +1,36 @@
+use std::{io, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use nominal::{Plan, Renamer, WriteOptions};
+
+/// Returns a plan renaming files deep in a directory tree, each in its own
+/// directory, so that every line is compressed around a long ancestor.
+fn deep_plan(len: usize, depth: usize) -> Plan<PathBuf, PathBuf> {
+    let renamer: Renamer<PathBuf, PathBuf> = (0..len)
+        .map(|index| {
+            let mut dir: PathBuf = (0..depth)
+                .map(|level| format!("level-{}-{}", level, index % (level + 2)))
+                .collect();
+            dir.push(format!("leaf-{}", index));
+            (dir.join("old.txt"), dir.join("new.txt"))
+        })
+        .collect();
+    renamer.plan().unwrap()
+}
+
+fn render(c: &mut Criterion) {
+    let plan = deep_plan(500_000, 24);
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("write_to", |b| {
+        b.iter(|| plan.write_to(&mut io::sink()).unwrap())
+    });
+    let options = WriteOptions::new().compress(false);
+    group.bench_function("write_with_uncompressed", |b| {
+        b.iter(|| plan.write_with(&mut io::sink(), &options).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
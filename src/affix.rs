@@ -0,0 +1,403 @@
+use std::path::{Path, PathBuf};
+
+use crate::{generator::TargetGenerator, renamer::Renamer};
+
+/// A transformation of file names, as applied to the targets of a renamer
+/// by [`Renamer::strip_name_prefix`] and the like.
+///
+/// As a [`TargetGenerator`], the transformation is applied to the file name
+/// of each source instead, to preview it one source at a time. Sources it
+/// skips have no target.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use nominal::{NameAffix, Renamer, TargetGenerator};
+/// let affix = NameAffix::strip_prefix("IMG_");
+/// assert_eq!(
+///     affix.target_for(Path::new("IMG_0001.jpg"), 0),
+///     Some(PathBuf::from("0001.jpg"))
+/// );
+/// assert_eq!(affix.target_for(Path::new("notes.txt"), 1), None);
+///
+/// let sources = ["IMG_0001.jpg", "notes.txt"];
+/// let renamer = Renamer::from_generator(sources, &affix);
+/// assert_eq!(renamer.plan()?.len(), 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameAffix {
+    kind: AffixKind,
+    affix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AffixKind {
+    StripPrefix,
+    StripSuffix,
+    AddPrefix,
+    AddSuffix,
+}
+
+impl NameAffix {
+    /// Removes a prefix from file names, as
+    /// [`Renamer::strip_name_prefix`] does.
+    pub fn strip_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            kind: AffixKind::StripPrefix,
+            affix: prefix.into(),
+        }
+    }
+
+    /// Removes a suffix from the stem of file names, as
+    /// [`Renamer::strip_name_suffix`] does.
+    pub fn strip_suffix(suffix: impl Into<String>) -> Self {
+        Self {
+            kind: AffixKind::StripSuffix,
+            affix: suffix.into(),
+        }
+    }
+
+    /// Adds a prefix to file names, as [`Renamer::add_name_prefix`] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix contains a path separator.
+    pub fn add_prefix(prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        assert_affix(&prefix);
+        Self {
+            kind: AffixKind::AddPrefix,
+            affix: prefix,
+        }
+    }
+
+    /// Adds a suffix to the stem of file names, as
+    /// [`Renamer::add_name_suffix`] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the suffix contains a path separator.
+    pub fn add_suffix(suffix: impl Into<String>) -> Self {
+        let suffix = suffix.into();
+        assert_affix(&suffix);
+        Self {
+            kind: AffixKind::AddSuffix,
+            affix: suffix,
+        }
+    }
+
+    /// Returns the transformed file name from its stem and extension, or
+    /// [`None`] if it is skipped.
+    fn apply(&self, stem: &str, extension: Option<&str>) -> Option<String> {
+        let affix = self.affix.as_str();
+        if affix.is_empty() {
+            return None;
+        }
+        let name = match self.kind {
+            AffixKind::StripPrefix => {
+                let stem = stem.strip_prefix(affix)?;
+                (!stem.is_empty()).then(|| join_name(stem, extension))
+            }
+            AffixKind::StripSuffix => {
+                let stem = stem.strip_suffix(affix)?;
+                (!stem.is_empty()).then(|| join_name(stem, extension))
+            }
+            AffixKind::AddPrefix => (!stem.starts_with('.'))
+                .then(|| join_name(&format!("{}{}", affix, stem), extension)),
+            AffixKind::AddSuffix => Some(join_name(&format!("{}{}", stem, affix), extension)),
+        }?;
+        // Like an empty name, these do not name an entry of the parent.
+        (name != "." && name != "..").then_some(name)
+    }
+}
+
+impl TargetGenerator for NameAffix {
+    fn target_for(&self, source: &Path, _index: usize) -> Option<PathBuf> {
+        let (stem, extension) = split_name(source)?;
+        Some(source.with_file_name(self.apply(stem, extension)?))
+    }
+}
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Removes a prefix from the file name of the targets.
+    ///
+    /// Like the other name transformations, this rewrites the targets of the
+    /// operations added so far, so that transformations can be chained. To
+    /// transform the names of existing files, add them with their own path
+    /// as target first: operations left unchanged are dropped from the plan.
+    ///
+    /// Targets whose file name does not start with the prefix are skipped.
+    /// So are targets whose stem (the file name without its extension) would
+    /// be left empty, such as `IMG_` or `IMG_.jpg` with the prefix `IMG_`, and
+    /// targets whose file name would be `.` or `..`, such as `IMG_..`.
+    /// Targets whose file name is missing or not valid Unicode are always
+    /// skipped. Returns the number of rewritten targets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+    /// renamer.add("IMG_0001.jpg".into(), "IMG_0001.jpg".into());
+    /// renamer.add("IMG_.jpg".into(), "IMG_.jpg".into());
+    /// renamer.add("notes.txt".into(), "notes.txt".into());
+    /// assert_eq!(renamer.strip_name_prefix("IMG_"), 1);
+    ///
+    /// let plan = renamer.plan()?;
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"IMG_0001.jpg => 0001.jpg\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn strip_name_prefix(&mut self, prefix: &str) -> usize {
+        self.map_names(&NameAffix::strip_prefix(prefix))
+    }
+
+    /// Removes a suffix from the stem of the targets, before their
+    /// extension.
+    ///
+    /// Targets whose stem does not end with the suffix, or would be left
+    /// empty, and targets whose file name would be `.` or `..`, are skipped. See [`strip_name_prefix`](Self::strip_name_prefix)
+    /// for how targets are rewritten. Returns the number of rewritten
+    /// targets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+    /// renamer.add("report (1).pdf".into(), "report (1).pdf".into());
+    /// renamer.add(" (1).pdf".into(), " (1).pdf".into());
+    /// assert_eq!(renamer.strip_name_suffix(" (1)"), 1);
+    ///
+    /// let plan = renamer.plan()?;
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"report (1).pdf => report.pdf\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn strip_name_suffix(&mut self, suffix: &str) -> usize {
+        self.map_names(&NameAffix::strip_suffix(suffix))
+    }
+
+    /// Adds a prefix to the file name of the targets.
+    ///
+    /// Targets whose file name starts with a dot are skipped, since they
+    /// would no longer be hidden. See
+    /// [`strip_name_prefix`](Self::strip_name_prefix) for how targets are
+    /// rewritten. Returns the number of rewritten targets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefix contains a path separator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+    /// renamer.add("dir/a.txt".into(), "dir/a.txt".into());
+    /// assert_eq!(renamer.add_name_prefix("old_"), 1);
+    ///
+    /// let plan = renamer.plan()?;
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"dir/{a.txt => old_a.txt}\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn add_name_prefix(&mut self, prefix: &str) -> usize {
+        self.map_names(&NameAffix::add_prefix(prefix))
+    }
+
+    /// Adds a suffix to the stem of the targets, before their extension.
+    ///
+    /// See [`strip_name_prefix`](Self::strip_name_prefix) for how targets are
+    /// rewritten. Returns the number of rewritten targets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the suffix contains a path separator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+    /// renamer.add("archive.tar.gz".into(), "archive.tar.gz".into());
+    /// assert_eq!(renamer.add_name_suffix("-backup"), 1);
+    ///
+    /// let plan = renamer.plan()?;
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"archive.tar.gz => archive.tar-backup.gz\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn add_name_suffix(&mut self, suffix: &str) -> usize {
+        self.map_names(&NameAffix::add_suffix(suffix))
+    }
+
+    /// Rewrites the file name of every target with an affix, skipping the
+    /// targets it leaves as is.
+    fn map_names(&mut self, affix: &NameAffix) -> usize {
+        let mut count = 0;
+        for rename in &mut self.renames {
+            let target = &mut rename.target;
+            let Some((stem, extension)) = split_name(target) else {
+                continue;
+            };
+            if let Some(name) = affix.apply(stem, extension) {
+                target.set_file_name(name);
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// Splits the file name of a path into its stem and extension, if it is
+/// valid Unicode.
+fn split_name(path: &Path) -> Option<(&str, Option<&str>)> {
+    let stem = path.file_stem()?.to_str()?;
+    match path.extension() {
+        Some(extension) => Some((stem, Some(extension.to_str()?))),
+        None => Some((stem, None)),
+    }
+}
+
+fn join_name(stem: &str, extension: Option<&str>) -> String {
+    match extension {
+        Some(extension) => format!("{}.{}", stem, extension),
+        None => stem.to_owned(),
+    }
+}
+
+fn assert_affix(affix: &str) {
+    assert!(
+        !affix.contains(std::path::is_separator),
+        "invalid name affix: {:?}",
+        affix
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::NameAffix;
+    use crate::{Renamer, TargetGenerator};
+
+    fn targets<F>(names: &[&str], f: F) -> Vec<String>
+    where
+        F: FnOnce(&mut Renamer<PathBuf, PathBuf>),
+    {
+        let mut renamer: Renamer<PathBuf, PathBuf> = names
+            .iter()
+            .map(|name| (name.into(), name.into()))
+            .collect();
+        f(&mut renamer);
+        renamer
+            .renames
+            .iter()
+            .map(|rename| rename.target.to_str().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn strip_prefix() {
+        let names = [
+            "IMG_",
+            "IMG_.jpg",
+            "IMG_1.jpg",
+            "IMG_IMG_2",
+            ".IMG_3",
+            "img_4",
+            "IMG_..",
+        ];
+        let stripped = targets(&names, |renamer| {
+            assert_eq!(renamer.strip_name_prefix("IMG_"), 2);
+        });
+        assert_eq!(
+            stripped,
+            ["IMG_", "IMG_.jpg", "1.jpg", "IMG_2", ".IMG_3", "img_4", "IMG_.."]
+        );
+        assert_eq!(
+            targets(&names, |renamer| {
+                assert_eq!(renamer.strip_name_prefix(""), 0);
+            }),
+            names
+        );
+    }
+
+    #[test]
+    fn strip_suffix() {
+        let names = ["_old", "_old.txt", "a_old.txt", "b.txt_old", "c_old.tar.gz"];
+        let stripped = targets(&names, |renamer| {
+            assert_eq!(renamer.strip_name_suffix("_old"), 1);
+        });
+        assert_eq!(
+            stripped,
+            ["_old", "_old.txt", "a.txt", "b.txt_old", "c_old.tar.gz"]
+        );
+        // Names left as `.` or `..` are skipped, as empty ones are.
+        let names = ["._x.", "a_x."];
+        let stripped = targets(&names, |renamer| {
+            assert_eq!(renamer.strip_name_suffix("_x"), 1);
+        });
+        assert_eq!(stripped, ["._x.", "a."]);
+        assert!(
+            Renamer::from_generator(names, &NameAffix::strip_suffix("_x"))
+                .plan()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn add_affixes() {
+        let names = ["a.txt", ".hidden", "dir/b", "caf\u{e9}.txt"];
+        let renamed = targets(&names, |renamer| {
+            assert_eq!(renamer.add_name_prefix("x-"), 3);
+            assert_eq!(renamer.add_name_suffix("-y"), 4);
+        });
+        assert_eq!(
+            renamed,
+            ["x-a-y.txt", ".hidden-y", "dir/x-b-y", "x-caf\u{e9}-y.txt"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid name affix")]
+    fn add_separator() {
+        targets(&["a"], |renamer| {
+            renamer.add_name_prefix("dir/");
+        });
+    }
+
+    #[test]
+    fn generator() {
+        let names = ["_old", "a_old.txt", "b.txt_old", "dir/c_old"];
+        let affix = NameAffix::strip_suffix("_old");
+        let generated: Vec<_> = (names.iter().enumerate())
+            .map(|(index, name)| affix.target_for(Path::new(name), index))
+            .collect();
+        assert_eq!(
+            generated,
+            [
+                None,
+                Some(PathBuf::from("a.txt")),
+                None,
+                Some(PathBuf::from("dir/c"))
+            ]
+        );
+        // The renamer methods rewrite targets the same way.
+        let stripped = targets(&names, |renamer| {
+            assert_eq!(renamer.strip_name_suffix("_old"), 2);
+        });
+        assert_eq!(stripped, ["_old", "a.txt", "b.txt_old", "dir/c"]);
+    }
+}
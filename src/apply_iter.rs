@@ -0,0 +1,442 @@
+use std::{
+    collections::{HashMap, HashSet},
+    iter::FusedIterator,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    cycles,
+    error::{ApplyError, ApplyErrorDetails, IoOp},
+    operation::{Rename, RenameStrategy},
+    options::ApplyOptions,
+    parents::MissingParents,
+    plan::{check_source, Anchor, Plan},
+    relocate::MovedDirs,
+    report::RenameStatus,
+};
+
+/// An iterator applying a [`Plan`] lazily.
+///
+/// This struct is created by [`Plan::apply_iter`].
+#[derive(Debug)]
+pub struct ApplyIter<'a, S, T> {
+    plan: &'a Plan<S, T>,
+    next: usize,
+    options: ApplyOptions<'static>,
+    missing_parents: Option<MissingParents>,
+    anchor: Anchor,
+    moved_dirs: MovedDirs,
+    /// The sources moved aside to break cycles, with their temporary path,
+    /// or the reason why they could not be.
+    parked_sources: HashMap<usize, Result<(PathBuf, PathBuf), ApplyErrorDetails>>,
+    /// The breakers of the swaps exchanged with their first operation.
+    exchanged: HashSet<usize>,
+}
+
+impl<'a, S, T> ApplyIter<'a, S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    pub(crate) fn new(plan: &'a Plan<S, T>) -> Self {
+        Self {
+            plan,
+            next: 0,
+            options: ApplyOptions::new(),
+            // Parents cannot be missing when targets stay in their source
+            // directory. Otherwise, each operation checks its own parent.
+            missing_parents: plan.same_directory.then(MissingParents::default),
+            anchor: plan.anchor(),
+            moved_dirs: MovedDirs::new(&plan.renames),
+            parked_sources: HashMap::new(),
+            exchanged: HashSet::new(),
+        }
+    }
+
+    /// Moves the source of a cycle breaker aside, before the first operation
+    /// of its cycle.
+    fn park(&mut self, breaker: usize) {
+        let rename = &self.plan.renames[breaker];
+        let (source, target) = (rename.source.as_ref(), rename.target.as_ref());
+        let source = (self.moved_dirs)
+            .relocate(source, target)
+            .map_or_else(|| source.to_owned(), |(source, _)| source);
+        let source = match &self.anchor {
+            Anchor::BaseDir(base_dir) if rename.is_relative() => base_dir.join(source),
+            // The breaker fails on its own.
+            Anchor::Missing(_) if rename.is_relative() => return,
+            _ => source,
+        };
+        let parked = match cycles::park(&source, &self.options) {
+            Ok(temp_path) => Ok((temp_path, source)),
+            Err(err) => Err(ApplyErrorDetails::io(IoOp::Rename, &source, err)),
+        };
+        self.parked_sources.insert(breaker, parked);
+    }
+
+    /// Exchanges the source and target of the first operation of a swap,
+    /// where supported, or parks the source of its breaker otherwise.
+    fn exchange(
+        &mut self,
+        index: usize,
+        breaker: usize,
+        source: &Path,
+        target: &Path,
+    ) -> Option<Result<RenameStrategy, ApplyError>> {
+        let exchanged = match (self.anchored(source), self.anchored(target)) {
+            (Some(first), Some(second)) => Rename::new(first, second)
+                .exchange_checked(&self.options, self.plan.stamps.get(&index)),
+            _ => None,
+        };
+        match exchanged {
+            Some(Ok(strategy)) => {
+                self.exchanged.insert(breaker);
+                Some(Ok(strategy))
+            }
+            Some(Err(err)) => Some(Err(err)),
+            None => {
+                self.park(breaker);
+                None
+            }
+        }
+    }
+
+    /// Anchors a relative path to the base directory of the plan, if it
+    /// exists.
+    fn anchored(&self, path: &Path) -> Option<PathBuf> {
+        match &self.anchor {
+            Anchor::BaseDir(base_dir) => Some(base_dir.join(path)),
+            Anchor::Missing(_) if path.is_relative() => None,
+            _ => Some(path.to_owned()),
+        }
+    }
+
+    /// Checks the current source of an operation against its stamp, if any,
+    /// and returns the outcome of the operation if it changed.
+    fn check_source(
+        &self,
+        index: usize,
+        source: &Path,
+        target: &Path,
+    ) -> Option<Result<RenameStrategy, ApplyError>> {
+        let mut warnings = Vec::new();
+        let current = self.anchored(source)?;
+        let status = check_source(
+            self.plan.stamps.get(&index),
+            &current,
+            &self.options,
+            &mut warnings,
+        )?;
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+        Some(match status {
+            RenameStatus::Failed(details) => Err(ApplyError::new(source, target, details)),
+            _ => Ok(RenameStrategy::Skipped),
+        })
+    }
+
+    /// Applies an operation, anchoring its relative paths.
+    fn apply_rename<U, V>(
+        &mut self,
+        index: usize,
+        rename: &Rename<U, V>,
+        missing_parents: bool,
+    ) -> Result<RenameStrategy, ApplyError>
+    where
+        U: AsRef<Path>,
+        V: AsRef<Path>,
+    {
+        let missing_parents = if missing_parents {
+            self.missing_parents.as_mut()
+        } else {
+            None
+        };
+        let same_file = (self.plan.case_insensitive_dirs)
+            .same_file(rename.source.as_ref(), rename.target.as_ref());
+        let stamp = self.plan.stamps.get(&index);
+        match &self.anchor {
+            Anchor::BaseDir(base_dir) if rename.is_relative() => {
+                rename.anchored(base_dir).apply_checked(
+                    &self.options,
+                    same_file,
+                    stamp,
+                    missing_parents,
+                    &mut Vec::new(),
+                )
+            }
+            Anchor::Missing(base_dir) if rename.is_relative() => Err(ApplyError::new(
+                rename.source.as_ref(),
+                rename.target.as_ref(),
+                ApplyErrorDetails::MissingBaseDir(base_dir.clone()),
+            )),
+            _ => rename.apply_checked(
+                &self.options,
+                same_file,
+                stamp,
+                missing_parents,
+                &mut Vec::new(),
+            ),
+        }
+    }
+}
+
+impl<'a, S, T> ApplyIter<'a, S, T> {
+    /// Consumes the iterator and returns a plan of the rename operations not
+    /// attempted yet.
+    ///
+    /// The remaining operations keep the paths of the plan: those under a
+    /// directory moved by the attempted operations are no longer relocated.
+    pub fn into_remaining_plan(self) -> Plan<&'a S, &'a T> {
+        // The breakers of the swaps exchanged are applied already.
+        let left: Vec<usize> = (self.plan.execution[self.next..].iter())
+            .copied()
+            .filter(|index| !self.exchanged.contains(index))
+            .collect();
+        let mut remaining = left.clone();
+        remaining.sort_unstable();
+        // The remaining operations keep their relative execution order.
+        let execution = left
+            .iter()
+            .map(|index| remaining.binary_search(index).unwrap())
+            .collect();
+        let renames: Vec<_> = remaining
+            .iter()
+            .map(|&index| {
+                let rename = &self.plan.renames[index];
+                Rename {
+                    source: &rename.source,
+                    target: &rename.target,
+                    tag: rename.tag.clone(),
+                    priority: rename.priority,
+                }
+            })
+            .collect();
+        // The cycles left midway are no longer broken.
+        let cycles = (self.plan.cycles.iter())
+            .filter_map(|(first, breaker)| {
+                let first = remaining.binary_search(first).ok()?;
+                Some((first, remaining.binary_search(breaker).ok()?))
+            })
+            .collect();
+        Plan {
+            renames,
+            execution,
+            cycles,
+            stats: self.plan.stats.clone(),
+            collator: Arc::clone(&self.plan.collator),
+            same_directory: self.plan.same_directory,
+            on_stat_error: self.plan.on_stat_error,
+            case_insensitive_dirs: self.plan.case_insensitive_dirs.clone(),
+            skipped: self.plan.skipped.clone(),
+            base_dir: self.plan.base_dir.clone(),
+            stamps: (remaining.iter().enumerate())
+                .filter_map(|(new, index)| Some((new, self.plan.stamps.get(index)?.clone())))
+                .collect(),
+            hidden: (remaining.iter().enumerate())
+                .filter(|(_, index)| self.plan.hidden.contains(index))
+                .map(|(new, _)| new)
+                .collect(),
+        }
+    }
+}
+
+impl<'a, S, T> Iterator for ApplyIter<'a, S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    type Item = Result<&'a Rename<S, T>, ApplyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = *self.plan.execution.get(self.next)?;
+        let rename = &self.plan.renames[index];
+        self.next += 1;
+        // Swaps are exchanged in one system call where supported.
+        let mut swap = None;
+        if let Some(&breaker) = self.plan.cycles.get(&index) {
+            if self.plan.renames[breaker].target.as_ref() == rename.source.as_ref() {
+                swap = Some(breaker);
+            } else {
+                self.park(breaker);
+            }
+        }
+        let relocated = (self.moved_dirs)
+            .relocate(rename.source.as_ref(), rename.target.as_ref())
+            .map(|(source, target)| Rename {
+                source,
+                target,
+                tag: rename.tag.clone(),
+                priority: rename.priority,
+            });
+        let (source, target) = match &relocated {
+            Some(relocated) => (relocated.source.as_path(), relocated.target.as_path()),
+            None => (rename.source.as_ref(), rename.target.as_ref()),
+        };
+        let applied = |result: &Result<RenameStrategy, ApplyError>| {
+            matches!(
+                result,
+                Ok(RenameStrategy::Rename
+                    | RenameStrategy::Merged
+                    | RenameStrategy::AlreadyApplied
+                    | RenameStrategy::Exchanged
+                    | RenameStrategy::Copied)
+            )
+        };
+        // The parents of relocated targets were not known when planning.
+        let result = match self.parked_sources.remove(&index) {
+            Some(Ok((temp_path, parked_source))) => {
+                // A parked source is renamed from its temporary path.
+                let current = Rename {
+                    source: temp_path,
+                    target,
+                    tag: rename.tag.clone(),
+                    priority: rename.priority,
+                };
+                let result = match self.check_source(index, &current.source, target) {
+                    Some(result) => result.map_err(|err| err.with_tag(rename.tag.clone())),
+                    None => self.apply_rename(index, &current, relocated.is_none()),
+                };
+                if !applied(&result) {
+                    restore(&current.source, &parked_source);
+                }
+                result
+            }
+            Some(Err(details)) => {
+                Err(ApplyError::new(source, target, details).with_tag(rename.tag.clone()))
+            }
+            None if self.exchanged.remove(&index) => Ok(RenameStrategy::Exchanged),
+            None => match self.check_source(index, source, target) {
+                Some(result) => result.map_err(|err| err.with_tag(rename.tag.clone())),
+                None => {
+                    match swap.and_then(|breaker| self.exchange(index, breaker, source, target)) {
+                        Some(result) => result.map_err(|err| err.with_tag(rename.tag.clone())),
+                        None => match &relocated {
+                            // The directory moved earlier brought the entry to
+                            // its target.
+                            Some(_) if source == target => Ok(RenameStrategy::AlreadyApplied),
+                            Some(relocated) => self.apply_rename(index, relocated, false),
+                            None => self.apply_rename(index, rename, true),
+                        },
+                    }
+                }
+            },
+        };
+        if applied(&result) {
+            self.moved_dirs.record(index, source, target);
+        }
+        Some(
+            result
+                .map(|_| rename)
+                .map_err(|err| err.with_index(Some(index))),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.plan.renames.len() - self.next;
+        (len, Some(len))
+    }
+}
+
+impl<S, T> Drop for ApplyIter<'_, S, T> {
+    fn drop(&mut self) {
+        // Cycles left midway are not completed.
+        for (_, parked) in self.parked_sources.drain() {
+            if let Ok((temp_path, source)) = parked {
+                restore(&temp_path, &source);
+            }
+        }
+        cycles::prune_staging(&self.options);
+    }
+}
+
+/// Moves a parked source back, if its path is still free.
+fn restore(temp_path: &Path, source: &Path) {
+    if let Err(err) = cycles::unpark(temp_path, source) {
+        warn!(
+            "could not move {:?} back from the temporary path {:?}: {}",
+            source, temp_path, err
+        );
+    }
+}
+
+impl<S, T> ExactSizeIterator for ApplyIter<'_, S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+}
+
+impl<S, T> FusedIterator for ApplyIter<'_, S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use crate::{
+        error::{ApplyErrorDetails, IoOp},
+        renamer::Renamer,
+    };
+
+    #[test]
+    fn apply_iter() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name| temp_dir.path().join(name);
+        fs::write(path("b.txt"), b"")?;
+        fs::write(path("c.txt"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("a.txt"), path("1.txt"));
+        renamer.add(path("b.txt"), path("2.txt"));
+        renamer.add(path("c.txt"), path("3.txt"));
+        let plan = renamer.plan().unwrap();
+
+        let mut iter = plan.apply_iter();
+        assert_eq!(iter.len(), 3);
+        let err = iter.next().unwrap().unwrap_err();
+        assert!(matches!(
+            err.details,
+            ApplyErrorDetails::Io {
+                op: IoOp::Rename,
+                ..
+            }
+        ));
+        assert_eq!(iter.next().unwrap().unwrap().target, path("2.txt"));
+        assert!(path("2.txt").exists());
+
+        let remaining = iter.into_remaining_plan();
+        assert_eq!(remaining.len(), 1);
+        assert!(path("c.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn source_changed() -> io::Result<()> {
+        use crate::{options::PlanOptions, stamp::StampField};
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name| temp_dir.path().join(name);
+        fs::write(path("a"), b"a")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("b"));
+        let plan = renamer
+            .plan_with(&PlanOptions::new().stamp_sources(true))
+            .unwrap();
+        fs::write(path("a"), b"changed")?;
+
+        let err = plan.apply_iter().next().unwrap().unwrap_err();
+        assert!(matches!(
+            err.details,
+            ApplyErrorDetails::SourceChanged(StampField::Size)
+        ));
+        assert!(path("a").exists());
+        Ok(())
+    }
+}
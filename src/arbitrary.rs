@@ -0,0 +1,66 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{operation::Rename, renamer::Renamer};
+
+/// The components of the generated paths, sharing prefixes, and differing
+/// only by case or by Unicode normalization, so that the generated paths
+/// overlap.
+const COMPONENTS: [&str; 8] = ["a", "A", "ab", "a.b", "b", "B", "caf\u{e9}", "cafe\u{301}"];
+
+/// Generates a relative path of one or two components.
+fn arbitrary_path(u: &mut Unstructured<'_>) -> Result<PathBuf> {
+    let len = u.int_in_range(1..=2)?;
+    (0..len).map(|_| u.choose(&COMPONENTS).copied()).collect()
+}
+
+/// Generates an operation between overlapping relative paths, with a
+/// priority between -1 and 1, and no tag.
+impl<'a> Arbitrary<'a> for Rename<PathBuf, PathBuf> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            source: arbitrary_path(u)?,
+            target: arbitrary_path(u)?,
+            tag: None,
+            priority: u.int_in_range(-1..=1)?,
+        })
+    }
+}
+
+/// Generates a renamer made of single operations, chains and cycles, as
+/// generated for [`Rename`].
+///
+/// The targets are distinct, so that the plan of the renamer is
+/// [valid](crate::Plan::assert_valid) whenever it can be built.
+impl<'a> Arbitrary<'a> for Renamer<PathBuf, PathBuf> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut renames = Vec::new();
+        let mut targets = HashSet::new();
+        for _ in 0..u.int_in_range(0..=8)? {
+            let group: Vec<Rename<PathBuf, PathBuf>> = match u.int_in_range(0..=2)? {
+                0 => vec![Rename::arbitrary(u)?],
+                // A chain, or a cycle when it goes back to its first path.
+                links => {
+                    let len = u.int_in_range(2..=4)?;
+                    let mut paths = (0..len)
+                        .map(|_| arbitrary_path(u))
+                        .collect::<Result<Vec<_>>>()?;
+                    if links == 2 {
+                        paths.push(paths[0].clone());
+                    }
+                    paths
+                        .windows(2)
+                        .map(|pair| Rename::new(pair[0].clone(), pair[1].clone()))
+                        .collect()
+                }
+            };
+            renames.extend(
+                group
+                    .into_iter()
+                    .filter(|rename| targets.insert(rename.target.clone())),
+            );
+        }
+        Ok(Self { renames })
+    }
+}
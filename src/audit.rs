@@ -0,0 +1,177 @@
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use serde_json::Value;
+
+use crate::{json::path_to_json, report::RenameStatus, warning::Warning};
+
+pub(crate) type AuditWriter<'a> = Box<dyn Write + 'a>;
+
+/// An audit log, appended to while applying a plan.
+///
+/// See [`ApplyOptions::audit_log`](crate::ApplyOptions::audit_log).
+pub(crate) struct AuditLog<'a, 'w> {
+    writer: &'a RefCell<AuditWriter<'w>>,
+    /// The error that stopped the log, if any.
+    error: Option<io::Error>,
+}
+
+impl<'a, 'w> AuditLog<'a, 'w> {
+    pub(crate) fn new(writer: &'a RefCell<AuditWriter<'w>>) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Appends the outcome of an operation, unless it was not attempted.
+    pub(crate) fn record(&mut self, source: &Path, target: &Path, status: &RenameStatus) {
+        if self.error.is_some() {
+            return;
+        }
+        let (status, message) = match status {
+            RenameStatus::Applied => ("applied", Value::Null),
+            RenameStatus::Merged => ("merged", Value::Null),
+            RenameStatus::Skipped => ("skipped", Value::Null),
+            RenameStatus::AlreadyApplied => ("already_applied", Value::Null),
+            RenameStatus::Deferred => ("deferred", Value::Null),
+            RenameStatus::Failed(details) => ("failed", Value::from(details.to_string())),
+            RenameStatus::Pending => return,
+        };
+        let line = format!(
+            "{} {} {} {} {}\n",
+            rfc3339(SystemTime::now()),
+            status,
+            path_to_json(source),
+            path_to_json(target),
+            message
+        );
+        let mut writer = self.writer.borrow_mut();
+        if let Err(error) = writer
+            .write_all(line.as_bytes())
+            .and_then(|()| writer.flush())
+        {
+            warn!("could not write to the audit log: {}", error);
+            self.error = Some(error);
+        }
+    }
+
+    /// Returns the warning raised if the log could not be written.
+    pub(crate) fn finish(self) -> Option<Warning> {
+        self.error.map(|error| Warning::AuditLog { error })
+    }
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, with millisecond
+/// precision.
+fn rfc3339(time: SystemTime) -> String {
+    let elapsed = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Converts days since the epoch to a civil date, see
+    // <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{AuditLog, AuditWriter};
+    use crate::{
+        error::{ApplyErrorDetails, TargetKind},
+        report::RenameStatus,
+        warning::Warning,
+    };
+
+    #[test]
+    fn rfc3339() {
+        let at = |secs, millis| {
+            super::rfc3339(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis),
+            )
+        };
+        assert_eq!(at(0, 0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(at(951_782_400, 5), "2000-02-29T00:00:00.005Z");
+        assert_eq!(at(1_792_000_000, 999), "2026-10-14T17:46:40.999Z");
+        assert_eq!(at(4_107_542_399, 0), "2100-02-28T23:59:59.000Z");
+    }
+
+    #[test]
+    fn audit_log() {
+        let mut output = Vec::new();
+        let writer: RefCell<AuditWriter<'_>> = RefCell::new(Box::new(&mut output));
+        let mut log = AuditLog::new(&writer);
+        log.record(Path::new("a"), Path::new("b\nc"), &RenameStatus::Applied);
+        log.record(Path::new("d"), Path::new("e"), &RenameStatus::Pending);
+        log.record(
+            Path::new("f"),
+            Path::new("g"),
+            &RenameStatus::Failed(ApplyErrorDetails::TargetExists {
+                kind: TargetKind::File,
+            }),
+        );
+        assert!(log.finish().is_none());
+        drop(writer);
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                r#"applied "a" "b\nc" null"#,
+                r#"failed "f" "g" "target already exists (file)""#,
+            ]
+        );
+
+        struct Broken;
+
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let writer: RefCell<AuditWriter<'_>> = RefCell::new(Box::new(Broken));
+        let mut log = AuditLog::new(&writer);
+        log.record(Path::new("a"), Path::new("b"), &RenameStatus::Applied);
+        assert!(matches!(log.finish(), Some(Warning::AuditLog { .. })));
+    }
+}
@@ -0,0 +1,325 @@
+//! A minimal command-line front end, wiring the pieces of the crate together
+//! as an application would.
+//!
+//! It is a reference consumer rather than a product: the integration tests
+//! drive it against temporary directories, to catch regressions in how the
+//! pieces compose.
+//!
+//! ```text
+//! nominal-cli [--journal FILE] rename [APPLY FLAGS] DIR TEMPLATE
+//! nominal-cli [--journal FILE] flatten [APPLY FLAGS] [FLATTEN FLAGS] DIR
+//! nominal-cli [--journal FILE] undo
+//! ```
+//!
+//! `rename` renames the visible entries of a directory, sorted by name,
+//! after a template where `{name}`, `{stem}` and `{ext}` stand for the file
+//! name, its stem and its extension, and `{n}` for the position of the entry,
+//! from 1, padded with zeros. `flatten` moves every file under a directory
+//! directly into it, joining the components of their paths with a
+//! separator. Both preview the plan with colors, prompt for
+//! confirmation, apply it while reporting progress, and record it in the undo
+//! journal, `.nominal-journal.json` by default. `undo` undoes the last
+//! recorded plan.
+//!
+//! The apply flags are `--yes`, to skip the confirmation, `--dry-run`, to
+//! check the plan without applying it, and `--progress FILE`, to write the
+//! progress of the apply to a file. The flatten flags are `--glob PATTERN`,
+//! `--min-size BYTES` and `--max-size BYTES`, to only move the matching files,
+//! `--separator SEP`, to join the components with, and `--skip-hidden`, to
+//! leave hidden entries and their contents in place.
+//!
+//! The crate has no regular expression support, so targets are only built
+//! from templates.
+
+use std::{
+    cell::Cell,
+    env,
+    error::Error,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use nominal::{
+    ApplyOptions, FlattenOptions, Journal, JournalWriter, LogEvent, Plan, PlanOptions, Renamer,
+    Session, SourceFilter, WriteOptions,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// The undo journal used unless `--journal` is given.
+const DEFAULT_JOURNAL: &str = ".nominal-journal.json";
+
+/// The flags of the subcommands applying a plan.
+#[derive(Debug, Default)]
+struct ApplyFlags {
+    yes: bool,
+    dry_run: bool,
+    progress: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    match run(env::args_os().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut args: Vec<OsString>) -> Result<()> {
+    let mut journal = PathBuf::from(DEFAULT_JOURNAL);
+    if args.first().is_some_and(|arg| arg == "--journal") {
+        args.remove(0);
+        journal = take_value(&mut args, "--journal")?.into();
+    }
+    if args.is_empty() {
+        return Err("missing subcommand: rename, flatten or undo".into());
+    }
+    let command = args.remove(0);
+    match command.to_str() {
+        Some("rename") => {
+            let flags = apply_flags(&mut args, &mut [], &mut [])?;
+            let [dir, template] = positional(args, ["DIR", "TEMPLATE"])?;
+            let template = template
+                .into_string()
+                .map_err(|_| "the template is not valid Unicode")?;
+            let renamer = from_template(Path::new(&dir), &template)?;
+            apply(renamer, &flags, &journal)
+        }
+        Some("flatten") => {
+            let (mut glob, mut min_size, mut max_size, mut separator) = (None, None, None, None);
+            let mut skip_hidden = false;
+            let flags = apply_flags(
+                &mut args,
+                &mut [
+                    ("--glob", &mut glob),
+                    ("--min-size", &mut min_size),
+                    ("--max-size", &mut max_size),
+                    ("--separator", &mut separator),
+                ],
+                &mut [("--skip-hidden", &mut skip_hidden)],
+            )?;
+            let [dir] = positional(args, ["DIR"])?;
+            let mut filter = SourceFilter::new();
+            if let Some(glob) = glob {
+                filter = filter.name_glob(&unicode(glob, "--glob")?);
+            }
+            if let Some(size) = min_size {
+                filter = filter.min_size(unicode(size, "--min-size")?.parse()?);
+            }
+            if let Some(size) = max_size {
+                filter = filter.max_size(unicode(size, "--max-size")?.parse()?);
+            }
+            let mut options = FlattenOptions::new()
+                .source_filter(filter)
+                .include_hidden(!skip_hidden);
+            if let Some(separator) = separator {
+                let separator = unicode(separator, "--separator")?;
+                if separator.is_empty() || separator.contains(std::path::is_separator) {
+                    return Err(format!("invalid separator: {:?}", separator).into());
+                }
+                options = options.separator(separator);
+            }
+            let mut renamer = Renamer::new();
+            for skipped in renamer.flatten(&dir, &options)? {
+                eprintln!("skipped {}", skipped.display());
+            }
+            if options.filtered() > 0 {
+                eprintln!("filtered {} entries", options.filtered());
+            }
+            apply(renamer, &flags, &journal)
+        }
+        Some("undo") => {
+            let [] = positional(args, [])?;
+            undo(&journal)
+        }
+        _ => Err(format!("unknown subcommand: {}", command.to_string_lossy()).into()),
+    }
+}
+
+/// Removes the apply flags from the arguments, and the given flags taking a
+/// value or not.
+fn apply_flags(
+    args: &mut Vec<OsString>,
+    valued: &mut [(&str, &mut Option<OsString>)],
+    switches: &mut [(&str, &mut bool)],
+) -> Result<ApplyFlags> {
+    let mut flags = ApplyFlags::default();
+    let mut rest = Vec::new();
+    while !args.is_empty() {
+        let arg = args.remove(0);
+        match arg.to_str() {
+            Some("--yes") => flags.yes = true,
+            Some("--dry-run") => flags.dry_run = true,
+            Some("--progress") => flags.progress = Some(take_value(args, "--progress")?.into()),
+            Some(name) if name.starts_with("--") => {
+                if let Some((_, value)) = switches.iter_mut().find(|(flag, _)| *flag == name) {
+                    **value = true;
+                    continue;
+                }
+                let (_, value) = (valued.iter_mut())
+                    .find(|(flag, _)| *flag == name)
+                    .ok_or_else(|| format!("unknown flag: {}", name))?;
+                **value = Some(take_value(args, name)?);
+            }
+            _ => rest.push(arg),
+        }
+    }
+    *args = rest;
+    Ok(flags)
+}
+
+/// Removes the value of a flag from the front of the arguments.
+fn take_value(args: &mut Vec<OsString>, flag: &str) -> Result<OsString> {
+    if args.is_empty() {
+        return Err(format!("missing value for {}", flag).into());
+    }
+    Ok(args.remove(0))
+}
+
+/// Converts the value of a flag to a string.
+fn unicode(value: OsString, flag: &str) -> Result<String> {
+    value
+        .into_string()
+        .map_err(|_| format!("the value of {} is not valid Unicode", flag).into())
+}
+
+/// Returns the positional arguments, checking that there are as many as
+/// named.
+fn positional<const N: usize>(args: Vec<OsString>, names: [&str; N]) -> Result<[OsString; N]> {
+    args.try_into()
+        .map_err(|_| format!("expected arguments: {}", names.join(" ")).into())
+}
+
+/// Builds the renames of the visible entries of a directory, after a
+/// template.
+fn from_template(dir: &Path, template: &str) -> Result<Renamer<PathBuf, PathBuf>> {
+    let mut sources = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().as_encoded_bytes().starts_with(b".") {
+            sources.push(entry.path());
+        }
+    }
+    sources.sort();
+    let width = sources.len().to_string().len();
+    let generator = |source: &Path, index: usize| {
+        let name = source.file_name()?.to_str()?;
+        let stem = source.file_stem()?.to_str()?;
+        let ext = source
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+        let n = format!("{:0width$}", index + 1, width = width);
+        let placeholders = [
+            ("{name}", name),
+            ("{stem}", stem),
+            ("{ext}", ext),
+            ("{n}", &n),
+        ];
+        // The template is expanded in one pass, so that placeholders in the
+        // substituted names are left as is.
+        let mut target = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            target.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match placeholders
+                .iter()
+                .find(|(placeholder, _)| rest.starts_with(placeholder))
+            {
+                Some((placeholder, value)) => {
+                    target.push_str(value);
+                    rest = &rest[placeholder.len()..];
+                }
+                None => {
+                    target.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        target.push_str(rest);
+        Some(source.with_file_name(target))
+    };
+    Ok(Renamer::from_generator(sources, &generator))
+}
+
+/// Previews, confirms and applies the renames, then records them in the
+/// journal.
+fn apply(renamer: Renamer<PathBuf, PathBuf>, flags: &ApplyFlags, journal: &Path) -> Result<()> {
+    let renamed = Cell::new(0);
+    let total = Cell::new(0);
+    let mut options = ApplyOptions::new().log(|event| {
+        if let LogEvent::Rename { source, target } = event {
+            renamed.set(renamed.get() + 1);
+            eprintln!(
+                "[{}/{}] {} => {}",
+                renamed.get(),
+                total.get(),
+                source.display(),
+                target.display()
+            );
+        }
+    });
+    if let Some(progress) = &flags.progress {
+        options = options.progress_file(progress, 1);
+    }
+    let mut session = Session::new(PlanOptions::new(), options);
+    let plan: Plan<_, _> = session.plan(renamer)?;
+    if plan.is_empty() {
+        println!("nothing to rename");
+        return Ok(());
+    }
+    total.set(plan.len());
+    session.write_colored(&plan, &mut io::stdout().lock(), &WriteOptions::new())?;
+    let collisions = plan.collisions()?;
+    if let Some(collision) = collisions.first() {
+        return Err(format!(
+            "{} target(s) already exist, such as {}",
+            collisions.len(),
+            collision.target.display()
+        )
+        .into());
+    }
+
+    if flags.dry_run {
+        let report = session.dry_run(&plan);
+        report.into_result()?;
+        println!("dry run: {} entries would be renamed", plan.len());
+        return Ok(());
+    }
+    if !flags.yes && plan.confirm()? != Some(true) {
+        println!("aborted");
+        return Ok(());
+    }
+    let mut writer = JournalWriter::open(journal)?;
+    let report = session.apply(plan);
+    // The applied renames are recorded even if others failed.
+    writer.append(&report)?;
+    let applied = report.applied();
+    report.into_result()?;
+    println!("renamed {} entries", applied);
+    Ok(())
+}
+
+/// Undoes the last plan recorded in the journal.
+fn undo(journal: &Path) -> Result<()> {
+    if Journal::verify(journal)? > 0 {
+        eprintln!("removed a torn record from the journal");
+    }
+    let mut journal = Journal::open(journal)?;
+    // A failed undo keeps the operations it did not undo, which are saved
+    // too.
+    let result = journal.undo_stack_mut().undo_last();
+    journal.save()?;
+    let Some(plan) = result? else {
+        println!("nothing to undo");
+        return Ok(());
+    };
+    plan.write_to(&mut io::stdout().lock())?;
+    println!("undid {} renames", plan.len());
+    Ok(())
+}
@@ -0,0 +1,68 @@
+/// The optional features this build of the crate was compiled with.
+///
+/// Applications adapting to the crate, such as offering a colored preview
+/// only with the `ansi` feature, can branch on these rather than mirror the
+/// features of the crate in their own: features are unified across a
+/// dependency graph, so they may be enabled by another crate. Tests can also
+/// assert the expected build configuration.
+///
+/// The capabilities are returned by [`capabilities`], and are also available
+/// in const contexts as [`Capabilities::CURRENT`].
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::{Capabilities, PlanOrdering};
+/// let capabilities = nominal::capabilities();
+/// // Natural sorting is the default with the `unicode` feature.
+/// assert_eq!(
+///     capabilities.unicode,
+///     PlanOrdering::default() != PlanOrdering::Bytewise
+/// );
+///
+/// const COLORED: bool = Capabilities::CURRENT.ansi;
+/// assert_eq!(COLORED, capabilities.ansi);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Colored output, such as `Plan::write_colored_to`.
+    pub ansi: bool,
+    /// Interactive confirmation, such as `Plan::confirm`.
+    pub confirm: bool,
+    /// `miette` diagnostics for the errors of the crate.
+    pub diagnostics: bool,
+    /// Content digests, such as `ApplyOptions::record_digests`.
+    pub digest: bool,
+    /// JSON serialization of plans, reports, undo stacks and options, and the
+    /// files written as JSON, such as progress files and journals.
+    pub serde: bool,
+    /// Content sniffing, to fix the extensions of files.
+    pub sniff: bool,
+    /// Warnings and events emitted through `tracing`.
+    pub tracing: bool,
+    /// Unicode collation and normalization, such as
+    /// [`PlanOrdering::Unicode`](crate::PlanOrdering).
+    pub unicode: bool,
+}
+
+impl Capabilities {
+    /// The capabilities of this build of the crate.
+    pub const CURRENT: Self = Self {
+        ansi: cfg!(feature = "ansi"),
+        confirm: cfg!(feature = "confirm"),
+        diagnostics: cfg!(feature = "diagnostics"),
+        digest: cfg!(feature = "digest"),
+        serde: cfg!(feature = "serde"),
+        sniff: cfg!(feature = "sniff"),
+        tracing: cfg!(feature = "tracing"),
+        unicode: cfg!(feature = "unicode"),
+    };
+}
+
+/// Returns the optional features this build of the crate was compiled with.
+///
+/// See [`Capabilities`].
+pub const fn capabilities() -> Capabilities {
+    Capabilities::CURRENT
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{error::PlanError, fsutil::existing_ancestor, path::path_exists, planner::FsCache};
+
+/// The target directories of a plan found to be case-insensitive.
+///
+/// See [`probe_case_sensitivity`](crate::PlanOptions::probe_case_sensitivity).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CaseInsensitiveDirs {
+    /// The directories, as written in the target paths.
+    pub(crate) dirs: HashSet<PathBuf>,
+}
+
+impl CaseInsensitiveDirs {
+    /// Probes the parent directory of every target.
+    ///
+    /// Missing directories are assumed to behave like their closest existing
+    /// ancestor. Each existing directory is probed once, and the results are
+    /// kept in the cache.
+    pub(crate) fn probe<'a, I>(targets: I, cache: &mut FsCache) -> Result<Self, PlanError>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let mut dirs = HashSet::new();
+        for target in targets {
+            let Some(dir) = target.parent() else {
+                continue;
+            };
+            if dirs.contains(dir) {
+                continue;
+            }
+            let fail = |error| PlanError::CaseProbe {
+                dir: dir.to_owned(),
+                error,
+            };
+            let existing = existing_ancestor(dir).map_err(fail)?;
+            if cache.is_case_insensitive(existing).map_err(fail)? {
+                dirs.insert(dir.to_owned());
+            }
+        }
+        Ok(Self { dirs })
+    }
+
+    /// Returns `true` if no directory is case-insensitive.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Tests whether the parent directory of a target is case-insensitive.
+    pub(crate) fn contains_parent(&self, target: &Path) -> bool {
+        !self.is_empty() && target.parent().is_some_and(|dir| self.dirs.contains(dir))
+    }
+
+    /// Tests whether a target names its own source, on a case-insensitive
+    /// directory.
+    pub(crate) fn same_file(&self, source: &Path, target: &Path) -> bool {
+        source.parent() == target.parent()
+            && self.contains_parent(target)
+            && source.file_name().map(fold_case) == target.file_name().map(fold_case)
+    }
+}
+
+/// Folds the case of a file name, if it is valid Unicode.
+pub(crate) fn fold_case(name: &OsStr) -> OsString {
+    match name.to_str() {
+        Some(name) => name.to_lowercase().into(),
+        None => name.to_owned(),
+    }
+}
+
+/// Tests whether a directory is case-insensitive, by creating a probe file
+/// and looking it up under another case.
+pub(crate) fn is_case_insensitive(dir: &Path) -> io::Result<bool> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!(".nominal-case-probe-{}-{}", process::id(), count);
+        let probe = dir.join(&name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&probe)
+        {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+        let insensitive = path_exists(dir.join(name.to_uppercase()));
+        fs::remove_file(&probe)?;
+        return insensitive;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::Path};
+
+    use super::{is_case_insensitive, CaseInsensitiveDirs};
+    use crate::planner::FsCache;
+
+    #[test]
+    fn probe() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir(root.join("dir"))?;
+        let insensitive = is_case_insensitive(root)?;
+        // The probe file is removed.
+        assert_eq!(fs::read_dir(root)?.count(), 1);
+
+        let targets = [root.join("a"), root.join("dir/b"), root.join("missing/c")];
+        let mut cache = FsCache::default();
+        let dirs =
+            CaseInsensitiveDirs::probe(targets.iter().map(|target| target.as_path()), &mut cache)
+                .map_err(io::Error::other)?;
+        for target in &targets {
+            assert_eq!(dirs.contains_parent(target), insensitive);
+        }
+        assert_eq!(
+            dirs.same_file(&root.join("a.txt"), &root.join("A.TXT")),
+            insensitive
+        );
+        assert!(!dirs.same_file(&root.join("a.txt"), &root.join("b.txt")));
+        assert!(!dirs.same_file(&root.join("a.txt"), &root.join("dir/A.TXT")));
+        assert!(!CaseInsensitiveDirs::default().same_file(Path::new("a"), Path::new("A")));
+        Ok(())
+    }
+}
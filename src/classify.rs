@@ -0,0 +1,186 @@
+use std::{borrow::Cow, path::Path};
+
+use crate::path::normalize;
+
+/// The class of a rename operation, as returned by
+/// [`Plan::classify`](crate::Plan::classify).
+///
+/// Classes are computed lexically, from the paths alone once
+/// [normalized](crate::path::normalize). When several classes apply, the
+/// first one listed here wins. Whether an operation touches a hidden or
+/// system file is reported apart, by
+/// [`Plan::is_hidden`](crate::Plan::is_hidden).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RenameClass {
+    /// The source and target only differ by Unicode normalization, such as
+    /// a precomposed `é` and an `e` followed by a combining accent.
+    ///
+    /// Only detected with the `unicode` feature.
+    NormalizationOnly,
+    /// The source and target only differ by case.
+    ///
+    /// Such renames behave differently on case-insensitive filesystems.
+    /// Paths that are not valid Unicode are never case-only.
+    CaseOnly,
+    /// The target is in another directory than the source, under the same
+    /// file name.
+    Move,
+    /// The target is in another directory than the source, under another
+    /// file name.
+    MoveAndRename,
+    /// The source and target are in the same directory, and have the same
+    /// stem, but different extensions.
+    ExtensionOnly,
+    /// The source and target are in the same directory, under different
+    /// file names.
+    RenameInPlace,
+}
+
+#[allow(non_upper_case_globals)]
+impl RenameClass {
+    /// The former name of [`RenameInPlace`](Self::RenameInPlace).
+    #[deprecated(note = "renamed to `RenameClass::RenameInPlace`")]
+    pub const Plain: Self = Self::RenameInPlace;
+
+    /// The former name of [`MoveAndRename`](Self::MoveAndRename).
+    ///
+    /// The moves keeping their file name, which it also covered, are now
+    /// classified as [`Move`](Self::Move).
+    #[deprecated(note = "split into `RenameClass::Move` and `RenameClass::MoveAndRename`")]
+    pub const CrossDirectory: Self = Self::MoveAndRename;
+
+    /// Classifies a rename operation from its source and target paths.
+    pub(crate) fn of(source: &Path, target: &Path) -> Self {
+        let (source, target) = (&*normalize(source), &*normalize(target));
+        // Paths that are not valid Unicode are never normalization-only or
+        // case-only, rather than compared once lossily converted.
+        if let (Some(source_str), Some(target_str)) = (source.to_str(), target.to_str()) {
+            let source_nfc = to_nfc(source_str);
+            let target_nfc = to_nfc(target_str);
+            if source_str != target_str && source_nfc == target_nfc {
+                return Self::NormalizationOnly;
+            }
+            if source_nfc.to_lowercase() == target_nfc.to_lowercase() {
+                return Self::CaseOnly;
+            }
+        }
+        if source.parent() != target.parent() {
+            if source.file_name() == target.file_name() {
+                return Self::Move;
+            }
+            return Self::MoveAndRename;
+        }
+        if source.file_stem() == target.file_stem() && source.extension() != target.extension() {
+            return Self::ExtensionOnly;
+        }
+        Self::RenameInPlace
+    }
+
+    /// Returns the style used to tint the arrow of this class.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn style(self) -> nu_ansi_term::Style {
+        use nu_ansi_term::Color;
+
+        match self {
+            Self::NormalizationOnly => Color::Magenta.bold(),
+            Self::CaseOnly => Color::Yellow.bold(),
+            Self::Move => Color::Blue.normal(),
+            Self::MoveAndRename => Color::Blue.bold(),
+            Self::ExtensionOnly => Color::Cyan.normal(),
+            Self::RenameInPlace => nu_ansi_term::Style::new(),
+        }
+    }
+}
+
+/// Returns the NFC form of a string.
+pub(crate) fn to_nfc(s: &str) -> Cow<'_, str> {
+    #[cfg(feature = "unicode")]
+    {
+        let normalizer = icu_normalizer::ComposingNormalizer::new_nfc();
+        if !normalizer.is_normalized(s) {
+            return Cow::Owned(normalizer.normalize(s));
+        }
+    }
+    Cow::Borrowed(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::RenameClass;
+    #[cfg(unix)]
+    use crate::Renamer;
+
+    #[test]
+    fn classes() {
+        let class = |source, target| RenameClass::of(Path::new(source), Path::new(target));
+        assert_eq!(class("dir/a.txt", "dir/A.txt"), RenameClass::CaseOnly);
+        assert_eq!(class("Dir/a.txt", "dir/a.txt"), RenameClass::CaseOnly);
+        assert_eq!(class("dir/a.txt", "other/a.txt"), RenameClass::Move);
+        assert_eq!(class("a.txt", "other/a.txt"), RenameClass::Move);
+        assert_eq!(
+            class("dir/a.txt", "other/b.txt"),
+            RenameClass::MoveAndRename
+        );
+        assert_eq!(class("dir/a.txt", "dir/a.md"), RenameClass::ExtensionOnly);
+        assert_eq!(class("dir/a", "dir/a.md"), RenameClass::ExtensionOnly);
+        assert_eq!(class("dir/a.txt", "dir/b.txt"), RenameClass::RenameInPlace);
+        #[allow(deprecated)]
+        {
+            assert_eq!(RenameClass::Plain, RenameClass::RenameInPlace);
+            assert_eq!(RenameClass::CrossDirectory, RenameClass::MoveAndRename);
+        }
+        // Parents are compared once normalized.
+        assert_eq!(
+            class("dir/./a.txt", "other/../dir/b.txt"),
+            RenameClass::RenameInPlace
+        );
+        // Hidden files are classified as any other.
+        assert_eq!(class("dir/.a", "dir/.A"), RenameClass::CaseOnly);
+        assert_eq!(class("dir/a", "dir/.a"), RenameClass::RenameInPlace);
+        #[cfg(unix)]
+        {
+            use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+            // Both names would be lossily converted to the same string.
+            let source = Path::new(OsStr::from_bytes(b"dir/\xff.txt"));
+            let target = Path::new(OsStr::from_bytes(b"dir/\xfe.txt"));
+            assert_eq!(RenameClass::of(source, target), RenameClass::RenameInPlace);
+        }
+        #[cfg(feature = "unicode")]
+        {
+            assert_eq!(
+                class("caf\u{e9}.txt", "cafe\u{301}.txt"),
+                RenameClass::NormalizationOnly
+            );
+            assert_eq!(
+                class("caf\u{e9}.txt", "CAFE\u{301}.txt"),
+                RenameClass::CaseOnly
+            );
+        }
+        #[cfg(not(feature = "unicode"))]
+        assert_eq!(
+            class("caf\u{e9}.txt", "cafe\u{301}.txt"),
+            RenameClass::RenameInPlace
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hidden() {
+        let mut renamer = Renamer::new();
+        renamer.add(".a", ".A");
+        renamer.add("b", "c");
+        renamer.add("dir/d", "dir/.d");
+        renamer.add(".dir/e", ".dir/f");
+        let plan = renamer.plan().unwrap();
+        let hidden: Vec<_> = (0..plan.len())
+            .filter(|&index| plan.is_hidden(index))
+            .map(|index| plan.get(index).unwrap().source)
+            .collect();
+        assert_eq!(hidden, [".a", "dir/d"]);
+        assert_eq!(plan.summary().hidden, 2);
+    }
+}
@@ -0,0 +1,275 @@
+//! Path collation.
+//!
+//! These are the comparisons this crate uses to sort plans, exposed so that
+//! downstream tools can sort their own listings, such as the skipped
+//! entries of a plan, the same way as the plan, on every platform and with
+//! every feature set.
+
+use std::{
+    cmp::Ordering,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use crate::error::PlanError;
+
+/// The collation used to sort a plan, as returned by
+/// [`Plan::ordering`](crate::Plan::ordering), and requested with
+/// [`PlanOptions::ordering`](crate::PlanOptions::ordering).
+///
+/// The [default](Self::default) is `Unicode` with the
+/// `unicode` feature, and [`Bytewise`](Self::Bytewise) without. Since
+/// features are unified across a dependency graph, a crate relying on a
+/// specific order should request it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PlanOrdering {
+    /// Paths are compared with the ICU collator for the root locale, with
+    /// numeric ordering, so that `file2` sorts before `file10`.
+    ///
+    /// This requires the `unicode` feature. The order may change between ICU
+    /// versions.
+    #[cfg(feature = "unicode")]
+    #[default]
+    Unicode,
+    /// Paths are compared component-wise, by the bytes of each component.
+    ///
+    /// This order does not depend on features, versions or platforms, as
+    /// long as paths are valid Unicode. It can also be requested with
+    /// [`stable_bytewise_order`](crate::PlanOptions::stable_bytewise_order).
+    #[cfg_attr(not(feature = "unicode"), default)]
+    Bytewise,
+}
+
+/// Compares two paths as the targets of a plan built with the default
+/// [collation](PlanOrdering::default) are sorted.
+///
+/// This is [`PathCollation::compare`], with a collation created once per
+/// thread. Plans built with another collation are compared with their own
+/// [collation](crate::Plan::collation). If the default collation cannot be
+/// created, paths are compared by their bytes instead, as with
+/// [`PlanOrdering::Bytewise`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::{cmp::Ordering, path::Path};
+/// # use nominal::{collate::compare_paths, Renamer};
+/// let mut renamer = Renamer::new();
+/// renamer.add("a", "file10.txt");
+/// renamer.add("b", "file2.txt");
+/// renamer.add("c", "File1.txt");
+/// let plan = renamer.plan()?;
+///
+/// let targets: Vec<&Path> = plan
+///     .renames()
+///     .iter()
+///     .map(|rename| Path::new(rename.target))
+///     .collect();
+/// assert!(targets
+///     .windows(2)
+///     .all(|pair| compare_paths(pair[0], pair[1]) == Ordering::Less));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn compare_paths(p1: &Path, p2: &Path) -> Ordering {
+    thread_local! {
+        static DEFAULT: Option<PathCollation> = PathCollation::new(PlanOrdering::default())
+            .map_err(|err| warn!("comparing paths bytewise: {}", err))
+            .ok();
+    }
+    DEFAULT.with(|collation| match collation {
+        Some(collation) => collation.compare(p1, p2),
+        None => compare_bytewise(p1, p2),
+    })
+}
+
+/// A handle on the collation of a plan, to sort other paths the same way.
+///
+/// A handle is returned by [`Plan::collation`](crate::Plan::collation),
+/// sharing the collator of the plan, or created from a [`PlanOrdering`].
+/// With the `unicode` feature, creating a collator is costly, so a handle is
+/// meant to be reused, and is cheap to clone.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{cmp::Ordering, path::Path};
+/// # use nominal::{collate::PathCollation, PlanOrdering};
+/// let collation = PathCollation::new(PlanOrdering::Bytewise)?;
+/// let mut paths = [Path::new("b"), Path::new("a/c"), Path::new("a-c")];
+/// paths.sort_by(|p1, p2| collation.compare(p1, p2));
+/// assert_eq!(paths, [Path::new("a/c"), Path::new("a-c"), Path::new("b")]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct PathCollation {
+    collator: Arc<PathCollator>,
+}
+
+impl PathCollation {
+    /// Creates a handle on a new collator, with the specified collation.
+    pub fn new(ordering: PlanOrdering) -> Result<Self, PlanError> {
+        Ok(Self {
+            collator: PathCollator::shared(ordering)?,
+        })
+    }
+
+    /// Wraps the collator of a plan.
+    pub(crate) fn from_collator(collator: Arc<PathCollator>) -> Self {
+        Self { collator }
+    }
+
+    /// Returns the collation of this handle.
+    pub fn ordering(&self) -> PlanOrdering {
+        self.collator.ordering()
+    }
+
+    /// Compares two paths as the targets of a plan are sorted.
+    ///
+    /// Paths equal under the collation, such as paths differing only by
+    /// Unicode normalization, are then compared by their bytes, so that only
+    /// equal paths compare equal, as in the plan.
+    pub fn compare(&self, p1: &Path, p2: &Path) -> Ordering {
+        self.collator.compare_total(p1, p2)
+    }
+}
+
+/// Compares paths with the collation used to sort plans.
+#[derive(Debug)]
+pub(crate) struct PathCollator {
+    #[cfg(feature = "unicode")]
+    collator: Option<icu_collator::Collator>,
+}
+
+impl PathCollator {
+    /// Creates a new collator, with the specified collation.
+    pub(crate) fn new(ordering: PlanOrdering) -> Result<Self, PlanError> {
+        match ordering {
+            #[cfg(feature = "unicode")]
+            PlanOrdering::Unicode => {
+                use icu_collator::{Collator, CollatorOptions};
+
+                let mut collator_opts = CollatorOptions::new();
+                collator_opts.numeric = Some(icu_collator::Numeric::On);
+                let collator = Collator::try_new(Default::default(), collator_opts)?;
+                Ok(Self {
+                    collator: Some(collator),
+                })
+            }
+            PlanOrdering::Bytewise => Ok(Self {
+                #[cfg(feature = "unicode")]
+                collator: None,
+            }),
+        }
+    }
+
+    /// Creates a new collator, to be shared between plans.
+    pub(crate) fn shared(ordering: PlanOrdering) -> Result<Arc<Self>, PlanError> {
+        // The ICU collator is neither `Send` nor `Sync`, but plans should
+        // stay `Send` without the `unicode` feature.
+        #[allow(clippy::arc_with_non_send_sync)]
+        Ok(Arc::new(Self::new(ordering)?))
+    }
+
+    /// Returns the collation used by this collator.
+    pub(crate) fn ordering(&self) -> PlanOrdering {
+        #[cfg(feature = "unicode")]
+        if self.collator.is_some() {
+            return PlanOrdering::Unicode;
+        }
+        PlanOrdering::Bytewise
+    }
+
+    /// Compares two paths.
+    pub(crate) fn compare(&self, p1: &Path, p2: &Path) -> Ordering {
+        #[cfg(all(feature = "unicode", unix))]
+        if let Some(collator) = &self.collator {
+            use std::os::unix::ffi::OsStrExt;
+
+            return collator.compare_utf8(p1.as_os_str().as_bytes(), p2.as_os_str().as_bytes());
+        }
+        #[cfg(all(feature = "unicode", windows))]
+        if let Some(collator) = &self.collator {
+            use std::os::windows::ffi::OsStrExt;
+
+            let p1: Vec<u16> = p1.as_os_str().encode_wide().collect();
+            let p2: Vec<u16> = p2.as_os_str().encode_wide().collect();
+            return collator.compare_utf16(&p1, &p2);
+        }
+        compare_bytewise(p1, p2)
+    }
+
+    /// Compares two paths, breaking the ties of the collation bytewise, so
+    /// that only equal paths compare equal.
+    pub(crate) fn compare_total(&self, p1: &Path, p2: &Path) -> Ordering {
+        self.compare(p1, p2).then_with(|| compare_bytewise(p1, p2))
+    }
+}
+
+/// Compares two paths component-wise, by the bytes of each component.
+///
+/// Unlike [`Path::cmp`], this does not depend on how the platform compares
+/// path prefixes.
+fn compare_bytewise(p1: &Path, p2: &Path) -> Ordering {
+    fn bytes(component: Component<'_>) -> &[u8] {
+        component.as_os_str().as_encoded_bytes()
+    }
+    p1.components().map(bytes).cmp(p2.components().map(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{compare_paths, PathCollator, PlanOrdering};
+    use crate::{PlanOptions, Renamer};
+
+    #[test]
+    fn bytewise() {
+        let collator = PathCollator::new(PlanOrdering::Bytewise).unwrap();
+        assert_eq!(collator.ordering(), PlanOrdering::Bytewise);
+        let mut paths = ["b", "a/b", "file10", "a-b", "B", "\u{e9}", "file2", "z"];
+        paths.sort_by(|p1, p2| collator.compare(Path::new(p1), Path::new(p2)));
+        assert_eq!(
+            paths,
+            ["B", "a/b", "a-b", "b", "file10", "file2", "z", "\u{e9}"]
+        );
+    }
+
+    #[test]
+    fn same_order_as_plans() {
+        let targets = [
+            "file10",
+            "file2",
+            "File1",
+            "b/a",
+            "b-a",
+            "caf\u{e9}",
+            "cafe\u{301}",
+            "\u{e9}",
+            "z",
+        ];
+        let sorted = |options: &PlanOptions| {
+            let renamer: Renamer<_, _> = (targets.iter().enumerate())
+                .map(|(index, target)| (index.to_string(), Path::new(target)))
+                .collect();
+            let plan = renamer.plan_with(options).unwrap();
+            let sorted: Vec<_> = plan.renames().iter().map(|rename| rename.target).collect();
+            (plan.collation(), sorted)
+        };
+
+        let (collation, expected) = sorted(&PlanOptions::new());
+        let mut paths: Vec<_> = targets.iter().map(Path::new).collect();
+        paths.sort_by(|p1, p2| compare_paths(p1, p2));
+        assert_eq!(paths, expected);
+        paths.reverse();
+        paths.sort_by(|p1, p2| collation.compare(p1, p2));
+        assert_eq!(paths, expected);
+
+        let (collation, expected) = sorted(&PlanOptions::new().ordering(PlanOrdering::Bytewise));
+        assert_eq!(collation.ordering(), PlanOrdering::Bytewise);
+        paths.reverse();
+        paths.sort_by(|p1, p2| collation.compare(p1, p2));
+        assert_eq!(paths, expected);
+    }
+}
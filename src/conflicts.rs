@@ -0,0 +1,417 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    classify::to_nfc,
+    error::TargetKind,
+    options::{FollowSymlinks, StatErrorPolicy},
+    path::normalize,
+    plan::Plan,
+    planner::FsCache,
+    tag::Tag,
+};
+
+/// Options for [`Plan::conflicts`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ConflictOptions {
+    case_fold: bool,
+    normalization_fold: bool,
+}
+
+impl ConflictOptions {
+    /// Creates the default options, which only report exact conflicts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether names differing only by case conflict, as on
+    /// case-insensitive filesystems.
+    ///
+    /// Disabled by default.
+    pub fn case_fold(mut self, case_fold: bool) -> Self {
+        self.case_fold = case_fold;
+        self
+    }
+
+    /// Sets whether names differing only by Unicode normalization conflict,
+    /// as on filesystems normalizing file names.
+    ///
+    /// This has no effect without the `unicode` feature. Disabled by
+    /// default.
+    pub fn normalization_fold(mut self, normalization_fold: bool) -> Self {
+        self.normalization_fold = normalization_fold;
+        self
+    }
+
+    /// Returns the key under which a name is indexed.
+    pub(crate) fn key(&self, name: &OsStr) -> OsString {
+        let Some(name) = name.to_str() else {
+            return name.to_owned();
+        };
+        let name = if self.normalization_fold {
+            to_nfc(name)
+        } else {
+            name.into()
+        };
+        if self.case_fold {
+            name.to_lowercase().into()
+        } else {
+            name.into_owned().into()
+        }
+    }
+
+    /// Returns the kind of conflict between two names with the same key.
+    fn kind(&self, name_1: &OsStr, name_2: &OsStr, internal: bool) -> ConflictKind {
+        if name_1 == name_2 {
+            if internal {
+                ConflictKind::InternalDuplicate
+            } else {
+                ConflictKind::ExistingFile
+            }
+        } else if self.normalization_fold
+            && name_1.to_str().map(to_nfc) == name_2.to_str().map(to_nfc)
+        {
+            ConflictKind::NormalizationFold
+        } else {
+            ConflictKind::CaseFold
+        }
+    }
+}
+
+/// A conflict reported by [`Plan::conflicts`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Conflict {
+    /// The index of the conflicting operation, in plan order.
+    pub index: usize,
+    /// The kind of conflict.
+    pub kind: ConflictKind,
+    /// The path the target conflicts with: the target of an earlier
+    /// operation in plan order, or an existing file.
+    pub path: PathBuf,
+    /// The kind of the existing entry the target conflicts with, or [`None`]
+    /// for conflicts with the target of another operation.
+    ///
+    /// Tells apart the entries that could be merged with, such as
+    /// directories, from the ones that could only be replaced. It is also
+    /// [`None`] if the entry was removed since its directory was listed.
+    pub target_kind: Option<TargetKind>,
+    /// The tag of the conflicting operation, if any.
+    pub tag: Option<Tag>,
+}
+
+/// The kind of a [`Conflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConflictKind {
+    /// The target is also the target of an earlier operation.
+    InternalDuplicate,
+    /// The target already exists, and is not vacated by the plan.
+    ExistingFile,
+    /// The target only differs by case from the target of an earlier
+    /// operation, or from an existing file.
+    ///
+    /// See [`ConflictOptions::case_fold`].
+    CaseFold,
+    /// The target only differs by Unicode normalization from the target of
+    /// an earlier operation, or from an existing file.
+    ///
+    /// See [`ConflictOptions::normalization_fold`].
+    NormalizationFold,
+}
+
+impl<S, T> Plan<S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    /// Returns the conflicts between the targets of the plan, and with the
+    /// existing files.
+    ///
+    /// The targets and the entries of their parent directories are indexed
+    /// together, so that every kind of conflict is found in a single pass.
+    /// Each parent directory is read once. Entries that are the source of an
+    /// operation are not reported, since they will be vacated. As in
+    /// [`collisions`](Self::collisions), paths are compared once the
+    /// symbolic links in their parents are resolved.
+    ///
+    /// In the directories found to be case-insensitive by
+    /// [`probe_case_sensitivity`](crate::PlanOptions::probe_case_sensitivity),
+    /// names are compared as with [`ConflictOptions::case_fold`].
+    ///
+    /// Conflicts are returned in plan order. An operation conflicting with
+    /// several paths is reported once for each. Targets without a file name
+    /// are ignored.
+    ///
+    /// Fails if a path cannot be inspected, unless
+    /// [`PlanOptions::on_stat_error`](crate::PlanOptions::on_stat_error)
+    /// says otherwise: such a target then only conflicts with the other
+    /// targets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ConflictKind, ConflictOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("a.txt"))?;
+    /// File::create(path("B.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("A.txt"));
+    /// renamer.add(path("c.txt"), path("b.txt"));
+    /// renamer.add(path("d.txt"), path("b.txt"));
+    ///
+    /// let plan = renamer.plan()?;
+    /// let options = ConflictOptions::new().case_fold(true);
+    /// let kinds: Vec<_> = plan
+    ///     .conflicts(&options)?
+    ///     .into_iter()
+    ///     .map(|conflict| (conflict.index, conflict.kind))
+    ///     .collect();
+    /// assert_eq!(
+    ///     kinds,
+    ///     [
+    ///         (1, ConflictKind::CaseFold),
+    ///         (2, ConflictKind::CaseFold),
+    ///         (2, ConflictKind::InternalDuplicate),
+    ///     ]
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn conflicts(&self, options: &ConflictOptions) -> io::Result<Vec<Conflict>> {
+        self.conflicts_cached(options, &mut FsCache::default())
+    }
+
+    /// Returns the conflicts of the plan, looking up the filesystem through
+    /// the given cache.
+    pub(crate) fn conflicts_cached(
+        &self,
+        options: &ConflictOptions,
+        cache: &mut FsCache,
+    ) -> io::Result<Vec<Conflict>> {
+        let lenient = self.on_stat_error != StatErrorPolicy::Fail;
+        let sources: HashSet<PathBuf> = self
+            .renames
+            .iter()
+            .map(|r| match cache.resolve_parent(r.source.as_ref()) {
+                Err(_) if lenient => Ok(normalize(r.source.as_ref())),
+                resolved => resolved,
+            })
+            .collect::<io::Result<_>>()?;
+
+        // The earlier targets of each directory, keyed by folded name. The
+        // existing entries are indexed the same way by the cache.
+        let mut targets: HashMap<PathBuf, HashMap<OsString, Vec<usize>>> = HashMap::new();
+        let folded = options.clone().case_fold(true);
+        let mut conflicts = Vec::new();
+        for (index, rename) in self.renames.iter().enumerate() {
+            let options = if self
+                .case_insensitive_dirs
+                .contains_parent(rename.target.as_ref())
+            {
+                &folded
+            } else {
+                options
+            };
+            // A target that cannot be inspected still conflicts with the
+            // other targets.
+            let (target, inspectable) = match cache.resolve_parent(rename.target.as_ref()) {
+                Ok(resolved) => (resolved, true),
+                Err(_) if lenient => (normalize(rename.target.as_ref()), false),
+                Err(err) => return Err(err),
+            };
+            let (Some(dir), Some(name)) = (target.parent(), target.file_name()) else {
+                continue;
+            };
+            let key = options.key(name);
+
+            let existing = match inspectable.then(|| cache.dir_index(dir, options)) {
+                Some(Ok(index)) => index.get(&key),
+                Some(Err(err)) if !lenient => return Err(err),
+                _ => None,
+            };
+            for entry in existing.into_iter().flatten() {
+                let path = dir.join(entry);
+                if sources.contains(&path) {
+                    continue;
+                }
+                let target_kind = match TargetKind::of(&path, FollowSymlinks::No) {
+                    Err(_) if lenient => None,
+                    target_kind => target_kind?,
+                };
+                conflicts.push(Conflict {
+                    index,
+                    kind: options.kind(name, entry, false),
+                    target_kind,
+                    path,
+                    tag: rename.tag.clone(),
+                });
+            }
+
+            let earlier = targets
+                .entry(dir.to_owned())
+                .or_default()
+                .entry(key)
+                .or_default();
+            for &other in earlier.iter() {
+                let other_target = self.renames[other].target.as_ref();
+                let other_name = other_target.file_name().unwrap_or_default();
+                conflicts.push(Conflict {
+                    index,
+                    kind: options.kind(name, other_name, true),
+                    path: other_target.to_owned(),
+                    target_kind: None,
+                    tag: rename.tag.clone(),
+                });
+            }
+            earlier.push(index);
+        }
+        Ok(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::{ConflictKind, ConflictOptions};
+    use crate::{Renamer, TargetKind};
+
+    #[test]
+    fn combined_case_fold() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::write(root.join("README"), b"")?;
+        fs::write(root.join("Notes"), b"")?;
+        fs::write(root.join("source-1"), b"")?;
+        fs::write(root.join("source-2"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(root.join("source-1"), root.join("readme"));
+        renamer.add(root.join("source-2"), root.join("notes"));
+        let plan = renamer.plan().unwrap();
+
+        // The targets neither collide with each other, nor exactly with an
+        // existing file.
+        assert!(plan.conflicts(&ConflictOptions::new())?.is_empty());
+
+        let conflicts = plan.conflicts(&ConflictOptions::new().case_fold(true))?;
+        let conflicts: Vec<_> = conflicts
+            .iter()
+            .map(|conflict| (conflict.index, conflict.kind, conflict.path.clone()))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                (0, ConflictKind::CaseFold, root.join("Notes")),
+                (1, ConflictKind::CaseFold, root.join("README")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn target_kind() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::create_dir_all(root.join("full/sub"))?;
+        fs::write(root.join("file"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(root.join("a"), root.join("file"));
+        renamer.add(root.join("b"), root.join("full"));
+        renamer.add(root.join("c"), root.join("full"));
+        let plan = renamer.plan().unwrap();
+        let conflicts = plan.conflicts(&ConflictOptions::new())?;
+        let conflicts: Vec<_> = conflicts
+            .iter()
+            .map(|conflict| (conflict.index, conflict.kind, conflict.target_kind))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                (0, ConflictKind::ExistingFile, Some(TargetKind::File)),
+                (
+                    1,
+                    ConflictKind::ExistingFile,
+                    Some(TargetKind::Dir { empty: false })
+                ),
+                (
+                    2,
+                    ConflictKind::ExistingFile,
+                    Some(TargetKind::Dir { empty: false })
+                ),
+                (2, ConflictKind::InternalDuplicate, None),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn vacated_and_missing() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::write(root.join("a"), b"")?;
+        fs::write(root.join("b"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(root.join("a"), root.join("b"));
+        renamer.add(root.join("b"), root.join("c"));
+        renamer.add(root.join("d"), root.join("missing/e"));
+        let plan = renamer.plan().unwrap();
+        assert!(plan
+            .conflicts(&ConflictOptions::new().case_fold(true))?
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn case_insensitive_dirs() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::create_dir(root.join("folded"))?;
+        fs::write(root.join("README"), b"")?;
+        fs::write(root.join("folded/README"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(root.join("a"), root.join("readme"));
+        renamer.add(root.join("b"), root.join("folded/readme"));
+        let mut plan = renamer.plan().unwrap();
+        // Pretend that only one of the directories is case-insensitive.
+        plan.case_insensitive_dirs.dirs.insert(root.join("folded"));
+        let conflicts = plan.conflicts(&ConflictOptions::new())?;
+        let conflicts: Vec<_> = conflicts
+            .iter()
+            .map(|conflict| (conflict.index, conflict.kind, conflict.path.clone()))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [(0, ConflictKind::CaseFold, root.join("folded/README"))]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn normalization_fold() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(root.join("a"), root.join("caf\u{e9}"));
+        renamer.add(root.join("b"), root.join("cafe\u{301}"));
+        let plan = renamer.plan().unwrap();
+        assert!(plan.conflicts(&ConflictOptions::new())?.is_empty());
+        let conflicts = plan.conflicts(&ConflictOptions::new().normalization_fold(true))?;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, ConflictKind::NormalizationFold);
+        Ok(())
+    }
+}
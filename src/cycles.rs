@@ -0,0 +1,281 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fsutil::rename_noreplace, operation::Rename, options::ApplyOptions, path::path_exists,
+    staging::Staging, tempname::create_temp_sibling,
+};
+
+/// Finds the cycles of operations, each renaming its source to the source of
+/// the next one, and reorders the execution so that every cycle can be
+/// broken through a temporary path.
+///
+/// In each cycle, the operation executed first is the breaker: its source is
+/// moved aside before the other operations run, each once its target is
+/// vacated, and the breaker is executed last, from the temporary path. The
+/// other operations move up to the position of the breaker.
+///
+/// Only the cycles whose sources all exist are broken: a missing source is
+/// a free path the cycle can already go through, as with the temporary paths
+/// of a compacted [`UndoStack`](crate::UndoStack).
+///
+/// Returns the breaker of each cycle, keyed by the operation now executed
+/// first in the cycle.
+pub(crate) fn break_cycles<S, T>(
+    renames: &[Rename<S, T>],
+    execution: &mut Vec<usize>,
+) -> HashMap<usize, usize>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    // Operations sharing a source cannot all be executed, and are not
+    // followed.
+    let mut by_source: HashMap<&Path, Option<usize>> = HashMap::with_capacity(renames.len());
+    for (index, rename) in renames.iter().enumerate() {
+        by_source
+            .entry(rename.source.as_ref())
+            .and_modify(|next| *next = None)
+            .or_insert(Some(index));
+    }
+    let next: Vec<Option<usize>> = (renames.iter())
+        .map(|rename| by_source.get(rename.target.as_ref()).copied().flatten())
+        .collect();
+    drop(by_source);
+
+    // Each operation leads to one other at most, so that following them
+    // from every operation not visited yet finds every cycle once.
+    let mut walks = vec![usize::MAX; renames.len()];
+    let mut cycles = Vec::new();
+    for start in 0..renames.len() {
+        let mut current = Some(start);
+        while let Some(index) = current.filter(|&index| walks[index] == usize::MAX) {
+            walks[index] = start;
+            current = next[index];
+        }
+        // The walk is a cycle if it came back to one of its own operations.
+        let Some(first) = current.filter(|&index| walks[index] == start) else {
+            continue;
+        };
+        let mut cycle = vec![first];
+        let mut member = next[first].unwrap();
+        while member != first {
+            cycle.push(member);
+            member = next[member].unwrap();
+        }
+        let exists = |&member: &usize| {
+            let source = renames[member].source.as_ref();
+            path_exists(source).unwrap_or(true)
+        };
+        if cycle.len() > 1 && cycle.iter().all(exists) {
+            cycles.push(cycle);
+        }
+    }
+    if cycles.is_empty() {
+        return HashMap::new();
+    }
+
+    let mut position = vec![0; renames.len()];
+    for (rank, &index) in execution.iter().enumerate() {
+        position[index] = rank;
+    }
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::with_capacity(cycles.len());
+    for cycle in cycles {
+        let len = cycle.len();
+        let start = (0..len).min_by_key(|&i| position[cycle[i]]).unwrap();
+        // Once the source of the breaker is moved aside, the operation
+        // renaming to it can run, and so on backwards around the cycle.
+        let group = (1..=len)
+            .map(|offset| cycle[(start + len - offset) % len])
+            .collect();
+        groups.insert(cycle[start], group);
+    }
+    let mut breakers = HashMap::with_capacity(groups.len());
+    let mut grouped = vec![false; renames.len()];
+    let mut ordered = Vec::with_capacity(execution.len());
+    for &index in execution.iter() {
+        if let Some(group) = groups.remove(&index) {
+            breakers.insert(group[0], index);
+            for &member in &group {
+                grouped[member] = true;
+            }
+            ordered.extend(group);
+        } else if !grouped[index] {
+            ordered.push(index);
+        }
+    }
+    *execution = ordered;
+    breakers
+}
+
+/// Moves the source of a cycle breaker aside, into the
+/// [staging directory](ApplyOptions::staging_dir) if any, and otherwise to a
+/// temporary sibling named by the [namer](ApplyOptions::temp_namer), and
+/// returns its temporary path.
+pub(crate) fn park(source: &Path, options: &ApplyOptions) -> io::Result<PathBuf> {
+    if let Some(dir) = &options.staging_dir {
+        return Staging::new(dir).stage(source);
+    }
+    create_temp_sibling(options.namer(), source, |temp_path| {
+        if path_exists(temp_path)? {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        rename_noreplace(source, temp_path)
+    })
+}
+
+/// Moves a parked source back, unless its path was taken since.
+pub(crate) fn unpark(temp_path: &Path, source: &Path) -> io::Result<()> {
+    if path_exists(source)? {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the source path was taken",
+        ));
+    }
+    rename_noreplace(temp_path, source)
+}
+
+/// Removes the records of the staging directory whose source was moved on,
+/// once an apply is done, and the directory once empty.
+///
+/// The sources still parked, which could not be moved back, are kept.
+pub(crate) fn prune_staging(options: &ApplyOptions) {
+    let Some(dir) = &options.staging_dir else {
+        return;
+    };
+    if let Err(err) = Staging::prune(dir) {
+        warn!(
+            "could not clean up the staging directory {:?}: {}",
+            dir, err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, io};
+
+    use super::break_cycles;
+    use crate::{operation::Rename, ApplyOptions, PlanOptions, RenameStatus, Renamer, UndoStack};
+
+    #[test]
+    fn cycle_order() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for name in ["a", "b", "c", "d", "e", "f", "g", "m", "x"] {
+            fs::write(path(name), name)?;
+        }
+
+        let renames: Vec<Rename<_, _>> = [
+            ("x", "y"),
+            // A cycle of three.
+            ("b", "c"),
+            ("a", "b"),
+            ("c", "a"),
+            // A swap.
+            ("d", "e"),
+            ("e", "d"),
+            // Sharing a source, so not followed.
+            ("f", "g"),
+            ("g", "f"),
+            ("g", "h"),
+            // Through a missing path.
+            ("m", "n"),
+            ("n", "m"),
+        ]
+        .into_iter()
+        .map(|(source, target)| Rename::new(path(source), path(target)))
+        .collect();
+        let mut execution = vec![0, 3, 1, 2, 5, 4, 6, 7, 8, 9, 10];
+        let breakers = break_cycles(&renames, &mut execution);
+        // `c => a` comes first, so it breaks the cycle and goes last: once
+        // `c` is moved aside, `b => c` runs, then `a => b`.
+        assert_eq!(execution, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(breakers, HashMap::from([(1, 3), (4, 5)]));
+        Ok(())
+    }
+
+    #[test]
+    fn apply_cycles() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for name in ["a", "b", "c", "d", "e"] {
+            fs::write(path(name), name)?;
+        }
+
+        let renamer: Renamer<_, _> = [("a", "b"), ("b", "c"), ("c", "a"), ("d", "e"), ("e", "d")]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        let plan = renamer.plan().unwrap();
+        assert_eq!(plan.stats().cycles, 2);
+        assert!(plan.collisions()?.is_empty());
+        let report = plan.apply_with(&ApplyOptions::new());
+        assert!(report.is_success(), "{:?}", report);
+        for (name, content) in [("a", "c"), ("b", "a"), ("c", "b"), ("d", "e"), ("e", "d")] {
+            assert_eq!(fs::read_to_string(path(name))?, content);
+        }
+        // Nothing is left at a temporary path.
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 5);
+        // The report holds the operations as planned.
+        assert!((report.entries().iter())
+            .any(|entry| entry.source == path("a") && entry.target == path("b")));
+
+        let mut undo_stack = UndoStack::new();
+        undo_stack.push(&report);
+        undo_stack.undo_last().unwrap();
+        for name in ["a", "b", "c", "d", "e"] {
+            assert_eq!(fs::read_to_string(path(name))?, name);
+        }
+
+        // Iterating breaks cycles as well.
+        let renamer: Renamer<_, _> = [("a", "b"), ("b", "a")]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        assert!(renamer.plan().unwrap().apply_iter().all(|r| r.is_ok()));
+        assert_eq!(fs::read_to_string(path("a"))?, "b");
+        assert_eq!(fs::read_to_string(path("b"))?, "a");
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_cycle() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for name in ["a", "b", "c"] {
+            fs::write(path(name), name)?;
+        }
+
+        let renamer: Renamer<_, _> = [("a", "b"), ("b", "c"), ("c", "a")]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        let plan = (renamer.plan_with(&PlanOptions::new().stamp_sources(true))).unwrap();
+        // `c` is moved aside first, then `b => c` fails on the changed `b`.
+        fs::write(path("b"), "changed")?;
+        let report = plan.apply_with(&ApplyOptions::new());
+        let statuses: Vec<_> = (report.entries().iter())
+            .map(|entry| (entry.source.clone(), &entry.status))
+            .collect();
+        assert!(
+            matches!(
+                &statuses[..],
+                [
+                    (b, RenameStatus::Failed(_)),
+                    (a, RenameStatus::Pending),
+                    (c, RenameStatus::Pending),
+                ] if *a == path("a") && *b == path("b") && *c == path("c")
+            ),
+            "{:?}",
+            statuses
+        );
+        // The source moved aside is moved back.
+        assert_eq!(fs::read_to_string(path("c"))?, "c");
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 3);
+        Ok(())
+    }
+}
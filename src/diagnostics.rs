@@ -0,0 +1,225 @@
+use std::{fmt::Display, io};
+
+use miette::Diagnostic;
+
+use crate::error::{
+    ApplyError, ApplyErrorDetails, Error, PermissionReason, PlanError, TargetKind, UndoError,
+};
+
+impl Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.as_diagnostic().code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.as_diagnostic().help()
+    }
+}
+
+impl Error {
+    fn as_diagnostic(&self) -> &dyn Diagnostic {
+        match self {
+            Error::Plan(err) => err,
+            Error::Apply(err) => err,
+            Error::Undo(err) => err,
+        }
+    }
+}
+
+impl Diagnostic for PlanError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match *self {
+            PlanError::ChangesDirectory(_) => Some(Box::new("nominal::plan::changes_directory")),
+            PlanError::DuplicateSources(_) => Some(Box::new("nominal::plan::duplicate_sources")),
+            PlanError::InvalidTarget { .. } => Some(Box::new("nominal::plan::invalid_target")),
+            PlanError::EmptySource { .. } => Some(Box::new("nominal::plan::empty_source")),
+            PlanError::NonPortableTargets(_) => {
+                Some(Box::new("nominal::plan::non_portable_targets"))
+            }
+            PlanError::CaseProbe { .. } => Some(Box::new("nominal::plan::case_probe")),
+            #[cfg(feature = "unicode")]
+            PlanError::IcuCollator(_) => Some(Box::new("nominal::plan::icu_collator")),
+        }
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match *self {
+            PlanError::ChangesDirectory(_) => Some(Box::new(
+                "keep every target in its source directory, or disable the same-directory mode",
+            )),
+            PlanError::DuplicateSources(_) => Some(Box::new(
+                "the sources are the same file reached through different paths; keep only one",
+            )),
+            PlanError::InvalidTarget { .. } => Some(Box::new(
+                "end the target with the file name to rename the source to",
+            )),
+            PlanError::EmptySource { .. } => Some(Box::new("give the path of the file to rename")),
+            PlanError::NonPortableTargets(_) => Some(Box::new(
+                "rename the targets, or sanitize them before planning",
+            )),
+            PlanError::CaseProbe { .. } => Some(Box::new(
+                "probing needs write access to the target directories; disable it otherwise",
+            )),
+            #[cfg(feature = "unicode")]
+            PlanError::IcuCollator(_) => Some(Box::new(
+                "the collation data for the default locale is unavailable",
+            )),
+        }
+    }
+}
+
+impl Diagnostic for UndoError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match self {
+            UndoError::Blocked(_) => Some(Box::new("nominal::undo::blocked")),
+            UndoError::Plan(err) => err.code(),
+            UndoError::Apply(err) => err.code(),
+        }
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        match self {
+            UndoError::Blocked(_) => Some(Box::new(
+                "restore the renamed paths, or move away the files now at their original paths",
+            )),
+            UndoError::Plan(err) => err.help(),
+            UndoError::Apply(err) => err.help(),
+        }
+    }
+}
+
+impl Diagnostic for ApplyError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let code = match self.details {
+            ApplyErrorDetails::TargetExists { .. } => "nominal::apply::target_exists",
+            ApplyErrorDetails::Io { .. } => "nominal::apply::io",
+            ApplyErrorDetails::CrossesBoundary(_) => "nominal::apply::crosses_boundary",
+            ApplyErrorDetails::InsufficientSpace(_) => "nominal::apply::insufficient_space",
+            ApplyErrorDetails::TimedOut(_) => "nominal::apply::timed_out",
+            ApplyErrorDetails::SymlinkedParent(_) => "nominal::apply::symlinked_parent",
+            ApplyErrorDetails::MissingBaseDir(_) => "nominal::apply::missing_base_dir",
+            ApplyErrorDetails::SymlinkTraversal(_) => "nominal::apply::symlink_traversal",
+            ApplyErrorDetails::ContentChanged => "nominal::apply::content_changed",
+            ApplyErrorDetails::SourceChanged(_) => "nominal::apply::source_changed",
+            ApplyErrorDetails::InvalidTarget(_) => "nominal::apply::invalid_target",
+            ApplyErrorDetails::EmptySource => "nominal::apply::empty_source",
+            ApplyErrorDetails::KindMismatch { .. } => "nominal::apply::kind_mismatch",
+            ApplyErrorDetails::MergeFailed(_) => "nominal::apply::merge_failed",
+            ApplyErrorDetails::ContainsCurrentDir(_) => "nominal::apply::contains_current_dir",
+            ApplyErrorDetails::PermissionDenied { .. } => "nominal::apply::permission_denied",
+            ApplyErrorDetails::LockHeld(_) => "nominal::apply::lock_held",
+        };
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let help = match &self.details {
+            ApplyErrorDetails::TargetExists {
+                kind: TargetKind::Dir { .. },
+            } => {
+                "a directory already exists at the target path; move it away, choose another target, or merge directories in the apply options"
+            }
+            ApplyErrorDetails::TargetExists { .. } => {
+                "a file already exists at the target path; move it away or choose another target"
+            }
+            ApplyErrorDetails::Io { error, .. } => match error.kind() {
+                io::ErrorKind::NotFound => "the source path no longer exists",
+                io::ErrorKind::PermissionDenied => {
+                    "check the permissions of the source and target directories"
+                }
+                _ => return None,
+            },
+            ApplyErrorDetails::CrossesBoundary(_) => {
+                "fix the target path, or relax the boundary in the apply options"
+            }
+            ApplyErrorDetails::InsufficientSpace(_) => {
+                "free space on the target filesystem, or rename fewer files at once"
+            }
+            ApplyErrorDetails::TimedOut(_) => {
+                "the filesystem is not responding; check that it is still mounted"
+            }
+            ApplyErrorDetails::SymlinkedParent(_) => {
+                "use the resolved target path, or allow symbolic links in the apply options"
+            }
+            ApplyErrorDetails::MissingBaseDir(_) => {
+                "rebase the plan onto the directory its relative paths now live in"
+            }
+            ApplyErrorDetails::SymlinkTraversal(_) => {
+                "use the resolved paths, or allow traversing symbolic links in the apply options"
+            }
+            ApplyErrorDetails::ContentChanged => {
+                "the file was modified after it was renamed; restore it or undo it by hand"
+            }
+            ApplyErrorDetails::SourceChanged(_) => {
+                "the source was modified after planning; plan again, or skip the changed sources"
+            }
+            ApplyErrorDetails::InvalidTarget(_) => {
+                "end the target with the file name to rename the source to"
+            }
+            ApplyErrorDetails::EmptySource => "give the path of the file to rename",
+            ApplyErrorDetails::KindMismatch { .. } => {
+                "the source changed since the plan was made; review it before applying again"
+            }
+            ApplyErrorDetails::MergeFailed(_) => {
+                "resolve the clash in the target directory, then apply again to merge the rest"
+            }
+            ApplyErrorDetails::ContainsCurrentDir(_) => {
+                "change to another directory first, or allow renaming it in the apply options"
+            }
+            ApplyErrorDetails::PermissionDenied { reason, .. } => match reason {
+                PermissionReason::SourceDirNotWritable => {
+                    "grant write permission on the source directory, or run as its owner"
+                }
+                PermissionReason::TargetDirNotWritable => {
+                    "grant write permission on the target directory, or choose another target"
+                }
+                PermissionReason::StickyDir => {
+                    "only the owner of the source or of its directory can rename it; run as them"
+                }
+                PermissionReason::TargetDirNotSearchable => {
+                    "grant search permission on the directory, or choose a stat error policy"
+                }
+            },
+            ApplyErrorDetails::LockHeld(_) => {
+                "wait for the other apply to finish, or force the lock if it is stale"
+            }
+        };
+        Some(Box::new(help))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use miette::Diagnostic;
+
+    use crate::error::{ApplyError, ApplyErrorDetails, Error, IoOp, TargetKind};
+
+    #[test]
+    fn apply_error_code() {
+        let err = ApplyError::target_exists("a", "b", TargetKind::File);
+        assert_eq!(
+            err.code().unwrap().to_string(),
+            "nominal::apply::target_exists"
+        );
+        assert!(err.help().is_some());
+
+        let err = ApplyError::new(
+            "a",
+            "b",
+            ApplyErrorDetails::io(IoOp::Rename, "a", io::Error::other("oops")),
+        );
+        assert_eq!(err.code().unwrap().to_string(), "nominal::apply::io");
+        assert!(err.help().is_none());
+    }
+
+    #[test]
+    fn error_forwards() {
+        let err = Error::from(ApplyError::target_exists("a", "b", TargetKind::File));
+        assert_eq!(
+            err.code().unwrap().to_string(),
+            "nominal::apply::target_exists"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{operation::Rename, options::WriteOptions, path::normalize, plan::Plan};
+
+/// The differences between two plans, as returned by [`Plan::diff`].
+///
+/// Operations are matched by source path, once lexically
+/// [normalized](crate::path::normalize), and compared by normalized target.
+/// Sources with the same targets in both plans are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlanDiff {
+    added: Vec<Rename<PathBuf, PathBuf>>,
+    removed: Vec<Rename<PathBuf, PathBuf>>,
+    changed: Vec<ChangedTarget>,
+}
+
+/// An operation whose target changed between two plans.
+///
+/// See [`PlanDiff::changed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChangedTarget {
+    /// The source path, as in the new plan.
+    pub source: PathBuf,
+    /// The target path in the old plan.
+    pub old_target: PathBuf,
+    /// The target path in the new plan.
+    pub new_target: PathBuf,
+}
+
+impl<S, T> Plan<S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    /// Returns what changed from this plan to another, such as after the
+    /// user tweaked how the targets are named.
+    ///
+    /// The operations found in both plans are dropped first. A source left
+    /// with a single operation in each plan has its target
+    /// [changed](PlanDiff::changed); otherwise, the operations left are
+    /// [added](PlanDiff::added) or [removed](PlanDiff::removed). This is
+    /// only ambiguous for sources renamed more than once, which planning
+    /// allows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Renamer, WriteOptions};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a.txt", "1.txt");
+    /// renamer.add("b.txt", "2.txt");
+    /// renamer.add("c.txt", "3.txt");
+    /// let old = renamer.plan()?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("./a.txt", "1.txt");
+    /// renamer.add("b.txt", "02.txt");
+    /// renamer.add("d.txt", "4.txt");
+    /// let new = renamer.plan()?;
+    ///
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.changed()[0].old_target.to_str(), Some("2.txt"));
+    ///
+    /// let mut output = Vec::new();
+    /// diff.write_to(&mut output)?;
+    /// assert_eq!(
+    ///     String::from_utf8(output)?,
+    ///     "- c.txt => 3.txt\n~ b.txt => 02.txt\n+ d.txt => 4.txt\n"
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn diff<S2, T2>(&self, other: &Plan<S2, T2>) -> PlanDiff
+    where
+        S2: AsRef<Path>,
+        T2: AsRef<Path>,
+    {
+        let mut old = by_source(self);
+        let mut new = by_source(other);
+        // Operations found in both plans are dropped from both sides.
+        for (source, new_renames) in &mut new {
+            if let Some(old_renames) = old.get_mut(source) {
+                new_renames.retain(|(_, rename)| {
+                    let target = normalize(rename.target.as_ref());
+                    match (old_renames.iter())
+                        .position(|(_, old)| normalize(old.target.as_ref()) == target)
+                    {
+                        Some(position) => {
+                            old_renames.remove(position);
+                            false
+                        }
+                        None => true,
+                    }
+                });
+            }
+        }
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for (source, new_renames) in new {
+            match (&new_renames[..], old.get(&source).map(Vec::as_slice)) {
+                ([(index, new_rename)], Some([(_, old_rename)])) => {
+                    changed.push((
+                        *index,
+                        ChangedTarget {
+                            source: new_rename.source.as_ref().to_owned(),
+                            old_target: old_rename.target.as_ref().to_owned(),
+                            new_target: new_rename.target.as_ref().to_owned(),
+                        },
+                    ));
+                    old.remove(&source);
+                }
+                _ => added.extend(new_renames),
+            }
+        }
+        let mut removed: Vec<_> = old.into_values().flatten().collect();
+
+        // Each side is kept in the order of its plan.
+        removed.sort_by_key(|(index, _)| *index);
+        changed.sort_by_key(|(index, _)| *index);
+        added.sort_by_key(|(index, _)| *index);
+        PlanDiff {
+            added: added.into_iter().map(|(_, rename)| owned(rename)).collect(),
+            removed: removed
+                .into_iter()
+                .map(|(_, rename)| owned(rename))
+                .collect(),
+            changed: changed.into_iter().map(|(_, changed)| changed).collect(),
+        }
+    }
+}
+
+impl PlanDiff {
+    /// Returns the operations of the new plan whose source is not renamed
+    /// to the same target in the old plan, in new plan order.
+    pub fn added(&self) -> &[Rename<PathBuf, PathBuf>] {
+        &self.added
+    }
+
+    /// Returns the operations of the old plan whose source is not renamed
+    /// to the same target in the new plan, in old plan order.
+    pub fn removed(&self) -> &[Rename<PathBuf, PathBuf>] {
+        &self.removed
+    }
+
+    /// Returns the sources renamed to a different target in each plan, in
+    /// new plan order.
+    pub fn changed(&self) -> &[ChangedTarget] {
+        &self.changed
+    }
+
+    /// Returns `true` if the plans rename the same sources to the same
+    /// targets.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Writes the differences to the specified writer.
+    pub fn write_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.write_with(writer, &WriteOptions::default())
+    }
+
+    /// Writes the differences to the specified writer, with the specified
+    /// options.
+    ///
+    /// The removed operations come first, prefixed with `-`, then the
+    /// changed ones, prefixed with `~` and written with their new target,
+    /// then the added ones, prefixed with `+`.
+    pub fn write_with<W>(&self, writer: &mut W, options: &WriteOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut line = String::new();
+        for (marker, rename) in self.marked() {
+            line.clear();
+            let _ = write!(line, "{} ", marker.symbol());
+            rename.push_line(&mut line, options);
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the differences to the specified writer, with ANSI colors and
+    /// the specified options.
+    ///
+    /// Markers are colored by kind of difference, and paths as in
+    /// [`Plan::write_colored_with`]. Warnings are emitted through
+    /// [`TracingSink`](crate::TracingSink).
+    #[cfg(feature = "ansi")]
+    pub fn write_colored_with<W>(&self, writer: &mut W, options: &WriteOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let ls_colors = crate::plan::ls_colors(&mut crate::TracingSink);
+        let mut line = String::new();
+        for (marker, rename) in self.marked() {
+            line.clear();
+            let _ = write!(line, "{} ", marker.style().paint(marker.symbol()));
+            rename.push_colored_line(&ls_colors, &mut line, options);
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the operations to write, with their marker.
+    fn marked(&self) -> impl Iterator<Item = (Marker, Rename<&Path, &Path>)> {
+        fn borrowed(rename: &Rename<PathBuf, PathBuf>) -> Rename<&Path, &Path> {
+            Rename::new(&rename.source, &rename.target)
+        }
+        let removed = (self.removed.iter()).map(|rename| (Marker::Removed, borrowed(rename)));
+        let changed = self.changed.iter().map(|changed| {
+            let rename = Rename::new(changed.source.as_path(), changed.new_target.as_path());
+            (Marker::Changed, rename)
+        });
+        let added = (self.added.iter()).map(|rename| (Marker::Added, borrowed(rename)));
+        removed.chain(changed).chain(added)
+    }
+}
+
+/// The kind of a written difference.
+#[derive(Clone, Copy)]
+enum Marker {
+    Removed,
+    Changed,
+    Added,
+}
+
+impl Marker {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Removed => "-",
+            Self::Changed => "~",
+            Self::Added => "+",
+        }
+    }
+
+    #[cfg(feature = "ansi")]
+    fn style(self) -> nu_ansi_term::Style {
+        use nu_ansi_term::Color;
+
+        match self {
+            Self::Removed => Color::Red.normal(),
+            Self::Changed => Color::Yellow.normal(),
+            Self::Added => Color::Green.normal(),
+        }
+    }
+}
+
+/// The operations of a plan, with their index in plan order, keyed by
+/// normalized source.
+type BySource<'a, S, T> = HashMap<PathBuf, Vec<(usize, &'a Rename<S, T>)>>;
+
+/// Groups the operations of a plan by normalized source, with their index in
+/// plan order.
+fn by_source<S, T>(plan: &Plan<S, T>) -> BySource<'_, S, T>
+where
+    S: AsRef<Path>,
+{
+    let mut renames: HashMap<_, Vec<_>> = HashMap::new();
+    for (index, rename) in plan.renames().iter().enumerate() {
+        renames
+            .entry(normalize(rename.source.as_ref()))
+            .or_default()
+            .push((index, rename));
+    }
+    renames
+}
+
+/// Copies an operation into owned paths.
+fn owned<S, T>(rename: &Rename<S, T>) -> Rename<PathBuf, PathBuf>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    Rename {
+        source: rename.source.as_ref().to_owned(),
+        target: rename.target.as_ref().to_owned(),
+        tag: rename.tag.clone(),
+        priority: rename.priority,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::{Rename, Renamer};
+
+    #[test]
+    fn diff() {
+        let plan = |entries: &[(&'static str, &'static str)]| {
+            let mut renamer = Renamer::new();
+            for &(source, target) in entries {
+                renamer.add(source, target);
+            }
+            renamer.plan().unwrap()
+        };
+        let old = plan(&[("a", "x/1"), ("b", "2"), ("b", "3"), ("b", "8"), ("c", "4")]);
+        let new = plan(&[
+            ("./a", "x/./1"),
+            ("b", "3"),
+            ("b", "5"),
+            ("c", "6"),
+            ("d", "7"),
+        ]);
+
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        // `b` is left with two old targets for one new, which are not paired.
+        let pairs = |renames: &[Rename<PathBuf, PathBuf>]| -> Vec<(PathBuf, PathBuf)> {
+            renames
+                .iter()
+                .map(|rename| (rename.source.clone(), rename.target.clone()))
+                .collect()
+        };
+        let pair = |source: &str, target: &str| (PathBuf::from(source), PathBuf::from(target));
+        assert_eq!(pairs(diff.removed()), [pair("b", "2"), pair("b", "8")]);
+        assert_eq!(pairs(diff.added()), [pair("b", "5"), pair("d", "7")]);
+        assert_eq!(diff.changed().len(), 1);
+        assert_eq!(diff.changed()[0].source, Path::new("c"));
+        assert_eq!(diff.changed()[0].old_target, Path::new("4"));
+        assert_eq!(diff.changed()[0].new_target, Path::new("6"));
+
+        let reverse = new.diff(&old);
+        assert_eq!(reverse.added(), diff.removed());
+        assert_eq!(reverse.removed(), diff.added());
+    }
+}
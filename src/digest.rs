@@ -0,0 +1,120 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use sha2::{Digest as _, Sha256};
+
+use crate::{inspect, options::FollowSymlinks};
+
+/// The SHA-256 digest of the content of a file.
+///
+/// See [`ApplyOptions::record_digests`](crate::ApplyOptions::record_digests).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    /// Returns the bytes of the digest.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Parses a digest from its hexadecimal form, as formatted by
+    /// [`Display`](fmt::Display), whatever the case of its digits.
+    ///
+    /// Returns [`None`] if the string is not 64 hexadecimal digits.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(Self(bytes))
+    }
+}
+
+/// Formats the digest in lowercase hexadecimal.
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the digest of a regular file, or [`None`] if the path is not a
+/// regular file or is larger than `max_size` bytes.
+///
+/// The file is read in chunks, so that it is never held in memory.
+pub(crate) fn digest_file(path: &Path, max_size: u64) -> io::Result<Option<Digest>> {
+    let metadata = inspect::metadata(path, FollowSymlinks::No)?;
+    if !metadata.is_file() || metadata.len() > max_size {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Some(Digest(hasher.finalize().into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::{digest_file, Digest};
+
+    #[test]
+    fn sha256_vectors() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("a");
+        let digest = |content: &[u8]| {
+            fs::write(&path, content)?;
+            Ok::<_, io::Error>(digest_file(&path, u64::MAX)?.unwrap().to_string())
+        };
+        assert_eq!(
+            digest(b"")?,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            digest(b"abc")?,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hex() {
+        let digest = Digest([0xab; 32]);
+        assert_eq!(Digest::from_hex(&digest.to_string()), Some(digest));
+        assert_eq!(Digest::from_hex(&"AB".repeat(32)), Some(digest));
+        assert_eq!(Digest::from_hex("ab"), None);
+        assert_eq!(Digest::from_hex(&"zz".repeat(32)), None);
+        assert_eq!(Digest::from_hex(&"é".repeat(32)), None);
+        assert_eq!(Digest::from_hex(&"+a".repeat(32)), None);
+    }
+
+    #[test]
+    fn files() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("a");
+        fs::write(&path, b"abc")?;
+        assert!(digest_file(&path, 3)?.is_some());
+        // Larger files and directories are skipped.
+        assert_eq!(digest_file(&path, 2)?, None);
+        assert_eq!(digest_file(temp_dir.path(), u64::MAX)?, None);
+        Ok(())
+    }
+}
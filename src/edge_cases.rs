@@ -0,0 +1,120 @@
+//! The behavior of the API on degenerate inputs: empty paths, and plans
+//! without any operation.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ApplyErrorDetails, ApplyOptions, ConflictOptions, InvalidTargetReason, LockScope, Plan,
+    PlanError, PlanOptions, Rename, Renamer, Session, UndoStack,
+};
+
+#[test]
+fn empty_paths() {
+    let plan = |source: &'static str, target: &'static str| {
+        let mut renamer = Renamer::new();
+        renamer.add(source, target);
+        renamer.plan()
+    };
+    assert!(matches!(
+        plan("", "a"),
+        Err(PlanError::EmptySource { target }) if target == Path::new("a")
+    ));
+    assert!(matches!(
+        plan("a", ""),
+        Err(PlanError::InvalidTarget {
+            reason: InvalidTargetReason::Empty,
+            ..
+        })
+    ));
+    // The target is checked first.
+    assert!(matches!(
+        plan("", ""),
+        Err(PlanError::InvalidTarget {
+            reason: InvalidTargetReason::Empty,
+            ..
+        })
+    ));
+    // Even where the operation would otherwise be skipped as a no-op.
+    let mut renamer = Renamer::new();
+    renamer.add("a", "a");
+    renamer.add("", "");
+    assert!(renamer.plan().is_err());
+
+    let err = Rename::new("", "a").apply().unwrap_err();
+    assert!(matches!(err.details, ApplyErrorDetails::EmptySource));
+    let err = Rename::new("a", "").apply().unwrap_err();
+    assert!(matches!(
+        err.details,
+        ApplyErrorDetails::InvalidTarget(InvalidTargetReason::Empty)
+    ));
+}
+
+#[test]
+fn empty_plan() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let empty = || Renamer::<PathBuf, PathBuf>::new().plan().unwrap();
+
+    let plan = empty();
+    assert!(plan.is_empty());
+    assert!(plan.skipped().is_empty());
+    assert!(plan.collisions()?.is_empty());
+    assert!(plan.conflicts(&ConflictOptions::new())?.is_empty());
+    assert_eq!(plan.apply_iter().count(), 0);
+    let mut output = Vec::new();
+    plan.write_to(&mut output)?;
+    assert!(output.is_empty());
+    #[cfg(feature = "confirm")]
+    assert!(plan.confirm()?.is_none());
+
+    empty().apply().unwrap();
+
+    // Nothing is locked, nor written to the filesystem.
+    let options = ApplyOptions::new().lock(Some(LockScope::CommonAncestor));
+    let report = empty().apply_with(&options);
+    assert!(report.is_empty() && report.is_success());
+    assert!(report.created_dirs().is_empty());
+    report.into_result().unwrap();
+    let options = ApplyOptions::new().lock(Some(LockScope::Path(temp_dir.path().to_owned())));
+    assert!(empty().apply_with(&options).is_success());
+    assert_eq!(fs::read_dir(temp_dir.path())?.count(), 0);
+
+    let mut session = Session::new(PlanOptions::new(), ApplyOptions::new());
+    let plan = session.plan(Renamer::<PathBuf, PathBuf>::new()).unwrap();
+    assert!(session.dry_run(&plan).is_empty());
+    assert!(session.apply(plan).is_success());
+
+    // Empty reports are not recorded.
+    let mut undo_stack = UndoStack::new();
+    undo_stack.push(&empty().apply_with(&ApplyOptions::new()));
+    assert!(undo_stack.is_empty());
+    assert!(undo_stack.undo_last().unwrap().is_none());
+    assert!(undo_stack.undo_all().unwrap().is_empty());
+    assert_eq!(undo_stack.compact(), 0);
+    Ok(())
+}
+
+#[test]
+fn only_noops() {
+    let mut renamer = Renamer::new();
+    renamer.add("a", "a");
+    renamer.add("b", "b");
+    let plan: Plan<_, _> = renamer.plan().unwrap();
+    assert!(plan.is_empty());
+    assert_eq!(plan.skipped().len(), 2);
+    assert!(plan.apply_with(&ApplyOptions::new()).is_success());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn empty_journal_paths() {
+    let json = serde_json::json!({
+        "version": 1,
+        "plans": [[{ "source": "", "target": "a" }]],
+    });
+    assert!(UndoStack::from_json_value(&json).is_err());
+    let json = serde_json::json!({ "version": 1, "plans": [] });
+    assert!(UndoStack::from_json_value(&json).unwrap().is_empty());
+}
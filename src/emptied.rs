@@ -0,0 +1,135 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::fsutil::resolve_parent;
+
+/// The source directories that applying a plan would leave empty, as
+/// reported by [`Plan::dirs_emptied`](crate::Plan::dirs_emptied).
+///
+/// Nothing is removed: the directories are only found.
+#[derive(Debug, Default)]
+pub(crate) struct EmptiedDirs {
+    /// The emptied directories, deepest first.
+    dirs: Vec<PathBuf>,
+}
+
+impl EmptiedDirs {
+    /// Finds the directories emptied by moving away the given sources to
+    /// the given targets, given the current state of the filesystem.
+    ///
+    /// Paths are compared once the symbolic links in their parents are
+    /// resolved.
+    pub(crate) fn find<'a, S, T>(sources: S, targets: T) -> io::Result<Self>
+    where
+        S: IntoIterator<Item = &'a Path>,
+        T: IntoIterator<Item = &'a Path>,
+    {
+        let sources: HashSet<PathBuf> = sources
+            .into_iter()
+            .map(resolve_parent)
+            .collect::<io::Result<_>>()?;
+        // Directories receiving a target are not emptied.
+        let mut filled = HashSet::new();
+        for target in targets {
+            let target = resolve_parent(target)?;
+            filled.extend(target.ancestors().skip(1).map(Path::to_owned));
+        }
+        // Directories containing no source cannot be emptied, and are not
+        // even read.
+        let mut candidates: Vec<&Path> = sources
+            .iter()
+            .flat_map(|source| source.ancestors().skip(1))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        // The children of a directory are checked before it.
+        candidates.sort_by(|dir_1, dir_2| {
+            let depth = |dir: &Path| dir.components().count();
+            depth(dir_2)
+                .cmp(&depth(dir_1))
+                .then_with(|| dir_1.cmp(dir_2))
+        });
+
+        let mut emptied = HashMap::new();
+        let mut dirs = Vec::new();
+        for dir in candidates {
+            if emptied.contains_key(dir) {
+                continue;
+            }
+            let is_emptied = !filled.contains(dir)
+                && !sources.contains(dir)
+                && is_emptied(dir, &sources, &emptied)?;
+            if is_emptied {
+                dirs.push(dir.to_owned());
+                emptied.insert(dir, true);
+            } else {
+                // The ancestors of a directory that is not emptied are not
+                // emptied either.
+                for ancestor in dir.ancestors() {
+                    emptied.insert(ancestor, false);
+                }
+            }
+        }
+        Ok(Self { dirs })
+    }
+
+    /// Returns the emptied directories, deepest first.
+    pub(crate) fn into_dirs(self) -> Vec<PathBuf> {
+        self.dirs
+    }
+}
+
+/// Tests whether every entry of a directory is a source or an emptied
+/// directory.
+fn is_emptied(
+    dir: &Path,
+    sources: &HashSet<PathBuf>,
+    emptied: &HashMap<&Path, bool>,
+) -> io::Result<bool> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if !sources.contains(&path) && emptied.get(path.as_path()) != Some(&true) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::EmptiedDirs;
+
+    #[test]
+    fn recursive() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        for dir in ["a/b/c", "a/d", "e", "f"] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+        for file in ["a/b/c/1", "a/b/2", "a/d/3", "a/d/4", "e/5", "f/6"] {
+            fs::write(root.join(file), b"")?;
+        }
+        fs::write(root.join("keep"), b"")?;
+
+        let sources = ["a/b/c/1", "a/b/2", "a/d/3", "e/5", "f/6"].map(|file| root.join(file));
+        let targets = [root.join("f/7")];
+        let dirs = EmptiedDirs::find(
+            sources.iter().map(|source| source.as_path()),
+            targets.iter().map(|target| target.as_path()),
+        )?
+        .into_dirs();
+        // `a/d` still holds `4`, and `f` receives `7`.
+        assert_eq!(dirs, [root.join("a/b/c"), root.join("a/b"), root.join("e")]);
+        Ok(())
+    }
+}
@@ -1,7 +1,20 @@
-use std::{fmt, io, path::PathBuf};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use thiserror::Error;
 
+use crate::{
+    inspect,
+    lock::LockHolder,
+    options::{Boundary, FollowSymlinks, SourceKind},
+    portable::NameIssue,
+    stamp::StampField,
+    tag::Tag,
+};
+
 /// The general error type for this crate.
 #[derive(Debug, Error)]
 #[error(transparent)]
@@ -10,6 +23,8 @@ pub enum Error {
     Plan(#[from] PlanError),
     /// An apply error.
     Apply(#[from] ApplyError),
+    /// An undo error.
+    Undo(#[from] UndoError),
 }
 
 /// The error type returned from
@@ -17,12 +32,80 @@ pub enum Error {
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum PlanError {
+    /// Some rename operations move their source to another directory, while
+    /// [`PlanOptions::same_directory`](crate::PlanOptions::same_directory)
+    /// is enabled.
+    ///
+    /// Holds the source and target paths of the offending operations.
+    #[error("{} rename operation(s) change directory", .0.len())]
+    ChangesDirectory(Vec<(PathBuf, PathBuf)>),
+    /// Some rename operations have sources referring to the same directory
+    /// entry, while
+    /// [`PlanOptions::resolve_identity`](crate::PlanOptions::resolve_identity)
+    /// is enabled.
+    ///
+    /// Holds the pairs of conflicting source paths.
+    #[error("{} pair(s) of rename operations have the same source", .0.len())]
+    DuplicateSources(Vec<(PathBuf, PathBuf)>),
+    /// The target of a rename operation does not end with a file name.
+    #[error("invalid target {target:?}: {reason}")]
+    InvalidTarget {
+        /// The target path.
+        target: PathBuf,
+        /// Why the target is invalid.
+        reason: InvalidTargetReason,
+    },
+    /// The source of a rename operation is empty.
+    #[error("the source of the rename operation to {target:?} is empty")]
+    EmptySource {
+        /// The target path of the operation.
+        target: PathBuf,
+    },
+    /// The file names of some targets break the rules of the
+    /// [validation profile](crate::PlanOptions::validation_profile).
+    ///
+    /// Holds the offending targets, with the first issue of each.
+    #[error("{} target(s) break the naming rules of the validation profile", .0.len())]
+    NonPortableTargets(Vec<(PathBuf, NameIssue)>),
+    /// The case sensitivity of a target directory could not be probed, while
+    /// [`probe_case_sensitivity`](crate::PlanOptions::probe_case_sensitivity)
+    /// is enabled.
+    #[error("could not probe the case sensitivity of {dir:?}: {error}")]
+    CaseProbe {
+        /// The target directory.
+        dir: PathBuf,
+        /// The error raised while probing.
+        error: io::Error,
+    },
     #[cfg(feature = "unicode")]
     /// The ICU collator could not be created.
     #[error("could not create collator: {0}")]
     IcuCollator(#[from] icu_collator::Error),
 }
 
+/// The error type returned from
+/// [`UndoStack::undo_last`](crate::UndoStack::undo_last) and
+/// [`UndoStack::undo_all`](crate::UndoStack::undo_all).
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum UndoError {
+    /// Some operations can no longer be undone, because their paths changed
+    /// since the plan was applied.
+    ///
+    /// Holds an error for every blocking operation, renaming its recorded
+    /// target back to its recorded source. Nothing was renamed.
+    #[error("{} rename operation(s) can no longer be undone", .0.len())]
+    Blocked(Vec<ApplyError>),
+    /// The undo plan could not be created.
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    /// An undo operation failed.
+    ///
+    /// The operations undone before the failure are removed from the stack.
+    #[error(transparent)]
+    Apply(#[from] ApplyError),
+}
+
 /// The error type returned from [`Plan::apply`](crate::plan::Plan::apply).
 #[derive(Debug)]
 pub struct ApplyError {
@@ -32,6 +115,9 @@ pub struct ApplyError {
     pub target: PathBuf,
     /// The details of the error.
     pub details: ApplyErrorDetails,
+    /// The tag of the rename operation, if any.
+    pub tag: Option<Tag>,
+    index: Option<usize>,
 }
 
 /// The details of an [`ApplyError`].
@@ -39,9 +125,278 @@ pub struct ApplyError {
 #[non_exhaustive]
 pub enum ApplyErrorDetails {
     /// The target path already exists.
-    TargetExists,
-    /// An I/O error occurred.
-    Io(io::Error),
+    ///
+    /// The target is checked before renaming. On Linux and Windows, the
+    /// rename itself also refuses to replace a target created since, on the
+    /// filesystems supporting it.
+    TargetExists {
+        /// The kind of the existing entry.
+        kind: TargetKind,
+    },
+    /// A filesystem call failed.
+    Io {
+        /// The failed operation.
+        op: IoOp,
+        /// The path the operation was made on.
+        path: PathBuf,
+        /// The error returned by the filesystem.
+        error: io::Error,
+    },
+    /// The rename operation crosses the configured boundary.
+    CrossesBoundary(Boundary),
+    /// The sources copied to another filesystem do not fit in its available
+    /// space.
+    ///
+    /// See
+    /// [`skip_free_space_check`](crate::ApplyOptions::skip_free_space_check).
+    InsufficientSpace(Box<SpaceShortage>),
+    /// A filesystem call did not complete within the operation timeout.
+    TimedOut(Duration),
+    /// The target parent is or traverses the given symbolic link.
+    ///
+    /// See
+    /// [`deny_symlinked_parents`](crate::ApplyOptions::deny_symlinked_parents).
+    SymlinkedParent(PathBuf),
+    /// The operation holds a relative path, and the base directory of the
+    /// plan does not exist.
+    ///
+    /// See [`Plan::base_dir`](crate::Plan::base_dir).
+    MissingBaseDir(PathBuf),
+    /// The source or target path traverses the given symbolic link, one of
+    /// its ancestors.
+    ///
+    /// See [`no_symlink_traversal`](crate::ApplyOptions::no_symlink_traversal).
+    SymlinkTraversal(PathBuf),
+    /// The renamed file no longer has the content it was renamed with, and
+    /// cannot be undone.
+    ///
+    #[cfg_attr(
+        feature = "digest",
+        doc = "See [`record_digests`](crate::ApplyOptions::record_digests)."
+    )]
+    #[cfg_attr(
+        not(feature = "digest"),
+        doc = "See `ApplyOptions::record_digests`, with the `digest` feature."
+    )]
+    ContentChanged,
+    /// The source changed since the plan was made: the given field of its
+    /// stamp no longer matches.
+    ///
+    /// See [`PlanOptions::stamp_sources`](crate::PlanOptions::stamp_sources).
+    SourceChanged(StampField),
+    /// The target path does not end with a file name.
+    InvalidTarget(InvalidTargetReason),
+    /// The source path is empty.
+    EmptySource,
+    /// The source is not of the expected kind.
+    ///
+    /// See [`ApplyOptions::expect`](crate::ApplyOptions::expect).
+    KindMismatch {
+        /// The expected kind.
+        expected: SourceKind,
+        /// The actual kind.
+        actual: SourceKind,
+    },
+    /// A child of the source directory could not be moved into the existing
+    /// target directory.
+    ///
+    /// Holds the error of the child. The children moved before the failure
+    /// stay in the target directory. See
+    /// [`ApplyOptions::dir_merge`](crate::ApplyOptions::dir_merge).
+    MergeFailed(Box<ApplyError>),
+    /// The source is the current directory of the process, or one of its
+    /// ancestors.
+    ///
+    /// Holds the canonical current directory. See
+    /// [`allow_renaming_current_dir`][allow].
+    ///
+    /// [allow]: crate::ApplyOptions::allow_renaming_current_dir
+    ContainsCurrentDir(PathBuf),
+    /// Permission to rename the source, or to look up the target, was
+    /// denied, because of the given directory.
+    ///
+    /// Rename denials are only diagnosed on Unix, by checking the access to,
+    /// and the mode of, the parent directories once the rename failed, without
+    /// writing to them. Lookup denials are diagnosed on every platform, by
+    /// inspecting the ancestors of the target. Undiagnosed denials are reported
+    /// as [`Io`](Self::Io) errors.
+    PermissionDenied {
+        /// Why permission was denied.
+        reason: PermissionReason,
+        /// The directory at fault.
+        dir: PathBuf,
+        /// The error returned by the filesystem.
+        error: io::Error,
+    },
+    /// Another apply holds the lock on the tree.
+    ///
+    /// See [`ApplyOptions::lock`](crate::ApplyOptions::lock).
+    LockHeld(Box<LockHolder>),
+}
+
+/// A filesystem operation made while renaming.
+///
+/// See [`ApplyErrorDetails::Io`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IoOp {
+    /// Looking up a path, such as to check whether it exists.
+    Stat,
+    /// Creating the parent directory of the target.
+    CreateDir,
+    /// Listing a directory, such as to merge it.
+    ReadDir,
+    /// Renaming the source.
+    Rename,
+    /// Copying the source to another filesystem.
+    ///
+    /// See [`copy_across_devices`](crate::ApplyOptions::copy_across_devices).
+    Copy,
+    /// Removing an emptied directory, or a source copied to another
+    /// filesystem.
+    Remove,
+    /// Taking the lock of the apply.
+    ///
+    /// See [`ApplyOptions::lock`](crate::ApplyOptions::lock).
+    Lock,
+}
+
+/// The kind of directory entry found at a target path.
+///
+/// See [`ApplyErrorDetails::TargetExists`] and
+/// [`Conflict::target_kind`](crate::Conflict::target_kind).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TargetKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Dir {
+        /// Whether the directory is empty. Directories that cannot be listed
+        /// are not considered empty.
+        empty: bool,
+    },
+    /// A symbolic link, whatever it points to.
+    Symlink,
+    /// Another kind of entry, such as a named pipe or a socket.
+    Other,
+}
+
+impl TargetKind {
+    /// Returns the kind of the entry at a path, or [`None`] if there is none.
+    pub(crate) fn of(path: &Path, follow: FollowSymlinks) -> io::Result<Option<Self>> {
+        let Some(metadata) = inspect::entry(path, follow)? else {
+            return Ok(None);
+        };
+        let file_type = metadata.file_type();
+        Ok(Some(match SourceKind::of_type(file_type) {
+            SourceKind::File => Self::File,
+            SourceKind::Dir => Self::Dir {
+                empty: (path.read_dir()).is_ok_and(|mut entries| entries.next().is_none()),
+            },
+            SourceKind::Symlink => Self::Symlink,
+            _ => Self::Other,
+        }))
+    }
+}
+
+impl fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetKind::File => write!(f, "file"),
+            TargetKind::Dir { empty: true } => write!(f, "empty directory"),
+            TargetKind::Dir { empty: false } => write!(f, "non-empty directory"),
+            TargetKind::Symlink => write!(f, "symbolic link"),
+            TargetKind::Other => write!(f, "special file"),
+        }
+    }
+}
+
+/// Why permission was denied.
+///
+/// See [`ApplyErrorDetails::PermissionDenied`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PermissionReason {
+    /// The parent directory of the source is not writable.
+    SourceDirNotWritable,
+    /// The parent directory of the target, or its closest existing ancestor,
+    /// is not writable.
+    TargetDirNotWritable,
+    /// The parent directory of the source, or of the target it replaces, has
+    /// the sticky bit set, as `/tmp` does, and neither it nor the entry is
+    /// owned by the current user.
+    StickyDir,
+    /// The target cannot be looked up, since its parent directory, or the
+    /// given ancestor, cannot be searched.
+    ///
+    /// See [`ApplyOptions::on_stat_error`](crate::ApplyOptions::on_stat_error).
+    TargetDirNotSearchable,
+}
+
+/// The space missing on a filesystem for the sources copied to it.
+///
+/// See [`ApplyErrorDetails::InsufficientSpace`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SpaceShortage {
+    /// The target path on the filesystem whose available space was looked
+    /// up.
+    pub path: PathBuf,
+    /// The number of bytes copied to the filesystem by the plan.
+    pub required: u64,
+    /// The number of bytes available on the filesystem.
+    pub available: u64,
+}
+
+/// Why a target path is invalid.
+///
+/// See [`PlanError::InvalidTarget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidTargetReason {
+    /// The path is empty.
+    Empty,
+    /// The path ends with a separator, as in `dir/`.
+    TrailingSeparator,
+    /// The path ends with a `.` or `..` component.
+    DotComponent,
+    /// The path has no final component, such as `/`.
+    NoFileName,
+}
+
+impl InvalidTargetReason {
+    /// Returns why a target path is invalid, if it is.
+    pub(crate) fn of(path: &Path) -> Option<Self> {
+        let path_str = path.to_string_lossy();
+        if path_str.is_empty() {
+            return Some(Self::Empty);
+        }
+        if path_str.ends_with(std::path::is_separator) {
+            return Some(Self::TrailingSeparator);
+        }
+        let last = path_str.rsplit(std::path::is_separator).next();
+        if matches!(last, Some("." | "..")) {
+            return Some(Self::DotComponent);
+        }
+        if path.file_name().is_none() {
+            return Some(Self::NoFileName);
+        }
+        None
+    }
+}
+
+impl fmt::Display for InvalidTargetReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidTargetReason::Empty => write!(f, "path is empty"),
+            InvalidTargetReason::TrailingSeparator => write!(f, "path ends with a separator"),
+            InvalidTargetReason::DotComponent => {
+                write!(f, "path ends with a `.` or `..` component")
+            }
+            InvalidTargetReason::NoFileName => write!(f, "path has no file name"),
+        }
+    }
 }
 
 impl fmt::Display for ApplyError {
@@ -57,8 +412,94 @@ impl fmt::Display for ApplyError {
 impl fmt::Display for ApplyErrorDetails {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ApplyErrorDetails::TargetExists => write!(f, "target already exists"),
-            ApplyErrorDetails::Io(err) => write!(f, "{}", err),
+            ApplyErrorDetails::TargetExists { kind } => {
+                write!(f, "target already exists ({})", kind)
+            }
+            ApplyErrorDetails::Io { op, path, error } => match op {
+                IoOp::Stat => write!(f, "could not look up {:?}: {}", path, error),
+                IoOp::CreateDir => {
+                    write!(f, "could not create parent directory {:?}: {}", path, error)
+                }
+                IoOp::ReadDir => write!(f, "could not list {:?}: {}", path, error),
+                // The rename is already described.
+                IoOp::Rename => write!(f, "{}", error),
+                IoOp::Copy => write!(f, "could not copy {:?}: {}", path, error),
+                IoOp::Remove => write!(f, "could not remove {:?}: {}", path, error),
+                IoOp::Lock => write!(f, "could not lock {:?}: {}", path, error),
+            },
+            ApplyErrorDetails::CrossesBoundary(boundary) => match boundary {
+                Boundary::SameFilesystem => write!(f, "target is on another filesystem"),
+                Boundary::SameGitRepo => write!(f, "target is in another Git repository"),
+                Boundary::None => write!(f, "target crosses a boundary"),
+            },
+            ApplyErrorDetails::InsufficientSpace(shortage) => write!(
+                f,
+                "not enough space on the filesystem of {:?}: {} bytes to copy, {} bytes available",
+                shortage.path, shortage.required, shortage.available
+            ),
+            ApplyErrorDetails::TimedOut(timeout) => {
+                write!(f, "operation timed out after {:?}", timeout)
+            }
+            ApplyErrorDetails::SymlinkedParent(link) => {
+                write!(f, "target parent traverses the symbolic link {:?}", link)
+            }
+            ApplyErrorDetails::MissingBaseDir(dir) => {
+                write!(f, "base directory {:?} of the plan does not exist", dir)
+            }
+            ApplyErrorDetails::SymlinkTraversal(link) => {
+                write!(f, "path traverses the symbolic link {:?}", link)
+            }
+            ApplyErrorDetails::ContentChanged => {
+                write!(f, "content changed since the file was renamed")
+            }
+            ApplyErrorDetails::SourceChanged(field) => {
+                write!(f, "source {} changed since the plan was made", field)
+            }
+            ApplyErrorDetails::InvalidTarget(reason) => write!(f, "invalid target: {}", reason),
+            ApplyErrorDetails::EmptySource => write!(f, "source is empty"),
+            ApplyErrorDetails::KindMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "expected the source to be a {}, found a {}",
+                    expected, actual
+                )
+            }
+            ApplyErrorDetails::MergeFailed(err) => {
+                write!(f, "could not merge into the target directory: {}", err)
+            }
+            ApplyErrorDetails::ContainsCurrentDir(dir) => {
+                write!(f, "source contains the current directory {:?}", dir)
+            }
+            ApplyErrorDetails::LockHeld(holder) => {
+                write!(f, "another apply is in progress (")?;
+                match holder.pid {
+                    Some(pid) => write!(f, "pid {}", pid)?,
+                    None => write!(f, "unknown pid")?,
+                }
+                let elapsed = holder.started.and_then(|started| started.elapsed().ok());
+                match elapsed {
+                    Some(elapsed) => write!(f, ", started {}s ago)", elapsed.as_secs()),
+                    None => write!(f, ")"),
+                }
+            }
+            ApplyErrorDetails::PermissionDenied { reason, dir, .. } => match reason {
+                PermissionReason::SourceDirNotWritable => {
+                    write!(f, "permission denied: cannot write to the source directory {:?}", dir)
+                }
+                PermissionReason::TargetDirNotWritable => {
+                    write!(f, "permission denied: cannot write to the target directory {:?}", dir)
+                }
+                PermissionReason::StickyDir => write!(
+                    f,
+                    "permission denied: the source directory {:?} is sticky, and the source belongs to another user",
+                    dir
+                ),
+                PermissionReason::TargetDirNotSearchable => write!(
+                    f,
+                    "permission denied: cannot look up the target in the directory {:?}",
+                    dir
+                ),
+            },
         }
     }
 }
@@ -66,8 +507,33 @@ impl fmt::Display for ApplyErrorDetails {
 impl std::error::Error for ApplyError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.details {
-            ApplyErrorDetails::TargetExists => None,
-            ApplyErrorDetails::Io(err) => Some(err),
+            ApplyErrorDetails::TargetExists { .. }
+            | ApplyErrorDetails::CrossesBoundary(_)
+            | ApplyErrorDetails::InsufficientSpace(_)
+            | ApplyErrorDetails::TimedOut(_)
+            | ApplyErrorDetails::SymlinkedParent(_)
+            | ApplyErrorDetails::MissingBaseDir(_)
+            | ApplyErrorDetails::SymlinkTraversal(_)
+            | ApplyErrorDetails::ContentChanged
+            | ApplyErrorDetails::SourceChanged(_)
+            | ApplyErrorDetails::InvalidTarget(_)
+            | ApplyErrorDetails::EmptySource
+            | ApplyErrorDetails::KindMismatch { .. }
+            | ApplyErrorDetails::ContainsCurrentDir(_)
+            | ApplyErrorDetails::LockHeld(_) => None,
+            ApplyErrorDetails::Io { error, .. }
+            | ApplyErrorDetails::PermissionDenied { error, .. } => Some(error),
+            ApplyErrorDetails::MergeFailed(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+impl ApplyErrorDetails {
+    pub(crate) fn io(op: IoOp, path: impl Into<PathBuf>, error: io::Error) -> Self {
+        Self::Io {
+            op,
+            path: path.into(),
+            error,
         }
     }
 }
@@ -82,18 +548,56 @@ impl ApplyError {
             source: source.into(),
             target: target.into(),
             details,
+            tag: None,
+            index: None,
         }
     }
 
-    pub(crate) fn target_exists(source: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
-        Self::new(source, target, ApplyErrorDetails::TargetExists)
+    pub(crate) fn with_tag(mut self, tag: Option<Tag>) -> Self {
+        self.tag = tag;
+        self
     }
 
-    pub(crate) fn from_io(
-        from: impl Into<PathBuf>,
-        to: impl Into<PathBuf>,
-        source: io::Error,
+    pub(crate) fn with_index(mut self, index: Option<usize>) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Returns the index of the failed operation in plan order, if the error
+    /// was returned from applying a [`Plan`](crate::Plan).
+    ///
+    /// The operation is retrieved with [`Plan::get`](crate::Plan::get), or
+    /// from the [`ReportEntry::index`](crate::ReportEntry::index) of the
+    /// report. Unlike the paths, the index matches whatever the operation
+    /// paths were rewritten into. Errors of single operations and of undos
+    /// have no index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add_tagged(path("missing.txt"), path("a.txt"), "first");
+    ///
+    /// let plan = renamer.plan()?;
+    /// let mut apply = plan.apply_iter();
+    /// let err = apply.next().unwrap().unwrap_err();
+    /// let rename = plan.get(err.rename_index().unwrap()).unwrap();
+    /// assert_eq!(rename.tag.as_ref().unwrap().downcast_ref(), Some(&"first"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rename_index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub(crate) fn target_exists(
+        source: impl Into<PathBuf>,
+        target: impl Into<PathBuf>,
+        kind: TargetKind,
     ) -> Self {
-        Self::new(from, to, ApplyErrorDetails::Io(source))
+        Self::new(source, target, ApplyErrorDetails::TargetExists { kind })
     }
 }
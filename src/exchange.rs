@@ -0,0 +1,268 @@
+use std::{io, path::Path};
+
+use crate::{
+    cycles,
+    error::{ApplyError, ApplyErrorDetails, IoOp},
+    fsutil::{self, rename_noreplace},
+    log::LogEvent,
+    operation::RenameStrategy,
+    options::ApplyOptions,
+};
+
+/// An operation swapping two paths, each taking the entry of the other.
+///
+/// Plans do not hold exchanges: two operations renaming each other's source
+/// are detected as a swap when planning, and exchanged the same way when
+/// applied. See [`PlanStats::cycles`](crate::PlanStats::cycles).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange<P, Q> {
+    /// The first path.
+    pub first: P,
+    /// The second path.
+    pub second: Q,
+}
+
+impl<P, Q> Exchange<P, Q> {
+    /// Creates a new exchange operation.
+    pub fn new(first: P, second: Q) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<P, Q> Exchange<P, Q>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    /// Executes the exchange operation.
+    pub fn apply(&self) -> Result<RenameStrategy, ApplyError> {
+        self.apply_with(&ApplyOptions::new())
+    }
+
+    /// Executes the exchange operation with the specified options.
+    ///
+    /// The paths are swapped atomically where the platform supports it, on
+    /// Linux and macOS, and [`RenameStrategy::Exchanged`] is returned.
+    /// Otherwise, the second entry is moved to a temporary path named by
+    /// [`ApplyOptions::temp_namer`], the first one is renamed to the second
+    /// path, then the second one to the first path, and
+    /// [`RenameStrategy::Rename`] is returned. If the last step fails, the
+    /// error names the temporary path the second entry is left at.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::Exchange;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// fs::write(path("a.txt"), "a")?;
+    /// fs::write(path("b.txt"), "b")?;
+    ///
+    /// Exchange::new(path("a.txt"), path("b.txt")).apply()?;
+    /// assert_eq!(fs::read_to_string(path("a.txt"))?, "b");
+    /// assert_eq!(fs::read_to_string(path("b.txt"))?, "a");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_with(&self, options: &ApplyOptions) -> Result<RenameStrategy, ApplyError> {
+        let (first, second) = (self.first.as_ref(), self.second.as_ref());
+        let fail = |details| ApplyError::new(first, second, details);
+        if try_exchange(first, second, options).map_err(fail)? {
+            return Ok(RenameStrategy::Exchanged);
+        }
+        options.emit(LogEvent::Rename {
+            source: first,
+            target: second,
+        });
+        let temp_path = cycles::park(second, options)
+            .map_err(|err| fail(ApplyErrorDetails::io(IoOp::Rename, second, err)))?;
+        let swapped = match rename_noreplace(first, second) {
+            Ok(()) => rename_noreplace(&temp_path, first)
+                .map_err(|err| fail(ApplyErrorDetails::io(IoOp::Rename, &temp_path, err))),
+            Err(err) => {
+                if let Err(err) = cycles::unpark(&temp_path, second) {
+                    warn!(
+                        "could not move {:?} back from the temporary path {:?}: {}",
+                        second, temp_path, err
+                    );
+                }
+                Err(fail(ApplyErrorDetails::io(IoOp::Rename, first, err)))
+            }
+        };
+        cycles::prune_staging(options);
+        swapped.map(|()| RenameStrategy::Rename)
+    }
+}
+
+/// Swaps two paths atomically, where the platform supports it.
+///
+/// Returns `false` if it does not, so that the swap goes through a
+/// temporary path instead.
+pub(crate) fn try_exchange(
+    first: &Path,
+    second: &Path,
+    options: &ApplyOptions,
+) -> Result<bool, ApplyErrorDetails> {
+    let (first_owned, second_owned) = (first.to_owned(), second.to_owned());
+    match options.run(IoOp::Rename, first, move || {
+        fsutil::exchange(&first_owned, &second_owned)
+    }) {
+        Ok(()) => {
+            options.emit(LogEvent::Rename {
+                source: first,
+                target: second,
+            });
+            Ok(true)
+        }
+        Err(ApplyErrorDetails::Io { error, .. }) if error.kind() == io::ErrorKind::Unsupported => {
+            Ok(false)
+        }
+        Err(details) => Err(details),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        ffi::{OsStr, OsString},
+        fs, io,
+    };
+
+    use super::Exchange;
+    use crate::{
+        fsutil, ApplyOptions, RenameStatus, RenameStrategy, Renamer, TempNamer, UndoStack,
+    };
+
+    /// Counts the temporary names requested.
+    #[derive(Default)]
+    struct Counter(Cell<usize>);
+
+    impl TempNamer for Counter {
+        fn temp_name(&self, name: &OsStr, attempt: usize) -> OsString {
+            self.0.set(self.0.get() + 1);
+            let mut temp_name = name.to_owned();
+            temp_name.push(format!(".tmp{}", attempt));
+            temp_name
+        }
+    }
+
+    #[test]
+    fn exchange() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), "a")?;
+        fs::create_dir(path("b"))?;
+
+        let strategy = Exchange::new(path("a"), path("b")).apply().unwrap();
+        assert!(matches!(
+            strategy,
+            RenameStrategy::Exchanged | RenameStrategy::Rename
+        ));
+        assert!(path("a").is_dir());
+        assert_eq!(fs::read_to_string(path("b"))?, "a");
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 2);
+
+        // Nothing moves if a path is missing.
+        assert!(Exchange::new(path("b"), path("c")).apply().is_err());
+        assert_eq!(fs::read_to_string(path("b"))?, "a");
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn swaps() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("probe-1"), "")?;
+        fs::write(path("probe-2"), "")?;
+        let native = fsutil::exchange(&path("probe-1"), &path("probe-2")).is_ok();
+        fs::remove_file(path("probe-1"))?;
+        fs::remove_file(path("probe-2"))?;
+        for name in ["a", "b", "c", "d"] {
+            fs::write(path(name), name)?;
+        }
+
+        let renamer: Renamer<_, _> = [("a", "b"), ("b", "a"), ("c", "d"), ("d", "c")]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        let counter = Counter::default();
+        let options = ApplyOptions::new().temp_namer(&counter);
+        let report = renamer.plan().unwrap().apply_with(&options);
+        assert!(report.is_success(), "{:?}", report);
+        assert!(
+            (report.entries().iter()).all(|entry| matches!(entry.status, RenameStatus::Applied))
+        );
+        for (name, content) in [("a", "b"), ("b", "a"), ("c", "d"), ("d", "c")] {
+            assert_eq!(fs::read_to_string(path(name))?, content);
+        }
+        // Without native support, swaps go through a temporary path.
+        assert_eq!(counter.0.get() == 0, native);
+
+        let mut undo_stack = UndoStack::new();
+        undo_stack.push(&report);
+        undo_stack.undo_last().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            assert_eq!(fs::read_to_string(path(name))?, name);
+        }
+
+        // Iterating exchanges as well, and the remaining plan leaves out the
+        // operations exchanged.
+        let renamer: Renamer<_, _> = [("a", "b"), ("b", "a")]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        let plan = renamer.plan().unwrap();
+        let mut iter = plan.apply_iter();
+        assert!(iter.next().unwrap().is_ok());
+        let remaining = iter.into_remaining_plan();
+        assert_eq!(remaining.len(), if native { 0 } else { 1 });
+        Ok(())
+    }
+
+    #[test]
+    fn relocated_swaps() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        let renamer = || {
+            // The directory is moved first, and the swap follows it.
+            let mut renamer = Renamer::new();
+            renamer.add_with_priority(path("dir"), path("moved"), 1);
+            renamer.add(path("dir/a"), path("dir/b"));
+            renamer.add(path("dir/b"), path("dir/a"));
+            renamer
+        };
+        let check = |moved: &str| -> io::Result<()> {
+            assert!(!path("dir").exists());
+            assert_eq!(fs::read_to_string(path(moved).join("a"))?, "b");
+            assert_eq!(fs::read_to_string(path(moved).join("b"))?, "a");
+            assert_eq!(fs::read_dir(path(moved))?.count(), 2);
+            Ok(())
+        };
+        fs::create_dir(path("dir"))?;
+        fs::write(path("dir/a"), "a")?;
+        fs::write(path("dir/b"), "b")?;
+
+        let report = renamer()
+            .plan()
+            .unwrap()
+            .apply_with(&ApplyOptions::new().preserve_parent_mtimes(true));
+        assert!(report.is_success(), "{:?}", report);
+        for entry in report.entries() {
+            assert!(matches!(entry.status, RenameStatus::Applied));
+            assert!(entry.duration.is_some());
+        }
+        check("moved")?;
+
+        fs::rename(path("moved"), path("dir"))?;
+        fs::rename(path("dir/a"), path("dir/c"))?;
+        fs::rename(path("dir/b"), path("dir/a"))?;
+        fs::rename(path("dir/c"), path("dir/b"))?;
+        let plan = renamer().plan().unwrap();
+        for result in plan.apply_iter() {
+            result.unwrap();
+        }
+        check("moved")
+    }
+}
@@ -0,0 +1,231 @@
+use std::{fs, io, time::SystemTime};
+
+use crate::options::SourceKind;
+
+/// A filter on the entries read from a directory, when building renames
+/// from it.
+///
+/// The criteria are evaluated from the [`fs::DirEntry`] read from the
+/// directory: the kind of an entry comes with it, and its metadata is only
+/// read when a size or a modification time is checked. On Windows, it also
+/// comes with the entry, so that no extra system call is made. Symbolic links
+/// are not followed: their own size and modification time are checked.
+///
+/// See [`FlattenOptions::source_filter`](crate::FlattenOptions::source_filter).
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs;
+/// # use nominal::{FlattenOptions, Renamer, SourceFilter};
+/// let temp_dir = tempfile::tempdir()?;
+/// fs::create_dir_all(temp_dir.path().join("a"))?;
+/// fs::write(temp_dir.path().join("a/empty.txt"), b"")?;
+/// fs::write(temp_dir.path().join("a/full.txt"), b"full")?;
+/// fs::write(temp_dir.path().join("a/full.md"), b"full")?;
+///
+/// let filter = SourceFilter::new().min_size(1).name_glob("*.txt");
+/// let options = FlattenOptions::new().source_filter(filter);
+/// let mut renamer = Renamer::new();
+/// renamer.flatten(temp_dir.path(), &options)?;
+/// assert_eq!(options.filtered(), 2);
+///
+/// renamer.plan()?.apply()?;
+/// assert!(temp_dir.path().join("a__full.txt").exists());
+/// assert!(temp_dir.path().join("a/empty.txt").exists());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SourceFilter {
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    kinds: Vec<SourceKind>,
+    name_glob: Option<Vec<char>>,
+}
+
+impl SourceFilter {
+    /// Creates a filter accepting every entry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rejects the entries smaller than `size`, in bytes.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = Some(size);
+        self
+    }
+
+    /// Rejects the entries larger than `size`, in bytes.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Rejects the entries last modified before `time`.
+    pub fn modified_after(mut self, time: SystemTime) -> Self {
+        self.modified_after = Some(time);
+        self
+    }
+
+    /// Rejects the entries last modified at or after `time`.
+    pub fn modified_before(mut self, time: SystemTime) -> Self {
+        self.modified_before = Some(time);
+        self
+    }
+
+    /// Rejects the entries not of one of the specified kinds.
+    ///
+    /// [`SourceKind::Any`] accepts every kind, as does an empty set of kinds.
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = SourceKind>) -> Self {
+        self.kinds = kinds.into_iter().collect();
+        self
+    }
+
+    /// Rejects the entries whose file name does not match a glob pattern.
+    ///
+    /// `*` matches any sequence of characters, and `?` any single
+    /// character. Matching is case-sensitive, and file names that are not
+    /// valid Unicode never match.
+    pub fn name_glob(mut self, pattern: &str) -> Self {
+        self.name_glob = Some(pattern.chars().collect());
+        self
+    }
+
+    /// Tests whether an entry is accepted.
+    pub(crate) fn accepts(&self, entry: &fs::DirEntry) -> io::Result<bool> {
+        if !self.kinds.is_empty() {
+            let file_type = entry.file_type()?;
+            let kind = SourceKind::of_type(file_type);
+            if !self.kinds.iter().any(|expected| expected.matches(kind)) {
+                return Ok(false);
+            }
+        }
+        if let Some(pattern) = &self.name_glob {
+            let name = entry.file_name();
+            match name.to_str() {
+                Some(name) if glob_match(pattern, &name.chars().collect::<Vec<_>>()) => {}
+                _ => return Ok(false),
+            }
+        }
+        let checks_size = self.min_size.is_some() || self.max_size.is_some();
+        let checks_time = self.modified_after.is_some() || self.modified_before.is_some();
+        if !checks_size && !checks_time {
+            return Ok(true);
+        }
+        let metadata = entry.metadata()?;
+        let size = metadata.len();
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+        {
+            return Ok(false);
+        }
+        if checks_time {
+            let modified = metadata.modified()?;
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self
+                    .modified_before
+                    .is_some_and(|before| modified >= before)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Tests whether a name matches a glob pattern of `*` and `?` wildcards.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    // The last `*` seen, and the position in the name it was tried at.
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // The `*` swallows one more character.
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    n = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        time::{Duration, SystemTime},
+    };
+
+    use super::SourceFilter;
+    use crate::SourceKind;
+
+    #[test]
+    fn glob_match() {
+        let glob_match = |pattern: &str, name: &str| {
+            let chars = |s: &str| s.chars().collect::<Vec<_>>();
+            super::glob_match(&chars(pattern), &chars(name))
+        };
+        assert!(glob_match("*.txt", "a.txt"));
+        assert!(glob_match("*.txt", ".txt"));
+        assert!(!glob_match("*.txt", "a.txt.bak"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("*a*b", "xxbxxa"));
+        assert!(glob_match("**", ""));
+        assert!(glob_match("é?", "éè"));
+    }
+
+    #[test]
+    fn filter() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("small.txt"), b"a")?;
+        fs::write(path("large.txt"), b"abcdef")?;
+        fs::create_dir(path("dir.txt"))?;
+
+        let accepted = |filter: &SourceFilter| -> io::Result<Vec<String>> {
+            let mut names = Vec::new();
+            for entry in fs::read_dir(temp_dir.path())? {
+                let entry = entry?;
+                if filter.accepts(&entry)? {
+                    names.push(entry.file_name().into_string().unwrap());
+                }
+            }
+            names.sort();
+            Ok(names)
+        };
+
+        let filter = SourceFilter::new();
+        assert_eq!(accepted(&filter)?.len(), 3);
+
+        let filter = SourceFilter::new().kinds([SourceKind::File]).max_size(4);
+        assert_eq!(accepted(&filter)?, ["small.txt"]);
+
+        let filter = SourceFilter::new().name_glob("*.txt").min_size(2);
+        assert!(accepted(&filter)?.contains(&"large.txt".to_owned()));
+        assert!(!accepted(&filter)?.contains(&"small.txt".to_owned()));
+
+        let now = SystemTime::now();
+        let filter = SourceFilter::new().modified_after(now + Duration::from_secs(3600));
+        assert!(accepted(&filter)?.is_empty());
+        let filter = SourceFilter::new().modified_before(now + Duration::from_secs(3600));
+        assert_eq!(accepted(&filter)?.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,490 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    filter::SourceFilter, fsutil::is_hidden_entry, path::path_exists, renamer::Renamer,
+    sink::RenameSink,
+};
+
+/// Options for [`Renamer::flatten`] and [`Renamer::unflatten`].
+#[derive(Debug)]
+pub struct FlattenOptions {
+    separator: String,
+    source_filter: SourceFilter,
+    include_hidden: bool,
+    /// The number of entries rejected by the source filter in the last
+    /// call.
+    filtered: AtomicUsize,
+}
+
+impl FlattenOptions {
+    /// Creates the default options, with `__` as separator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the separator between the components of a flattened name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the separator is empty, or contains a path separator.
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        let separator = separator.into();
+        assert!(
+            !separator.is_empty() && !separator.contains(std::path::is_separator),
+            "invalid flatten separator: {:?}",
+            separator
+        );
+        self.separator = separator;
+        self
+    }
+
+    /// Sets the filter on the files to move.
+    ///
+    /// Directories are always walked into, whatever the filter. The files it
+    /// rejects are left in place, and are not returned as skipped: they are
+    /// [counted](Self::filtered) instead.
+    pub fn source_filter(mut self, filter: SourceFilter) -> Self {
+        self.source_filter = filter;
+        self
+    }
+
+    /// Sets whether hidden entries are moved, and hidden directories walked
+    /// into.
+    ///
+    /// On Unix, hidden entries are those whose name starts with a dot. On
+    /// Windows, they are those with the hidden attribute, read from the
+    /// directory listing. Hidden entries that are not included are left in
+    /// place, and are not returned as skipped. Included by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{FlattenOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// fs::create_dir_all(temp_dir.path().join("a/.git"))?;
+    /// fs::write(temp_dir.path().join("a/.git/HEAD"), b"")?;
+    /// fs::write(temp_dir.path().join("a/b.txt"), b"")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// let options = FlattenOptions::new().include_hidden(false);
+    /// renamer.flatten(temp_dir.path(), &options)?;
+    /// # #[cfg(unix)]
+    /// assert_eq!(renamer.plan()?.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn include_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Returns the number of files rejected by the
+    /// [source filter](Self::source_filter) in the last call to
+    /// [`Renamer::flatten`] or [`Renamer::unflatten`] with these options, for
+    /// summaries such as "skipped 3 files".
+    pub fn filtered(&self) -> usize {
+        self.filtered.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self {
+            separator: "__".to_owned(),
+            source_filter: SourceFilter::default(),
+            include_hidden: true,
+            filtered: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Clone for FlattenOptions {
+    fn clone(&self) -> Self {
+        Self {
+            separator: self.separator.clone(),
+            source_filter: self.source_filter.clone(),
+            include_hidden: self.include_hidden,
+            filtered: AtomicUsize::new(self.filtered()),
+        }
+    }
+}
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Adds rename operations moving every file under `root` directly into
+    /// `root`.
+    ///
+    /// The former path of each file, relative to `root`, is encoded into its
+    /// new name by joining its components with the
+    /// [separator](FlattenOptions::separator), so that `root/a/b/c.txt`
+    /// becomes `root/a__b__c.txt`. Symbolic links are moved, not followed.
+    /// Directories are left in place, empty.
+    ///
+    /// Files that could not be unflattened back to their current path are
+    /// skipped, and returned. This is the case when a component contains the
+    /// separator, is not valid Unicode, or when the new name is already taken
+    /// by another file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{FlattenOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// fs::create_dir_all(temp_dir.path().join("a/b"))?;
+    /// fs::write(temp_dir.path().join("a/b/c.txt"), b"")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// let skipped = renamer.flatten(temp_dir.path(), &FlattenOptions::new())?;
+    /// assert!(skipped.is_empty());
+    ///
+    /// renamer.plan()?.apply()?;
+    /// assert!(temp_dir.path().join("a__b__c.txt").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn flatten<P>(&mut self, root: P, options: &FlattenOptions) -> io::Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        flatten_into(self, root.as_ref(), options)
+    }
+
+    /// Adds rename operations moving the files directly under `root` back
+    /// into subdirectories, reverting [`flatten`](Self::flatten).
+    ///
+    /// The name of each file is split on the
+    /// [separator](FlattenOptions::separator), so that `root/a__b__c.txt`
+    /// becomes `root/a/b/c.txt`. Files whose name does not contain the
+    /// separator are left in place.
+    ///
+    /// Files that cannot be unflattened are skipped, and returned. This is
+    /// the case when their name is not valid Unicode or has an empty
+    /// component, when their new path is already taken, or when it is also
+    /// the parent directory of another new path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{FlattenOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// fs::write(temp_dir.path().join("a__b__c.txt"), b"")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// let options = FlattenOptions::new();
+    /// let skipped = renamer.unflatten(temp_dir.path(), &options)?;
+    /// assert!(skipped.is_empty());
+    ///
+    /// renamer.plan()?.apply()?;
+    /// assert!(temp_dir.path().join("a/b/c.txt").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn unflatten<P>(&mut self, root: P, options: &FlattenOptions) -> io::Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        unflatten_into(self, root.as_ref(), options)
+    }
+}
+
+/// Adds rename operations moving every file under `root` directly into
+/// `root`, to any sink, as [`Renamer::flatten`] does.
+pub fn flatten_into(
+    sink: &mut dyn RenameSink,
+    root: &Path,
+    options: &FlattenOptions,
+) -> io::Result<Vec<PathBuf>> {
+    options.filtered.store(0, Ordering::Relaxed);
+    let mut files = Vec::new();
+    walk(root, options, &mut files)?;
+
+    let mut skipped = Vec::new();
+    let mut by_target: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for source in files {
+        let relative = source.strip_prefix(root).unwrap();
+        if relative.parent() == Some(Path::new("")) {
+            // Already at the root.
+            continue;
+        }
+        match flat_name(relative, &options.separator) {
+            Some(name) => by_target.entry(root.join(name)).or_default().push(source),
+            None => skipped.push(source),
+        }
+    }
+    let mut renames = Vec::new();
+    for (target, sources) in by_target {
+        if sources.len() > 1 || path_exists(&target)? {
+            skipped.extend(sources);
+        } else {
+            renames.extend(sources.into_iter().map(|source| (source, target.clone())));
+        }
+    }
+    renames.sort();
+    for (source, target) in renames {
+        sink.add(&source, &target);
+    }
+    skipped.sort();
+    Ok(skipped)
+}
+
+/// Adds rename operations moving the files directly under `root` back into
+/// subdirectories, to any sink, as [`Renamer::unflatten`] does.
+pub fn unflatten_into(
+    sink: &mut dyn RenameSink,
+    root: &Path,
+    options: &FlattenOptions,
+) -> io::Result<Vec<PathBuf>> {
+    options.filtered.store(0, Ordering::Relaxed);
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() && options.accepts(&entry)? {
+            entries.push(entry.path());
+        }
+    }
+    entries.sort();
+
+    let mut skipped = Vec::new();
+    let mut renames = Vec::new();
+    for source in entries {
+        let name = source.file_name().unwrap();
+        let Some(name) = name.to_str() else {
+            skipped.push(source);
+            continue;
+        };
+        if !name.contains(&options.separator) {
+            continue;
+        }
+        if name.split(&options.separator).any(str::is_empty) {
+            skipped.push(source);
+            continue;
+        }
+        let target: PathBuf = name.split(&options.separator).collect();
+        renames.push((source, root.join(target)));
+    }
+
+    // A new path must not be the parent of another one.
+    let parents: HashSet<PathBuf> = renames
+        .iter()
+        .flat_map(|(_, target)| target.ancestors().skip(1))
+        .map(Path::to_owned)
+        .collect();
+    for (source, target) in renames {
+        if parents.contains(&target) || path_exists(&target)? || has_file_parent(root, &target)? {
+            skipped.push(source);
+        } else {
+            sink.add(&source, &target);
+        }
+    }
+    Ok(skipped)
+}
+
+impl FlattenOptions {
+    /// Tests whether an entry is walked into or moved, hidden entries aside.
+    fn includes(&self, entry: &fs::DirEntry) -> io::Result<bool> {
+        Ok(self.include_hidden || !is_hidden_entry(entry)?)
+    }
+
+    /// Tests whether a non-directory entry is moved, counting it if the
+    /// source filter rejects it.
+    fn accepts(&self, entry: &fs::DirEntry) -> io::Result<bool> {
+        if !self.includes(entry)? {
+            return Ok(false);
+        }
+        let accepted = self.source_filter.accepts(entry)?;
+        if !accepted {
+            self.filtered.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(accepted)
+    }
+}
+
+/// Collects the paths of the non-directory entries under a directory,
+/// accepted by the options.
+fn walk(dir: &Path, options: &FlattenOptions, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            if options.includes(&entry)? {
+                walk(&entry.path(), options, files)?;
+            }
+        } else if options.accepts(&entry)? {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Tests whether a parent of a path under `root` exists and is not a
+/// directory.
+fn has_file_parent(root: &Path, path: &Path) -> io::Result<bool> {
+    for ancestor in path.ancestors().skip(1) {
+        if ancestor == root {
+            break;
+        }
+        if path_exists(ancestor)? && !ancestor.is_dir() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Encodes a relative path into a flat name, if it can be decoded back.
+fn flat_name(relative: &Path, separator: &str) -> Option<String> {
+    let components = relative
+        .iter()
+        .map(|component| component.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    if components
+        .iter()
+        .any(|component| component.contains(separator))
+    {
+        return None;
+    }
+    let name = components.join(separator);
+    // The separator may also straddle two components.
+    name.split(separator)
+        .eq(components.iter().copied())
+        .then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        path::{Path, PathBuf},
+    };
+
+    use super::FlattenOptions;
+    use crate::{Renamer, SourceFilter};
+
+    fn files(root: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        super::walk(root, &Default::default(), &mut files)?;
+        let mut files: Vec<_> = files
+            .into_iter()
+            .map(|file| file.strip_prefix(root).unwrap().to_owned())
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    #[test]
+    fn flat_name() {
+        let flat_name = |path| super::flat_name(Path::new(path), "__");
+        assert_eq!(flat_name("a/b/c.txt").as_deref(), Some("a__b__c.txt"));
+        assert_eq!(flat_name("a__b/c.txt"), None);
+        assert_eq!(flat_name("a_/_b"), None);
+        assert_eq!(flat_name("a_/b"), None);
+    }
+
+    #[test]
+    fn collisions() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b"))?;
+        fs::write(root.join("a/b/c"), b"")?;
+        fs::write(root.join("a/d"), b"")?;
+        fs::write(root.join("a__d"), b"")?;
+
+        let mut renamer = Renamer::new();
+        let skipped = renamer.flatten(root, &FlattenOptions::new())?;
+        assert_eq!(skipped, [root.join("a/d")]);
+        renamer.plan().unwrap().apply().unwrap();
+
+        fs::write(root.join("a__b"), b"")?;
+        let mut renamer = Renamer::new();
+        let mut skipped = renamer.unflatten(root, &FlattenOptions::new())?;
+        skipped.sort();
+        assert_eq!(skipped, [root.join("a__b"), root.join("a__d")]);
+        assert!(renamer.plan().unwrap().apply().is_ok());
+        assert!(root.join("a/b/c").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn sinks() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b"))?;
+        fs::write(root.join("a/b/c"), b"")?;
+
+        let mut renames = Vec::new();
+        let skipped = super::flatten_into(&mut renames, root, &FlattenOptions::new())?;
+        assert!(skipped.is_empty());
+        assert_eq!(renames, [(root.join("a/b/c"), root.join("a__b__c"))]);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> io::Result<()> {
+        // A small linear congruential generator, for reproducible trees.
+        let mut state: u32 = 0x2545_f491;
+        let mut next = |bound: u32| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) % bound
+        };
+        let options = FlattenOptions::new().separator("_");
+        for _ in 0..20 {
+            let temp_dir = tempfile::tempdir()?;
+            let root = temp_dir.path();
+            for _ in 0..next(12) + 1 {
+                let mut path = root.to_owned();
+                for _ in 0..next(4) + 1 {
+                    let name: String = (0..next(3) + 1)
+                        .map(|_| ['a', 'b', 'c', '-'][next(4) as usize])
+                        .collect();
+                    path.push(name);
+                }
+                // The tree cannot have a file and a directory at the same
+                // path.
+                if !path.exists() && path.ancestors().skip(1).all(|a| !a.is_file()) {
+                    fs::create_dir_all(path.parent().unwrap())?;
+                    fs::write(&path, b"")?;
+                }
+            }
+            let before = files(root)?;
+
+            let mut renamer = Renamer::new();
+            assert!(renamer.flatten(root, &options)?.is_empty());
+            renamer.plan().unwrap().apply().unwrap();
+            assert!(files(root)?
+                .iter()
+                .all(|file| file.parent() == Some(Path::new(""))));
+
+            let mut renamer = Renamer::new();
+            assert!(renamer.unflatten(root, &options)?.is_empty());
+            renamer.plan().unwrap().apply().unwrap();
+            assert_eq!(files(root)?, before);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn filtered() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::create_dir(path("a"))?;
+        for file in ["a/b.txt", "a/c.md", "a__d.md"] {
+            fs::write(path(file), b"")?;
+        }
+        let filter = SourceFilter::new().name_glob("*.txt");
+        let options = FlattenOptions::new().source_filter(filter);
+
+        // The count starts over with every call.
+        for _ in 0..2 {
+            let mut renamer = Renamer::new();
+            assert!(renamer.flatten(temp_dir.path(), &options)?.is_empty());
+            assert_eq!(options.filtered(), 2);
+        }
+        let mut renamer = Renamer::new();
+        assert!(renamer.unflatten(temp_dir.path(), &options)?.is_empty());
+        assert_eq!(options.filtered(), 1);
+        assert_eq!(options.clone().filtered(), 1);
+        Ok(())
+    }
+}
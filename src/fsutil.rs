@@ -1,85 +1,807 @@
-use std::{io, path::Path};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-/// Returns the common ancestor of two paths.
-pub fn common_ancestor<'a>(path_1: &'a Path, path_2: &'a Path) -> Option<&'a Path> {
-    path_1
-        .ancestors()
-        .find(|&ancestor| !ancestor.as_os_str().is_empty() && path_2.starts_with(ancestor))
-}
+#[cfg(unix)]
+use crate::error::PermissionReason;
+use crate::path::{normalize, path_exists};
 
-/// Tests whether a path exists.
+/// Returns the closest ancestor of a path that exists, including the path
+/// itself.
 ///
+/// Relative paths with no existing ancestor resolve to the current directory.
 /// This function does not follow symbolic links.
-pub fn path_exists<P>(path: P) -> io::Result<bool>
-where
-    P: AsRef<Path>,
-{
-    let path = path.as_ref();
-    match path.symlink_metadata() {
-        Ok(_) => Ok(true),
-        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
-        Err(err) => Err(err),
+pub fn existing_ancestor(path: &Path) -> io::Result<&Path> {
+    for ancestor in path.ancestors() {
+        if ancestor.as_os_str().is_empty() {
+            return Ok(Path::new("."));
+        }
+        if path_exists(ancestor)? {
+            return Ok(ancestor);
+        }
+    }
+    Ok(path)
+}
+
+/// Returns the deepest ancestor of a path that can be inspected, which is
+/// the directory denying the lookup of the path when it cannot be.
+///
+/// Ancestors are followed, as by the lookup itself. The current directory
+/// stands for the empty parent of a relative path.
+pub fn unsearchable_dir(path: &Path) -> Option<&Path> {
+    (path.parent()?.ancestors())
+        .map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        })
+        .find(|ancestor| ancestor.metadata().is_ok())
+}
+
+/// Flushes the entries of the parent directory of a path to disk, so that
+/// the creation or the replacement of the path survives a crash.
+///
+/// Directories cannot be flushed on Windows, where this does nothing.
+pub fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(parent)?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// Tests whether a directory entry is hidden.
+///
+/// On Unix, hidden entries are those whose name starts with a dot. On
+/// Windows, they are those with the hidden attribute, which comes with the
+/// entry, so that no extra system call is made.
+pub fn is_hidden_entry(entry: &fs::DirEntry) -> io::Result<bool> {
+    #[cfg(windows)]
+    return Ok(crate::windows::is_hidden(&entry.metadata()?, false));
+    #[cfg(not(windows))]
+    Ok(is_dotfile(&entry.path()))
+}
+
+/// Tests whether a path is hidden, or is a system file, given its metadata
+/// if it was read.
+///
+/// On Unix, these are the paths whose file name starts with a dot. On
+/// Windows, they are the paths whose metadata has the hidden or system
+/// attribute.
+pub fn is_hidden(path: &Path, metadata: Option<&fs::Metadata>) -> bool {
+    #[cfg(windows)]
+    {
+        let _ = path;
+        metadata.is_some_and(|metadata| crate::windows::is_hidden(metadata, true))
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = metadata;
+        is_dotfile(path)
+    }
+}
+
+/// Tests whether the file name of a path starts with a dot.
+#[cfg(not(windows))]
+fn is_dotfile(path: &Path) -> bool {
+    (path.file_name()).is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// Returns the deepest ancestor of a path that is a symbolic link, including
+/// the path itself.
+///
+/// Ancestors that do not exist are skipped.
+pub fn symlinked_ancestor(path: &Path) -> io::Result<Option<&Path>> {
+    for ancestor in path.ancestors() {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        match ancestor.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => return Ok(Some(ancestor)),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves the symbolic links in the parent of a path, without resolving
+/// the path itself.
+///
+/// If the parent does not exist, its closest existing ancestor is resolved,
+/// and the missing components are appended lexically.
+pub fn resolve_parent(path: &Path) -> io::Result<PathBuf> {
+    let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Ok(path.to_owned());
+    };
+    let mut missing = Vec::new();
+    for ancestor in parent.ancestors() {
+        let dir = if ancestor.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            ancestor
+        };
+        if path_exists(dir)? {
+            let mut resolved = dir.canonicalize()?;
+            resolved.extend(missing.iter().rev());
+            resolved.push(file_name);
+            return Ok(normalize(&resolved));
+        }
+        missing.extend(ancestor.components().next_back());
+    }
+    Ok(path.to_owned())
+}
+
+/// An identifier of a directory entry.
+///
+/// See [`entry_id`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntryId {
+    #[cfg(unix)]
+    dir: (u64, u64),
+    #[cfg(windows)]
+    dir: PathBuf,
+    name: std::ffi::OsString,
+}
+
+/// Returns an identifier of the directory entry of a path.
+///
+/// Two paths have the same identifier when they name the same entry in the
+/// same directory, e.g. through a symbolic link to the directory. Hard links
+/// to the same file are different entries. On Unix, the directory is
+/// identified by its device and inode numbers. On Windows, it is identified
+/// by its canonicalized path.
+pub fn entry_id(path: &Path) -> io::Result<EntryId> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path has no file name",
+        ));
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    #[cfg(unix)]
+    let dir = {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = parent.metadata()?;
+        (metadata.dev(), metadata.ino())
+    };
+    #[cfg(windows)]
+    let dir = parent.canonicalize()?;
+    Ok(EntryId {
+        dir,
+        name: name.to_owned(),
+    })
+}
+
+/// Returns an identifier of the filesystem holding a path.
+///
+/// If the path does not exist, the filesystem of its closest existing
+/// ancestor is returned. On Unix, this is the device ID. On Windows, this is
+/// derived from the prefix of the canonicalized path, so volumes mounted in
+/// folders are not told apart.
+pub fn device_of(path: &Path) -> io::Result<u64> {
+    let path = existing_ancestor(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        Ok(path.symlink_metadata()?.dev())
+    }
+    #[cfg(windows)]
+    {
+        use std::{
+            collections::hash_map::DefaultHasher,
+            hash::{Hash, Hasher},
+            path::Component,
+        };
+
+        let path = path.canonicalize()?;
+        let mut hasher = DefaultHasher::new();
+        if let Some(Component::Prefix(prefix)) = path.components().next() {
+            prefix
+                .as_os_str()
+                .to_string_lossy()
+                .to_lowercase()
+                .hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+}
+
+/// Returns the root of the Git repository containing a path, if any.
+///
+/// The repository is discovered by looking for a `.git` entry in the
+/// canonicalized ancestors of the path.
+pub fn git_root(path: &Path) -> io::Result<Option<PathBuf>> {
+    let path = existing_ancestor(path)?.canonicalize()?;
+    for ancestor in path.ancestors() {
+        if path_exists(ancestor.join(".git"))? {
+            return Ok(Some(ancestor.to_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Creates a directory along with its missing ancestors, and returns the
+/// created directories, outermost first.
+///
+/// On Unix, `mode` is set on every created directory regardless of the
+/// umask. Directories that already exist are left untouched. On other
+/// platforms, `mode` is ignored and the directories inherit the default
+/// permissions, e.g. the ACLs of their parent on Windows.
+pub fn create_dir_all_with_mode(path: &Path, mode: Option<u32>) -> io::Result<Vec<PathBuf>> {
+    let mut missing = Vec::new();
+    for ancestor in path.ancestors() {
+        if ancestor.as_os_str().is_empty() || path_exists(ancestor)? {
+            break;
+        }
+        missing.push(ancestor);
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    let mut created = Vec::with_capacity(missing.len());
+    for dir in missing.into_iter().rev() {
+        match fs::create_dir(dir) {
+            Ok(()) => {}
+            // Created concurrently by someone else.
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && dir.is_dir() => continue,
+            Err(err) => return Err(err),
+        }
+        #[cfg(unix)]
+        if let Some(mode) = mode {
+            use std::os::unix::fs::PermissionsExt;
+
+            fs::set_permissions(dir, fs::Permissions::from_mode(mode))?;
+        }
+        created.push(dir.to_owned());
+    }
+    Ok(created)
+}
+
+/// Finds why renaming a source to a target was denied permission, and the
+/// directory at fault.
+///
+/// Nothing is written: the parent directories are checked for write and
+/// search permission as the effective user of the process, and their mode
+/// and owner are read. Returns `None` if no cause is found, such as when the
+/// source is a directory moved to another parent, which needs write
+/// permission on the source itself.
+#[cfg(unix)]
+pub fn permission_denial(
+    source: &Path,
+    target: &Path,
+) -> io::Result<Option<(PermissionReason, PathBuf)>> {
+    let source_dir = existing_ancestor(source.parent().unwrap_or(source))?;
+    if !is_writable_dir(source_dir)? {
+        return Ok(Some((
+            PermissionReason::SourceDirNotWritable,
+            source_dir.to_owned(),
+        )));
+    }
+    let target_dir = existing_ancestor(target.parent().unwrap_or(target))?;
+    if !is_writable_dir(target_dir)? {
+        return Ok(Some((
+            PermissionReason::TargetDirNotWritable,
+            target_dir.to_owned(),
+        )));
+    }
+    if is_sticky_protected(source_dir, source)? {
+        return Ok(Some((PermissionReason::StickyDir, source_dir.to_owned())));
+    }
+    // Replacing an entry also unlinks it.
+    if path_exists(target)? && is_sticky_protected(target_dir, target)? {
+        return Ok(Some((PermissionReason::StickyDir, target_dir.to_owned())));
+    }
+    Ok(None)
+}
+
+/// Tests whether the effective user of the process may create and remove
+/// entries in a directory.
+#[cfg(unix)]
+fn is_writable_dir(dir: &Path) -> io::Result<bool> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let dir_c = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: the path is NUL-terminated.
+    let result = unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            dir_c.as_ptr(),
+            libc::W_OK | libc::X_OK,
+            libc::AT_EACCESS,
+        )
+    };
+    if result == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EACCES | libc::EROFS) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Tests whether an entry of a directory is protected by its sticky bit from
+/// being unlinked by the effective user of the process, which owns neither
+/// the directory nor the entry.
+#[cfg(unix)]
+fn is_sticky_protected(dir: &Path, entry: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    const STICKY_BIT: u32 = 0o1000;
+
+    let dir_metadata = dir.metadata()?;
+    if dir_metadata.mode() & STICKY_BIT == 0 {
+        return Ok(false);
+    }
+    // SAFETY: geteuid cannot fail.
+    let user = unsafe { libc::geteuid() };
+    Ok(user != 0 && user != dir_metadata.uid() && user != entry.symlink_metadata()?.uid())
+}
+
+/// Sets the modification time of a directory.
+pub fn set_dir_mtime(path: &Path, mtime: SystemTime) -> io::Result<()> {
+    #[cfg(unix)]
+    let dir = fs::File::open(path)?;
+    #[cfg(windows)]
+    let dir = {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        const FILE_WRITE_ATTRIBUTES: u32 = 0x0100;
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+        fs::OpenOptions::new()
+            .access_mode(FILE_WRITE_ATTRIBUTES)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)?
+    };
+    dir.set_modified(mtime)
+}
+
+/// Returns the number of bytes available to the current user on the
+/// filesystem of a path, or of its closest existing ancestor.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let path = existing_ancestor(path)?;
+    #[cfg(unix)]
+    {
+        use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+        let path_c = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: the path is NUL-terminated, and the buffer is only read
+        // once filled.
+        let stat = unsafe {
+            if libc::statvfs(path_c.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            stat.assume_init()
+        };
+        #[allow(clippy::unnecessary_cast)]
+        Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+    #[cfg(windows)]
+    return crate::windows::available_space(path);
+}
+
+/// Returns the total size of the files under a path, or of the path itself
+/// if it is not a directory, without following symbolic links.
+pub fn tree_size(path: &Path) -> io::Result<u64> {
+    let metadata = path.symlink_metadata()?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += tree_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// Copies a file, a symbolic link or a directory tree to a missing target.
+///
+/// Symbolic links are copied as links. If the copy fails, whatever was
+/// copied is removed.
+pub fn copy_tree(source: &Path, target: &Path) -> io::Result<()> {
+    let copied = copy_entry(source, target);
+    if copied.is_err() && target.symlink_metadata().is_ok() {
+        let _ = remove_tree(target);
+    }
+    copied
+}
+
+fn copy_entry(source: &Path, target: &Path) -> io::Result<()> {
+    let metadata = source.symlink_metadata()?;
+    if metadata.is_symlink() {
+        let link = fs::read_link(source)?;
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(link, target);
+        #[cfg(windows)]
+        return if fs::metadata(source).is_ok_and(|metadata| metadata.is_dir()) {
+            std::os::windows::fs::symlink_dir(link, target)
+        } else {
+            std::os::windows::fs::symlink_file(link, target)
+        };
+    }
+    if !metadata.is_dir() {
+        // The copy never replaces an entry created since the target was
+        // checked.
+        let mut reader = fs::File::open(source)?;
+        let mut writer = fs::File::create_new(target)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.set_permissions(metadata.permissions())?;
+        return writer.set_modified(metadata.modified()?);
+    }
+    fs::create_dir(target)?;
+    for entry in fs::read_dir(source)? {
+        let name = entry?.file_name();
+        copy_entry(&source.join(&name), &target.join(&name))?;
+    }
+    fs::set_permissions(target, metadata.permissions())
+}
+
+/// Removes a file, a symbolic link or a directory tree.
+pub fn remove_tree(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Renames a path, failing with [`io::ErrorKind::AlreadyExists`] instead of
+/// replacing an existing target.
+///
+/// The kernel enforces this atomically: on Linux, with `renameat2` and
+/// `RENAME_NOREPLACE`, on macOS, with `renamex_np` and `RENAME_EXCL`, and on
+/// Windows, with `MoveFileExW` without `MOVEFILE_REPLACE_EXISTING`. On other
+/// platforms, and on the filesystems not supporting the flag, this is a
+/// plain [`fs::rename`], which the caller guards by checking that the target
+/// is missing beforehand.
+pub fn rename_noreplace(source: &Path, target: &Path) -> io::Result<()> {
+    match rename_exclusive(source, target) {
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => fs::rename(source, target),
+        renamed => renamed,
+    }
+}
+
+/// Renames a path as [`rename_noreplace`], failing with
+/// [`io::ErrorKind::Unsupported`] where the kernel cannot refuse to replace
+/// an existing target.
+pub fn rename_exclusive(source: &Path, target: &Path) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let source_c = CString::new(source.as_os_str().as_bytes())?;
+        let target_c = CString::new(target.as_os_str().as_bytes())?;
+        // SAFETY: both paths are NUL-terminated, and outlive the call.
+        #[cfg(target_os = "linux")]
+        let renamed = unsafe {
+            libc::syscall(
+                libc::SYS_renameat2,
+                libc::AT_FDCWD,
+                source_c.as_ptr(),
+                libc::AT_FDCWD,
+                target_c.as_ptr(),
+                libc::RENAME_NOREPLACE,
+            )
+        };
+        // SAFETY: both paths are NUL-terminated, and outlive the call.
+        #[cfg(target_os = "macos")]
+        let renamed =
+            unsafe { libc::renamex_np(source_c.as_ptr(), target_c.as_ptr(), libc::RENAME_EXCL) };
+        if renamed == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        // Kernels before 3.15 lack the call, and some filesystems the flag.
+        if matches!(
+            err.raw_os_error(),
+            Some(libc::ENOSYS | libc::EINVAL | libc::ENOTSUP)
+        ) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, err));
+        }
+        Err(err)
+    }
+    #[cfg(windows)]
+    return crate::windows::move_noreplace(source, target);
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = (source, target);
+        Err(io::ErrorKind::Unsupported.into())
     }
 }
 
+/// Swaps two paths atomically, each taking the entry of the other.
+///
+/// This uses `renameat2` with `RENAME_EXCHANGE` on Linux, and `renamex_np`
+/// with `RENAME_SWAP` on macOS. Elsewhere, and on the filesystems not
+/// supporting it, this fails with [`io::ErrorKind::Unsupported`].
+pub fn exchange(first: &Path, second: &Path) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+        let first_c = CString::new(first.as_os_str().as_bytes())?;
+        let second_c = CString::new(second.as_os_str().as_bytes())?;
+        // SAFETY: both paths are NUL-terminated, and outlive the call.
+        #[cfg(target_os = "linux")]
+        let swapped = unsafe {
+            libc::syscall(
+                libc::SYS_renameat2,
+                libc::AT_FDCWD,
+                first_c.as_ptr(),
+                libc::AT_FDCWD,
+                second_c.as_ptr(),
+                libc::RENAME_EXCHANGE,
+            )
+        };
+        // SAFETY: both paths are NUL-terminated, and outlive the call.
+        #[cfg(target_os = "macos")]
+        let swapped =
+            unsafe { libc::renamex_np(first_c.as_ptr(), second_c.as_ptr(), libc::RENAME_SWAP) };
+        if swapped == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if !matches!(
+            err.raw_os_error(),
+            Some(libc::ENOSYS | libc::EINVAL | libc::ENOTSUP)
+        ) {
+            return Err(err);
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let _ = (first, second);
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cannot swap paths atomically",
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs, io, path::Path};
 
     #[test]
-    fn common_ancestor() {
-        let path_1 = Path::new("/a/b/c/d");
-        let path_2 = Path::new("/a/b/e/f");
+    fn existing_ancestor() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_path = temp_dir.path().join("dir");
+        fs::create_dir(&dir_path)?;
+
+        assert_eq!(super::existing_ancestor(&dir_path)?, dir_path);
         assert_eq!(
-            super::common_ancestor(path_1, path_2),
-            Some(Path::new("/a/b"))
+            super::existing_ancestor(&dir_path.join("a/b"))?,
+            dir_path.as_path()
         );
-
-        let path_1 = Path::new("/a/b/c/d");
-        let path_2 = Path::new("/a/b/c/d/e/f");
         assert_eq!(
-            super::common_ancestor(path_1, path_2),
-            Some(Path::new("/a/b/c/d"))
+            super::existing_ancestor(Path::new("does-not-exist/a"))?,
+            Path::new(".")
         );
+        Ok(())
+    }
 
-        let path_1 = Path::new("/a/b/c/d");
-        let path_2 = Path::new("/x/y/z");
-        assert_eq!(super::common_ancestor(path_1, path_2), Some(Path::new("/")));
+    #[test]
+    fn rename_noreplace() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), "a")?;
+        fs::write(path("b"), "b")?;
 
-        let path_1 = Path::new("a/b/c/d");
-        let path_2 = Path::new("x/y/z");
-        assert_eq!(super::common_ancestor(path_1, path_2), None);
+        super::rename_noreplace(&path("a"), &path("c"))?;
+        assert_eq!(fs::read_to_string(path("c"))?, "a");
+        #[cfg(any(target_os = "linux", windows))]
+        {
+            let err = super::rename_noreplace(&path("c"), &path("b")).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+            assert_eq!(fs::read_to_string(path("b"))?, "b");
+            assert_eq!(fs::read_to_string(path("c"))?, "a");
+        }
+        Ok(())
     }
 
     #[test]
-    fn path_exists() -> io::Result<()> {
+    fn rename_exclusive() -> io::Result<()> {
         let temp_dir = tempfile::tempdir()?;
-        let file_path = temp_dir.path().join("file.txt");
-        let link_path = temp_dir.path().join("link.txt");
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), "a")?;
+        fs::write(path("b"), "b")?;
+
+        // The target is never replaced, even where the flag is unsupported.
+        let err = super::rename_exclusive(&path("a"), &path("b")).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::AlreadyExists | io::ErrorKind::Unsupported
+        ));
+        assert_eq!(fs::read_to_string(path("a"))?, "a");
+        assert_eq!(fs::read_to_string(path("b"))?, "b");
+        Ok(())
+    }
 
-        // The file does not exist yet.
-        assert!(!super::path_exists(&file_path)?);
+    #[test]
+    fn exchange() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), "a")?;
+        fs::create_dir(path("b"))?;
 
-        // Create the file, check that it exists.
+        match super::exchange(&path("a"), &path("b")) {
+            Ok(()) => {
+                assert!(path("a").is_dir());
+                assert_eq!(fs::read_to_string(path("b"))?, "a");
+            }
+            Err(err) => {
+                assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+                assert!(path("b").is_dir());
+            }
+        }
+        // Both paths must exist.
+        assert!(super::exchange(&path("a"), &path("c")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn device_of() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let file_path = temp_dir.path().join("file.txt");
         fs::File::create(&file_path)?;
-        assert!(super::path_exists(&file_path)?);
 
-        // Create a symbolic link, check that it exists.
+        assert_eq!(
+            super::device_of(&file_path)?,
+            super::device_of(&temp_dir.path().join("new/file.txt"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn git_root() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo_path = temp_dir.path().join("repo");
+        fs::create_dir_all(repo_path.join(".git"))?;
+        let repo_path = repo_path.canonicalize()?;
+
+        assert_eq!(
+            super::git_root(&repo_path.join("a/b.txt"))?,
+            Some(repo_path.clone())
+        );
+        assert_eq!(super::git_root(&repo_path)?, Some(repo_path));
+        assert_ne!(
+            super::git_root(temp_dir.path())?,
+            Some(temp_dir.path().join("repo"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn create_dir_all_with_mode() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name| temp_dir.path().join(name);
+        fs::create_dir(path("a"))?;
+
+        let created = super::create_dir_all_with_mode(&path("a/b/c"), Some(0o750))?;
+        assert_eq!(created, [path("a/b"), path("a/b/c")]);
+        assert!(super::create_dir_all_with_mode(&path("a/b/c"), Some(0o750))?.is_empty());
+
         #[cfg(unix)]
         {
-            std::os::unix::fs::symlink(&file_path, &link_path)?;
-            assert!(super::path_exists(&link_path)?);
-        }
-        #[cfg(windows)]
-        {
-            std::os::windows::fs::symlink_file(&file_path, &link_path)?;
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = |name| fs::metadata(path(name)).map(|m| m.permissions().mode() & 0o7777);
+            assert_ne!(mode("a")?, 0o750);
+            assert_eq!(mode("a/b")?, 0o750);
+            assert_eq!(mode("a/b/c")?, 0o750);
         }
-        assert!(super::path_exists(&link_path)?);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() -> io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::create_dir(root.join("real"))?;
+        symlink("real", root.join("link"))?;
+
+        assert_eq!(
+            super::symlinked_ancestor(&root.join("link/sub"))?,
+            Some(root.join("link").as_path())
+        );
+        assert_eq!(
+            super::symlinked_ancestor(&root.join("link"))?,
+            Some(root.join("link").as_path())
+        );
+        assert_eq!(super::symlinked_ancestor(&root.join("real/sub"))?, None);
 
-        // Remove the file, check that the symbolic link still exists.
-        fs::remove_file(&file_path)?;
-        assert!(super::path_exists(&link_path)?);
+        assert_eq!(
+            super::resolve_parent(&root.join("link/sub/file.txt"))?,
+            root.join("real/sub/file.txt")
+        );
+        assert_eq!(
+            super::resolve_parent(&root.join("link"))?,
+            root.join("link")
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn entry_id() -> io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir(root.join("real"))?;
+        symlink("real", root.join("link"))?;
+        fs::write(root.join("real/a"), b"")?;
+        fs::hard_link(root.join("real/a"), root.join("real/b"))?;
 
+        let id = |name| super::entry_id(&root.join(name));
+        assert_eq!(id("real/a")?, id("link/a")?);
+        assert_eq!(id("real/./a")?, id("real/a")?);
+        assert_ne!(id("real/a")?, id("real/b")?);
+        assert!(super::entry_id(Path::new("/")).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permission_denial() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::error::PermissionReason;
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir(root.join("locked"))?;
+        fs::write(root.join("locked/a"), b"")?;
+        fs::write(root.join("b"), b"")?;
+
+        assert_eq!(
+            super::permission_denial(&root.join("b"), &root.join("c"))?,
+            None
+        );
+        // Nothing is written to the directories.
+        assert_eq!(fs::read_dir(root)?.count(), 2);
+
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o555))?;
+        let privileged = super::is_writable_dir(&root.join("locked"))?;
+        let source_denial = super::permission_denial(&root.join("locked/a"), &root.join("c"))?;
+        let target_denial = super::permission_denial(&root.join("b"), &root.join("locked/c/d"))?;
+        fs::set_permissions(root.join("locked"), fs::Permissions::from_mode(0o755))?;
+        // Permissions are not enforced for privileged users.
+        if privileged {
+            assert_eq!(source_denial, None);
+            assert_eq!(target_denial, None);
+        } else {
+            assert_eq!(
+                source_denial,
+                Some((PermissionReason::SourceDirNotWritable, root.join("locked")))
+            );
+            assert_eq!(
+                target_denial,
+                Some((PermissionReason::TargetDirNotWritable, root.join("locked")))
+            );
+        }
         Ok(())
     }
 }
@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use crate::renamer::Renamer;
+
+/// A rule computing the target of each source, one source at a time.
+///
+/// A generator lets an interactive front end preview a renaming lazily,
+/// computing the targets of the visible sources only, then build the
+/// [`Renamer`] of every source with [`Renamer::from_generator`] once the
+/// renaming is committed. Closures taking the source and its index are
+/// generators, as are the name transformations of
+/// [`NameAffix`](crate::NameAffix) and, with the `sniff` feature, the fixes
+/// of extensions.
+///
+/// Generators must be deterministic: the target of a source depends on the
+/// source and its index only, and not on the sources previewed before, so
+/// that the preview and the committed plan agree. Rules depending on the
+/// other sources, such as numbering files by directory, are to be computed
+/// ahead of time.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use nominal::{Renamer, TargetGenerator};
+/// let numbered = |source: &Path, index: usize| {
+///     let extension = source.extension()?.to_str()?;
+///     Some(source.with_file_name(format!("{:03}.{}", index + 1, extension)))
+/// };
+/// let sources = ["b.jpg", "a.jpg", "notes"].map(PathBuf::from);
+///
+/// // Only the second row is visible.
+/// assert_eq!(
+///     numbered.target_for(&sources[1], 1),
+///     Some(PathBuf::from("002.jpg"))
+/// );
+///
+/// let renamer = Renamer::from_generator(sources, &numbered);
+/// let mut output = Vec::new();
+/// renamer.plan()?.write_to(&mut output)?;
+/// assert_eq!(output, b"b.jpg => 001.jpg\na.jpg => 002.jpg\n");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait TargetGenerator {
+    /// Returns the target of a source, given its index among the sources,
+    /// or [`None`] if the source is left as is.
+    fn target_for(&self, source: &Path, index: usize) -> Option<PathBuf>;
+}
+
+impl<F> TargetGenerator for F
+where
+    F: Fn(&Path, usize) -> Option<PathBuf>,
+{
+    fn target_for(&self, source: &Path, index: usize) -> Option<PathBuf> {
+        self(source, index)
+    }
+}
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Creates a renamer with an operation for every source the generator
+    /// has a target for.
+    ///
+    /// Sources are indexed in iteration order, counting those the generator
+    /// leaves as is, so that indices match the rows of a preview listing
+    /// every source. See [`TargetGenerator`].
+    pub fn from_generator<I>(sources: I, generator: &dyn TargetGenerator) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<PathBuf>,
+    {
+        (sources.into_iter().enumerate())
+            .filter_map(|(index, source)| {
+                let source = source.into();
+                let target = generator.target_for(&source, index)?;
+                Some((source, target))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::TargetGenerator;
+    use crate::Renamer;
+
+    /// Upper-cases the file name of every other source.
+    struct EveryOther;
+
+    impl TargetGenerator for EveryOther {
+        fn target_for(&self, source: &Path, index: usize) -> Option<PathBuf> {
+            let name = source.file_name()?.to_str()?;
+            index
+                .is_multiple_of(2)
+                .then(|| source.with_file_name(name.to_uppercase()))
+        }
+    }
+
+    #[test]
+    fn preview_matches_commit() {
+        let sources: Vec<PathBuf> = (0..10)
+            .map(|index| PathBuf::from(format!("dir/file{}", index)))
+            .collect();
+        // The rows are previewed out of order, as when scrolling back.
+        let preview: Vec<_> = [7, 2, 3, 0]
+            .into_iter()
+            .map(|index| (index, EveryOther.target_for(&sources[index], index)))
+            .collect();
+
+        let renamer = Renamer::from_generator(sources.iter().cloned(), &EveryOther);
+        assert_eq!(renamer.renames.len(), 5);
+        for (index, target) in preview {
+            let committed = (renamer.renames.iter())
+                .find(|rename| rename.source == sources[index])
+                .map(|rename| rename.target.clone());
+            assert_eq!(committed, target);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! The inspection of the entries at the paths of a plan.
+//!
+//! Planning, preflight checks, dry runs and applies inspect sources and
+//! targets through these functions, so that they agree on symbolic links: a
+//! link is inspected itself unless the caller explicitly follows it, and a
+//! dangling link is then an existing entry.
+
+use std::{fs, io, path::Path};
+
+use crate::options::FollowSymlinks;
+
+/// Returns the metadata of the entry at a path.
+pub(crate) fn metadata(path: &Path, follow: FollowSymlinks) -> io::Result<fs::Metadata> {
+    match follow {
+        FollowSymlinks::No => path.symlink_metadata(),
+        FollowSymlinks::Yes => path.metadata(),
+    }
+}
+
+/// Returns the metadata of the entry at a path, or [`None`] if there is none.
+///
+/// When following symbolic links, a dangling link is no entry.
+pub(crate) fn entry(path: &Path, follow: FollowSymlinks) -> io::Result<Option<fs::Metadata>> {
+    match metadata(path, follow) {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Tests whether there is an entry at a path.
+pub(crate) fn exists(path: &Path, follow: FollowSymlinks) -> io::Result<bool> {
+    Ok(entry(path, follow)?.is_some())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, io, os::unix::fs::symlink, path::PathBuf};
+
+    use super::exists;
+    use crate::{
+        ApplyErrorDetails, ApplyOptions, ApplyReport, FollowSymlinks, PlanOptions, RenameStatus,
+        Renamer, Session, SourceKind, TargetKind,
+    };
+
+    #[test]
+    fn dangling_symlinks() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        symlink("missing", path("link"))?;
+        assert!(exists(&path("link"), FollowSymlinks::No)?);
+        assert!(!exists(&path("link"), FollowSymlinks::Yes)?);
+
+        let outcome = |report: &ApplyReport| match &report.entries()[0].status {
+            RenameStatus::Pending => "pending",
+            RenameStatus::Applied => "applied",
+            RenameStatus::Failed(ApplyErrorDetails::TargetExists {
+                kind: TargetKind::Symlink,
+            }) => "target exists",
+            RenameStatus::Failed(ApplyErrorDetails::Io { error, .. })
+                if error.kind() == io::ErrorKind::NotFound =>
+            {
+                "not found"
+            }
+            status => panic!("unexpected status: {:?}", status),
+        };
+        // Returns whether the target collides, then the outcomes of a dry run
+        // and of an apply.
+        let run = |renames: &[(&str, &str)], options: ApplyOptions| {
+            let renamer: Renamer<PathBuf, PathBuf> = (renames.iter())
+                .map(|(source, target)| (path(source), path(target)))
+                .collect();
+            let mut session = Session::new(PlanOptions::new(), options);
+            let plan = session.plan(renamer).unwrap();
+            let collides = !plan.collisions().unwrap().is_empty();
+            let dry_run = session.dry_run(&plan);
+            let report = session.apply(plan);
+            (collides, outcome(&dry_run), outcome(&report))
+        };
+
+        // As a source, the link itself is renamed.
+        let options = ApplyOptions::new();
+        assert_eq!(
+            run(&[("link", "moved")], options),
+            (false, "pending", "applied")
+        );
+        assert_eq!(fs::read_link(path("moved"))?, PathBuf::from("missing"));
+        let options = ApplyOptions::new().expect(SourceKind::Symlink);
+        assert_eq!(
+            run(&[("moved", "link")], options),
+            (false, "pending", "applied")
+        );
+
+        // Once followed, it has no kind to check.
+        let options = ApplyOptions::new()
+            .expect(SourceKind::File)
+            .follow_symlinks(FollowSymlinks::Yes);
+        assert_eq!(
+            run(&[("link", "moved")], options),
+            (false, "not found", "not found")
+        );
+        assert!(path("link").symlink_metadata().is_ok());
+
+        // As an existing target, the link is kept: the dry run does not
+        // check targets, but the collision is found while planning.
+        fs::write(path("a"), "a")?;
+        let options = ApplyOptions::new();
+        assert_eq!(
+            run(&[("a", "link")], options),
+            (true, "pending", "target exists")
+        );
+        assert!(path("a").exists());
+
+        // Once followed, it is replaced.
+        let options = ApplyOptions::new().follow_symlinks(FollowSymlinks::Yes);
+        assert_eq!(run(&[("a", "link")], options), (true, "pending", "applied"));
+        assert_eq!(fs::read_to_string(path("link"))?, "a");
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::PlanError,
+    fsutil::sync_parent,
+    operation::Rename,
+    plan::Plan,
+    progress::replace_file,
+    report::ApplyReport,
+    tempname::DefaultTempNamer,
+    undo::{undo_plan, UndoStack},
+};
+
+/// An undo journal, persisted as a sequence of JSON representations of
+/// [`UndoStack`]s, whose plans are recorded one after the other.
+///
+/// A journal is written by the application, typically by appending every
+/// applied plan with a [`JournalWriter`], or by saving its whole undo stack,
+/// and inspected or compacted here, so that every tool built on this crate
+/// undoes journals the same way.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs::{self, File};
+/// # use nominal::{ApplyOptions, Journal, Renamer, UndoStack};
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = |name| temp_dir.path().join(name);
+/// File::create(path("a.txt"))?;
+///
+/// let mut undo_stack = UndoStack::new();
+/// for (source, target) in [("a.txt", "b.txt"), ("b.txt", "c.txt")] {
+///     let mut renamer = Renamer::new();
+///     renamer.add(path(source), path(target));
+///     undo_stack.push(&renamer.plan()?.apply_with(&ApplyOptions::new()));
+/// }
+/// fs::write(path("journal.json"), undo_stack.to_json_value().to_string())?;
+///
+/// let mut journal = Journal::open(path("journal.json"))?;
+/// assert_eq!(journal.entries().count(), 2);
+/// assert_eq!(journal.compact()?, 1);
+///
+/// journal.to_plan()?.apply()?;
+/// assert!(path("a.txt").exists());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+    undo_stack: UndoStack,
+}
+
+impl Journal {
+    /// Reads the journal at a path.
+    ///
+    /// A missing file is read as an empty journal. A file holding anything
+    /// but valid [undo stacks](UndoStack::from_json_value) is rejected with
+    /// [`io::ErrorKind::InvalidData`], or with
+    /// [`io::ErrorKind::UnexpectedEof`] if its last record is torn, as by a
+    /// crash while appending it: see [`verify`](Self::verify).
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let undo_stack = match fs::read(&path) {
+            Ok(content) => match read_records(&content) {
+                (undo_stack, _, None) => undo_stack,
+                (_, _, Some(err)) => return Err(err),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => UndoStack::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self { path, undo_stack })
+    }
+
+    /// Recovers the journal at a path from a crash, and returns the number
+    /// of bytes removed.
+    ///
+    /// A journal whose last record is torn, as when the application crashed
+    /// while appending it, is truncated after its last complete record,
+    /// which the application finished appending before. A torn record may
+    /// be followed by NUL bytes, as left by the filesystems that extend a
+    /// file before writing its content. Nothing is removed
+    /// from a missing or intact journal. Other damage cannot come from an
+    /// interrupted append: it is rejected with
+    /// [`io::ErrorKind::InvalidData`], leaving the file as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::Journal;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = temp_dir.path().join("journal.json");
+    /// let record = r#"{"version":1,"plans":[[{"source":"a","target":"b"}]]}"#;
+    /// fs::write(&path, format!("{record}\n{{\"version\":1,\"pla"))?;
+    /// assert!(Journal::open(&path).is_err());
+    ///
+    /// assert_eq!(Journal::verify(&path)?, 18);
+    /// assert_eq!(Journal::open(&path)?.entries().count(), 1);
+    /// assert_eq!(Journal::verify(&path)?, 0);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn verify<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+        let path = path.as_ref();
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        match read_records(&content) {
+            (_, _, None) => Ok(0),
+            (_, complete, Some(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                let file = fs::OpenOptions::new().write(true).open(path)?;
+                file.set_len(complete as u64)?;
+                file.sync_all()?;
+                Ok((content.len() - complete) as u64)
+            }
+            (_, _, Some(err)) => Err(err),
+        }
+    }
+
+    /// Returns the path of the journal.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the undo stack recorded in the journal.
+    pub fn undo_stack(&self) -> &UndoStack {
+        &self.undo_stack
+    }
+
+    /// Returns the undo stack recorded in the journal, to be changed, then
+    /// [saved](Self::save).
+    pub fn undo_stack_mut(&mut self) -> &mut UndoStack {
+        &mut self.undo_stack
+    }
+
+    /// Consumes the journal, returning its undo stack.
+    pub fn into_undo_stack(self) -> UndoStack {
+        self.undo_stack
+    }
+
+    /// Saves the undo stack of the journal as its only record.
+    ///
+    /// The file is replaced atomically, through a temporary file next to it
+    /// flushed to disk, and so is its directory entry, so that a crash
+    /// leaves either the old or the new journal, never a partial one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Journal, JournalWriter, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// File::create(path("a.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    /// let report = renamer.plan()?.apply_with(&ApplyOptions::new());
+    /// JournalWriter::open(path("journal.json"))?.append(&report)?;
+    ///
+    /// let mut journal = Journal::open(path("journal.json"))?;
+    /// journal.undo_stack_mut().undo_last()?;
+    /// journal.save()?;
+    /// assert!(path("a.txt").exists());
+    /// assert_eq!(Journal::open(path("journal.json"))?.entries().count(), 0);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn save(&self) -> io::Result<()> {
+        replace_file(&self.path, &record(&self.undo_stack), &DefaultTempNamer)
+    }
+
+    /// Returns the recorded operations, in apply order.
+    pub fn entries(&self) -> impl Iterator<Item = &Rename<PathBuf, PathBuf>> + '_ {
+        self.undo_stack.records.iter().flatten()
+    }
+
+    /// [Compacts](UndoStack::compact) the journal, and returns the number of
+    /// operations removed.
+    ///
+    /// The compacted journal replaces the file atomically, through a
+    /// temporary file next to it, so that readers never see a partial
+    /// journal. The journal is left untouched if nothing was removed.
+    pub fn compact(&mut self) -> io::Result<usize> {
+        let mut undo_stack = self.undo_stack.clone();
+        let removed = undo_stack.compact();
+        if removed > 0 {
+            replace_file(&self.path, &record(&undo_stack), &DefaultTempNamer)?;
+            self.undo_stack = undo_stack;
+        }
+        Ok(removed)
+    }
+
+    /// Returns the plan renaming every recorded file back to its first path,
+    /// with as few operations as needed.
+    ///
+    /// The journal is left as is: the plan undoes its
+    /// [compacted](UndoStack::compact) operations, without checking that they
+    /// can still be undone, as [`UndoStack::undo_last`] does.
+    pub fn to_plan(&self) -> Result<Plan<PathBuf, PathBuf>, PlanError> {
+        let mut undo_stack = self.undo_stack.clone();
+        undo_stack.compact();
+        undo_plan(undo_stack.records.first().map_or(&[], Vec::as_slice))
+    }
+}
+
+/// Appends the plans applied by an application to an undo journal.
+///
+/// Each [`ApplyReport`] is appended as a record of its own, holding the
+/// [undo stack](UndoStack::push) of the report, and followed by a newline.
+/// Records are buffered, then written and flushed to disk once every
+/// [`flush_every`](Self::flush_every) records, when [flushed](Self::flush)
+/// explicitly, and when the writer is dropped. A crash thus loses the
+/// records not flushed yet, and may leave a torn last record, which
+/// [`Journal::verify`] removes.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs::File;
+/// # use nominal::{ApplyOptions, Journal, JournalWriter, Renamer};
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = |name| temp_dir.path().join(name);
+/// File::create(path("a.txt"))?;
+///
+/// let mut writer = JournalWriter::open(path("journal.json"))?.flush_every(10);
+/// for (source, target) in [("a.txt", "b.txt"), ("b.txt", "c.txt")] {
+///     let mut renamer = Renamer::new();
+///     renamer.add(path(source), path(target));
+///     writer.append(&renamer.plan()?.apply_with(&ApplyOptions::new()))?;
+/// }
+/// assert_eq!(Journal::open(path("journal.json"))?.entries().count(), 0);
+///
+/// writer.flush()?;
+/// assert_eq!(Journal::open(path("journal.json"))?.entries().count(), 2);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct JournalWriter {
+    file: fs::File,
+    flush_every: usize,
+    /// The records not written yet.
+    buffer: Vec<u8>,
+    pending: usize,
+}
+
+impl JournalWriter {
+    /// Opens the journal at a path for appending, creating it if missing.
+    ///
+    /// The journal is first [verified](Journal::verify), so that records are
+    /// never appended after a torn one. A created journal is flushed to
+    /// disk along with its directory entry.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        Journal::verify(path)?;
+        let mut options = fs::OpenOptions::new();
+        options.append(true);
+        let file = match options.clone().create_new(true).open(path) {
+            Ok(file) => {
+                file.sync_all()?;
+                sync_parent(path)?;
+                file
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => options.open(path)?,
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            file,
+            flush_every: 1,
+            buffer: Vec::new(),
+            pending: 0,
+        })
+    }
+
+    /// Sets the number of records buffered before they are written and
+    /// flushed to disk.
+    ///
+    /// Larger batches make fewer calls to the filesystem, but a crash loses
+    /// more records. Zero is taken as one. Defaults to 1, flushing every
+    /// record as soon as it is appended.
+    pub fn flush_every(mut self, records: usize) -> Self {
+        self.flush_every = records.max(1);
+        self
+    }
+
+    /// Appends the operations applied in a report, flushing the buffered
+    /// records if they are due.
+    ///
+    /// Reports without any applied operation are not recorded.
+    pub fn append(&mut self, report: &ApplyReport) -> io::Result<()> {
+        let mut undo_stack = UndoStack::new();
+        undo_stack.push(report);
+        if undo_stack.is_empty() {
+            return Ok(());
+        }
+        self.buffer.extend(record(&undo_stack));
+        self.pending += 1;
+        if self.pending >= self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered records and flushes them to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(&self.buffer)?;
+        self.file.sync_data()?;
+        self.buffer.clear();
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+impl Drop for JournalWriter {
+    fn drop(&mut self) {
+        // Errors can only be handled by flushing explicitly.
+        let _ = self.flush();
+    }
+}
+
+/// Returns the record of an undo stack, followed by a newline.
+fn record(undo_stack: &UndoStack) -> Vec<u8> {
+    let mut content = undo_stack.to_json_value().to_string().into_bytes();
+    content.push(b'\n');
+    content
+}
+
+/// Reads the records of a journal.
+///
+/// Returns the undo stack of the complete records read, and the number of
+/// bytes they span, along with the error that stopped the reading, if any.
+/// A torn last record, even followed by NUL bytes, is an
+/// [`io::ErrorKind::UnexpectedEof`] error, and other damage an
+/// [`io::ErrorKind::InvalidData`] one.
+fn read_records(content: &[u8]) -> (UndoStack, usize, Option<io::Error>) {
+    // The filesystems extending a file before writing its content leave it
+    // filled with NUL bytes on a crash.
+    let end = content
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |last| last + 1);
+    let mut undo_stack = UndoStack::new();
+    let mut records =
+        serde_json::Deserializer::from_slice(&content[..end]).into_iter::<UndoStack>();
+    let mut complete = 0;
+    while let Some(record) = records.next() {
+        match record {
+            Ok(mut record) => {
+                undo_stack.append(&mut record);
+                complete = records.byte_offset();
+            }
+            Err(err) => return (undo_stack, complete, Some(err.into())),
+        }
+    }
+    let torn = (end < content.len())
+        .then(|| io::Error::new(io::ErrorKind::UnexpectedEof, "journal ends with NUL bytes"));
+    (undo_stack, complete, torn)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::{Journal, JournalWriter};
+    use crate::{ApplyOptions, Renamer, UndoStack};
+
+    #[test]
+    fn compact() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        let journal_path = path("journal.json");
+        for name in ["a", "b", "c", "d"] {
+            fs::write(path(name), name)?;
+        }
+
+        assert_eq!(Journal::open(&journal_path)?.entries().count(), 0);
+
+        let mut undo_stack = UndoStack::new();
+        let mut apply = |renames: &[(&str, &str)]| {
+            let renamer: Renamer<_, _> = renames
+                .iter()
+                .map(|(source, target)| (path(source), path(target)))
+                .collect();
+            undo_stack.push(&renamer.plan().unwrap().apply_with(&ApplyOptions::new()));
+        };
+        // A chain, a swap through a temporary file, and a round trip.
+        apply(&[("a", "a1")]);
+        apply(&[("a1", "a2")]);
+        apply(&[("b", "tmp")]);
+        apply(&[("c", "b")]);
+        apply(&[("tmp", "c")]);
+        apply(&[("d", "d1")]);
+        apply(&[("d1", "d")]);
+        fs::write(&journal_path, undo_stack.to_json_value().to_string())?;
+
+        let mut journal = Journal::open(&journal_path)?;
+        assert_eq!(journal.entries().count(), 7);
+        let plan = journal.to_plan().unwrap();
+        assert_eq!(plan.len(), 4);
+        assert_eq!(journal.entries().count(), 7);
+
+        assert_eq!(journal.compact()?, 3);
+        assert_eq!(journal.compact()?, 0);
+        let reopened = Journal::open(&journal_path)?;
+        assert_eq!(reopened.undo_stack(), journal.undo_stack());
+        assert_eq!(reopened.undo_stack().len(), 1);
+
+        plan.apply().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            assert_eq!(fs::read_to_string(path(name))?, name);
+        }
+        assert!(!path("a2").exists());
+
+        fs::write(&journal_path, b"{}")?;
+        let err = Journal::open(&journal_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn torn_records() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        let journal_path = path("journal.json");
+        fs::write(path("a"), "a")?;
+
+        let mut writer = JournalWriter::open(&journal_path)?.flush_every(2);
+        for (source, target) in [("a", "b"), ("b", "été"), ("été", "d")] {
+            let mut renamer = Renamer::new();
+            renamer.add(path(source), path(target));
+            writer.append(&renamer.plan().unwrap().apply_with(&ApplyOptions::new()))?;
+        }
+        // The third record is buffered.
+        assert_eq!(Journal::open(&journal_path)?.undo_stack().len(), 2);
+        drop(writer);
+        let content = fs::read(&journal_path)?;
+        let undo_stack = Journal::open(&journal_path)?.into_undo_stack();
+        let plans = undo_stack.to_json_value()["plans"].clone();
+        assert_eq!(plans.as_array().unwrap().len(), 3);
+        // Every record ends with a newline.
+        let ends: Vec<_> = (content.iter().enumerate())
+            .filter(|(_, &byte)| byte == b'\n')
+            .map(|(end, _)| end)
+            .collect();
+        assert_eq!(ends.len(), 3);
+
+        for (offset, zeros) in (0..=content.len()).flat_map(|offset| [(offset, 0), (offset, 16)]) {
+            let mut torn = content[..offset].to_vec();
+            torn.resize(offset + zeros, 0);
+            fs::write(&journal_path, &torn)?;
+            let removed = Journal::verify(&journal_path)?;
+            let recovered = fs::read(&journal_path)?;
+            assert_eq!(recovered.len() as u64 + removed, torn.len() as u64);
+            assert_eq!(recovered, content[..recovered.len()]);
+
+            let complete = ends.iter().filter(|&&end| end <= offset).count();
+            let undo_stack = Journal::open(&journal_path)?.into_undo_stack();
+            assert_eq!(
+                undo_stack.to_json_value()["plans"].as_array().unwrap()[..],
+                plans.as_array().unwrap()[..complete],
+                "offset {}, {} NUL bytes",
+                offset,
+                zeros
+            );
+            // The next record is appended after the last complete one.
+            let mut renamer = Renamer::new();
+            renamer.add(path("d"), path("e"));
+            let report = renamer.plan().unwrap().apply_with(&ApplyOptions::new());
+            JournalWriter::open(&journal_path)?.append(&report)?;
+            let undo_stack = Journal::open(&journal_path)?.into_undo_stack();
+            assert_eq!(undo_stack.len(), complete + 1);
+            fs::rename(path("e"), path("d"))?;
+        }
+
+        // Damage before the last record is not from an interrupted append.
+        let mut damaged = content.clone();
+        damaged[1] = b'!';
+        fs::write(&journal_path, &damaged)?;
+        let err = Journal::verify(&journal_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&journal_path)?, damaged);
+        Ok(())
+    }
+}
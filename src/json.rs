@@ -0,0 +1,1748 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+#[cfg(feature = "digest")]
+use crate::undo::RecordedDigest;
+use crate::{
+    collate::PlanOrdering,
+    error::{ApplyErrorDetails, IoOp, PermissionReason, TargetKind},
+    operation::Rename,
+    options::{
+        ApplyOptions, Boundary, DirMerge, ExecutionOrder, Explicit, FollowSymlinks, LockScope,
+        PlanOptions, SourceChange, SourceKind, StatErrorPolicy, Throttle, ValidationProfile,
+    },
+    plan::{Plan, SkipReason},
+    report::{ApplyReport, CreatedDir, RenameStatus},
+    stamp::{SourceStamp, StampField},
+    tag::Tag,
+    undo::UndoStack,
+    warning::Warning,
+};
+
+/// The version of the JSON representations.
+///
+/// Every representation written to disk records this version in its
+/// `version` field, and the version of this crate in its `nominal_version`
+/// field. The version is bumped whenever a backward-incompatible change is
+/// made to the JSON representations, and representations of any other
+/// version are rejected when read back, with an error naming both versions.
+/// Adding new optional fields is not a breaking change: the readers of these
+/// representations ignore the fields they do not know, so that
+/// representations written by newer versions of this crate can still be
+/// read.
+///
+/// Options are configuration rather than records, and are not versioned:
+/// [`PlanOptions::from_json_value`] and [`ApplyOptions::from_json_value`]
+/// only ignore unknown fields when asked to, and deserializing options with
+/// serde denies them.
+pub const JSON_VERSION: u64 = 1;
+
+/// The version of this crate, recorded next to [`JSON_VERSION`].
+pub(crate) const NOMINAL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+impl<S, T> Plan<S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    /// Returns the JSON representation of the plan.
+    ///
+    /// The representation is an object with the following fields:
+    ///
+    /// - `version`: the format version, see [`JSON_VERSION`].
+    /// - `nominal_version`: the version of the crate that wrote it.
+    /// - `operations`: an array of objects with `source` and `target` fields,
+    ///   and a `tag` field for operations with a
+    ///   [serializable](crate::Tag::serializable) tag, and a `priority` field
+    ///   for operations with a non-zero
+    ///   [priority](crate::Renamer::add_with_priority). Operations whose source
+    ///   is [stamped](crate::Plan::source_stamp) also have a `stamp` field, an
+    ///   object with `size`, `modified` and, with the `digest` feature,
+    ///   `digest` fields: the size in bytes, the modification time in
+    ///   seconds since the Unix epoch, and the digest in hexadecimal or
+    ///   `null`.
+    /// - `skipped`: an array of objects with `source`, `target`, `reason` and
+    ///   optional `tag` fields, for the [skipped](crate::Plan::skipped)
+    ///   entries. The reason is one of `"noop"`, `"coalesced"` or
+    ///   `"duplicate"`.
+    /// - `base_dir`: the [base directory](crate::Plan::base_dir), for plans
+    ///   that record one.
+    ///
+    /// Paths are represented as strings when they are valid UTF-8. Otherwise,
+    /// they are represented as an object with a single `bytes` field (an
+    /// array of bytes) on Unix, or a single `wide` field (an array of UTF-16
+    /// code units, which may hold unpaired surrogates) on Windows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("old.txt", "new.txt");
+    ///
+    /// let json = renamer.plan()?.to_json_value();
+    /// assert_eq!(json["version"], 1);
+    /// assert_eq!(json["operations"][0]["source"], "old.txt");
+    /// assert_eq!(json["operations"][0]["target"], "new.txt");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn to_json_value(&self) -> Value {
+        let operations: Vec<Value> = self
+            .renames
+            .iter()
+            .enumerate()
+            .map(|(index, rename)| {
+                let mut operation = json!({
+                    "source": path_to_json(rename.source.as_ref()),
+                    "target": path_to_json(rename.target.as_ref()),
+                });
+                insert_tag(&mut operation, rename.tag.as_ref());
+                if rename.priority != 0 {
+                    operation["priority"] = rename.priority.into();
+                }
+                if let Some(stamp) = self.stamps.get(&index) {
+                    operation["stamp"] = stamp_to_json(stamp);
+                }
+                operation
+            })
+            .collect();
+        let skipped: Vec<Value> = self
+            .skipped
+            .iter()
+            .map(|entry| {
+                let reason = match entry.reason {
+                    SkipReason::NoOp => "noop",
+                    SkipReason::Coalesced => "coalesced",
+                    SkipReason::Duplicate => "duplicate",
+                };
+                let mut skipped = json!({
+                    "source": path_to_json(&entry.source),
+                    "target": path_to_json(&entry.target),
+                    "reason": reason,
+                });
+                insert_tag(&mut skipped, entry.tag.as_ref());
+                skipped
+            })
+            .collect();
+        let mut plan = json!({
+            "version": JSON_VERSION,
+            "nominal_version": NOMINAL_VERSION,
+            "operations": operations,
+            "skipped": skipped,
+        });
+        if let Some(base_dir) = &self.base_dir {
+            plan["base_dir"] = path_to_json(base_dir);
+        }
+        plan
+    }
+}
+
+impl ApplyReport {
+    /// Returns the JSON representation of the report.
+    ///
+    /// The representation is an object with the following fields:
+    ///
+    /// - `version` and `nominal_version`: as in [`Plan::to_json_value`].
+    /// - `entries`: an array of objects with the following fields:
+    ///   - `source` and `target`: the paths, encoded as in
+    ///     [`Plan::to_json_value`].
+    ///   - `status`: one of `"applied"`, `"merged"`, `"skipped"`,
+    ///     `"already_applied"`, `"deferred"`, `"failed"` or `"pending"`.
+    ///   - `duration_secs`: the duration of the operation in seconds, or
+    ///     `null` if it was not attempted.
+    ///   - `error`: `null`, or for failed operations, an object with a `kind`
+    ///     field (`"target_exists"`, `"io"`, `"crosses_boundary"`,
+    ///     `"insufficient_space"`, `"timed_out"`, `"symlinked_parent"`,
+    ///     `"symlink_traversal"`,
+    ///     `"missing_base_dir"`, `"invalid_target"`, `"empty_source"`,
+    ///     `"kind_mismatch"`, `"merge_failed"`, `"content_changed"`,
+    ///     `"source_changed"`, `"contains_current_dir"`,
+    ///     `"permission_denied"` or `"lock_held"`) and a
+    ///     human-readable `message` field. Existing targets also have a
+    ///     `target_kind` field, among `"file"`, `"dir"`, `"symlink"` and
+    ///     `"other"`, and directories an `empty` boolean field. I/O errors
+    ///     also have an `op` field
+    ///     (`"stat"`, `"create_dir"`, `"read_dir"`, `"rename"`, `"copy"`,
+    ///     `"remove"` or `"lock"`), the `path` it was made on, and an
+    ///     `io_kind` field,
+    ///     such as `"not_found"` or `"permission_denied"`, or `"other"` for
+    ///     kinds without a dedicated name. Symbolic link errors also have a
+    ///     `link` field, and missing base directories a `base_dir` field.
+    ///     Kind mismatches also have `expected` and `actual` fields, among
+    ///     `"file"`, `"dir"`, `"symlink"`, `"other"` and `"any"`.
+    ///     Merge failures also have a `child` field, an object with the
+    ///     `source`, `target` and `error` of the child that failed. Current
+    ///     directory errors also have a `current_dir` field. Permission
+    ///     denials also have `reason` (`"source_dir_not_writable"`,
+    ///     `"target_dir_not_writable"`, `"sticky_dir"` or
+    ///     `"target_dir_not_searchable"`), `dir` and `io_kind` fields.
+    ///     Source changes also have a `field` field (`"size"`, `"modified"`
+    ///     or `"digest"`). Held locks also have the
+    ///     `path` of the lock file, and the `pid` of its holder and when it
+    ///     took the lock, in `started_secs` since the Unix epoch, or `null`
+    ///     if not recorded. Insufficient space errors also have the target
+    ///     `path` whose filesystem was checked, and the `required` and
+    ///     `available` numbers of bytes on it.
+    ///   - `tag`: the tag of the operation, if it is
+    ///     [serializable](crate::Tag::serializable).
+    ///   - `created_dirs`: the directories created for the operation, as in
+    ///     the top-level `created_dirs` field.
+    ///   - `digest`: the digest of the renamed file, in hexadecimal, if
+    ///     recorded with the `digest` feature.
+    /// - `created_dirs`: an array of objects with a `path` field and a `mode`
+    ///   field, the mode set on the directory as a number, or `null`.
+    /// - `rewritten_links`: an array of objects with `path`, `old_target` and
+    ///   `new_target` fields.
+    /// - `warnings`: an array of objects with a `kind` field
+    ///   (`"parent_mtime"`, `"symlinked_parent"`, `"progress_file"`,
+    ///   `"audit_log"`, `"ls_colors"`, `"dangling_symlink"`,
+    ///   `"symlink_scan"`, `"digest"`, `"unused_dir"`, `"source_changed"` or
+    ///   `"parked_source"`),
+    ///   a `path` field,
+    ///   except for audit log and `LS_COLORS` warnings, and a human-readable
+    ///   `message` field. Symbolic
+    ///   link warnings also have a `link` field, and their `path` is the
+    ///   target path of the operation. Dangling symbolic link warnings also
+    ///   have an `old_target` field, the content of the link. Source change
+    ///   warnings also have a `field` field, as source change errors do.
+    ///   Parked source warnings also have a `temp_path` field.
+    pub fn to_json_value(&self) -> Value {
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let (status, error) = match &entry.status {
+                    RenameStatus::Applied => ("applied", Value::Null),
+                    RenameStatus::Merged => ("merged", Value::Null),
+                    RenameStatus::Skipped => ("skipped", Value::Null),
+                    RenameStatus::AlreadyApplied => ("already_applied", Value::Null),
+                    RenameStatus::Deferred => ("deferred", Value::Null),
+                    RenameStatus::Failed(details) => ("failed", error_to_json(details)),
+                    RenameStatus::Pending => ("pending", Value::Null),
+                };
+                let mut entry_json = json!({
+                    "source": path_to_json(&entry.source),
+                    "target": path_to_json(&entry.target),
+                    "status": status,
+                    "duration_secs": entry.duration.map(|duration| duration.as_secs_f64()),
+                    "error": error,
+                    "created_dirs": created_dirs_to_json(&entry.created_dirs),
+                });
+                insert_tag(&mut entry_json, entry.tag.as_ref());
+                #[cfg(feature = "digest")]
+                if let Some(digest) = &entry.digest {
+                    entry_json["digest"] = digest.to_string().into();
+                }
+                entry_json
+            })
+            .collect();
+        let rewritten_links: Vec<Value> = self
+            .rewritten_links
+            .iter()
+            .map(|link| {
+                json!({
+                    "path": path_to_json(&link.path),
+                    "old_target": path_to_json(&link.old_target),
+                    "new_target": path_to_json(&link.new_target),
+                })
+            })
+            .collect();
+        let warnings: Vec<Value> = self.warnings.iter().map(warning_to_json).collect();
+        json!({
+            "version": JSON_VERSION,
+            "nominal_version": NOMINAL_VERSION,
+            "entries": entries,
+            "created_dirs": created_dirs_to_json(&self.created_dirs),
+            "rewritten_links": rewritten_links,
+            "warnings": warnings,
+        })
+    }
+}
+
+impl UndoStack {
+    /// Returns the JSON representation of the stack.
+    ///
+    /// The representation is an object with the following fields:
+    ///
+    /// - `version` and `nominal_version`: as in [`Plan::to_json_value`].
+    /// - `plans`: an array of the recorded plans, from the first recorded,
+    ///   each an array of objects with `source` and `target` fields, in
+    ///   apply order, and `tag` fields as in [`Plan::to_json_value`]. Paths
+    ///   are encoded as in [`Plan::to_json_value`]. With the `digest`
+    ///   feature, operations also have a `digest` field, the digest of the
+    ///   renamed file in hexadecimal, if recorded, and a `digest_max_size`
+    ///   field, the maximum size of the files digested, if any.
+    pub fn to_json_value(&self) -> Value {
+        #[cfg_attr(not(feature = "digest"), allow(unused_mut))]
+        let mut plans: Vec<Vec<Value>> = (self.records.iter())
+            .map(|record| {
+                (record.iter())
+                    .map(|rename| {
+                        let mut operation = json!({
+                            "source": path_to_json(&rename.source),
+                            "target": path_to_json(&rename.target),
+                        });
+                        insert_tag(&mut operation, rename.tag.as_ref());
+                        operation
+                    })
+                    .collect()
+            })
+            .collect();
+        #[cfg(feature = "digest")]
+        for (plan, digests) in plans.iter_mut().zip(&self.digests) {
+            for (operation, recorded) in plan.iter_mut().zip(digests) {
+                if let Some(recorded) = recorded {
+                    operation["digest"] = recorded.digest.to_string().into();
+                    if recorded.max_size != u64::MAX {
+                        operation["digest_max_size"] = recorded.max_size.into();
+                    }
+                }
+            }
+        }
+        json!({
+            "version": JSON_VERSION,
+            "nominal_version": NOMINAL_VERSION,
+            "plans": plans,
+        })
+    }
+
+    /// Restores a stack from its JSON representation.
+    ///
+    /// See [`to_json_value`](Self::to_json_value) for the representation.
+    /// Tags are restored as [serializable](crate::Tag::serializable) tags
+    /// holding a [`Value`]. Without the `digest` feature, stacks with
+    /// digests are rejected, since the digests could not be checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::UndoStack;
+    /// let json = serde_json::json!({
+    ///     "version": 1,
+    ///     "plans": [[{ "source": "old.txt", "target": "new.txt" }]],
+    /// });
+    /// let undo_stack = UndoStack::from_json_value(&json)?;
+    /// assert_eq!(undo_stack.len(), 1);
+    /// assert_eq!(undo_stack.to_json_value()["plans"], json["plans"]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_json_value(value: &Value) -> Result<Self, serde_json::Error> {
+        let invalid = |what| serde_json::Error::custom(format!("invalid undo stack: {what}"));
+        check_version(value).map_err(invalid)?;
+        let plans = value["plans"]
+            .as_array()
+            .ok_or_else(|| invalid("missing plans".to_owned()))?;
+        let mut undo_stack = Self::new();
+        for plan in plans {
+            let operations = plan
+                .as_array()
+                .ok_or_else(|| invalid("plan is not an array".to_owned()))?;
+            let mut record = Vec::with_capacity(operations.len());
+            #[cfg(feature = "digest")]
+            let mut digests = Vec::with_capacity(operations.len());
+            for operation in operations {
+                // Empty paths could not have been renamed.
+                let path = |field| {
+                    path_from_json(&operation[field])
+                        .filter(|path| !path.as_os_str().is_empty())
+                        .ok_or_else(|| invalid(format!("invalid {field} path")))
+                };
+                let rename = Rename::new(path("source")?, path("target")?);
+                record.push(match operation.get("tag") {
+                    Some(tag) => rename.with_tag(Tag::serializable(tag.clone())),
+                    None => rename,
+                });
+                match operation.get("digest") {
+                    #[cfg(feature = "digest")]
+                    Some(digest) => digests.push(Some(RecordedDigest {
+                        digest: (digest.as_str())
+                            .and_then(crate::digest::Digest::from_hex)
+                            .ok_or_else(|| invalid("invalid digest".to_owned()))?,
+                        max_size: match operation.get("digest_max_size") {
+                            Some(max_size) => max_size
+                                .as_u64()
+                                .ok_or_else(|| invalid("invalid digest max size".to_owned()))?,
+                            None => u64::MAX,
+                        },
+                    })),
+                    #[cfg(not(feature = "digest"))]
+                    Some(_) => {
+                        return Err(invalid("digests require the digest feature".to_owned()))
+                    }
+                    None => {
+                        #[cfg(feature = "digest")]
+                        digests.push(None);
+                    }
+                }
+            }
+            undo_stack.records.push(record);
+            #[cfg(feature = "digest")]
+            undo_stack.digests.push(digests);
+        }
+        Ok(undo_stack)
+    }
+}
+
+impl Serialize for UndoStack {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_json_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UndoStack {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json_value(&value).map_err(D::Error::custom)
+    }
+}
+
+impl PlanOptions {
+    /// Returns the JSON representation of the options.
+    ///
+    /// The representation is an object holding the explicitly set options
+    /// only, so that it can be [merged](Self::merge) over other options once
+    /// read back. Its fields are named after the setters:
+    ///
+    /// - `same_directory`, `resolve_identity`, `stable_bytewise_order`,
+    ///   `probe_case_sensitivity`, `coalesce_chains`, `assume_sorted` and
+    ///   `stamp_sources`: booleans.
+    /// - `execution_order`: one of `target_sorted`, `source_dir_grouped` and
+    ///   `unchanged`.
+    /// - `ordering`: `bytewise`, or `unicode` with the `unicode` feature.
+    /// - `validation_profile`: one of `native` and `smb_compatible`.
+    /// - `on_stat_error`: one of `fail`, `assume_missing` and `skip`.
+    /// - `stamp_digests`: a maximum size, in bytes, with the `digest`
+    ///   feature.
+    pub fn to_json_value(&self) -> Value {
+        let mut fields = OptionFields::new(&self.explicit);
+        fields.insert("same_directory", self.same_directory);
+        fields.insert(
+            "execution_order",
+            match self.execution_order {
+                ExecutionOrder::TargetSorted => "target_sorted",
+                ExecutionOrder::SourceDirGrouped => "source_dir_grouped",
+                ExecutionOrder::Unchanged => "unchanged",
+            },
+        );
+        fields.insert("resolve_identity", self.resolve_identity);
+        fields.insert("stable_bytewise_order", self.stable_bytewise_order);
+        if let Some(ordering) = self.ordering {
+            fields.insert(
+                "ordering",
+                match ordering {
+                    #[cfg(feature = "unicode")]
+                    PlanOrdering::Unicode => "unicode",
+                    PlanOrdering::Bytewise => "bytewise",
+                },
+            );
+        }
+        fields.insert("probe_case_sensitivity", self.probe_case_sensitivity);
+        fields.insert("coalesce_chains", self.coalesce_chains);
+        fields.insert("assume_sorted", self.assume_sorted);
+        fields.insert("stamp_sources", self.stamp_sources);
+        fields.insert(
+            "validation_profile",
+            match self.validation_profile {
+                ValidationProfile::Native => "native",
+                ValidationProfile::SmbCompatible => "smb_compatible",
+            },
+        );
+        fields.insert("on_stat_error", stat_error_policy_name(self.on_stat_error));
+        #[cfg(feature = "digest")]
+        if let Some(max_size) = self.stamp_digest_max_size {
+            fields.insert("stamp_digests", max_size);
+        }
+        fields.into_value()
+    }
+
+    /// Reads options from their JSON representation.
+    ///
+    /// See [`to_json_value`](Self::to_json_value) for the representation.
+    /// The options present are explicitly set, the others are left to their
+    /// default. Unknown fields are an error if `deny_unknown_fields` is
+    /// `true`, and are otherwise ignored with a warning. Deserializing with
+    /// serde denies unknown fields.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::PlanOptions;
+    /// # use serde_json::json;
+    /// let config = json!({ "same_directory": true, "colour": "auto" });
+    /// assert!(PlanOptions::from_json_value(&config, true).is_err());
+    ///
+    /// let options = PlanOptions::from_json_value(&config, false)?;
+    /// assert_eq!(options.to_json_value(), json!({ "same_directory": true }));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_json_value(
+        value: &Value,
+        deny_unknown_fields: bool,
+    ) -> Result<Self, serde_json::Error> {
+        let fields = option_fields(
+            value,
+            "plan options",
+            &[
+                "same_directory",
+                "execution_order",
+                "resolve_identity",
+                "stable_bytewise_order",
+                "ordering",
+                "probe_case_sensitivity",
+                "coalesce_chains",
+                "assume_sorted",
+                "stamp_sources",
+                "validation_profile",
+                "on_stat_error",
+                "stamp_digests",
+            ],
+            deny_unknown_fields,
+        )?;
+        let mut options = Self::new();
+        if let Some(value) = fields.bool("same_directory")? {
+            options = options.same_directory(value);
+        }
+        if let Some(value) = fields.get("execution_order") {
+            options = options.execution_order(match value.as_str() {
+                Some("target_sorted") => ExecutionOrder::TargetSorted,
+                Some("source_dir_grouped") => ExecutionOrder::SourceDirGrouped,
+                Some("unchanged") => ExecutionOrder::Unchanged,
+                _ => return Err(fields.invalid("execution_order")),
+            });
+        }
+        if let Some(value) = fields.bool("resolve_identity")? {
+            options = options.resolve_identity(value);
+        }
+        if let Some(value) = fields.bool("stable_bytewise_order")? {
+            options = options.stable_bytewise_order(value);
+        }
+        if let Some(value) = fields.get("ordering") {
+            options = options.ordering(match value.as_str() {
+                #[cfg(feature = "unicode")]
+                Some("unicode") => PlanOrdering::Unicode,
+                Some("bytewise") => PlanOrdering::Bytewise,
+                _ => return Err(fields.invalid("ordering")),
+            });
+        }
+        if let Some(value) = fields.bool("probe_case_sensitivity")? {
+            options = options.probe_case_sensitivity(value);
+        }
+        if let Some(value) = fields.bool("coalesce_chains")? {
+            options = options.coalesce_chains(value);
+        }
+        if let Some(value) = fields.bool("assume_sorted")? {
+            options = options.assume_sorted(value);
+        }
+        if let Some(value) = fields.bool("stamp_sources")? {
+            options = options.stamp_sources(value);
+        }
+        if let Some(value) = fields.get("validation_profile") {
+            options = options.validation_profile(match value.as_str() {
+                Some("native") => ValidationProfile::Native,
+                Some("smb_compatible") => ValidationProfile::SmbCompatible,
+                _ => return Err(fields.invalid("validation_profile")),
+            });
+        }
+        if let Some(value) = fields.get("on_stat_error") {
+            let policy = stat_error_policy_from_json(value)
+                .ok_or_else(|| fields.invalid("on_stat_error"))?;
+            options = options.on_stat_error(policy);
+        }
+        if let Some(value) = fields.get("stamp_digests") {
+            #[cfg(feature = "digest")]
+            {
+                let max_size = value
+                    .as_u64()
+                    .ok_or_else(|| fields.invalid("stamp_digests"))?;
+                options = options.stamp_digests(max_size);
+            }
+            // Digests cannot be honored without the feature.
+            #[cfg(not(feature = "digest"))]
+            {
+                let _ = value;
+                return Err(fields.invalid("stamp_digests"));
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl Serialize for PlanOptions {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_json_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PlanOptions {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json_value(&value, true).map_err(D::Error::custom)
+    }
+}
+
+impl ApplyOptions<'_> {
+    /// Returns the JSON representation of the options.
+    ///
+    /// As in [`PlanOptions::to_json_value`], the representation is an object
+    /// holding the explicitly set options only, named after the setters:
+    ///
+    /// - `continue_on_error`, `preserve_parent_mtimes`,
+    ///   `fix_renamed_symlinks`, `remove_unused_dirs`,
+    ///   `deny_symlinked_parents`, `no_symlink_traversal`,
+    ///   `allow_renaming_current_dir`, `idempotent`, `windows_defer_in_use`,
+    ///   `copy_across_devices`, `skip_free_space_check` and `force_lock`:
+    ///   booleans.
+    /// - `boundary`: one of `same_filesystem`, `same_git_repo` and `none`.
+    /// - `operation_timeout`: a number of seconds.
+    /// - `throttle`: an object with a `max_ops_per_sec` field, a number or
+    ///   `null`, and a `pause_every` field, `null` or an object with `every`
+    ///   and `secs` fields, a number of operations and of seconds.
+    /// - `fix_symlinks_under` and `staging_dir`: paths, encoded as in
+    ///   [`Plan::to_json_value`].
+    /// - `dir_permissions`: a mode, or `null`.
+    /// - `expect`: one of `file`, `dir`, `symlink`, `other` and `any`.
+    /// - `follow_symlinks`: one of `no` and `yes`.
+    /// - `on_stat_error`: one of `fail`, `assume_missing` and `skip`.
+    /// - `dir_merge`: one of `error`, `merge_into` and `skip`.
+    /// - `on_source_changed`: one of `fail` and `skip`.
+    /// - `lock`: `null`, `common_ancestor`, or an object with a `path` field.
+    /// - `progress_file`: an object with `path` and `every` fields.
+    /// - `record_digests`: a maximum size, in bytes, with the `digest`
+    ///   feature.
+    ///
+    /// The [log](Self::log) callback, the [audit log](Self::audit_log), the
+    /// [temporary namer](Self::temp_namer) and the [metrics](Self::metrics)
+    /// sink are left out.
+    pub fn to_json_value(&self) -> Value {
+        let mut fields = OptionFields::new(&self.explicit);
+        fields.insert(
+            "boundary",
+            match self.boundary {
+                Boundary::SameFilesystem => "same_filesystem",
+                Boundary::SameGitRepo => "same_git_repo",
+                Boundary::None => "none",
+            },
+        );
+        fields.insert("continue_on_error", self.continue_on_error);
+        if let Some(timeout) = self.operation_timeout {
+            fields.insert("operation_timeout", timeout.as_secs_f64());
+        }
+        fields.insert("throttle", throttle_to_json(&self.throttle));
+        fields.insert("preserve_parent_mtimes", self.preserve_parent_mtimes);
+        if let Some(root) = &self.fix_symlinks_under {
+            fields.insert("fix_symlinks_under", path_to_json(root));
+        }
+        if let Some(dir) = &self.staging_dir {
+            fields.insert("staging_dir", path_to_json(dir));
+        }
+        fields.insert("fix_renamed_symlinks", self.fix_renamed_symlinks);
+        fields.insert("dir_permissions", self.dir_permissions);
+        fields.insert("remove_unused_dirs", self.remove_unused_dirs);
+        fields.insert("deny_symlinked_parents", self.deny_symlinked_parents);
+        fields.insert("no_symlink_traversal", self.no_symlink_traversal);
+        fields.insert(
+            "allow_renaming_current_dir",
+            self.allow_renaming_current_dir,
+        );
+        fields.insert("idempotent", self.idempotent);
+        fields.insert("windows_defer_in_use", self.windows_defer_in_use);
+        fields.insert("copy_across_devices", self.copy_across_devices);
+        fields.insert("skip_free_space_check", self.skip_free_space_check);
+        fields.insert(
+            "lock",
+            match &self.lock {
+                None => Value::Null,
+                Some(LockScope::CommonAncestor) => "common_ancestor".into(),
+                Some(LockScope::Path(dir)) => json!({ "path": path_to_json(dir) }),
+            },
+        );
+        fields.insert("force_lock", self.force_lock);
+        fields.insert("expect", source_kind_name(self.expect));
+        fields.insert(
+            "follow_symlinks",
+            match self.follow_symlinks {
+                FollowSymlinks::No => "no",
+                FollowSymlinks::Yes => "yes",
+            },
+        );
+        fields.insert("on_stat_error", stat_error_policy_name(self.on_stat_error));
+        fields.insert(
+            "dir_merge",
+            match self.dir_merge {
+                DirMerge::Error => "error",
+                DirMerge::MergeInto => "merge_into",
+                DirMerge::Skip => "skip",
+            },
+        );
+        fields.insert(
+            "on_source_changed",
+            match self.on_source_changed {
+                SourceChange::Fail => "fail",
+                SourceChange::Skip => "skip",
+            },
+        );
+        if let Some((path, every)) = &self.progress_file {
+            fields.insert(
+                "progress_file",
+                json!({ "path": path_to_json(path), "every": every }),
+            );
+        }
+        #[cfg(feature = "digest")]
+        if let Some(max_size) = self.digest_max_size {
+            fields.insert("record_digests", max_size);
+        }
+        fields.into_value()
+    }
+
+    /// Reads options from their JSON representation.
+    ///
+    /// See [`to_json_value`](Self::to_json_value) for the representation,
+    /// and [`PlanOptions::from_json_value`] for how fields are handled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::ApplyOptions;
+    /// let config = serde_json::json!({
+    ///     "boundary": "same_filesystem",
+    ///     "operation_timeout": 2.5,
+    /// });
+    /// let config = ApplyOptions::from_json_value(&config, true)?;
+    /// let flags = ApplyOptions::new().continue_on_error(true);
+    ///
+    /// let options = config.merge(flags);
+    /// assert_eq!(
+    ///     options.to_json_value(),
+    ///     serde_json::json!({
+    ///         "boundary": "same_filesystem",
+    ///         "continue_on_error": true,
+    ///         "operation_timeout": 2.5,
+    ///     }),
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_json_value(
+        value: &Value,
+        deny_unknown_fields: bool,
+    ) -> Result<Self, serde_json::Error> {
+        let fields = option_fields(
+            value,
+            "apply options",
+            &[
+                "boundary",
+                "continue_on_error",
+                "operation_timeout",
+                "throttle",
+                "preserve_parent_mtimes",
+                "fix_symlinks_under",
+                "staging_dir",
+                "fix_renamed_symlinks",
+                "dir_permissions",
+                "remove_unused_dirs",
+                "deny_symlinked_parents",
+                "no_symlink_traversal",
+                "allow_renaming_current_dir",
+                "idempotent",
+                "windows_defer_in_use",
+                "copy_across_devices",
+                "skip_free_space_check",
+                "lock",
+                "force_lock",
+                "expect",
+                "follow_symlinks",
+                "on_stat_error",
+                "dir_merge",
+                "on_source_changed",
+                "progress_file",
+                "record_digests",
+            ],
+            deny_unknown_fields,
+        )?;
+        let mut options = Self::new();
+        if let Some(value) = fields.get("boundary") {
+            options = options.boundary(match value.as_str() {
+                Some("same_filesystem") => Boundary::SameFilesystem,
+                Some("same_git_repo") => Boundary::SameGitRepo,
+                Some("none") => Boundary::None,
+                _ => return Err(fields.invalid("boundary")),
+            });
+        }
+        if let Some(value) = fields.bool("continue_on_error")? {
+            options = options.continue_on_error(value);
+        }
+        if let Some(value) = fields.get("operation_timeout") {
+            let timeout = value
+                .as_f64()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .ok_or_else(|| fields.invalid("operation_timeout"))?;
+            options = options.operation_timeout(timeout);
+        }
+        if let Some(value) = fields.get("throttle") {
+            options = options
+                .throttle(throttle_from_json(value).ok_or_else(|| fields.invalid("throttle"))?);
+        }
+        if let Some(value) = fields.bool("preserve_parent_mtimes")? {
+            options = options.preserve_parent_mtimes(value);
+        }
+        if let Some(value) = fields.get("fix_symlinks_under") {
+            let root = path_from_json(value).ok_or_else(|| fields.invalid("fix_symlinks_under"))?;
+            options = options.fix_symlinks_under(root);
+        }
+        if let Some(value) = fields.get("staging_dir") {
+            let dir = path_from_json(value).ok_or_else(|| fields.invalid("staging_dir"))?;
+            options = options.staging_dir(dir);
+        }
+        if let Some(value) = fields.bool("fix_renamed_symlinks")? {
+            options = options.fix_renamed_symlinks(value);
+        }
+        if let Some(value) = fields.get("dir_permissions") {
+            let mode = match value {
+                Value::Null => None,
+                value => Some(
+                    value
+                        .as_u64()
+                        .and_then(|mode| u32::try_from(mode).ok())
+                        .ok_or_else(|| fields.invalid("dir_permissions"))?,
+                ),
+            };
+            options = options.dir_permissions(mode);
+        }
+        if let Some(value) = fields.bool("remove_unused_dirs")? {
+            options = options.remove_unused_dirs(value);
+        }
+        if let Some(value) = fields.bool("deny_symlinked_parents")? {
+            options = options.deny_symlinked_parents(value);
+        }
+        if let Some(value) = fields.bool("no_symlink_traversal")? {
+            options = options.no_symlink_traversal(value);
+        }
+        if let Some(value) = fields.bool("allow_renaming_current_dir")? {
+            options = options.allow_renaming_current_dir(value);
+        }
+        if let Some(value) = fields.bool("idempotent")? {
+            options = options.idempotent(value);
+        }
+        if let Some(value) = fields.bool("windows_defer_in_use")? {
+            options = options.windows_defer_in_use(value);
+        }
+        if let Some(value) = fields.bool("copy_across_devices")? {
+            options = options.copy_across_devices(value);
+        }
+        if let Some(value) = fields.bool("skip_free_space_check")? {
+            options = options.skip_free_space_check(value);
+        }
+        if let Some(value) = fields.get("lock") {
+            options = options.lock(match value {
+                Value::Null => None,
+                Value::String(scope) if scope == "common_ancestor" => {
+                    Some(LockScope::CommonAncestor)
+                }
+                value => Some(LockScope::Path(
+                    value
+                        .get("path")
+                        .and_then(path_from_json)
+                        .ok_or_else(|| fields.invalid("lock"))?,
+                )),
+            });
+        }
+        if let Some(value) = fields.bool("force_lock")? {
+            options = options.force_lock(value);
+        }
+        if let Some(value) = fields.get("expect") {
+            options = options.expect(match value.as_str() {
+                Some("file") => SourceKind::File,
+                Some("dir") => SourceKind::Dir,
+                Some("symlink") => SourceKind::Symlink,
+                Some("other") => SourceKind::Other,
+                Some("any") => SourceKind::Any,
+                _ => return Err(fields.invalid("expect")),
+            });
+        }
+        if let Some(value) = fields.get("follow_symlinks") {
+            options = options.follow_symlinks(match value.as_str() {
+                Some("no") => FollowSymlinks::No,
+                Some("yes") => FollowSymlinks::Yes,
+                _ => return Err(fields.invalid("follow_symlinks")),
+            });
+        }
+        if let Some(value) = fields.get("on_stat_error") {
+            let policy = stat_error_policy_from_json(value)
+                .ok_or_else(|| fields.invalid("on_stat_error"))?;
+            options = options.on_stat_error(policy);
+        }
+        if let Some(value) = fields.get("dir_merge") {
+            options = options.dir_merge(match value.as_str() {
+                Some("error") => DirMerge::Error,
+                Some("merge_into") => DirMerge::MergeInto,
+                Some("skip") => DirMerge::Skip,
+                _ => return Err(fields.invalid("dir_merge")),
+            });
+        }
+        if let Some(value) = fields.get("on_source_changed") {
+            options = options.on_source_changed(match value.as_str() {
+                Some("fail") => SourceChange::Fail,
+                Some("skip") => SourceChange::Skip,
+                _ => return Err(fields.invalid("on_source_changed")),
+            });
+        }
+        if let Some(value) = fields.get("progress_file") {
+            let path = path_from_json(&value["path"]);
+            let every = value["every"]
+                .as_u64()
+                .and_then(|every| usize::try_from(every).ok());
+            let (Some(path), Some(every)) = (path, every) else {
+                return Err(fields.invalid("progress_file"));
+            };
+            options = options.progress_file(path, every);
+        }
+        if let Some(value) = fields.get("record_digests") {
+            #[cfg(feature = "digest")]
+            {
+                let max_size = value
+                    .as_u64()
+                    .ok_or_else(|| fields.invalid("record_digests"))?;
+                options = options.record_digests(max_size);
+            }
+            // Digests cannot be honored without the feature.
+            #[cfg(not(feature = "digest"))]
+            {
+                let _ = value;
+                return Err(fields.invalid("record_digests"));
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl Serialize for ApplyOptions<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_json_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ApplyOptions<'_> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json_value(&value, true).map_err(D::Error::custom)
+    }
+}
+
+/// Returns the JSON representation of a stat error policy.
+fn stat_error_policy_name(policy: StatErrorPolicy) -> &'static str {
+    match policy {
+        StatErrorPolicy::Fail => "fail",
+        StatErrorPolicy::AssumeMissing => "assume_missing",
+        StatErrorPolicy::Skip => "skip",
+    }
+}
+
+/// Reads a stat error policy from its JSON representation.
+fn stat_error_policy_from_json(value: &Value) -> Option<StatErrorPolicy> {
+    match value.as_str()? {
+        "fail" => Some(StatErrorPolicy::Fail),
+        "assume_missing" => Some(StatErrorPolicy::AssumeMissing),
+        "skip" => Some(StatErrorPolicy::Skip),
+        _ => None,
+    }
+}
+
+/// Returns the JSON representation of a throttle.
+fn throttle_to_json(throttle: &Throttle) -> Value {
+    json!({
+        "max_ops_per_sec": throttle.max_ops_per_sec,
+        "pause_every": throttle.pause_every.map(|(every, duration)| {
+            json!({ "every": every, "secs": duration.as_secs_f64() })
+        }),
+    })
+}
+
+/// Reads a throttle from its JSON representation, rejecting the throttles
+/// that [`ApplyOptions::throttle`] would panic on.
+fn throttle_from_json(value: &Value) -> Option<Throttle> {
+    let object = value.as_object()?;
+    let max_ops_per_sec = match object.get("max_ops_per_sec") {
+        None | Some(Value::Null) => None,
+        Some(value) => Some(u32::try_from(value.as_u64()?).ok().filter(|&max| max > 0)?),
+    };
+    let pause_every = match object.get("pause_every") {
+        None | Some(Value::Null) => None,
+        Some(value) => {
+            let every = usize::try_from(value["every"].as_u64()?)
+                .ok()
+                .filter(|&every| every > 0)?;
+            let duration = Duration::try_from_secs_f64(value["secs"].as_f64()?).ok()?;
+            Some((every, duration))
+        }
+    };
+    Some(Throttle {
+        max_ops_per_sec,
+        pause_every,
+    })
+}
+
+/// The JSON object of options being written, holding the explicitly set
+/// ones only.
+struct OptionFields<'a> {
+    explicit: &'a Explicit,
+    object: Map<String, Value>,
+}
+
+impl<'a> OptionFields<'a> {
+    fn new(explicit: &'a Explicit) -> Self {
+        Self {
+            explicit,
+            object: Map::new(),
+        }
+    }
+
+    fn insert(&mut self, name: &str, value: impl Into<Value>) {
+        if self.explicit.contains(name) {
+            self.object.insert(name.to_owned(), value.into());
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Object(self.object)
+    }
+}
+
+/// The JSON object of options being read.
+struct OptionObject<'a> {
+    what: &'static str,
+    object: &'a Map<String, Value>,
+}
+
+impl OptionObject<'_> {
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.object.get(name)
+    }
+
+    fn bool(&self, name: &str) -> Result<Option<bool>, serde_json::Error> {
+        self.get(name)
+            .map(|value| value.as_bool().ok_or_else(|| self.invalid(name)))
+            .transpose()
+    }
+
+    fn invalid(&self, name: &str) -> serde_json::Error {
+        serde_json::Error::custom(format!("invalid {}: invalid {name}", self.what))
+    }
+}
+
+/// Checks the format version of a JSON representation, and returns why it
+/// cannot be read if it is not supported.
+fn check_version(value: &Value) -> Result<(), String> {
+    match value["version"].as_u64() {
+        Some(JSON_VERSION) => Ok(()),
+        Some(version) => {
+            let writer = match value["nominal_version"].as_str() {
+                Some(writer) => format!("nominal {writer}"),
+                None => "an unknown version of nominal".to_owned(),
+            };
+            Err(format!(
+                "unsupported format version {version}, written by {writer}; \
+                 nominal {NOMINAL_VERSION} only reads format version {JSON_VERSION}"
+            ))
+        }
+        None => Err("missing version".to_owned()),
+    }
+}
+
+/// Checks that a JSON value is an object of options, with known fields only
+/// if `deny_unknown_fields` is `true`.
+fn option_fields<'a>(
+    value: &'a Value,
+    what: &'static str,
+    known: &[&str],
+    deny_unknown_fields: bool,
+) -> Result<OptionObject<'a>, serde_json::Error> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| serde_json::Error::custom(format!("invalid {what}: not an object")))?;
+    for name in object.keys() {
+        if known.contains(&name.as_str()) {
+            continue;
+        }
+        if deny_unknown_fields {
+            return Err(serde_json::Error::custom(format!(
+                "invalid {what}: unknown field {name}"
+            )));
+        }
+        warn!("ignoring unknown field {} in {}", name, what);
+    }
+    Ok(OptionObject { what, object })
+}
+
+/// Adds the tag of an operation to its JSON object, if it is serializable.
+fn insert_tag(object: &mut Value, tag: Option<&Tag>) {
+    if let Some(tag) = tag.and_then(Tag::to_json) {
+        object["tag"] = tag;
+    }
+}
+
+pub(crate) fn path_to_json(path: &Path) -> Value {
+    if let Some(path) = path.to_str() {
+        return Value::from(path);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        json!({ "bytes": path.as_os_str().as_bytes() })
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+        json!({ "wide": wide })
+    }
+}
+
+/// Decodes a path encoded with [`path_to_json`].
+fn path_from_json(value: &Value) -> Option<PathBuf> {
+    if let Some(path) = value.as_str() {
+        return Some(PathBuf::from(path));
+    }
+    #[cfg(unix)]
+    {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+        let bytes = value.get("bytes")?.as_array()?;
+        let bytes = bytes
+            .iter()
+            .map(|byte| u8::try_from(byte.as_u64()?).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(OsString::from_vec(bytes).into())
+    }
+    #[cfg(windows)]
+    {
+        use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+        let wide = value.get("wide")?.as_array()?;
+        let wide = wide
+            .iter()
+            .map(|unit| u16::try_from(unit.as_u64()?).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(OsString::from_wide(&wide).into())
+    }
+}
+
+fn created_dirs_to_json(created_dirs: &[CreatedDir]) -> Value {
+    created_dirs
+        .iter()
+        .map(|dir| {
+            json!({
+                "path": path_to_json(&dir.path),
+                "mode": dir.mode,
+            })
+        })
+        .collect()
+}
+
+fn error_to_json(details: &ApplyErrorDetails) -> Value {
+    match details {
+        ApplyErrorDetails::TargetExists { kind } => {
+            let mut error = json!({
+                "kind": "target_exists",
+                "target_kind": match kind {
+                    TargetKind::File => "file",
+                    TargetKind::Dir { .. } => "dir",
+                    TargetKind::Symlink => "symlink",
+                    TargetKind::Other => "other",
+                },
+                "message": details.to_string(),
+            });
+            if let TargetKind::Dir { empty } = kind {
+                error["empty"] = (*empty).into();
+            }
+            error
+        }
+        ApplyErrorDetails::Io { op, path, error } => json!({
+            "kind": "io",
+            "op": match op {
+                IoOp::Stat => "stat",
+                IoOp::CreateDir => "create_dir",
+                IoOp::ReadDir => "read_dir",
+                IoOp::Rename => "rename",
+                IoOp::Copy => "copy",
+                IoOp::Remove => "remove",
+                IoOp::Lock => "lock",
+            },
+            "path": path_to_json(path),
+            "io_kind": io_kind_name(error.kind()),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::CrossesBoundary(_) => json!({
+            "kind": "crosses_boundary",
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::InsufficientSpace(shortage) => json!({
+            "kind": "insufficient_space",
+            "path": path_to_json(&shortage.path),
+            "required": shortage.required,
+            "available": shortage.available,
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::TimedOut(_) => json!({
+            "kind": "timed_out",
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::SymlinkedParent(link) => json!({
+            "kind": "symlinked_parent",
+            "link": path_to_json(link),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::MissingBaseDir(dir) => json!({
+            "kind": "missing_base_dir",
+            "base_dir": path_to_json(dir),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::SymlinkTraversal(link) => json!({
+            "kind": "symlink_traversal",
+            "link": path_to_json(link),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::InvalidTarget(_) => json!({
+            "kind": "invalid_target",
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::EmptySource => json!({
+            "kind": "empty_source",
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::KindMismatch { expected, actual } => json!({
+            "kind": "kind_mismatch",
+            "expected": source_kind_name(*expected),
+            "actual": source_kind_name(*actual),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::MergeFailed(err) => json!({
+            "kind": "merge_failed",
+            "child": {
+                "source": path_to_json(&err.source),
+                "target": path_to_json(&err.target),
+                "error": error_to_json(&err.details),
+            },
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::ContentChanged => json!({
+            "kind": "content_changed",
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::SourceChanged(field) => json!({
+            "kind": "source_changed",
+            "field": stamp_field_name(*field),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::PermissionDenied { reason, dir, error } => json!({
+            "kind": "permission_denied",
+            "reason": match reason {
+                PermissionReason::SourceDirNotWritable => "source_dir_not_writable",
+                PermissionReason::TargetDirNotWritable => "target_dir_not_writable",
+                PermissionReason::StickyDir => "sticky_dir",
+                PermissionReason::TargetDirNotSearchable => "target_dir_not_searchable",
+            },
+            "dir": path_to_json(dir),
+            "io_kind": io_kind_name(error.kind()),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::ContainsCurrentDir(dir) => json!({
+            "kind": "contains_current_dir",
+            "current_dir": path_to_json(dir),
+            "message": details.to_string(),
+        }),
+        ApplyErrorDetails::LockHeld(holder) => json!({
+            "kind": "lock_held",
+            "path": path_to_json(&holder.path),
+            "pid": holder.pid,
+            "started_secs": holder.started
+                .and_then(|started| started.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|started| started.as_secs()),
+            "message": details.to_string(),
+        }),
+    }
+}
+
+fn warning_to_json(warning: &Warning) -> Value {
+    match warning {
+        Warning::ParentMtime { path, .. } => json!({
+            "kind": "parent_mtime",
+            "path": path_to_json(path),
+            "message": warning.to_string(),
+        }),
+        Warning::SymlinkedParent { target, link } => json!({
+            "kind": "symlinked_parent",
+            "path": path_to_json(target),
+            "link": path_to_json(link),
+            "message": warning.to_string(),
+        }),
+        Warning::ProgressFile { path, .. } => json!({
+            "kind": "progress_file",
+            "path": path_to_json(path),
+            "message": warning.to_string(),
+        }),
+        Warning::AuditLog { .. } => json!({
+            "kind": "audit_log",
+            "message": warning.to_string(),
+        }),
+        #[cfg(feature = "ansi")]
+        Warning::LsColors => json!({
+            "kind": "ls_colors",
+            "message": warning.to_string(),
+        }),
+        Warning::DanglingSymlink { path, old_target } => json!({
+            "kind": "dangling_symlink",
+            "path": path_to_json(path),
+            "old_target": path_to_json(old_target),
+            "message": warning.to_string(),
+        }),
+        Warning::SymlinkScan { path, .. } => json!({
+            "kind": "symlink_scan",
+            "path": path_to_json(path),
+            "message": warning.to_string(),
+        }),
+        #[cfg(feature = "digest")]
+        Warning::Digest { path, .. } => json!({
+            "kind": "digest",
+            "path": path_to_json(path),
+            "message": warning.to_string(),
+        }),
+        Warning::UnusedDir { path, .. } => json!({
+            "kind": "unused_dir",
+            "path": path_to_json(path),
+            "message": warning.to_string(),
+        }),
+        Warning::SourceChanged { path, field } => json!({
+            "kind": "source_changed",
+            "path": path_to_json(path),
+            "field": stamp_field_name(*field),
+            "message": warning.to_string(),
+        }),
+        Warning::ParkedSource {
+            path, temp_path, ..
+        } => json!({
+            "kind": "parked_source",
+            "path": path_to_json(path),
+            "temp_path": path_to_json(temp_path),
+            "message": warning.to_string(),
+        }),
+    }
+}
+
+fn source_kind_name(kind: SourceKind) -> &'static str {
+    match kind {
+        SourceKind::File => "file",
+        SourceKind::Dir => "dir",
+        SourceKind::Symlink => "symlink",
+        SourceKind::Other => "other",
+        SourceKind::Any => "any",
+    }
+}
+
+fn stamp_to_json(stamp: &SourceStamp) -> Value {
+    let modified = (stamp.modified)
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+    let value = json!({ "size": stamp.size, "modified": modified });
+    #[cfg(feature = "digest")]
+    let value = {
+        let mut value = value;
+        value["digest"] = (stamp.digest).map_or(Value::Null, |digest| digest.to_string().into());
+        value
+    };
+    value
+}
+
+fn stamp_field_name(field: StampField) -> &'static str {
+    match field {
+        StampField::Size => "size",
+        StampField::Modified => "modified",
+        StampField::Digest => "digest",
+    }
+}
+
+fn io_kind_name(kind: io::ErrorKind) -> &'static str {
+    match kind {
+        io::ErrorKind::NotFound => "not_found",
+        io::ErrorKind::PermissionDenied => "permission_denied",
+        io::ErrorKind::AlreadyExists => "already_exists",
+        io::ErrorKind::Interrupted => "interrupted",
+        io::ErrorKind::InvalidInput => "invalid_input",
+        io::ErrorKind::TimedOut => "timed_out",
+        io::ErrorKind::Unsupported => "unsupported",
+        io::ErrorKind::NotADirectory => "not_a_directory",
+        io::ErrorKind::IsADirectory => "is_a_directory",
+        io::ErrorKind::DirectoryNotEmpty => "directory_not_empty",
+        io::ErrorKind::ReadOnlyFilesystem => "read_only_filesystem",
+        io::ErrorKind::StorageFull => "storage_full",
+        io::ErrorKind::CrossesDevices => "crosses_devices",
+        io::ErrorKind::InvalidFilename => "invalid_filename",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::PathBuf, time::Duration};
+
+    use serde_json::json;
+
+    use crate::{
+        error::{ApplyErrorDetails, IoOp, TargetKind},
+        operation::Rename,
+        options::{ApplyOptions, PlanOptions, SourceKind},
+        report::{ApplyReport, CreatedDir, RenameStatus, ReportEntry},
+        symlinks::RewrittenLink,
+        tag::Tag,
+        undo::UndoStack,
+        warning::Warning,
+        Renamer,
+    };
+
+    #[test]
+    fn plan_schema() {
+        let mut renamer = Renamer::new();
+        renamer.add("a/old.txt", "a/new.txt");
+        renamer.add_rename(Rename::new("b", "c").with_tag(Tag::serializable(json!({ "id": 1 }))));
+        renamer.add_tagged("d", "e", "opaque");
+        renamer.add_rename(Rename::new("f", "f").with_tag(Tag::serializable(2)));
+        let plan = renamer.plan().unwrap().with_base_dir("/base");
+        assert_eq!(
+            plan.to_json_value(),
+            json!({
+                "version": 1,
+                "nominal_version": super::NOMINAL_VERSION,
+                "operations": [
+                    { "source": "a/old.txt", "target": "a/new.txt" },
+                    { "source": "b", "target": "c", "tag": { "id": 1 } },
+                    { "source": "d", "target": "e" },
+                ],
+                "skipped": [
+                    { "source": "f", "target": "f", "reason": "noop", "tag": 2 },
+                ],
+                "base_dir": "/base",
+            })
+        );
+    }
+
+    #[test]
+    fn report_schema() {
+        let entry = |source: &str, target: &str, status| ReportEntry {
+            source: PathBuf::from(source),
+            target: PathBuf::from(target),
+            duration: match status {
+                RenameStatus::Pending => None,
+                _ => Some(Duration::from_millis(500)),
+            },
+            status,
+            tag: None,
+            index: 0,
+            created_dirs: Vec::new(),
+            #[cfg(feature = "digest")]
+            digest: None,
+        };
+        let report = ApplyReport {
+            entries: vec![
+                entry("a", "b", RenameStatus::Applied),
+                entry(
+                    "c",
+                    "d",
+                    RenameStatus::Failed(ApplyErrorDetails::TargetExists {
+                        kind: TargetKind::Dir { empty: false },
+                    }),
+                ),
+                entry(
+                    "e",
+                    "f",
+                    RenameStatus::Failed(ApplyErrorDetails::io(
+                        IoOp::CreateDir,
+                        "out",
+                        io::ErrorKind::NotFound.into(),
+                    )),
+                ),
+                entry("g", "h", RenameStatus::Pending),
+            ],
+            created_dirs: vec![CreatedDir {
+                path: PathBuf::from("dir"),
+                mode: Some(0o775),
+            }],
+            rewritten_links: vec![RewrittenLink {
+                path: PathBuf::from("link"),
+                old_target: PathBuf::from("b"),
+                new_target: PathBuf::from("c"),
+            }],
+            warnings: vec![Warning::ParentMtime {
+                path: PathBuf::from("x"),
+                error: io::ErrorKind::PermissionDenied.into(),
+            }],
+            #[cfg(feature = "digest")]
+            digest_max_size: None,
+        };
+        assert_eq!(
+            report.to_json_value(),
+            json!({
+                "version": 1,
+                "nominal_version": super::NOMINAL_VERSION,
+                "entries": [
+                    {
+                        "source": "a",
+                        "target": "b",
+                        "status": "applied",
+                        "duration_secs": 0.5,
+                        "error": null,
+                        "created_dirs": [],
+                    },
+                    {
+                        "source": "c",
+                        "target": "d",
+                        "status": "failed",
+                        "duration_secs": 0.5,
+                        "error": {
+                            "kind": "target_exists",
+                            "target_kind": "dir",
+                            "empty": false,
+                            "message": "target already exists (non-empty directory)",
+                        },
+                        "created_dirs": [],
+                    },
+                    {
+                        "source": "e",
+                        "target": "f",
+                        "status": "failed",
+                        "duration_secs": 0.5,
+                        "error": {
+                            "kind": "io",
+                            "op": "create_dir",
+                            "path": "out",
+                            "io_kind": "not_found",
+                            "message": "could not create parent directory \"out\": entity not found",
+                        },
+                        "created_dirs": [],
+                    },
+                    {
+                        "source": "g",
+                        "target": "h",
+                        "status": "pending",
+                        "duration_secs": null,
+                        "error": null,
+                        "created_dirs": [],
+                    },
+                ],
+                "created_dirs": [
+                    { "path": "dir", "mode": 0o775 },
+                ],
+                "rewritten_links": [
+                    { "path": "link", "old_target": "b", "new_target": "c" },
+                ],
+                "warnings": [
+                    {
+                        "kind": "parent_mtime",
+                        "path": "x",
+                        "message": "could not preserve the modification time of \"x\": permission denied",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path() {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+        let path = PathBuf::from(OsString::from_vec(b"caf\xe9".to_vec()));
+        let json = super::path_to_json(&path);
+        assert_eq!(json, json!({ "bytes": [0x63, 0x61, 0x66, 0xe9] }));
+        assert_eq!(super::path_from_json(&json).unwrap(), path);
+        assert!(super::path_from_json(&json!({ "bytes": [256] })).is_none());
+
+        // Undo journals keep the paths of the plans they record.
+        let json = json!({
+            "version": 1,
+            "nominal_version": super::NOMINAL_VERSION,
+            "plans": [[{ "source": json, "target": "b" }]],
+        });
+        let undo_stack = UndoStack::from_json_value(&json).unwrap();
+        assert_eq!(undo_stack.to_json_value(), json);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn unpaired_surrogates() {
+        use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf};
+
+        let path = PathBuf::from(OsString::from_wide(&[0x61, 0xd800]));
+        let json = super::path_to_json(&path);
+        assert_eq!(json, json!({ "wide": [0x61, 0xd800] }));
+        assert_eq!(super::path_from_json(&json).unwrap(), path);
+        assert!(super::path_from_json(&json!({ "wide": [0x10000] })).is_none());
+
+        // Undo journals keep the paths of the plans they record.
+        let json = json!({
+            "version": 1,
+            "nominal_version": super::NOMINAL_VERSION,
+            "plans": [[{ "source": json, "target": "b" }]],
+        });
+        let undo_stack = UndoStack::from_json_value(&json).unwrap();
+        assert_eq!(undo_stack.to_json_value(), json);
+    }
+
+    #[test]
+    fn undo_stack_roundtrip() {
+        let json = json!({
+            "version": 1,
+            "nominal_version": super::NOMINAL_VERSION,
+            "plans": [
+                [{ "source": "a", "target": "b" }, { "source": "c", "target": "d" }],
+                [{ "source": "b", "target": "e", "tag": [1, "x"] }],
+            ],
+        });
+        let undo_stack: UndoStack = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(undo_stack.len(), 2);
+        assert_eq!(serde_json::to_value(&undo_stack).unwrap(), json);
+
+        for invalid in [
+            json!({ "version": 2, "plans": [] }),
+            json!({ "version": 1 }),
+            json!({ "version": 1, "plans": [[{ "source": "a" }]] }),
+            json!({ "version": 1, "plans": [[{ "source": "a", "target": "b", "digest": "ab" }]] }),
+        ] {
+            assert!(serde_json::from_value::<UndoStack>(invalid).is_err());
+        }
+
+        // Digests are kept with the feature, and rejected without it.
+        let json = json!({
+            "version": 1,
+            "nominal_version": super::NOMINAL_VERSION,
+            "plans": [[
+                { "source": "a", "target": "b", "digest": "ab".repeat(32) },
+                { "source": "c", "target": "d" },
+                { "source": "e", "target": "f", "digest": "cd".repeat(32), "digest_max_size": 1024 },
+            ]],
+        });
+        let undo_stack = UndoStack::from_json_value(&json);
+        #[cfg(feature = "digest")]
+        assert_eq!(undo_stack.unwrap().to_json_value(), json);
+        #[cfg(not(feature = "digest"))]
+        assert!(undo_stack.is_err());
+    }
+
+    /// Undo stacks written by past and future versions, which must keep
+    /// loading, or be rejected, as they are today.
+    #[test]
+    fn undo_stack_fixtures() {
+        let load = |json: &str| UndoStack::from_json_value(&serde_json::from_str(json).unwrap());
+
+        let undo_stack = load(include_str!("../tests/fixtures/undo-stack-v1.json")).unwrap();
+        assert_eq!(undo_stack.len(), 2);
+        let json = undo_stack.to_json_value();
+        assert_eq!(json["plans"][0][1]["tag"], json!({ "id": 1 }));
+        assert_eq!(json["plans"][1][0]["source"], "caf\u{e9}");
+
+        // Newer minor versions may add fields, which are ignored.
+        let undo_stack = load(include_str!("../tests/fixtures/undo-stack-v1-newer.json")).unwrap();
+        assert_eq!(
+            undo_stack.to_json_value()["plans"],
+            json!([[{ "source": "a", "target": "b" }]])
+        );
+
+        let error = load(include_str!("../tests/fixtures/undo-stack-v2.json")).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("format version 2, written by nominal 2.0.0"));
+        assert!(message.contains(&format!(
+            "nominal {} only reads format version 1",
+            super::NOMINAL_VERSION
+        )));
+    }
+
+    #[test]
+    fn options_roundtrip() {
+        let json = json!({
+            "same_directory": false,
+            "execution_order": "source_dir_grouped",
+            "ordering": "bytewise",
+            "coalesce_chains": true,
+            "validation_profile": "smb_compatible",
+            "on_stat_error": "assume_missing",
+            "stamp_sources": true,
+        });
+        let options: PlanOptions = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&options).unwrap(), json);
+
+        let json = json!({
+            "boundary": "same_git_repo",
+            "operation_timeout": 0.5,
+            "throttle": { "max_ops_per_sec": 50, "pause_every": { "every": 1000, "secs": 1.5 } },
+            "fix_symlinks_under": "/srv",
+            "staging_dir": "/srv/.nominal-staging",
+            "dir_permissions": null,
+            "expect": "dir",
+            "follow_symlinks": "yes",
+            "on_stat_error": "skip",
+            "dir_merge": "merge_into",
+            "on_source_changed": "skip",
+            "idempotent": true,
+            "windows_defer_in_use": true,
+            "copy_across_devices": true,
+            "skip_free_space_check": false,
+            "lock": { "path": "/srv" },
+            "force_lock": true,
+            "progress_file": { "path": "progress.json", "every": 10 },
+        });
+        let options: ApplyOptions = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&options).unwrap(), json);
+
+        // Options set under another name than their field are merged too.
+        #[cfg(feature = "digest")]
+        {
+            let options = ApplyOptions::new().merge(ApplyOptions::new().record_digests(1024));
+            assert_eq!(options.to_json_value(), json!({ "record_digests": 1024 }));
+        }
+
+        // Explicitly set fields win, even when set to their default.
+        let options = options.merge(ApplyOptions::new().expect(SourceKind::Any));
+        assert_eq!(options.to_json_value()["expect"], "any");
+        assert_eq!(options.to_json_value()["dir_merge"], "merge_into");
+
+        for invalid in [
+            json!([]),
+            json!({ "boundary": "elsewhere" }),
+            json!({ "continue_on_error": "yes" }),
+            json!({ "operation_timeout": -1 }),
+            json!({ "throttle": { "max_ops_per_sec": 0 } }),
+            json!({ "throttle": { "pause_every": { "every": 0, "secs": 1 } } }),
+            json!({ "on_source_changed": "retry" }),
+            json!({ "lock": "everything" }),
+            json!({ "progress_file": { "path": "progress.json" } }),
+            json!({ "retries": 3 }),
+        ] {
+            assert!(serde_json::from_value::<ApplyOptions>(invalid).is_err());
+        }
+        let lenient = ApplyOptions::from_json_value(&json!({ "retries": 3 }), false).unwrap();
+        assert_eq!(lenient.to_json_value(), json!({}));
+    }
+}
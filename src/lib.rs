@@ -30,14 +30,128 @@
 //! # Ok::<(), Box<dyn std::error::Error>>(())
 //! ```
 
+#[macro_use]
+mod log;
+
+mod affix;
+mod apply_iter;
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(feature = "serde")]
+mod audit;
+mod capabilities;
+mod casefold;
+mod classify;
+pub mod collate;
+mod conflicts;
+mod cycles;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod diff;
+#[cfg(feature = "digest")]
+mod digest;
+#[cfg(test)]
+mod edge_cases;
+mod emptied;
 mod error;
+mod exchange;
+mod filter;
+mod flatten;
 mod fsutil;
+mod generator;
+mod inspect;
+#[cfg(feature = "serde")]
+mod journal;
+#[cfg(feature = "serde")]
+mod json;
+mod lock;
+mod messages;
+mod metrics;
+mod mtime;
+mod noop;
 mod operation;
+mod options;
+mod parents;
+pub mod path;
 mod plan;
+mod planner;
+mod portable;
+mod preflight;
+#[cfg(feature = "serde")]
+mod progress;
+mod quote;
+mod relocate;
 mod renamer;
+mod report;
+mod session;
+mod sidecar;
+mod sink;
+#[cfg(feature = "sniff")]
+mod sniff;
+mod staging;
+mod stamp;
+mod symlinks;
+mod table;
+mod tag;
+mod tempname;
+mod throttle;
+mod truncate;
+mod undo;
+mod warning;
+#[cfg(windows)]
+mod windows;
 
 pub use self::{
-    error::{ApplyError, ApplyErrorDetails, Error, PlanError},
-    plan::Plan,
+    affix::NameAffix,
+    apply_iter::ApplyIter,
+    capabilities::{capabilities, Capabilities},
+    classify::RenameClass,
+    collate::PlanOrdering,
+    conflicts::{Conflict, ConflictKind, ConflictOptions},
+    diff::{ChangedTarget, PlanDiff},
+    error::{
+        ApplyError, ApplyErrorDetails, Error, InvalidTargetReason, IoOp, PermissionReason,
+        PlanError, SpaceShortage, TargetKind, UndoError,
+    },
+    exchange::Exchange,
+    filter::SourceFilter,
+    flatten::{flatten_into, unflatten_into, FlattenOptions},
+    generator::TargetGenerator,
+    lock::LockHolder,
+    log::LogEvent,
+    messages::Messages,
+    metrics::MetricsSink,
+    noop::{is_noop, NoopOptions},
+    operation::{Applied, Rename, RenameStrategy},
+    options::{
+        ApplyOptions, Boundary, DirMerge, ExecutionOrder, FollowSymlinks, LockScope, PlanOptions,
+        SourceChange, SourceKind, StatErrorPolicy, Throttle, ValidationProfile, WriteOptions,
+    },
+    plan::{Plan, PlanStats, PlanSummary, SkipReason, SkippedEntry},
+    planner::Planner,
+    portable::{NameIssue, SanitizedTarget},
     renamer::Renamer,
+    report::{ApplyReport, CreatedDir, OperationStatus, RenameStatus, ReportEntry},
+    session::Session,
+    sink::RenameSink,
+    staging::{StagedEntry, Staging},
+    stamp::{SourceStamp, StampField},
+    symlinks::RewrittenLink,
+    table::TableOptions,
+    tag::Tag,
+    tempname::{DefaultTempNamer, TempNamer},
+    undo::UndoStack,
+    warning::{TracingSink, Warning, WarningSink},
+};
+
+#[cfg(feature = "digest")]
+pub use self::digest::Digest;
+#[cfg(feature = "confirm")]
+pub use self::options::{ConfirmOptions, PreviewOptions};
+#[cfg(feature = "sniff")]
+pub use self::sniff::{fix_extensions_into, FixExtensionOptions};
+#[cfg(feature = "serde")]
+pub use self::{
+    journal::{Journal, JournalWriter},
+    json::JSON_VERSION,
 };
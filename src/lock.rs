@@ -0,0 +1,318 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    error::{ApplyErrorDetails, IoOp},
+    options::LockScope,
+    path::{common_ancestor, normalize},
+};
+
+/// The name of the lock file, in the locked directory.
+pub(crate) const LOCK_FILE_NAME: &str = ".nominal.lock";
+
+/// How many times a lock file replaced under us is opened again.
+const MAX_ATTEMPTS: usize = 3;
+
+/// The apply holding a lock, as recorded in its lock file.
+///
+/// See [`ApplyErrorDetails::LockHeld`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockHolder {
+    /// The path of the lock file.
+    pub path: PathBuf,
+    /// The process holding the lock, if recorded.
+    pub pid: Option<u32>,
+    /// When the lock was taken, if recorded.
+    pub started: Option<SystemTime>,
+}
+
+/// An exclusive advisory lock on a directory tree, held until dropped.
+///
+/// The lock file records the process holding it and when it was taken, and
+/// is removed once released.
+#[derive(Debug)]
+pub(crate) struct ApplyLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl ApplyLock {
+    /// Takes the lock for the operations with the given paths.
+    ///
+    /// With `force`, a lock held by another apply is broken. A lock whose
+    /// holder is no longer running is always broken.
+    pub(crate) fn acquire<'a, I>(
+        scope: &LockScope,
+        paths: I,
+        force: bool,
+    ) -> Result<Self, ApplyErrorDetails>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let dir = match scope {
+            LockScope::CommonAncestor => common_dir(paths)?,
+            LockScope::Path(dir) => dir.clone(),
+        };
+        let path = dir.join(LOCK_FILE_NAME);
+        let io_err = |error| ApplyErrorDetails::io(IoOp::Lock, &path, error);
+        for _ in 0..MAX_ATTEMPTS {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .map_err(io_err)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    let (pid, started) = read_holder(&mut file);
+                    if !force && pid.is_none_or(is_running) {
+                        let holder = LockHolder { path, pid, started };
+                        return Err(ApplyErrorDetails::LockHeld(Box::new(holder)));
+                    }
+                    debug!("breaking the lock {:?} held by {:?}", path, pid);
+                    // The holder keeps its lock on the removed file.
+                    match fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                        Err(error) => return Err(io_err(error)),
+                    }
+                    continue;
+                }
+                Err(TryLockError::Error(error)) => return Err(io_err(error)),
+            }
+            // The file may have been removed by its previous holder, or broken
+            // by another apply, between opening and locking it.
+            if !is_linked(&file, &path) {
+                continue;
+            }
+            write_holder(&mut file).map_err(io_err)?;
+            return Ok(Self { file, path });
+        }
+        Err(io_err(io::Error::other(
+            "the lock file keeps being replaced",
+        )))
+    }
+}
+
+impl Drop for ApplyLock {
+    fn drop(&mut self) {
+        // The file is removed while locked, so that no other apply locks it
+        // once released, unless the lock was broken and the file is now
+        // another apply's.
+        if is_linked(&self.file, &self.path) {
+            if let Err(err) = fs::remove_file(&self.path) {
+                debug!("could not remove the lock file {:?}: {}", self.path, err);
+            }
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+/// Returns the deepest directory holding every given path.
+fn common_dir<'a, I>(paths: I) -> Result<PathBuf, ApplyErrorDetails>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let mut common: Option<PathBuf> = None;
+    let mut first = None;
+    for path in paths {
+        first.get_or_insert(path);
+        let absolute = std::path::absolute(path)
+            .map_err(|error| ApplyErrorDetails::io(IoOp::Lock, path, error))?;
+        let parent = normalize(&absolute);
+        let parent = parent.parent().unwrap_or(&parent);
+        common = match common {
+            None => Some(parent.to_owned()),
+            Some(common) => match common_ancestor(&common, parent) {
+                Some(ancestor) => Some(ancestor.to_owned()),
+                None => {
+                    let error = io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the operations have no common ancestor to lock",
+                    );
+                    return Err(ApplyErrorDetails::io(IoOp::Lock, path, error));
+                }
+            },
+        };
+    }
+    common.ok_or_else(|| {
+        let error = io::Error::new(io::ErrorKind::InvalidInput, "no operation to lock");
+        ApplyErrorDetails::io(IoOp::Lock, first.unwrap_or(Path::new("")), error)
+    })
+}
+
+/// Tests whether an opened file is still the one at a path.
+fn is_linked(file: &File, path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        match (file.metadata(), path.metadata()) {
+            (Ok(opened), Ok(linked)) => {
+                (opened.dev(), opened.ino()) == (linked.dev(), linked.ino())
+            }
+            _ => false,
+        }
+    }
+    // Open files cannot be replaced on Windows.
+    #[cfg(not(unix))]
+    {
+        let _ = file;
+        path.exists()
+    }
+}
+
+/// Reads the process holding a lock file, and when it took the lock.
+fn read_holder(file: &mut File) -> (Option<u32>, Option<SystemTime>) {
+    let mut content = String::new();
+    if file.read_to_string(&mut content).is_err() {
+        return (None, None);
+    }
+    let mut lines = content.lines();
+    let pid = lines.next().and_then(|line| line.parse().ok());
+    let started = (lines.next())
+        .and_then(|line| line.parse().ok())
+        .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+    (pid, started)
+}
+
+/// Records the current process and time in a locked file.
+fn write_holder(file: &mut File) -> io::Result<()> {
+    let started = (SystemTime::now().duration_since(SystemTime::UNIX_EPOCH))
+        .unwrap_or_default()
+        .as_secs();
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}\n{}\n", process::id(), started)?;
+    file.flush()
+}
+
+/// Tests whether a process is running.
+///
+/// Processes that cannot be checked are assumed to be running.
+fn is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return true;
+        };
+        // SAFETY: signal 0 only checks that the process exists.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        // The process exists, but belongs to another user.
+        io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(windows)]
+    {
+        crate::windows::is_running(pid)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, process};
+
+    use super::{ApplyLock, LOCK_FILE_NAME};
+    use crate::{error::ApplyErrorDetails, ApplyOptions, LockScope, RenameStatus, Renamer};
+
+    #[test]
+    fn lock_held() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+        fs::create_dir(path("dir"))?;
+
+        let scope = LockScope::CommonAncestor;
+        let paths = [path("a"), path("dir/b")];
+        let paths = || paths.iter().map(|path| path.as_path());
+        let lock = ApplyLock::acquire(&scope, paths(), false).unwrap();
+        assert_eq!(lock.path, path(LOCK_FILE_NAME));
+        let content = fs::read_to_string(path(LOCK_FILE_NAME))?;
+        assert!(content.starts_with(&format!("{}\n", process::id())));
+
+        let err = ApplyLock::acquire(&scope, paths(), false).unwrap_err();
+        let ApplyErrorDetails::LockHeld(holder) = &err else {
+            panic!("unexpected error: {:?}", err);
+        };
+        assert_eq!(holder.pid, Some(process::id()));
+        assert!(holder.started.is_some());
+        assert!(err
+            .to_string()
+            .starts_with("another apply is in progress (pid "));
+
+        // The apply fails fast, without renaming anything.
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("dir/b"));
+        renamer.add(path("c"), path("dir/d"));
+        let options = ApplyOptions::new().lock(Some(scope.clone()));
+        let report = renamer.plan().unwrap().apply_with(&options);
+        assert!(matches!(
+            report.entries()[0].status,
+            RenameStatus::Failed(ApplyErrorDetails::LockHeld(_))
+        ));
+        assert!(matches!(report.entries()[1].status, RenameStatus::Pending));
+        assert!(path("a").exists());
+
+        // Forcing breaks the lock, which is removed once released.
+        let forced = ApplyLock::acquire(&scope, paths(), true).unwrap();
+        drop(forced);
+        assert!(!path(LOCK_FILE_NAME).exists());
+        drop(lock);
+        Ok(())
+    }
+
+    #[test]
+    fn broken_lock() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let scope = LockScope::Path(temp_dir.path().to_owned());
+
+        // Releasing a broken lock leaves the lock of the apply that broke it.
+        let lock = ApplyLock::acquire(&scope, [], false).unwrap();
+        let forced = ApplyLock::acquire(&scope, [], true).unwrap();
+        drop(lock);
+        assert!(matches!(
+            ApplyLock::acquire(&scope, [], false),
+            Err(ApplyErrorDetails::LockHeld(_))
+        ));
+        drop(forced);
+        assert!(!temp_dir.path().join(LOCK_FILE_NAME).exists());
+        assert!(ApplyLock::acquire(&scope, [], false).is_ok());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_lock() -> io::Result<()> {
+        use std::fs::File;
+
+        let temp_dir = tempfile::tempdir()?;
+        let scope = LockScope::Path(temp_dir.path().to_owned());
+        let lock_path = temp_dir.path().join(LOCK_FILE_NAME);
+
+        // The lock is held, but its recorded holder exited.
+        let mut child = process::Command::new("true").spawn()?;
+        child.wait()?;
+        fs::write(&lock_path, format!("{}\n0\n", child.id()))?;
+        let held = File::open(&lock_path)?;
+        held.lock()?;
+
+        let lock = ApplyLock::acquire(&scope, [], false).unwrap();
+        assert!(fs::read_to_string(&lock_path)?.starts_with(&format!("{}\n", process::id())));
+        drop(lock);
+        Ok(())
+    }
+}
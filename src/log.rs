@@ -0,0 +1,83 @@
+use std::{fmt, path::Path, time::Duration};
+
+use crate::report::CreatedDir;
+
+/// Emits a debug message through [`tracing`], if enabled.
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+/// Emits a warning message through [`tracing`], if enabled.
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+/// An event reported to the callback set with
+/// [`ApplyOptions::log`](crate::options::ApplyOptions::log).
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum LogEvent<'a> {
+    /// The parent directory of a target is about to be created.
+    CreateParent {
+        /// The target path of the rename operation.
+        target: &'a Path,
+        /// The directory being created.
+        parent: &'a Path,
+    },
+    /// Directories were created for a target, as listed in the
+    /// [report entry](crate::ReportEntry::created_dirs) of the operation.
+    ParentCreated {
+        /// The target path of the rename operation.
+        target: &'a Path,
+        /// The created directories, in creation order.
+        created: &'a [CreatedDir],
+    },
+    /// A source is about to be renamed.
+    Rename {
+        /// The source path of the rename operation.
+        source: &'a Path,
+        /// The target path of the rename operation.
+        target: &'a Path,
+    },
+    /// The apply is about to pause before the next operation, as set by
+    /// [`ApplyOptions::throttle`](crate::options::ApplyOptions::throttle).
+    Pause {
+        /// How long the apply pauses.
+        duration: Duration,
+    },
+}
+
+impl fmt::Display for LogEvent<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogEvent::CreateParent { target, .. } => {
+                write!(f, "creating parent directory for {}", target.display())
+            }
+            LogEvent::ParentCreated { target, created } => {
+                write!(
+                    f,
+                    "created {} parent directories for {}",
+                    created.len(),
+                    target.display()
+                )
+            }
+            LogEvent::Rename { source, target } => {
+                write!(f, "renaming {} to {}", source.display(), target.display())
+            }
+            LogEvent::Pause { duration } => {
+                write!(f, "pausing for {:?} to throttle the renames", duration)
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::{Display, Write as _};
+
+/// The user-facing strings of this crate, to be overridden for localization.
+///
+/// Templates hold positional placeholders, such as `{0}`, replaced with the
+/// arguments documented for each template. Arguments can be reordered or
+/// omitted, and literal braces are written `{{` and `}}`.
+///
+/// Messages are set with
+/// [`WriteOptions::messages`](crate::WriteOptions::messages) and
+#[cfg_attr(
+    feature = "confirm",
+    doc = "[`ConfirmOptions::messages`](crate::ConfirmOptions::messages)."
+)]
+#[cfg_attr(
+    not(feature = "confirm"),
+    doc = "`ConfirmOptions::messages`, with the `confirm` feature."
+)]
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::{Messages, Renamer, WriteOptions};
+/// let mut renamer = Renamer::new();
+/// renamer.add("a.txt", "b.txt");
+/// renamer.add("c.txt", "c.txt");
+/// let plan = renamer.plan()?;
+///
+/// let messages = Messages::new().summary("{1} inchangé(s), {0} à renommer");
+/// let mut output = Vec::new();
+/// plan.write_summary(&mut output, &WriteOptions::new().messages(messages))?;
+/// assert_eq!(output, "1 inchangé(s), 1 à renommer\n".as_bytes());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Messages {
+    pub(crate) prompt: String,
+    pub(crate) yes_no_hint: String,
+    pub(crate) yes: String,
+    pub(crate) no: String,
+    pub(crate) summary: String,
+    pub(crate) more: String,
+    pub(crate) pager_hint: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            prompt: "Proceed?".to_owned(),
+            yes_no_hint: "[y/n]".to_owned(),
+            yes: "yes".to_owned(),
+            no: "no".to_owned(),
+            summary: "{0} rename operation(s), {1} unchanged".to_owned(),
+            more: "... and {0} more".to_owned(),
+            pager_hint: "[y/n/v]".to_owned(),
+        }
+    }
+}
+
+impl Messages {
+    /// Creates the default, English messages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the confirmation prompt template.
+    ///
+    /// `{0}` is the number of rename operations. Defaults to `Proceed?`.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Sets the hint displayed after the confirmation prompt.
+    ///
+    /// The answers are still typed with the `y` and `n` keys. Defaults to
+    /// `[y/n]`.
+    pub fn yes_no_hint(mut self, yes_no_hint: impl Into<String>) -> Self {
+        self.yes_no_hint = yes_no_hint.into();
+        self
+    }
+
+    /// Sets the positive answer, displayed once the prompt is confirmed.
+    ///
+    /// Defaults to `yes`.
+    pub fn yes(mut self, yes: impl Into<String>) -> Self {
+        self.yes = yes.into();
+        self
+    }
+
+    /// Sets the negative answer, displayed once the prompt is declined.
+    ///
+    /// Defaults to `no`.
+    pub fn no(mut self, no: impl Into<String>) -> Self {
+        self.no = no.into();
+        self
+    }
+
+    /// Sets the summary template, written by
+    /// [`Plan::write_summary`](crate::Plan::write_summary).
+    ///
+    /// `{0}` is the number of rename operations, `{1}` the number of entries
+    /// dropped because their source and target were equal, and `{2}` the
+    /// number of [skipped](crate::Plan::skipped) entries, whatever the
+    /// reason. Defaults to `{0} rename operation(s), {1} unchanged`.
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = summary.into();
+        self
+    }
+
+    /// Sets the template standing for the operations left out of a preview,
+    #[cfg_attr(
+        feature = "confirm",
+        doc = "written by [`Plan::write_preview`](crate::Plan::write_preview)."
+    )]
+    #[cfg_attr(
+        not(feature = "confirm"),
+        doc = "written by `Plan::write_preview`, with the `confirm` feature."
+    )]
+    ///
+    /// `{0}` is the number of operations left out. Defaults to
+    /// `... and {0} more`.
+    pub fn more(mut self, more: impl Into<String>) -> Self {
+        self.more = more.into();
+        self
+    }
+
+    /// Sets the hint displayed after the confirmation prompt, when the plan
+    /// can be paged through.
+    ///
+    /// The answers are still typed with the `y`, `n` and `v` keys. Defaults
+    /// to `[y/n/v]`.
+    #[cfg_attr(
+        feature = "confirm",
+        doc = "See [`PreviewOptions::pager`](crate::PreviewOptions::pager)."
+    )]
+    #[cfg_attr(
+        not(feature = "confirm"),
+        doc = "See `PreviewOptions::pager`, with the `confirm` feature."
+    )]
+    pub fn pager_hint(mut self, pager_hint: impl Into<String>) -> Self {
+        self.pager_hint = pager_hint.into();
+        self
+    }
+}
+
+/// Replaces the positional placeholders of a template with the given
+/// arguments.
+///
+/// Placeholders with no matching argument, and unmatched braces, are kept
+/// as is.
+pub(crate) fn format_template(template: &str, args: &[&dyn Display]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(index) = rest.find(['{', '}']) {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            output.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let arg = rest.strip_prefix('{').and_then(|after| {
+            let (position, after) = after.split_once('}')?;
+            let arg = args.get(position.parse::<usize>().ok()?)?;
+            Some((arg, after))
+        });
+        match arg {
+            Some((arg, after)) => {
+                // Writing to a string cannot fail.
+                let _ = write!(output, "{}", arg);
+                rest = after;
+            }
+            None => {
+                output.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// A confirmation theme displaying the configured messages.
+#[cfg(feature = "confirm")]
+pub(crate) struct ConfirmTheme<'a>(pub(crate) &'a Messages);
+
+#[cfg(feature = "confirm")]
+impl dialoguer::theme::Theme for ConfirmTheme<'_> {
+    fn format_confirm_prompt(
+        &self,
+        f: &mut dyn std::fmt::Write,
+        prompt: &str,
+        _default: Option<bool>,
+    ) -> std::fmt::Result {
+        if !prompt.is_empty() {
+            write!(f, "{} ", prompt)?;
+        }
+        write!(f, "{} ", self.0.yes_no_hint)
+    }
+
+    fn format_confirm_prompt_selection(
+        &self,
+        f: &mut dyn std::fmt::Write,
+        prompt: &str,
+        selection: Option<bool>,
+    ) -> std::fmt::Result {
+        let selection = selection.map(|yes| if yes { &self.0.yes } else { &self.0.no });
+        match selection {
+            Some(selection) if prompt.is_empty() => write!(f, "{}", selection),
+            Some(selection) => write!(f, "{} {}", prompt, selection),
+            None => write!(f, "{}", prompt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_template;
+
+    #[test]
+    fn templates() {
+        let format = |template| format_template(template, &[&1, &"two"]);
+        assert_eq!(format("{0} and {1}"), "1 and two");
+        assert_eq!(format("{1} before {0}"), "two before 1");
+        assert_eq!(format("{1}{1}"), "twotwo");
+        assert_eq!(format("{{0}} {0}"), "{0} 1");
+        assert_eq!(format("{2} {x} {} {0"), "{2} {x} {} {0");
+        assert_eq!(format("plain } text {"), "plain } text {");
+        assert_eq!(format("é{0}ü"), "é1ü");
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+/// A receiver of the metrics of an apply, to bridge them to a metrics
+/// library such as a Prometheus client.
+///
+/// Each method records a sample of one metric, and does nothing by default,
+/// so that a sink only implements the metrics it exports. The sink is set
+/// with [`ApplyOptions::metrics`](crate::ApplyOptions::metrics), and called
+/// by [`Plan::apply_with`](crate::Plan::apply_with) and
+/// [`Session::apply`](crate::Session::apply), once per operation, in
+/// execution order.
+///
+/// # Threading
+///
+/// An apply calls its sink from the thread it runs on, one call at a time,
+/// even when filesystem calls run on another thread under an
+/// [operation timeout](crate::ApplyOptions::operation_timeout). Variants
+/// applying operations concurrently are to keep this guarantee, by calling
+/// the sink from the thread driving the apply. Methods take `&self`, so that
+/// a single sink can be shared by applies running on several threads, in
+/// which case it synchronizes its own state, typically with atomic counters.
+///
+/// # Examples
+///
+/// ```
+/// # use std::{fs::File, sync::atomic::{AtomicUsize, Ordering}};
+/// # use nominal::{ApplyOptions, MetricsSink, Renamer};
+/// #[derive(Default)]
+/// struct Counter(AtomicUsize);
+///
+/// impl MetricsSink for Counter {
+///     fn operation_applied(&self) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = |name| temp_dir.path().join(name);
+/// File::create(path("a.txt"))?;
+///
+/// let mut renamer = Renamer::new();
+/// renamer.add(path("a.txt"), path("b.txt"));
+///
+/// let counter = Counter::default();
+/// let options = ApplyOptions::new().metrics(&counter);
+/// renamer.plan()?.apply_with(&options).into_result()?;
+/// assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait MetricsSink {
+    /// Counts an operation that was applied, or merged into an existing
+    /// directory.
+    ///
+    /// Operations skipped, found already applied or deferred are not
+    /// counted, as in [`ApplyReport::applied`](crate::ApplyReport::applied).
+    fn operation_applied(&self) {}
+
+    /// Counts an operation that failed, including while checked before
+    /// anything is renamed.
+    fn operation_failed(&self) {}
+
+    /// Counts the directories created for an operation.
+    ///
+    /// See [`ReportEntry::created_dirs`](crate::ReportEntry::created_dirs).
+    fn dirs_created(&self, count: usize) {
+        let _ = count;
+    }
+
+    /// Counts the bytes copied for an operation.
+    ///
+    /// Renames copy nothing, so this is only called by strategies copying
+    /// their source, of which there is none yet.
+    fn bytes_copied(&self, bytes: u64) {
+        let _ = bytes;
+    }
+
+    /// Records the duration of an attempted operation, as reported in
+    /// [`ReportEntry::duration`](crate::ReportEntry::duration).
+    fn operation_duration(&self, duration: Duration) {
+        let _ = duration;
+    }
+}
+
+impl<M: MetricsSink + ?Sized> MetricsSink for &M {
+    fn operation_applied(&self) {
+        (**self).operation_applied();
+    }
+
+    fn operation_failed(&self) {
+        (**self).operation_failed();
+    }
+
+    fn dirs_created(&self, count: usize) {
+        (**self).dirs_created(count);
+    }
+
+    fn bytes_copied(&self, bytes: u64) {
+        (**self).bytes_copied(bytes);
+    }
+
+    fn operation_duration(&self, duration: Duration) {
+        (**self).operation_duration(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, sync::Mutex, time::Duration};
+
+    use super::MetricsSink;
+    use crate::{ApplyOptions, Renamer};
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        applied: Mutex<usize>,
+        failed: Mutex<usize>,
+        dirs_created: Mutex<usize>,
+        durations: Mutex<Vec<Duration>>,
+    }
+
+    impl MetricsSink for Recorder {
+        fn operation_applied(&self) {
+            *self.applied.lock().unwrap() += 1;
+        }
+
+        fn operation_failed(&self) {
+            *self.failed.lock().unwrap() += 1;
+        }
+
+        fn dirs_created(&self, count: usize) {
+            *self.dirs_created.lock().unwrap() += count;
+        }
+
+        fn operation_duration(&self, duration: Duration) {
+            self.durations.lock().unwrap().push(duration);
+        }
+    }
+
+    #[test]
+    fn recording_sink() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+        fs::write(path("b"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("out/2025/a"));
+        renamer.add(path("b"), path("out/2025/b"));
+        renamer.add(path("missing"), path("c"));
+        let recorder = Recorder::default();
+        let options = ApplyOptions::new()
+            .continue_on_error(true)
+            .metrics(&recorder);
+        let report = renamer.plan().unwrap().apply_with(&options);
+        drop(options);
+        assert_eq!(*recorder.applied.lock().unwrap(), report.applied());
+        assert_eq!(*recorder.failed.lock().unwrap(), 1);
+        assert_eq!(
+            *recorder.dirs_created.lock().unwrap(),
+            report.created_dirs().len()
+        );
+        assert_eq!(recorder.durations.lock().unwrap().len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    fsutil::{existing_ancestor, set_dir_mtime},
+    warning::Warning,
+};
+
+/// Records the modification times of parent directories, to restore them
+/// once the plan is applied.
+#[derive(Debug, Default)]
+pub(crate) struct ParentMtimes {
+    seen: HashSet<PathBuf>,
+    mtimes: Vec<(PathBuf, SystemTime)>,
+    warnings: Vec<Warning>,
+}
+
+impl ParentMtimes {
+    /// Records the modification time of the closest existing parent of a
+    /// path, unless already recorded.
+    pub(crate) fn record(&mut self, path: &Path) {
+        let parent = path.parent().unwrap_or(Path::new(""));
+        let parent = match existing_ancestor(parent) {
+            Ok(parent) => parent,
+            Err(error) => {
+                self.warn(parent, error);
+                return;
+            }
+        };
+        if !self.seen.insert(parent.to_owned()) {
+            return;
+        }
+        match parent.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(mtime) => self.mtimes.push((parent.to_owned(), mtime)),
+            Err(error) => self.warn(parent, error),
+        }
+    }
+
+    /// Restores the recorded modification times, and returns the warnings.
+    pub(crate) fn restore(mut self) -> Vec<Warning> {
+        for (path, mtime) in std::mem::take(&mut self.mtimes) {
+            if let Err(error) = set_dir_mtime(&path, mtime) {
+                self.warn(&path, error);
+            }
+        }
+        self.warnings
+    }
+
+    fn warn(&mut self, path: &Path, error: std::io::Error) {
+        warn!(
+            "could not preserve the modification time of {}: {}",
+            path.display(),
+            error
+        );
+        self.warnings.push(Warning::ParentMtime {
+            path: path.to_owned(),
+            error,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        time::{Duration, SystemTime},
+    };
+
+    use super::ParentMtimes;
+    use crate::fsutil::set_dir_mtime;
+
+    #[test]
+    fn restore() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir_1 = temp_dir.path().join("dir-1");
+        let dir_2 = temp_dir.path().join("dir-2");
+        fs::create_dir(&dir_1)?;
+        fs::create_dir(&dir_2)?;
+        let old_mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        set_dir_mtime(&dir_1, old_mtime)?;
+        set_dir_mtime(&dir_2, old_mtime)?;
+
+        let source = dir_1.join("old.txt");
+        let target = dir_2.join("sub/new.txt");
+        fs::File::create(&source)?;
+        set_dir_mtime(&dir_1, old_mtime)?;
+
+        let mut mtimes = ParentMtimes::default();
+        mtimes.record(&source);
+        mtimes.record(&target);
+        fs::create_dir(dir_2.join("sub"))?;
+        fs::rename(&source, &target)?;
+        assert_ne!(dir_1.metadata()?.modified()?, old_mtime);
+
+        assert!(mtimes.restore().is_empty());
+        assert_eq!(dir_1.metadata()?.modified()?, old_mtime);
+        assert_eq!(dir_2.metadata()?.modified()?, old_mtime);
+        Ok(())
+    }
+}
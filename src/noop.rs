@@ -0,0 +1,62 @@
+use std::path::Path;
+
+/// Options for [`is_noop`].
+///
+/// There are no options yet: every no-op is detected the same way. Their
+/// [default](Self::default) is what planning uses.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct NoopOptions {}
+
+impl NoopOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Tests whether renaming `source` to `target` would change nothing.
+///
+/// This is the test [`Renamer::plan_with`](crate::Renamer::plan_with) uses to
+/// skip operations as [`SkipReason::NoOp`](crate::SkipReason::NoOp), so that
+/// the operations counted beforehand match the plan. Paths are compared
+/// component-wise, as [`Path`] does: `a/b` and `a//b/` are the same path,
+/// but `a` and `./a` are not, nor are paths differing only by case.
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::{is_noop, NoopOptions, Renamer};
+/// let options = NoopOptions::new();
+/// assert!(is_noop("a/b.txt", "a//b.txt", &options));
+/// assert!(!is_noop("a/b.txt", "a/B.txt", &options));
+///
+/// let mut renamer = Renamer::new();
+/// renamer.add("a/b.txt", "a//b.txt");
+/// assert_eq!(renamer.plan()?.stats().noops, 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn is_noop<S, T>(source: S, target: T, options: &NoopOptions) -> bool
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    let _ = options;
+    source.as_ref() == target.as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_noop, NoopOptions};
+
+    #[test]
+    fn noops() {
+        let options = NoopOptions::new();
+        for (source, target) in [("a", "a"), ("a/b", "a/./b"), ("a/", "a")] {
+            assert!(is_noop(source, target, &options), "{source} => {target}");
+        }
+        for (source, target) in [("a", "./a"), ("a", "A"), ("a/../b", "b"), ("a", "/a")] {
+            assert!(!is_noop(source, target, &options), "{source} => {target}");
+        }
+    }
+}
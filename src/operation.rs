@@ -1,21 +1,80 @@
-use std::{fmt, fs, path::Path};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    fs, io,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    error::ApplyError,
-    fsutil::{common_ancestor, path_exists},
+    classify::RenameClass,
+    error::{
+        ApplyError, ApplyErrorDetails, InvalidTargetReason, IoOp, PermissionReason, TargetKind,
+    },
+    exchange,
+    fsutil::{
+        copy_tree, create_dir_all_with_mode, is_hidden, remove_tree, rename_exclusive,
+        rename_noreplace,
+    },
+    inspect,
+    log::LogEvent,
+    options::{ApplyOptions, DirMerge, FollowSymlinks, SourceKind, StatErrorPolicy, WriteOptions},
+    parents::MissingParents,
+    path::{normalize, split_common},
+    preflight,
+    report::CreatedDir,
+    stamp::SourceStamp,
+    tag::Tag,
+    warning::Warning,
 };
 
 /// A rename operation.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Rename<S, T> {
+    /// The source path.
     pub source: S,
+    /// The target path.
     pub target: T,
+    /// The user payload attached to the operation, if any.
+    pub tag: Option<Tag>,
+    /// The priority of the operation: operations with a higher priority are
+    /// executed first. Defaults to zero.
+    ///
+    /// See [`Renamer::add_with_priority`](crate::Renamer::add_with_priority).
+    pub priority: i32,
 }
 
 impl<S, T> Rename<S, T> {
     /// Creates a new rename operation.
     pub fn new(source: S, target: T) -> Self {
-        Self { source, target }
+        Self {
+            source,
+            target,
+            tag: None,
+            priority: 0,
+        }
+    }
+
+    /// Attaches a tag to the rename operation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Rename, Tag};
+    /// let rename = Rename::new("old.txt", "new.txt");
+    /// let rename = rename.with_tag(Tag::new("row 42"));
+    /// assert_eq!(rename.tag.unwrap().downcast_ref(), Some(&"row 42"));
+    /// ```
+    pub fn with_tag(mut self, tag: Tag) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// Sets the priority of the rename operation.
+    ///
+    /// See [`Renamer::add_with_priority`](crate::Renamer::add_with_priority).
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
     }
 }
 
@@ -24,85 +83,566 @@ where
     S: AsRef<Path>,
     T: AsRef<Path>,
 {
-    pub fn write_to<W>(&self, writer: &mut W) -> std::io::Result<()>
+    /// Returns the common ancestor and the remaining parts of the source and
+    /// target, as displayed.
+    fn split(&self, options: &WriteOptions) -> (Option<&Path>, &Path, &Path) {
+        let source = self.source.as_ref();
+        let target = self.target.as_ref();
+        match split_common(source, target).filter(|_| options.compress) {
+            Some((common, source, target)) => (Some(common), source, target),
+            None => (None, source, target),
+        }
+    }
+
+    /// Tests whether the source or the target is relative.
+    pub(crate) fn is_relative(&self) -> bool {
+        self.source.as_ref().is_relative() || self.target.as_ref().is_relative()
+    }
+
+    /// Returns the rename operation, with relative paths joined to a base
+    /// directory.
+    pub(crate) fn anchored(&self, base_dir: &Path) -> Rename<PathBuf, PathBuf> {
+        Rename {
+            source: base_dir.join(self.source.as_ref()),
+            target: base_dir.join(self.target.as_ref()),
+            tag: self.tag.clone(),
+            priority: self.priority,
+        }
+    }
+
+    /// Returns the class of the rename operation.
+    pub(crate) fn class(&self) -> RenameClass {
+        RenameClass::of(self.source.as_ref(), self.target.as_ref())
+    }
+
+    /// Tests whether the source or target of the rename operation is hidden,
+    /// or is a system file, given the metadata of the source if it was read.
+    pub(crate) fn is_hidden(&self, metadata: Option<&fs::Metadata>) -> bool {
+        is_hidden(&normalize(self.source.as_ref()), metadata)
+            || is_hidden(&normalize(self.target.as_ref()), None)
+    }
+
+    /// Writes the rename operation to the specified writer.
+    pub fn write_to<W>(&self, writer: &mut W, options: &WriteOptions) -> std::io::Result<()>
     where
         W: std::io::Write,
     {
-        writeln!(writer, "{}", self)
+        let mut line = String::new();
+        self.push_line(&mut line, options);
+        writer.write_all(line.as_bytes())
+    }
+
+    /// Appends the rename operation to a line, as written by
+    /// [`write_to`](Self::write_to), along with its terminator.
+    ///
+    /// Writers of many operations reuse the same line, and write it at once.
+    pub(crate) fn push_line(&self, line: &mut String, options: &WriteOptions) {
+        let _ = match self.split(options) {
+            (Some(common), source, target) => write!(
+                line,
+                "{}/{{{} {} {}}}",
+                options.display(common),
+                options.display(source),
+                options.arrow,
+                options.display(target)
+            ),
+            (None, source, target) => write!(
+                line,
+                "{} {} {}",
+                options.display(source),
+                options.arrow,
+                options.display(target)
+            ),
+        };
+        line.push_str(options.terminator_str());
     }
 
+    /// Writes the rename operation to the specified writer, with colors.
     #[cfg(feature = "ansi")]
     pub fn write_colored_to<W>(
         &self,
         ls_colors: &lscolors::LsColors,
         w: &mut W,
+        options: &WriteOptions,
     ) -> std::io::Result<()>
     where
         W: std::io::Write,
     {
-        let source = self.source.as_ref();
-        let target = self.target.as_ref();
+        let mut line = String::new();
+        self.push_colored_line(ls_colors, &mut line, options);
+        w.write_all(line.as_bytes())
+    }
 
-        let source_style = style_for_path(ls_colors, source);
-        let target_style = style_for_path(ls_colors, target);
+    /// Appends the rename operation to a line, as written by
+    /// [`write_colored_to`](Self::write_colored_to), along with its
+    /// terminator.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn push_colored_line(
+        &self,
+        ls_colors: &lscolors::LsColors,
+        line: &mut String,
+        options: &WriteOptions,
+    ) {
+        let source_style = style_for_path(ls_colors, self.source.as_ref());
+        let target_style = style_for_path(ls_colors, self.target.as_ref());
+        let arrow_style = if options.tint_classes {
+            self.class().style()
+        } else {
+            nu_ansi_term::Style::new()
+        };
 
-        match common_ancestor(source, target) {
-            Some(common) => {
+        match self.split(options) {
+            (Some(common), source, target) => {
                 let common_style = style_for_path(ls_colors, common);
-                let source = source.strip_prefix(common).unwrap();
-                let target = target.strip_prefix(common).unwrap();
-
-                writeln!(
-                    w,
-                    "{}{}/{}{{{}{}{} => {}{}{}}}",
+                let _ = write!(
+                    line,
+                    "{}{}/{}{{{}{}{} {} {}{}{}}}",
                     common_style.prefix(),
-                    common.display(),
+                    options.display(common),
                     common_style.suffix(),
                     source_style.prefix(),
-                    source.display(),
+                    options.display(source),
                     source_style.suffix(),
+                    arrow_style.paint(&options.arrow),
                     target_style.prefix(),
-                    target.display(),
+                    options.display(target),
                     target_style.suffix()
-                )?;
+                );
             }
-            None => {
-                writeln!(
-                    w,
-                    "{}{}{} => {}{}{}",
+            (None, source, target) => {
+                let _ = write!(
+                    line,
+                    "{}{}{} {} {}{}{}",
                     source_style.prefix(),
-                    source.display(),
+                    options.display(source),
                     source_style.suffix(),
+                    arrow_style.paint(&options.arrow),
                     target_style.prefix(),
-                    target.display(),
+                    options.display(target),
                     target_style.suffix()
-                )?;
+                );
             }
         }
-        Ok(())
+        line.push_str(options.terminator_str());
     }
 
     /// Executes the rename operation.
     pub fn apply(&self) -> Result<(), ApplyError> {
+        self.apply_with(&ApplyOptions::new()).map(|_| ())
+    }
+
+    /// Executes the rename operation with the specified options.
+    ///
+    /// The operation goes through the same checks and steps as in
+    /// [`Plan::apply_with`](crate::Plan::apply_with). Options acting on the
+    /// whole plan, such as [`ApplyOptions::continue_on_error`],
+    /// [`ApplyOptions::preserve_parent_mtimes`] and
+    /// [`ApplyOptions::fix_symlinks_under`], are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Rename};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("sub/new.txt");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let rename = Rename::new(&old_path, &new_path);
+    /// let applied = rename.apply_with(&ApplyOptions::new())?;
+    /// assert_eq!(applied.created_parents.len(), 1);
+    /// assert!(new_path.exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_with(&self, options: &ApplyOptions) -> Result<Applied, ApplyError> {
+        let source = self.source.as_ref();
+        let target = self.target.as_ref();
+        let fail = |details| ApplyError::new(source, target, details).with_tag(self.tag.clone());
+        if let Some(reason) = InvalidTargetReason::of(target) {
+            return Err(fail(ApplyErrorDetails::InvalidTarget(reason)));
+        }
+        if source.as_os_str().is_empty() {
+            return Err(fail(ApplyErrorDetails::EmptySource));
+        }
+        let mut warnings = Vec::new();
+        if let Some(details) = preflight::check(
+            std::slice::from_ref(self),
+            &HashMap::new(),
+            options,
+            &mut warnings,
+        )
+        .remove(&0)
+        {
+            return Err(fail(details));
+        }
+        let mut created_parents = Vec::new();
+        let strategy = self.apply_checked(options, false, None, None, &mut created_parents)?;
+        Ok(Applied {
+            created_parents,
+            strategy,
+            warnings,
+        })
+    }
+
+    /// Executes the rename operation, once it passed the preflight checks.
+    ///
+    /// If `same_file` is set, the target names the source itself, and is not
+    /// checked for existence. If `missing_parents` is given, it is used to
+    /// tell whether the target parent must be created, instead of checking
+    /// the filesystem. Created directories are appended to `created_dirs`.
+    pub(crate) fn apply_checked(
+        &self,
+        options: &ApplyOptions,
+        same_file: bool,
+        stamp: Option<&SourceStamp>,
+        missing_parents: Option<&mut MissingParents>,
+        created_dirs: &mut Vec<CreatedDir>,
+    ) -> Result<RenameStrategy, ApplyError> {
         let source = self.source.as_ref();
         let target = self.target.as_ref();
+        let fail = |details| ApplyError::new(source, target, details).with_tag(self.tag.clone());
 
-        // We check before renaming to avoid overwriting the target.
-        if path_exists(target).map_err(|err| ApplyError::from_io(source, target, err))? {
-            return Err(ApplyError::target_exists(source, target));
+        // We check before renaming to avoid overwriting the target, unless
+        // the target is the source itself.
+        let (target_owned, follow) = (target.to_owned(), options.follow_symlinks);
+        let mut assumed_missing = false;
+        let existing = if same_file {
+            None
+        } else {
+            match options.run(IoOp::Stat, target, move || {
+                TargetKind::of(&target_owned, follow)
+            }) {
+                Ok(existing) => existing,
+                Err(details @ ApplyErrorDetails::Io { .. }) => match options.on_stat_error {
+                    StatErrorPolicy::Fail => return Err(fail(diagnose_lookup(details, target))),
+                    StatErrorPolicy::AssumeMissing => {
+                        assumed_missing = true;
+                        None
+                    }
+                    StatErrorPolicy::Skip => return Ok(RenameStrategy::Skipped),
+                },
+                Err(details) => return Err(fail(details)),
+            }
+        };
+        if let Some(kind) = existing {
+            if options.idempotent && self.already_applied(options, stamp).map_err(fail)? {
+                return Ok(RenameStrategy::AlreadyApplied);
+            }
+            if options.dir_merge == DirMerge::Error || !self.both_dirs(options).map_err(fail)? {
+                return Err(fail(ApplyErrorDetails::TargetExists { kind }));
+            }
+            if options.dir_merge == DirMerge::Skip {
+                return Ok(RenameStrategy::Skipped);
+            }
+            self.merge_into(options, created_dirs).map_err(fail)?;
+            return Ok(RenameStrategy::Merged);
         }
 
         if let Some(target_parent) = target.parent() {
-            if !target_parent.exists() {
-                tracing::debug!("creating parent directory for {}", target.display());
-                fs::create_dir_all(target_parent)
-                    .map_err(|err| ApplyError::from_io(source, target, err))?;
+            let create_parent =
+                match missing_parents {
+                    Some(missing_parents) => missing_parents.take(target),
+                    None => {
+                        // Parents are followed, as by the rename itself.
+                        let parent_owned = target_parent.to_owned();
+                        !options
+                            .run(IoOp::Stat, target_parent, move || {
+                                Ok(inspect::exists(&parent_owned, FollowSymlinks::Yes)
+                                    .unwrap_or(false))
+                            })
+                            .map_err(fail)?
+                    }
+                };
+            if create_parent {
+                options.emit(LogEvent::CreateParent {
+                    target,
+                    parent: target_parent,
+                });
+                let parent_owned = target_parent.to_owned();
+                let mode = options.dir_permissions;
+                let created = options
+                    .run(IoOp::CreateDir, target_parent, move || {
+                        create_dir_all_with_mode(&parent_owned, mode)
+                    })
+                    .map_err(|details| diagnose(details, source, target))
+                    .map_err(fail)?;
+                // The mode is only applied on Unix.
+                let mode = mode.filter(|_| cfg!(unix));
+                let start = created_dirs.len();
+                created_dirs.extend(created.into_iter().map(|path| CreatedDir { path, mode }));
+                options.emit(LogEvent::ParentCreated {
+                    target,
+                    created: &created_dirs[start..],
+                });
             }
         }
-        tracing::debug!("renaming {} to {}", source.display(), target.display());
-        fs::rename(source, target).map_err(|err| ApplyError::from_io(source, target, err))?;
-        Ok(())
+        options.emit(LogEvent::Rename { source, target });
+        // The target may have been created since it was checked: where
+        // supported, the kernel refuses to replace it. A target that could
+        // not be checked is never replaced, even where unsupported.
+        let (source_owned, target_owned) = (source.to_owned(), target.to_owned());
+        let renamed = options
+            .run(IoOp::Rename, source, move || {
+                if same_file {
+                    return fs::rename(source_owned, target_owned);
+                }
+                if assumed_missing {
+                    return rename_exclusive(&source_owned, &target_owned);
+                }
+                match rename_noreplace(&source_owned, &target_owned) {
+                    // A dangling symbolic link is missing once followed, and
+                    // replaced as such.
+                    Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                        if TargetKind::of(&target_owned, follow)?.is_some() {
+                            return Err(err);
+                        }
+                        fs::rename(source_owned, target_owned)
+                    }
+                    renamed => renamed,
+                }
+            })
+            .map_err(|details| match details {
+                ApplyErrorDetails::Io { error, .. }
+                    if error.kind() == io::ErrorKind::AlreadyExists =>
+                {
+                    let kind = TargetKind::of(target, follow);
+                    ApplyErrorDetails::TargetExists {
+                        kind: kind.ok().flatten().unwrap_or(TargetKind::Other),
+                    }
+                }
+                details => diagnose(details, source, target),
+            });
+        if let Err(ApplyErrorDetails::Io { error, .. }) = &renamed {
+            if options.copy_across_devices && error.kind() == io::ErrorKind::CrossesDevices {
+                self.copy_across(options).map_err(fail)?;
+                return Ok(RenameStrategy::Copied);
+            }
+        }
+        #[cfg(windows)]
+        if let Err(ApplyErrorDetails::Io { error, .. }) = &renamed {
+            if options.windows_defer_in_use && crate::windows::is_in_use(error) {
+                match crate::windows::rename_on_reboot(source, target) {
+                    Ok(()) => return Ok(RenameStrategy::Deferred),
+                    Err(err) => debug!("could not defer {:?}: {}", source, err),
+                }
+            }
+        }
+        renamed.map_err(fail)?;
+        Ok(RenameStrategy::Rename)
+    }
+
+    /// Copies the source to a target on another filesystem, then removes the
+    /// source.
+    fn copy_across(&self, options: &ApplyOptions) -> Result<(), ApplyErrorDetails> {
+        let (source, target) = (self.source.as_ref(), self.target.as_ref());
+        let (source_owned, target_owned) = (source.to_owned(), target.to_owned());
+        options.run(IoOp::Copy, source, move || {
+            copy_tree(&source_owned, &target_owned)
+        })?;
+        let source_owned = source.to_owned();
+        options.run(IoOp::Remove, source, move || remove_tree(&source_owned))
+    }
+
+    /// Exchanges the source and the target of the first operation of a swap,
+    /// the target being the source of the other operation, and returns
+    /// [`None`] if the swap must go through a temporary path instead.
+    ///
+    /// As with [`apply_checked`](Self::apply_checked), an operation
+    /// [already applied](ApplyOptions::idempotent) is left to it, and
+    /// failures are diagnosed. The target is moved to the source rather than
+    /// replaced, so that, as when the other operation is moved aside first,
+    /// [`ApplyOptions::dir_merge`] does not apply.
+    pub(crate) fn exchange_checked(
+        &self,
+        options: &ApplyOptions,
+        stamp: Option<&SourceStamp>,
+    ) -> Option<Result<RenameStrategy, ApplyError>> {
+        let source = self.source.as_ref();
+        let target = self.target.as_ref();
+        let fail = |details| ApplyError::new(source, target, details).with_tag(self.tag.clone());
+
+        if options.idempotent {
+            match self.already_applied(options, stamp) {
+                Ok(true) => return None,
+                Ok(false) => {}
+                Err(details) => return Some(Err(fail(details))),
+            }
+        }
+        match exchange::try_exchange(source, target, options) {
+            Ok(true) => Some(Ok(RenameStrategy::Exchanged)),
+            Ok(false) => None,
+            Err(details) => Some(Err(fail(diagnose(details, source, target)))),
+        }
     }
+
+    /// Tests whether the operation looks applied already: the source is gone,
+    /// and the existing target is of the expected kind and matches the stamp
+    /// of the source.
+    ///
+    /// Without a stamp, nothing tells the renamed source from another file,
+    /// and the operation is not taken as applied.
+    pub(crate) fn already_applied(
+        &self,
+        options: &ApplyOptions,
+        stamp: Option<&SourceStamp>,
+    ) -> Result<bool, ApplyErrorDetails> {
+        let Some(stamp) = stamp else {
+            return Ok(false);
+        };
+        let (source, target) = (self.source.as_ref(), self.target.as_ref());
+        let follow = options.follow_symlinks;
+        let source_owned = source.to_owned();
+        if options.run(IoOp::Stat, source, move || {
+            inspect::exists(&source_owned, follow)
+        })? {
+            return Ok(false);
+        }
+        let target_owned = target.to_owned();
+        let kind = options.run(IoOp::Stat, target, move || {
+            SourceKind::of(&target_owned, follow)
+        })?;
+        if !options.expect.matches(kind) {
+            return Ok(false);
+        }
+        let (stamp, target_owned) = (stamp.clone(), target.to_owned());
+        let changed = options.run(IoOp::Stat, target, move || stamp.changed(&target_owned))?;
+        Ok(changed.is_none())
+    }
+
+    /// Tests whether the source and the existing target are both
+    /// directories, without following symbolic links.
+    fn both_dirs(&self, options: &ApplyOptions) -> Result<bool, ApplyErrorDetails> {
+        let (source, target) = (self.source.as_ref(), self.target.as_ref());
+        let is_dir = |path: &Path| {
+            let path_owned = path.to_owned();
+            options.run(IoOp::Stat, path, move || {
+                Ok(SourceKind::of(&path_owned, FollowSymlinks::No)? == SourceKind::Dir)
+            })
+        };
+        Ok(is_dir(source)? && is_dir(target)?)
+    }
+
+    /// Moves the children of the source directory into the existing target
+    /// directory, then removes the emptied source.
+    ///
+    /// Failures on a child are wrapped in
+    /// [`ApplyErrorDetails::MergeFailed`].
+    fn merge_into(
+        &self,
+        options: &ApplyOptions,
+        created_dirs: &mut Vec<CreatedDir>,
+    ) -> Result<(), ApplyErrorDetails> {
+        let (source, target) = (self.source.as_ref(), self.target.as_ref());
+        let source_owned = source.to_owned();
+        let mut children = options.run(IoOp::ReadDir, source, move || {
+            fs::read_dir(source_owned)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<io::Result<Vec<_>>>()
+        })?;
+        // Children are merged in a deterministic order.
+        children.sort();
+        for name in children {
+            let child = Rename::new(source.join(&name), target.join(&name));
+            child
+                .apply_checked(options, false, None, None, created_dirs)
+                .map_err(|err| ApplyErrorDetails::MergeFailed(Box::new(err)))?;
+        }
+        let source_owned = source.to_owned();
+        options.run(IoOp::Remove, source, move || fs::remove_dir(source_owned))
+    }
+}
+
+/// Replaces a permission denial with its cause, if it can be found.
+fn diagnose(details: ApplyErrorDetails, source: &Path, target: &Path) -> ApplyErrorDetails {
+    #[cfg(not(unix))]
+    let _ = (source, target);
+    match details {
+        #[cfg(unix)]
+        ApplyErrorDetails::Io { op, path, error }
+            if error.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            match crate::fsutil::permission_denial(source, target) {
+                Ok(Some((reason, dir))) => {
+                    ApplyErrorDetails::PermissionDenied { reason, dir, error }
+                }
+                // The original error is more useful than a failed diagnosis.
+                _ => ApplyErrorDetails::Io { op, path, error },
+            }
+        }
+        details => details,
+    }
+}
+
+/// Diagnoses the failure to inspect a target, finding the directory that
+/// cannot be searched.
+fn diagnose_lookup(details: ApplyErrorDetails, target: &Path) -> ApplyErrorDetails {
+    match details {
+        ApplyErrorDetails::Io { op, path, error }
+            if error.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            match crate::fsutil::unsearchable_dir(target) {
+                Some(dir) => ApplyErrorDetails::PermissionDenied {
+                    reason: PermissionReason::TargetDirNotSearchable,
+                    dir: dir.to_owned(),
+                    error,
+                },
+                None => ApplyErrorDetails::Io { op, path, error },
+            }
+        }
+        details => details,
+    }
+}
+
+/// The outcome of a successful [`Rename::apply_with`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Applied {
+    /// The target parent directories created before renaming, outermost
+    /// first.
+    pub created_parents: Vec<CreatedDir>,
+    /// The strategy used to rename the source.
+    pub strategy: RenameStrategy,
+    /// The warnings raised while checking the operation.
+    pub warnings: Vec<Warning>,
+}
+
+/// The strategy used to execute a rename operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RenameStrategy {
+    /// The source was renamed with a plain rename system call.
+    Rename,
+    /// The children of the source directory were moved into the existing
+    /// target directory, and the source was removed.
+    ///
+    /// See [`DirMerge::MergeInto`].
+    Merged,
+    /// Nothing was renamed, since the target directory exists, or the target
+    /// could not be inspected.
+    ///
+    /// See [`DirMerge::Skip`] and [`StatErrorPolicy::Skip`].
+    Skipped,
+    /// Nothing was renamed, since the operation was applied already.
+    ///
+    /// See [`ApplyOptions::idempotent`].
+    AlreadyApplied,
+    /// Nothing was renamed yet, since the source is in use: the rename is
+    /// scheduled for the next reboot.
+    ///
+    /// See [`ApplyOptions::windows_defer_in_use`].
+    Deferred,
+    /// The source and the target were swapped in one system call, the
+    /// target being the source of another operation renaming it back.
+    ///
+    /// See [`Exchange`](crate::Exchange).
+    Exchanged,
+    /// The source was copied to the target on another filesystem, then
+    /// removed.
+    ///
+    /// See [`ApplyOptions::copy_across_devices`].
+    Copied,
 }
 
 impl<S, T> From<(S, T)> for Rename<S, T> {
@@ -123,21 +663,17 @@ where
     T: AsRef<Path>,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let source = self.source.as_ref();
-        let target = self.target.as_ref();
-        match common_ancestor(source, target) {
-            Some(common) => {
-                let source = source.strip_prefix(common).unwrap();
-                let target = target.strip_prefix(common).unwrap();
-                write!(
-                    f,
-                    "{}/{{{} => {}}}",
-                    common.display(),
-                    source.display(),
-                    target.display()
-                )
+        match self.split(&WriteOptions::default()) {
+            (Some(common), source, target) => write!(
+                f,
+                "{}/{{{} => {}}}",
+                common.display(),
+                source.display(),
+                target.display()
+            ),
+            (None, source, target) => {
+                write!(f, "{} => {}", source.display(), target.display())
             }
-            None => write!(f, "{} => {}", source.display(), target.display()),
         }
     }
 }
@@ -154,3 +690,269 @@ where
         .map(Style::to_nu_ansi_term_style)
         .unwrap_or_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use crate::{
+        ApplyErrorDetails, ApplyOptions, DirMerge, PlanOptions, RenameStatus, Renamer, SourceKind,
+        TargetKind,
+    };
+
+    #[test]
+    fn target_kind() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::create_dir_all(path("full/sub"))?;
+        fs::create_dir(path("empty"))?;
+        for file in ["a", "b", "c", "file"] {
+            fs::write(path(file), file)?;
+        }
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("file"));
+        renamer.add(path("b"), path("empty"));
+        renamer.add(path("c"), path("full"));
+        let report = renamer
+            .plan()
+            .unwrap()
+            .apply_with(&ApplyOptions::new().continue_on_error(true));
+        let kinds: Vec<_> = (report.entries().iter())
+            .map(|entry| match &entry.status {
+                RenameStatus::Failed(ApplyErrorDetails::TargetExists { kind }) => *kind,
+                status => panic!("unexpected status: {:?}", status),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                TargetKind::Dir { empty: true },
+                TargetKind::File,
+                TargetKind::Dir { empty: false },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dir_merge() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for dir in ["old/sub", "new/sub", "file-target"] {
+            fs::create_dir_all(path(dir))?;
+        }
+        for file in ["old/a", "old/sub/b", "old/sub/c", "new/sub/c", "file"] {
+            fs::write(path(file), file)?;
+        }
+        let apply = |dir_merge| {
+            let mut renamer = Renamer::new();
+            renamer.add(path("old"), path("new"));
+            renamer.add(path("file-target"), path("file"));
+            renamer.plan().unwrap().apply_with(
+                &ApplyOptions::new()
+                    .dir_merge(dir_merge)
+                    .continue_on_error(true),
+            )
+        };
+
+        let report = apply(DirMerge::Skip);
+        assert!(matches!(
+            report.entries()[0].status,
+            RenameStatus::Failed(ApplyErrorDetails::TargetExists {
+                kind: TargetKind::File
+            })
+        ));
+        assert!(matches!(report.entries()[1].status, RenameStatus::Skipped));
+        assert!(path("old/a").exists());
+
+        // `old/sub/c` clashes with `new/sub/c`, after `old/a` and `old/sub/b`
+        // were moved.
+        let report = apply(DirMerge::MergeInto);
+        let RenameStatus::Failed(ApplyErrorDetails::MergeFailed(err)) = &report.entries()[1].status
+        else {
+            panic!("unexpected status: {:?}", report.entries()[1].status);
+        };
+        let ApplyErrorDetails::MergeFailed(err) = &err.details else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(err.source, path("old/sub/c"));
+        assert!(matches!(
+            err.details,
+            ApplyErrorDetails::TargetExists {
+                kind: TargetKind::File
+            }
+        ));
+        assert!(path("new/a").exists() && path("new/sub/b").exists());
+        assert_eq!(fs::read(path("new/sub/c"))?, b"new/sub/c");
+
+        fs::remove_file(path("new/sub/c"))?;
+        let report = apply(DirMerge::MergeInto);
+        assert!(matches!(report.entries()[1].status, RenameStatus::Merged));
+        assert!(!path("old").exists());
+        assert_eq!(fs::read(path("new/sub/c"))?, b"old/sub/c");
+        Ok(())
+    }
+
+    #[test]
+    fn idempotent() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+        fs::write(path("c"), b"")?;
+        fs::create_dir(path("e"))?;
+        let plan = || {
+            let mut renamer = Renamer::new();
+            renamer.add(path("a"), path("b"));
+            renamer.add(path("c"), path("d"));
+            renamer.add(path("e"), path("f"));
+            (renamer.plan_with(&PlanOptions::new().stamp_sources(true))).unwrap()
+        };
+        let options = || ApplyOptions::new().idempotent(true).continue_on_error(true);
+        let [first, second, third] = [plan(), plan(), plan()];
+        assert_eq!(first.apply_with(&ApplyOptions::new()).applied(), 3);
+
+        // The source of an ambiguous operation exists again, and the
+        // directory has no stamp.
+        fs::write(path("c"), b"")?;
+        let report = second.apply_with(&options());
+        let statuses: Vec<_> = report.entries().iter().map(|entry| &entry.status).collect();
+        assert!(matches!(
+            statuses[..],
+            [
+                RenameStatus::AlreadyApplied,
+                RenameStatus::Failed(ApplyErrorDetails::SourceChanged(_)),
+                RenameStatus::Failed(ApplyErrorDetails::TargetExists { .. }),
+            ]
+        ));
+        assert_eq!(report.applied(), 0);
+
+        // A target replaced by another file does not match the stamp, and
+        // its missing source fails the preflight checks.
+        fs::write(path("b"), b"other")?;
+        let report = third.apply_with(&options().expect(SourceKind::File));
+        assert!(matches!(
+            report.entries()[0].status,
+            RenameStatus::Failed(ApplyErrorDetails::Io { .. })
+        ));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stat_errors() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::{ConflictOptions, PermissionReason, PlanOptions, StatErrorPolicy};
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+        fs::create_dir(path("locked"))?;
+        fs::set_permissions(path("locked"), fs::Permissions::from_mode(0o000))?;
+        // Permissions are not enforced for privileged users.
+        let privileged = path("locked/b").symlink_metadata().is_ok()
+            || path("locked/b").symlink_metadata().unwrap_err().kind()
+                != io::ErrorKind::PermissionDenied;
+        let run = |policy| {
+            let mut renamer = Renamer::new();
+            renamer.add(path("a"), path("locked/sub/b"));
+            let plan = renamer
+                .plan_with(&PlanOptions::new().on_stat_error(policy))
+                .unwrap();
+            let collisions = plan.collisions().and_then(|collisions| {
+                let conflicts = plan.conflicts(&ConflictOptions::new())?;
+                Ok((collisions.len(), conflicts.len()))
+            });
+            let report = plan.apply_with(&ApplyOptions::new().on_stat_error(policy));
+            let outcome = match &report.entries()[0].status {
+                RenameStatus::Applied => "applied".to_owned(),
+                RenameStatus::Skipped => "skipped".to_owned(),
+                RenameStatus::Failed(ApplyErrorDetails::PermissionDenied {
+                    reason: PermissionReason::TargetDirNotSearchable,
+                    dir,
+                    ..
+                }) => format!("not searchable: {}", dir.display()),
+                RenameStatus::Failed(_) => "failed".to_owned(),
+                status => panic!("unexpected status: {:?}", status),
+            };
+            (collisions, outcome)
+        };
+        let outcomes = [
+            StatErrorPolicy::Fail,
+            StatErrorPolicy::Skip,
+            StatErrorPolicy::AssumeMissing,
+        ]
+        .map(run);
+        fs::set_permissions(path("locked"), fs::Permissions::from_mode(0o755))?;
+        if privileged {
+            return Ok(());
+        }
+
+        let [fail, skip, assume_missing] = outcomes;
+        assert!(fail.0.is_err());
+        assert_eq!(
+            fail.1,
+            format!("not searchable: {}", path("locked").display())
+        );
+        assert_eq!((skip.0.ok(), skip.1.as_str()), (Some((0, 0)), "skipped"));
+        // The rename is attempted, and denied as well.
+        assert_eq!(
+            (assume_missing.0.ok(), assume_missing.1.as_str()),
+            (Some((0, 0)), "failed")
+        );
+        assert!(path("a").exists());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copy_across_devices() -> io::Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        use crate::{error::IoOp, RenameStrategy};
+
+        let temp_dir = tempfile::tempdir()?;
+        let Ok(other_dir) = tempfile::tempdir_in("/dev/shm") else {
+            return Ok(());
+        };
+        if fs::metadata(temp_dir.path())?.dev() == fs::metadata(other_dir.path())?.dev() {
+            return Ok(());
+        }
+        let path = |name: &str| temp_dir.path().join(name);
+        let other = |name: &str| other_dir.path().join(name);
+        fs::create_dir_all(path("dir/sub"))?;
+        fs::write(path("dir/sub/file"), b"file")?;
+        std::os::unix::fs::symlink("sub/file", path("dir/link"))?;
+        let rename = super::Rename::new(path("dir"), other("dir"));
+
+        let err = rename.apply_with(&ApplyOptions::new()).unwrap_err();
+        assert!(matches!(
+            &err.details,
+            ApplyErrorDetails::Io { op: IoOp::Rename, error, .. }
+                if error.kind() == io::ErrorKind::CrossesDevices
+        ));
+
+        let options = ApplyOptions::new().copy_across_devices(true);
+        let applied = rename.apply_with(&options).unwrap();
+        assert_eq!(applied.strategy, RenameStrategy::Copied);
+        assert!(!path("dir").exists());
+        assert_eq!(fs::read(other("dir/link"))?, b"file");
+        assert_eq!(
+            fs::read_link(other("dir/link"))?,
+            std::path::Path::new("sub/file")
+        );
+
+        // The copy never replaces an existing target.
+        fs::write(path("file"), b"")?;
+        fs::write(other("file"), b"other")?;
+        let rename = super::Rename::new(path("file"), other("file"));
+        let err = rename.apply_with(&options).unwrap_err();
+        assert!(matches!(
+            err.details,
+            ApplyErrorDetails::TargetExists { .. }
+        ));
+        assert_eq!(fs::read(other("file"))?, b"other");
+        Ok(())
+    }
+}
@@ -0,0 +1,2367 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    collate::PlanOrdering,
+    error::{ApplyErrorDetails, IoOp},
+    inspect,
+    log::LogEvent,
+    messages::Messages,
+    metrics::MetricsSink,
+    report::{OperationStatus, RenameStatus},
+    tempname::{DefaultTempNamer, TempNamer},
+    truncate::truncate_path,
+};
+
+#[cfg(feature = "serde")]
+use crate::audit::AuditWriter;
+
+type LogCallback<'a> = Box<dyn FnMut(LogEvent<'_>) + 'a>;
+
+/// Options for [`Renamer::plan_with`](crate::renamer::Renamer::plan_with).
+#[derive(Debug, Clone, Default)]
+pub struct PlanOptions {
+    pub(crate) same_directory: bool,
+    pub(crate) execution_order: ExecutionOrder,
+    pub(crate) resolve_identity: bool,
+    pub(crate) stable_bytewise_order: bool,
+    pub(crate) ordering: Option<PlanOrdering>,
+    pub(crate) probe_case_sensitivity: bool,
+    pub(crate) coalesce_chains: bool,
+    pub(crate) assume_sorted: bool,
+    pub(crate) stamp_sources: bool,
+    pub(crate) validation_profile: ValidationProfile,
+    pub(crate) on_stat_error: StatErrorPolicy,
+    #[cfg(feature = "digest")]
+    pub(crate) stamp_digest_max_size: Option<u64>,
+    pub(crate) explicit: Explicit,
+}
+
+/// The names of the options set explicitly, with a setter or when
+/// deserialized.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Explicit(BTreeSet<&'static str>);
+
+impl Explicit {
+    pub(crate) fn insert(&mut self, name: &'static str) {
+        self.0.insert(name);
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+}
+
+/// Copies the explicitly set fields of `overrides` into `options`.
+///
+/// Fields are recorded as set under the name of their setter, which is the
+/// name of the field unless given as `field = "setter"`.
+macro_rules! merge_fields {
+    ($options:ident, $overrides:ident, $($field:ident $(= $setter:literal)?),* $(,)?) => {
+        $(
+            if $overrides.explicit.contains(merge_fields!(@setter $field $($setter)?)) {
+                $options.$field = $overrides.$field;
+            }
+        )*
+    };
+    (@setter $field:ident) => {
+        stringify!($field)
+    };
+    (@setter $field:ident $setter:literal) => {
+        $setter
+    };
+}
+
+/// The order in which the operations of a plan are executed.
+///
+/// See [`PlanOptions::execution_order`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExecutionOrder {
+    /// The operations are executed in plan order, sorted by target.
+    #[default]
+    TargetSorted,
+    /// The operations are grouped by source directory, and sorted by target
+    /// within each group.
+    SourceDirGrouped,
+    /// The operations are executed in the order they were added.
+    Unchanged,
+}
+
+impl PlanOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns these options, with the options explicitly set in `overrides`
+    /// taking precedence.
+    ///
+    /// Options are explicitly set by their setter, or when present in a
+    /// deserialized representation. This layers the options given on the
+    /// command line over the ones loaded from a configuration file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, Renamer};
+    /// let config = PlanOptions::new()
+    ///     .same_directory(true)
+    ///     .coalesce_chains(true);
+    /// let flags = PlanOptions::new().same_directory(false);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("photos/a.jpg", "archive/a.jpg");
+    /// renamer.add("archive/a.jpg", "archive/b.jpg");
+    ///
+    /// // The photo changes directory, and the chain is coalesced.
+    /// let plan = renamer.plan_with(&config.merge(flags))?;
+    /// assert_eq!(plan.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn merge(mut self, overrides: Self) -> Self {
+        merge_fields!(
+            self,
+            overrides,
+            same_directory,
+            execution_order,
+            resolve_identity,
+            stable_bytewise_order,
+            ordering,
+            probe_case_sensitivity,
+            coalesce_chains,
+            assume_sorted,
+            stamp_sources,
+            validation_profile,
+            on_stat_error,
+        );
+        #[cfg(feature = "digest")]
+        merge_fields!(self, overrides, stamp_digest_max_size = "stamp_digests");
+        self.explicit.0.extend(overrides.explicit.0);
+        self
+    }
+
+    /// Sets whether every target must stay in the directory of its source.
+    ///
+    /// When enabled, planning fails with
+    /// [`PlanError::ChangesDirectory`](crate::PlanError::ChangesDirectory)
+    /// if the target parent of any operation differs from its source parent.
+    /// Parents are compared once made absolute and normalized, so a target
+    /// given as a bare file name is in the current directory, not in the
+    /// directory of its source. In exchange, applying the plan skips every
+    /// parent directory check. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanError, PlanOptions, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("photos/a.jpg", "photos/b.jpg");
+    /// renamer.add("photos/c.jpg", "archive/c.jpg");
+    ///
+    /// let options = PlanOptions::new().same_directory(true);
+    /// let result = renamer.plan_with(&options);
+    /// let Err(PlanError::ChangesDirectory(pairs)) = result else {
+    ///     panic!("expected an error");
+    /// };
+    /// assert_eq!(pairs, [("photos/c.jpg".into(), "archive/c.jpg".into())]);
+    /// ```
+    pub fn same_directory(mut self, same_directory: bool) -> Self {
+        self.same_directory = same_directory;
+        self.explicit.insert("same_directory");
+        self
+    }
+
+    /// Sets the order in which the operations are executed.
+    ///
+    /// The plan is always sorted by target, as displayed by
+    /// [`Plan::write_to`](crate::Plan::write_to), but applying it can walk
+    /// the operations in another order. For instance, grouping them by
+    /// source directory avoids bouncing between directories on spinning
+    /// disks. Defaults to [`ExecutionOrder::TargetSorted`].
+    ///
+    /// Operations given a priority with
+    /// [`Renamer::add_with_priority`](crate::Renamer::add_with_priority) are
+    /// executed by decreasing priority first, this order only breaking ties.
+    /// Either way, an operation whose target is the source of another
+    /// operation is executed after it, so that a chain such as `a => b` and
+    /// `b => c` renames `b` first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{ApplyOptions, ExecutionOrder, PlanOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// fs::create_dir(path("x"))?;
+    /// fs::create_dir(path("y"))?;
+    /// for name in ["x/1", "y/2", "x/3"] {
+    ///     fs::write(path(name), b"")?;
+    /// }
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("x/1"), path("a"));
+    /// renamer.add(path("y/2"), path("b"));
+    /// renamer.add(path("x/3"), path("c"));
+    ///
+    /// let order = ExecutionOrder::SourceDirGrouped;
+    /// let options = PlanOptions::new().execution_order(order);
+    /// let plan = renamer.plan_with(&options)?;
+    /// let report = plan.apply_with(&ApplyOptions::new());
+    /// let targets: Vec<_> = report
+    ///     .entries()
+    ///     .iter()
+    ///     .map(|entry| &entry.target)
+    ///     .collect();
+    /// assert_eq!(targets, [&path("a"), &path("c"), &path("b")]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn execution_order(mut self, execution_order: ExecutionOrder) -> Self {
+        self.execution_order = execution_order;
+        self.explicit.insert("execution_order");
+        self
+    }
+
+    /// Sets whether sources are compared by identity rather than by path.
+    ///
+    /// When enabled, planning resolves the directory entry of every source,
+    /// and fails with
+    /// [`PlanError::DuplicateSources`](crate::PlanError::DuplicateSources)
+    /// if two operations have the same source, even when it is reached
+    /// through different paths, e.g. through a symbolic link to a directory.
+    /// Hard links to the same file are different entries, and are not
+    /// reported. Sources whose parent directory cannot be resolved, such as
+    /// missing ones, are compared by path. This accesses the filesystem, and
+    /// is disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanError, PlanOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// std::fs::write(path("a.txt"), b"")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    /// renamer.add(path("./a.txt"), path("c.txt"));
+    ///
+    /// let options = PlanOptions::new().resolve_identity(true);
+    /// assert!(matches!(
+    ///     renamer.plan_with(&options),
+    ///     Err(PlanError::DuplicateSources(_))
+    /// ));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn resolve_identity(mut self, resolve_identity: bool) -> Self {
+        self.resolve_identity = resolve_identity;
+        self.explicit.insert("resolve_identity");
+        self
+    }
+
+    /// Sets whether the plan is sorted bytewise, whatever the enabled
+    /// features.
+    ///
+    /// When enabled, the plan is sorted with
+    /// [`PlanOrdering::Bytewise`](crate::PlanOrdering::Bytewise), so that
+    /// the same inputs are written in the same order by every build, on every
+    /// platform. This is useful to diff plans over time. Otherwise, the
+    /// ordering depends on the `unicode` feature, see
+    /// [`Plan::ordering`](crate::Plan::ordering). This is the same as
+    /// requesting the [ordering](Self::ordering) `PlanOrdering::Bytewise`,
+    /// which takes precedence if set. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, PlanOrdering, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a", "file10");
+    /// renamer.add("b", "file2");
+    ///
+    /// let options = PlanOptions::new().stable_bytewise_order(true);
+    /// let plan = renamer.plan_with(&options)?;
+    /// assert_eq!(plan.ordering(), PlanOrdering::Bytewise);
+    ///
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"a => file10\nb => file2\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn stable_bytewise_order(mut self, stable_bytewise_order: bool) -> Self {
+        self.stable_bytewise_order = stable_bytewise_order;
+        self.explicit.insert("stable_bytewise_order");
+        self
+    }
+
+    /// Sets the collation the plan is sorted with, whatever the enabled
+    /// features.
+    ///
+    /// Without this option, plans are sorted with the
+    /// [default](PlanOrdering::default) collation, unless
+    /// [`stable_bytewise_order`](Self::stable_bytewise_order) is enabled.
+    /// The default depends on the `unicode` feature, which another crate
+    /// may enable: tests comparing written plans should request the
+    /// collation they expect.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, PlanOrdering, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a", "file10");
+    /// renamer.add("b", "file2");
+    ///
+    /// let options = PlanOptions::new().ordering(PlanOrdering::Bytewise);
+    /// let plan = renamer.plan_with(&options)?;
+    /// assert_eq!(plan.ordering(), PlanOrdering::Bytewise);
+    /// assert_eq!(plan.renames()[0].target, "file10");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn ordering(mut self, ordering: PlanOrdering) -> Self {
+        self.ordering = Some(ordering);
+        self.explicit.insert("ordering");
+        self
+    }
+
+    /// Returns the collation plans are sorted with.
+    pub(crate) fn plan_ordering(&self) -> PlanOrdering {
+        match self.ordering {
+            Some(ordering) => ordering,
+            None if self.stable_bytewise_order => PlanOrdering::Bytewise,
+            None => PlanOrdering::default(),
+        }
+    }
+
+    /// Sets whether the case sensitivity of the target directories is
+    /// probed.
+    ///
+    /// When enabled, planning creates a probe file in the parent directory
+    /// of every target, or in its closest existing ancestor, and looks it up
+    /// under another case. Each directory is probed once; the probe files
+    /// are removed right away, but the modification time of the directories
+    /// is updated. Planning fails with
+    /// [`PlanError::CaseProbe`](crate::PlanError::CaseProbe) if a directory
+    /// cannot be probed, e.g. when it is read-only.
+    ///
+    /// In case-insensitive directories, names differing only by case name
+    /// the same file, even when other directories are case-sensitive, such
+    /// as a case-sensitive volume mounted under a case-insensitive one. This
+    /// drives the rest of the plan:
+    ///
+    /// - [`Plan::collisions`](crate::Plan::collisions) does not report a
+    ///   target whose folded name is vacated by the plan, such as the target
+    ///   of a case-only rename.
+    /// - [`Plan::conflicts`](crate::Plan::conflicts) reports
+    ///   [`ConflictKind::CaseFold`](crate::ConflictKind::CaseFold) conflicts
+    ///   in these directories, whatever the
+    ///   [`ConflictOptions::case_fold`](crate::ConflictOptions::case_fold)
+    ///   option.
+    /// - Applying the plan does not fail with
+    ///   [`ApplyErrorDetails::TargetExists`] when the existing target is the
+    ///   source of a case-only rename.
+    ///
+    /// Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// std::fs::write(path("readme.md"), b"")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("readme.md"), path("README.md"));
+    ///
+    /// // The target of the case-only rename exists on case-insensitive
+    /// // filesystems, but it is its own source.
+    /// let options = PlanOptions::new().probe_case_sensitivity(true);
+    /// let plan = renamer.plan_with(&options)?;
+    /// assert!(plan.collisions()?.is_empty());
+    /// assert!(plan.apply().is_ok());
+    /// assert!(path("README.md").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn probe_case_sensitivity(mut self, probe_case_sensitivity: bool) -> Self {
+        self.probe_case_sensitivity = probe_case_sensitivity;
+        self.explicit.insert("probe_case_sensitivity");
+        self
+    }
+
+    /// Sets whether chained operations are merged.
+    ///
+    /// When enabled, an operation `a => b` followed by `b => c` is merged
+    /// into `a => c`, provided that the intermediate path `b` does not exist
+    /// and is not the target of any other operation, and that it is the
+    /// source of no other operation. Longer chains are merged into a single
+    /// operation, which is dropped if it ends where it started. The merged
+    /// operation takes the place of the first link of the chain, and the
+    /// number of merged entries is recorded in
+    /// [`PlanStats::coalesced`](crate::PlanStats::coalesced). Paths are
+    /// compared lexically, and chains whose intermediate path exists are left
+    /// alone. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a"), path("b"));
+    /// renamer.add(path("b"), path("c"));
+    ///
+    /// let options = PlanOptions::new().coalesce_chains(true);
+    /// let plan = renamer.plan_with(&options)?;
+    /// assert_eq!(plan.len(), 1);
+    /// assert_eq!(plan.stats().coalesced, 1);
+    ///
+    /// let mut output = Vec::new();
+    /// plan.write_with(&mut output, &Default::default())?;
+    /// assert!(output.ends_with(b"{a => c}\n"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn coalesce_chains(mut self, coalesce_chains: bool) -> Self {
+        self.coalesce_chains = coalesce_chains;
+        self.explicit.insert("coalesce_chains");
+        self
+    }
+
+    /// Sets whether the operations are expected to be added sorted by
+    /// target, then by source.
+    ///
+    /// When enabled, planning first compares every operation with the next
+    /// one, in the order the plan is sorted with (see
+    /// [`Plan::ordering`](crate::Plan::ordering)), and skips the sort if
+    /// they are in order. This saves the sort of large inputs that come
+    /// sorted already, such as from a database query; the comparisons are
+    /// still made with the collator, which only pays off when it is costly.
+    /// Otherwise, the operations are sorted anyway, with a warning, so that
+    /// the plan is the same either way. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("c", "a");
+    /// renamer.add("d", "b");
+    ///
+    /// let options = PlanOptions::new().assume_sorted(true);
+    /// let plan = renamer.plan_with(&options)?;
+    /// assert_eq!(plan.renames()[0].target, "a");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn assume_sorted(mut self, assume_sorted: bool) -> Self {
+        self.assume_sorted = assume_sorted;
+        self.explicit.insert("assume_sorted");
+        self
+    }
+
+    /// Sets whether the size and modification time of every source are
+    /// recorded in the plan, to be checked again just before renaming it.
+    ///
+    /// An operation whose source changed in between is failed with
+    /// [`ApplyErrorDetails::SourceChanged`], or skipped, as set by
+    /// [`ApplyOptions::on_source_changed`]. Directories are not checked,
+    /// since renaming their children changes them, nor are the sources that
+    /// cannot be read when planning, such as missing ones. Disabled by
+    /// default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{
+    /// #     ApplyOptions, PlanOptions, RenameStatus, Renamer, SourceChange,
+    /// # };
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// fs::write(path("a"), b"a")?;
+    /// fs::write(path("b"), b"b")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a"), path("a.bak"));
+    /// renamer.add(path("b"), path("b.bak"));
+    /// let plan = renamer.plan_with(&PlanOptions::new().stamp_sources(true))?;
+    ///
+    /// // The file changes after planning, and its rename is skipped.
+    /// fs::write(path("a"), b"changed")?;
+    /// let options = ApplyOptions::new().on_source_changed(SourceChange::Skip);
+    /// let report = plan.apply_with(&options);
+    /// assert!(matches!(report.entries()[0].status, RenameStatus::Skipped));
+    /// assert!(path("a").exists() && path("b.bak").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn stamp_sources(mut self, stamp_sources: bool) -> Self {
+        self.stamp_sources = stamp_sources;
+        self.explicit.insert("stamp_sources");
+        self
+    }
+
+    /// Sets the naming rules the targets are checked against.
+    ///
+    /// Under [`ValidationProfile::SmbCompatible`], planning fails with
+    /// [`PlanError::NonPortableTargets`](crate::PlanError::NonPortableTargets)
+    /// if the file name of any target breaks the Windows naming rules, even
+    /// when running on Unix: such names are created on a share mounted from
+    /// Unix, but Windows clients can neither open nor delete them. To fix
+    /// the targets instead, sanitize them with
+    /// [`Renamer::sanitize_targets`](crate::Renamer::sanitize_targets)
+    /// before planning. Defaults to [`ValidationProfile::Native`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{
+    /// #     NameIssue, PlanError, PlanOptions, Renamer, ValidationProfile,
+    /// # };
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a.txt", "share/notes ");
+    ///
+    /// let profile = ValidationProfile::SmbCompatible;
+    /// let options = PlanOptions::new().validation_profile(profile);
+    /// let err = renamer.plan_with(&options).unwrap_err();
+    /// let PlanError::NonPortableTargets(targets) = err else {
+    ///     panic!("unexpected error: {}", err);
+    /// };
+    /// assert_eq!(targets[0].1, NameIssue::TrailingSpace);
+    /// ```
+    pub fn validation_profile(mut self, validation_profile: ValidationProfile) -> Self {
+        self.validation_profile = validation_profile;
+        self.explicit.insert("validation_profile");
+        self
+    }
+
+    /// Sets what [`Plan::collisions`](crate::Plan::collisions) and
+    /// [`Plan::conflicts`](crate::Plan::conflicts) do when a path cannot be
+    /// inspected.
+    ///
+    /// With [`StatErrorPolicy::AssumeMissing`] or [`StatErrorPolicy::Skip`],
+    /// such a target is neither a collision nor a conflict, since applying
+    /// the plan with the same
+    /// [apply policy](crate::ApplyOptions::on_stat_error) either attempts or
+    /// skips the rename. Set both policies alike, so that the preview agrees
+    /// with the apply. Defaults to [`StatErrorPolicy::Fail`].
+    pub fn on_stat_error(mut self, policy: StatErrorPolicy) -> Self {
+        self.on_stat_error = policy;
+        self.explicit.insert("on_stat_error");
+        self
+    }
+
+    /// Also records the digest of the content of the sources, when they are
+    /// [stamped](Self::stamp_sources) and are regular files of at most
+    /// `max_size` bytes.
+    ///
+    /// The content of such a source is read again before renaming it, if its
+    /// size and modification time still match.
+    #[cfg(feature = "digest")]
+    pub fn stamp_digests(mut self, max_size: u64) -> Self {
+        self.stamp_digest_max_size = Some(max_size);
+        self.explicit.insert("stamp_digests");
+        self
+    }
+
+    /// Returns the maximum size of the sources whose digest is recorded in
+    /// their stamps, if any.
+    pub(crate) fn stamp_digest_max_size(&self) -> Option<u64> {
+        #[cfg(feature = "digest")]
+        return self.stamp_digest_max_size;
+        #[cfg(not(feature = "digest"))]
+        None
+    }
+}
+
+/// Options for [`Plan::apply_with`](crate::plan::Plan::apply_with).
+#[derive(Default)]
+pub struct ApplyOptions<'a> {
+    log: Option<RefCell<LogCallback<'a>>>,
+    pub(crate) boundary: Boundary,
+    pub(crate) continue_on_error: bool,
+    pub(crate) operation_timeout: Option<Duration>,
+    pub(crate) throttle: Throttle,
+    pub(crate) preserve_parent_mtimes: bool,
+    pub(crate) fix_symlinks_under: Option<PathBuf>,
+    pub(crate) fix_renamed_symlinks: bool,
+    pub(crate) dir_permissions: Option<u32>,
+    pub(crate) remove_unused_dirs: bool,
+    pub(crate) deny_symlinked_parents: bool,
+    pub(crate) no_symlink_traversal: bool,
+    pub(crate) allow_renaming_current_dir: bool,
+    pub(crate) idempotent: bool,
+    pub(crate) windows_defer_in_use: bool,
+    pub(crate) copy_across_devices: bool,
+    pub(crate) skip_free_space_check: bool,
+    pub(crate) lock: Option<LockScope>,
+    pub(crate) force_lock: bool,
+    pub(crate) expect: SourceKind,
+    pub(crate) follow_symlinks: FollowSymlinks,
+    pub(crate) on_stat_error: StatErrorPolicy,
+    pub(crate) dir_merge: DirMerge,
+    pub(crate) on_source_changed: SourceChange,
+    temp_namer: Option<Box<dyn TempNamer + 'a>>,
+    pub(crate) staging_dir: Option<PathBuf>,
+    metrics: Option<Box<dyn MetricsSink + 'a>>,
+    #[cfg(feature = "serde")]
+    pub(crate) progress_file: Option<(PathBuf, usize)>,
+    #[cfg(feature = "serde")]
+    pub(crate) audit_log: Option<RefCell<AuditWriter<'a>>>,
+    #[cfg(feature = "digest")]
+    pub(crate) digest_max_size: Option<u64>,
+    pub(crate) explicit: Explicit,
+}
+
+/// A boundary that rename operations must not cross.
+///
+/// See [`ApplyOptions::boundary`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Boundary {
+    /// The source and target must be on the same filesystem.
+    SameFilesystem,
+    /// The source and target must be in the same Git repository, or both
+    /// outside of any repository.
+    SameGitRepo,
+    /// No boundary is enforced.
+    #[default]
+    None,
+}
+
+/// A limit on the pace of rename operations, for filesystems that cannot keep
+/// up with many of them in a row, such as some network filesystems.
+///
+/// The default throttle does not limit anything. See
+/// [`ApplyOptions::throttle`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Throttle {
+    /// The maximum number of operations started per second, if any.
+    pub max_ops_per_sec: Option<u32>,
+    /// A number of operations, and how long to pause after every such
+    /// number of operations, if any.
+    pub pause_every: Option<(usize, Duration)>,
+}
+
+/// What to do when the source and the target of an operation are both
+/// directories, and the target exists.
+///
+/// See [`ApplyOptions::dir_merge`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DirMerge {
+    /// The operation fails with [`ApplyErrorDetails::TargetExists`], as for
+    /// any existing target.
+    #[default]
+    Error,
+    /// The children of the source are moved into the target, and the emptied
+    /// source is removed.
+    MergeInto,
+    /// The operation is skipped, leaving both directories untouched.
+    Skip,
+}
+
+/// What to do with an operation whose source changed since the plan was
+/// made.
+///
+/// See [`ApplyOptions::on_source_changed`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SourceChange {
+    /// The operation fails with [`ApplyErrorDetails::SourceChanged`].
+    #[default]
+    Fail,
+    /// The operation is skipped, with a
+    /// [`Warning::SourceChanged`](crate::Warning::SourceChanged), and the
+    /// others are applied.
+    Skip,
+}
+
+/// The naming rules targets are checked against when planning.
+///
+/// See [`PlanOptions::validation_profile`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationProfile {
+    /// The rules of the platform, enforced by the filesystem itself.
+    #[default]
+    Native,
+    /// The Windows naming rules, for targets on a share that Windows clients
+    /// access: file names must not end with a space or a dot, hold a
+    /// reserved character (`<>:"/\|?*` or a control character), or be a
+    /// reserved device name such as `CON`, `NUL` or `COM1`, with or without
+    /// an extension.
+    SmbCompatible,
+}
+
+/// The directory tree locked while applying a plan.
+///
+/// See [`ApplyOptions::lock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LockScope {
+    /// The deepest directory holding the sources and targets of every
+    /// operation.
+    CommonAncestor,
+    /// The given directory.
+    Path(PathBuf),
+}
+
+/// The kind of directory entry at a source path.
+///
+/// See [`ApplyOptions::expect`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SourceKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Dir,
+    /// A symbolic link, whatever it points to.
+    Symlink,
+    /// Another kind of entry, such as a named pipe or a socket.
+    Other,
+    /// Any kind of entry.
+    ///
+    /// This is only an expectation: an actual entry is never of this kind.
+    #[default]
+    Any,
+}
+
+impl SourceKind {
+    /// Returns the kind of the entry at a path.
+    pub(crate) fn of(path: &Path, follow: FollowSymlinks) -> io::Result<Self> {
+        Ok(Self::of_type(inspect::metadata(path, follow)?.file_type()))
+    }
+
+    /// Returns the kind of an entry of the specified type.
+    pub(crate) fn of_type(file_type: fs::FileType) -> Self {
+        if file_type.is_file() {
+            Self::File
+        } else if file_type.is_dir() {
+            Self::Dir
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Tests whether an actual kind meets this expectation.
+    pub(crate) fn matches(self, actual: Self) -> bool {
+        self == Self::Any || self == actual
+    }
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceKind::File => write!(f, "file"),
+            SourceKind::Dir => write!(f, "directory"),
+            SourceKind::Symlink => write!(f, "symbolic link"),
+            SourceKind::Other => write!(f, "special file"),
+            SourceKind::Any => write!(f, "any entry"),
+        }
+    }
+}
+
+/// Whether symbolic links are followed when inspecting sources and targets.
+///
+/// See [`ApplyOptions::follow_symlinks`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FollowSymlinks {
+    /// Symbolic links are inspected themselves, whatever they point to.
+    #[default]
+    No,
+    /// Symbolic links are inspected through what they point to.
+    Yes,
+}
+
+/// What to do when a target cannot be inspected, other than because it is
+/// missing.
+///
+/// Inspecting a target fails with a permission error when a parent
+/// directory cannot be searched, even though the rename itself may succeed,
+/// such as in a drop box directory writable but not readable by the current
+/// user. See [`ApplyOptions::on_stat_error`] and
+/// [`PlanOptions::on_stat_error`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StatErrorPolicy {
+    /// The operation fails, with the directory that could not be searched
+    /// when found.
+    #[default]
+    Fail,
+    /// The target is treated as missing, and the rename is attempted, failing
+    /// rather than replacing the target if it exists after all.
+    AssumeMissing,
+    /// The operation is skipped, leaving the source as is.
+    Skip,
+}
+
+impl<'a> ApplyOptions<'a> {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns these options, with the options explicitly set in `overrides`
+    /// taking precedence.
+    ///
+    /// See [`PlanOptions::merge`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Boundary, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("b.txt"))?;
+    ///
+    /// let config = ApplyOptions::new()
+    ///     .boundary(Boundary::SameFilesystem)
+    ///     .continue_on_error(true);
+    /// let flags = ApplyOptions::new().continue_on_error(false);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("missing.txt"), path("a.txt"));
+    /// renamer.add(path("b.txt"), path("c.txt"));
+    ///
+    /// // The apply stops at the first failure.
+    /// let report = renamer.plan()?.apply_with(&config.merge(flags));
+    /// assert_eq!(report.applied(), 0);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn merge(mut self, overrides: Self) -> Self {
+        merge_fields!(
+            self,
+            overrides,
+            log,
+            boundary,
+            continue_on_error,
+            operation_timeout,
+            throttle,
+            preserve_parent_mtimes,
+            fix_symlinks_under,
+            fix_renamed_symlinks,
+            dir_permissions,
+            remove_unused_dirs,
+            deny_symlinked_parents,
+            no_symlink_traversal,
+            allow_renaming_current_dir,
+            idempotent,
+            windows_defer_in_use,
+            copy_across_devices,
+            skip_free_space_check,
+            lock,
+            force_lock,
+            expect,
+            follow_symlinks,
+            on_stat_error,
+            dir_merge,
+            on_source_changed,
+            temp_namer,
+            staging_dir,
+            metrics,
+        );
+        #[cfg(feature = "serde")]
+        merge_fields!(self, overrides, progress_file, audit_log);
+        #[cfg(feature = "digest")]
+        merge_fields!(self, overrides, digest_max_size = "record_digests");
+        self.explicit.0.extend(overrides.explicit.0);
+        self
+    }
+
+    /// Sets a callback receiving a [`LogEvent`] for every step of the apply.
+    ///
+    /// The callback receives the same information as the `tracing` debug
+    /// messages, and is called whether or not the `tracing` feature is
+    /// enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let mut messages = Vec::new();
+    /// let options =
+    ///     ApplyOptions::new().log(|event| messages.push(event.to_string()));
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// drop(options);
+    ///
+    /// assert_eq!(messages.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn log<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(LogEvent<'_>) + 'a,
+    {
+        self.log = Some(RefCell::new(Box::new(callback)));
+        self.explicit.insert("log");
+        self
+    }
+
+    /// Sets the boundary that rename operations must not cross.
+    ///
+    /// The boundary is checked for every operation before anything is
+    /// renamed, and every crossing operation is reported as failed with
+    /// [`ApplyErrorDetails::CrossesBoundary`].
+    /// Targets that do not exist yet are checked against their closest
+    /// existing ancestor. Defaults to [`Boundary::None`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Boundary, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("sub/new.txt");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let options = ApplyOptions::new().boundary(Boundary::SameFilesystem);
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// assert!(new_path.exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self.explicit.insert("boundary");
+        self
+    }
+
+    /// Sets whether the apply continues with the next operations after a
+    /// failure.
+    ///
+    /// When disabled (the default), the apply stops at the first failure.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("b.txt"))?;
+    /// File::create(path("c.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("a-new.txt"));
+    /// renamer.add(path("b.txt"), path("b-new.txt"));
+    /// renamer.add(path("c.txt"), path("c-new.txt"));
+    ///
+    /// let options = ApplyOptions::new().continue_on_error(true);
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// assert_eq!(report.applied(), 2);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self.explicit.insert("continue_on_error");
+        self
+    }
+
+    /// Sets the maximum duration of each filesystem call made by a rename
+    /// operation.
+    ///
+    /// When set, every call runs on a helper thread, and a call that does not
+    /// complete in time fails the operation with
+    /// [`ApplyErrorDetails::TimedOut`]. The stuck call is left to complete in
+    /// the background. By default, there is no timeout.
+    pub fn operation_timeout(mut self, timeout: Duration) -> Self {
+        self.operation_timeout = Some(timeout);
+        self.explicit.insert("operation_timeout");
+        self
+    }
+
+    /// Sets a limit on the pace of the rename operations.
+    ///
+    /// The apply sleeps before an operation when it would exceed the limit,
+    /// and [logs](Self::log) a [`LogEvent::Pause`] beforehand, so that a
+    /// progress display can tell why nothing happens. The pauses are not
+    /// counted in the [duration](crate::ReportEntry::duration) of the
+    /// operations. By default, the operations are not throttled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the throttle allows zero operations per second, or pauses
+    /// every zero operations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{fs::File, time::Duration};
+    /// # use nominal::{ApplyOptions, LogEvent, Renamer, Throttle};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name: &str| temp_dir.path().join(name);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// for name in ["a", "b", "c"] {
+    ///     File::create(path(name))?;
+    ///     renamer.add(path(name), path(&format!("{name}.bak")));
+    /// }
+    ///
+    /// let mut pauses = 0;
+    /// let options = ApplyOptions::new()
+    ///     .throttle(Throttle {
+    ///         max_ops_per_sec: None,
+    ///         pause_every: Some((2, Duration::from_millis(10))),
+    ///     })
+    ///     .log(|event| {
+    ///         if let LogEvent::Pause { .. } = event {
+    ///             pauses += 1;
+    ///         }
+    ///     });
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// drop(options);
+    /// assert_eq!(pauses, 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn throttle(mut self, throttle: Throttle) -> Self {
+        assert!(
+            throttle.max_ops_per_sec != Some(0) && !matches!(throttle.pause_every, Some((0, _))),
+            "invalid throttle: {:?}",
+            throttle
+        );
+        self.throttle = throttle;
+        self.explicit.insert("throttle");
+        self
+    }
+
+    /// Sets whether the modification times of parent directories are
+    /// preserved.
+    ///
+    /// When enabled, the modification time of every source and target parent
+    /// directory is recorded before the first rename touching it, and
+    /// restored once the plan is applied. For targets whose parent does not
+    /// exist yet, the closest existing ancestor is preserved instead.
+    /// Failures to record or restore a time are reported as warnings in the
+    /// [`ApplyReport`](crate::ApplyReport). Disabled by default.
+    pub fn preserve_parent_mtimes(mut self, preserve_parent_mtimes: bool) -> Self {
+        self.preserve_parent_mtimes = preserve_parent_mtimes;
+        self.explicit.insert("preserve_parent_mtimes");
+        self
+    }
+
+    /// Sets a directory to scan for symbolic links to rewrite after applying
+    /// the plan.
+    ///
+    /// Once the plan is applied, every symbolic link under `root` whose
+    /// destination is a renamed source, or lies inside a renamed source
+    /// directory, is rewritten to point at the corresponding target. Relative
+    /// links stay relative, and absolute links stay absolute. Destinations
+    /// are resolved lexically, so links reaching a source through another
+    /// symbolic link are not detected.
+    ///
+    /// The rewritten links are listed in the
+    /// [`ApplyReport`](crate::ApplyReport).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    /// let link_path = temp_dir.path().join("link");
+    ///
+    /// File::create(&old_path)?;
+    /// # #[cfg(unix)]
+    /// std::os::unix::fs::symlink("old.txt", &link_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let options = ApplyOptions::new().fix_symlinks_under(temp_dir.path());
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// # #[cfg(unix)]
+    /// assert_eq!(
+    ///     std::fs::read_link(&link_path)?,
+    ///     std::path::Path::new("new.txt")
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn fix_symlinks_under(mut self, root: impl Into<PathBuf>) -> Self {
+        self.fix_symlinks_under = Some(root.into());
+        self.explicit.insert("fix_symlinks_under");
+        self
+    }
+
+    /// Sets whether renamed symbolic links pointing at another renamed
+    /// source are rewritten.
+    ///
+    /// Sources that are symbolic links are recorded before anything is
+    /// renamed. Once the plan is applied, each renamed link whose
+    /// destination is a renamed source, or lies inside a renamed source
+    /// directory, is rewritten at its new path to point at the
+    /// corresponding target. Relative links stay relative, and absolute
+    /// links stay absolute. Destinations are resolved lexically, as with
+    /// [`fix_symlinks_under`](Self::fix_symlinks_under), and the rewritten
+    /// links are listed in the [`ApplyReport`](crate::ApplyReport).
+    ///
+    /// When disabled, or when a link cannot be rewritten, the report holds a
+    /// [`Warning::DanglingSymlink`](crate::Warning::DanglingSymlink) for
+    /// each such link instead. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("data.txt"))?;
+    /// # #[cfg(unix)]
+    /// std::os::unix::fs::symlink("data.txt", path("latest"))?;
+    /// # #[cfg(windows)]
+    /// # std::os::windows::fs::symlink_file("data.txt", path("latest"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("data.txt"), path("data-v1.txt"));
+    /// renamer.add(path("latest"), path("current"));
+    ///
+    /// let options = ApplyOptions::new().fix_renamed_symlinks(true);
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// assert!(report.is_success() && report.warnings().is_empty());
+    /// assert_eq!(
+    ///     std::fs::read_link(path("current"))?,
+    ///     std::path::Path::new("data-v1.txt")
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn fix_renamed_symlinks(mut self, fix_renamed_symlinks: bool) -> Self {
+        self.fix_renamed_symlinks = fix_renamed_symlinks;
+        self.explicit.insert("fix_renamed_symlinks");
+        self
+    }
+
+    /// Sets the permissions of the directories created by the apply.
+    ///
+    /// On Unix, `mode` is set on every target parent directory the apply
+    /// creates, regardless of the umask. Directories that already exist are
+    /// never modified. On Windows, this option has no effect, and created
+    /// directories inherit the ACLs of their parent. By default, directories
+    /// are created with the default permissions.
+    ///
+    /// The created directories and their mode are listed in the
+    /// [`ApplyReport`](crate::ApplyReport).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("shared/new.txt");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let options = ApplyOptions::new().dir_permissions(Some(0o775));
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// let created = &report.created_dirs()[0];
+    /// assert_eq!(created.path, temp_dir.path().join("shared"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn dir_permissions(mut self, mode: Option<u32>) -> Self {
+        self.dir_permissions = mode;
+        self.explicit.insert("dir_permissions");
+        self
+    }
+
+    /// Sets whether the directories created by a failed apply are removed
+    /// when nothing was renamed into them.
+    ///
+    /// Target parents are created right before the first operation renaming
+    /// into them. If that operation then fails, the apply would leave empty
+    /// directories behind. When enabled and any operation fails, the created
+    /// directories that are still empty are removed, deepest first, and
+    /// dropped from the
+    /// [created directories](crate::ApplyReport::created_dirs) of the report
+    /// and of its entries. Directories that cannot be removed are reported
+    /// with a [`Warning::UnusedDir`](crate::Warning::UnusedDir). Disabled by
+    /// default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("missing.txt"), path("out/2025/07/new.txt"));
+    ///
+    /// let options = ApplyOptions::new().remove_unused_dirs(true);
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// assert!(!report.is_success());
+    /// assert!(report.created_dirs().is_empty());
+    /// assert!(!path("out").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn remove_unused_dirs(mut self, remove_unused_dirs: bool) -> Self {
+        self.remove_unused_dirs = remove_unused_dirs;
+        self.explicit.insert("remove_unused_dirs");
+        self
+    }
+
+    /// Sets whether operations whose target parent is or traverses a
+    /// symbolic link are rejected.
+    ///
+    /// Such operations may land in an unexpected directory, since the link is
+    /// followed when renaming and creating parents. By default, they are
+    /// reported with a
+    /// [`Warning::SymlinkedParent`](crate::Warning::SymlinkedParent) in the
+    /// [`ApplyReport`](crate::ApplyReport). When enabled, they fail the
+    /// preflight checks with [`ApplyErrorDetails::SymlinkedParent`] instead,
+    /// and nothing is renamed.
+    pub fn deny_symlinked_parents(mut self, deny_symlinked_parents: bool) -> Self {
+        self.deny_symlinked_parents = deny_symlinked_parents;
+        self.explicit.insert("deny_symlinked_parents");
+        self
+    }
+
+    /// Sets whether operations whose source or target traverses a symbolic
+    /// link are rejected.
+    ///
+    /// This is a stricter version of
+    /// [`deny_symlinked_parents`](Self::deny_symlinked_parents), guaranteeing
+    /// that nothing is renamed out of the directories named by the paths. Every
+    /// ancestor of the sources and targets is looked up, without following
+    /// links, and operations traversing a link fail the preflight checks with
+    /// [`ApplyErrorDetails::SymlinkTraversal`], so that nothing is renamed. The
+    /// paths are walked as written, so that links above a relative path, such
+    /// as in the current directory, are not found, while links in the system
+    /// directories holding an absolute path are. The sources and targets may
+    /// still be symbolic links themselves, since renaming a link does not
+    /// follow it. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{ApplyErrorDetails, Renamer};
+    /// # #[cfg(unix)]
+    /// # {
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let root = temp_dir.path().canonicalize()?;
+    /// std::fs::create_dir(root.join("real"))?;
+    /// std::os::unix::fs::symlink("real", root.join("link"))?;
+    /// std::fs::write(root.join("real/a.txt"), "a")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(root.join("link/a.txt"), root.join("b.txt"));
+    /// let options = nominal::ApplyOptions::new().no_symlink_traversal(true);
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// let err = report.into_result().unwrap_err();
+    /// let ApplyErrorDetails::SymlinkTraversal(link) = err.details else {
+    ///     panic!("unexpected error: {}", err);
+    /// };
+    /// assert_eq!(link, root.join("link"));
+    /// assert!(root.join("real/a.txt").exists());
+    /// # }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn no_symlink_traversal(mut self, no_symlink_traversal: bool) -> Self {
+        self.no_symlink_traversal = no_symlink_traversal;
+        self.explicit.insert("no_symlink_traversal");
+        self
+    }
+
+    /// Sets whether sources may be the current directory of the process, or
+    /// one of its ancestors.
+    ///
+    /// Renaming such a source leaves the process in a moved directory, where
+    /// the relative paths of the following operations no longer resolve.
+    /// Unless allowed, these operations fail the preflight checks with
+    /// [`ApplyErrorDetails::ContainsCurrentDir`], and nothing is renamed.
+    /// Sources are compared once their parent is canonicalized, so that `.`
+    /// and paths through symbolic links are caught, but a symbolic link to
+    /// the current directory is not. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{ApplyErrorDetails, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let work_dir = temp_dir.path().join("work");
+    ///
+    /// std::fs::create_dir(&work_dir)?;
+    /// std::env::set_current_dir(&work_dir)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(".", temp_dir.path().join("renamed"));
+    ///
+    /// let err = renamer.plan()?.apply().unwrap_err();
+    /// assert!(matches!(
+    ///     err.details,
+    ///     ApplyErrorDetails::ContainsCurrentDir(_)
+    /// ));
+    /// assert!(work_dir.exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn allow_renaming_current_dir(mut self, allow_renaming_current_dir: bool) -> Self {
+        self.allow_renaming_current_dir = allow_renaming_current_dir;
+        self.explicit.insert("allow_renaming_current_dir");
+        self
+    }
+
+    /// Sets whether operations found already applied succeed.
+    ///
+    /// When enabled, an operation whose target exists while its source is
+    /// gone is taken as applied by an earlier run, such as when a job is
+    /// retried, rather than failing with [`ApplyErrorDetails::TargetExists`].
+    /// It skips the preflight checks, and is reported as
+    /// [`RenameStatus::AlreadyApplied`](crate::RenameStatus::AlreadyApplied).
+    /// The existing target must be of the [expected](Self::expect) kind, and
+    /// match the [stamp](crate::PlanOptions::stamp_sources) of the source,
+    /// digest included if recorded. Without a stamp, as for directories or
+    /// plans made without stamps, nothing tells the renamed source from
+    /// another file, and the operation fails as usual. So do operations
+    /// whose source still exists. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{ApplyOptions, PlanOptions, RenameStatus, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// std::fs::write(path("a.txt"), "")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    /// let plan = renamer.plan_with(&PlanOptions::new().stamp_sources(true))?;
+    /// for result in plan.apply_iter() {
+    ///     result?;
+    /// }
+    ///
+    /// // Applying the plan again finds it already applied.
+    /// let report = plan.apply_with(&ApplyOptions::new().idempotent(true));
+    /// assert!(report.is_success());
+    /// assert!(matches!(
+    ///     report.entries()[0].status,
+    ///     RenameStatus::AlreadyApplied
+    /// ));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self.explicit.insert("idempotent");
+        self
+    }
+
+    /// Sets whether renames failing on Windows because another process holds
+    /// the source are scheduled for the next reboot.
+    ///
+    /// Some files cannot be renamed while they are open, such as by a shell
+    /// extension or an antivirus. As a last resort, once the rename failed
+    /// with a sharing or lock violation, it is scheduled with
+    /// `MoveFileExW` and `MOVEFILE_DELAY_UNTIL_REBOOT`, which usually
+    /// requires administrator privileges. The operation is then reported as
+    /// [`RenameStatus::Deferred`](crate::RenameStatus::Deferred): it is not
+    /// [applied](crate::ApplyReport::applied), and is neither recorded by an
+    /// [`UndoStack`](crate::UndoStack) nor followed by the fixes of the
+    /// renamed symbolic links. The target must not exist, as for any
+    /// rename. If the rename cannot be scheduled, the operation fails with
+    /// the original error. On other platforms, this option has no effect.
+    /// Disabled by default.
+    pub fn windows_defer_in_use(mut self, windows_defer_in_use: bool) -> Self {
+        self.windows_defer_in_use = windows_defer_in_use;
+        self.explicit.insert("windows_defer_in_use");
+        self
+    }
+
+    /// Sets whether sources that cannot be renamed to another filesystem are
+    /// copied to the target, then removed.
+    ///
+    /// A rename system call cannot move a path across filesystems. With this
+    /// option, a rename failing as such is replaced by a copy of the source,
+    /// its directory tree included, with symbolic links copied as links.
+    /// Once copied, the source is removed, and the operation is reported as
+    /// [`RenameStrategy::Copied`](crate::RenameStrategy::Copied). If the copy
+    /// fails, whatever was copied is removed and the source is left in
+    /// place.
+    ///
+    /// Unlike a rename, the copy is not atomic and needs space on the target
+    /// filesystem: before anything is renamed, the sources that would be
+    /// copied are checked to fit in the space available on each target
+    /// filesystem, unless
+    /// [`skip_free_space_check`](Self::skip_free_space_check) is set.
+    /// Disabled by default.
+    pub fn copy_across_devices(mut self, copy_across_devices: bool) -> Self {
+        self.copy_across_devices = copy_across_devices;
+        self.explicit.insert("copy_across_devices");
+        self
+    }
+
+    /// Sets whether the free space check of
+    /// [`copy_across_devices`](Self::copy_across_devices) is skipped.
+    ///
+    /// When checked, the sizes of the sources on another filesystem than
+    /// their targets are summed per target filesystem, and compared with the
+    /// space available there. If they do not fit, every such operation fails
+    /// with [`ApplyErrorDetails::InsufficientSpace`], and nothing is copied.
+    /// The check is an estimate: the space used by directories and the
+    /// concurrent use of the filesystem are not accounted for. Disabled by
+    /// default.
+    pub fn skip_free_space_check(mut self, skip_free_space_check: bool) -> Self {
+        self.skip_free_space_check = skip_free_space_check;
+        self.explicit.insert("skip_free_space_check");
+        self
+    }
+
+    /// Sets the directory tree locked while the plan is applied, so that
+    /// concurrent applies over the same tree do not race each other.
+    ///
+    /// The lock is an advisory lock on a `.nominal.lock` file in the locked
+    /// directory, taken with `flock` on Unix and `LockFileEx` on Windows
+    /// before anything is renamed, and released once the apply is over. The
+    /// file records the process holding the lock and when it took it. If
+    /// another apply holds the lock, the first operation fails with
+    /// [`ApplyErrorDetails::LockHeld`](crate::ApplyErrorDetails::LockHeld),
+    /// and the others are [pending](crate::RenameStatus::Pending). A lock
+    /// whose recorded holder is no longer running is stale, and broken. Only
+    /// applies taking the lock are kept out. Empty plans take no lock. By
+    /// default, nothing is locked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, LockScope, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("a.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    ///
+    /// let options = ApplyOptions::new().lock(Some(LockScope::CommonAncestor));
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// assert!(path("b.txt").exists());
+    /// // The lock file is removed once released.
+    /// assert!(!path(".nominal.lock").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn lock(mut self, lock: Option<LockScope>) -> Self {
+        self.lock = lock;
+        self.explicit.insert("lock");
+        self
+    }
+
+    /// Sets whether a [lock](Self::lock) held by another apply is broken.
+    ///
+    /// The other apply keeps running, unaware that its lock was broken:
+    /// this is only meant to recover from a lock that is stale, but whose
+    /// holder cannot be told apart from a running process, such as when it
+    /// was taken from another machine. Disabled by default.
+    pub fn force_lock(mut self, force_lock: bool) -> Self {
+        self.force_lock = force_lock;
+        self.explicit.insert("force_lock");
+        self
+    }
+
+    /// Sets whether the [digest](crate::Digest) of every renamed regular
+    /// file of at most `max_size` bytes is computed once renamed.
+    ///
+    /// Digests are reported in
+    /// [`ReportEntry::digest`](crate::ReportEntry::digest), and recorded by an
+    /// [`UndoStack`](crate::UndoStack): an operation is only undone if its file
+    /// still has the same content. Files are read in full, so `max_size` bounds
+    /// the time spent on a single file. A file that cannot be read is reported
+    /// with a [`Warning::Digest`](crate::Warning::Digest), and left without a
+    /// digest. Disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// std::fs::write(path("small.txt"), "small")?;
+    /// std::fs::write(path("large.txt"), "large, larger")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("small.txt"), path("a.txt"));
+    /// renamer.add(path("large.txt"), path("b.txt"));
+    ///
+    /// let options = ApplyOptions::new().record_digests(8);
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// assert!(report.entries()[0].digest.is_some());
+    /// assert!(report.entries()[1].digest.is_none());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "digest")]
+    pub fn record_digests(mut self, max_size: u64) -> Self {
+        self.digest_max_size = Some(max_size);
+        self.explicit.insert("record_digests");
+        self
+    }
+
+    /// Sets the kind of directory entry every source must be.
+    ///
+    /// The kind of each source is checked before anything is renamed,
+    /// without following symbolic links, and every mismatching operation is
+    /// reported as failed with [`ApplyErrorDetails::KindMismatch`]. This
+    /// guards plans generated ahead of time against sources replaced in the
+    /// meantime. Defaults to [`SourceKind::Any`], which skips the
+    /// check.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{ApplyErrorDetails, ApplyOptions, Renamer, SourceKind};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old");
+    /// let new_path = temp_dir.path().join("new");
+    ///
+    /// std::fs::create_dir(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let options = ApplyOptions::new().expect(SourceKind::File);
+    /// let report = renamer.plan()?.apply_with(&options);
+    /// let err = report.into_result().unwrap_err();
+    /// assert!(matches!(
+    ///     err.details,
+    ///     ApplyErrorDetails::KindMismatch {
+    ///         expected: SourceKind::File,
+    ///         actual: SourceKind::Dir,
+    ///     }
+    /// ));
+    /// assert!(old_path.exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn expect(mut self, kind: SourceKind) -> Self {
+        self.expect = kind;
+        self.explicit.insert("expect");
+        self
+    }
+
+    /// Sets whether symbolic links are followed when inspecting the sources
+    /// and the existing targets.
+    ///
+    /// By default, a symbolic link is inspected itself, whatever it points
+    /// to: a dangling link is an existing entry, of kind
+    /// [`SourceKind::Symlink`], and is never replaced as a target. Following
+    /// links checks the [expected kind](Self::expect) of a source against
+    /// the entry it points to, failing on dangling links, and treats a
+    /// target that is a dangling link as missing, so that the rename
+    /// replaces it. The rename itself always moves the link, not what it
+    /// points to, and parent directories are always followed.
+    ///
+    /// The same inspection is used by [dry runs](crate::Session::dry_run)
+    /// and applies, so that they agree on every source. Planning, including
+    /// [`Plan::collisions`](crate::Plan::collisions), never follows links.
+    /// Defaults to [`FollowSymlinks::No`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, FollowSymlinks, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// File::create(path("new.txt"))?;
+    /// # #[cfg(unix)]
+    /// std::os::unix::fs::symlink("missing.txt", path("latest"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("new.txt"), path("latest"));
+    ///
+    /// let options = ApplyOptions::new().follow_symlinks(FollowSymlinks::Yes);
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// assert!(path("latest").is_file());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn follow_symlinks(mut self, follow: FollowSymlinks) -> Self {
+        self.follow_symlinks = follow;
+        self.explicit.insert("follow_symlinks");
+        self
+    }
+
+    /// Sets what to do when a target cannot be inspected, other than because
+    /// it is missing.
+    ///
+    /// By default, the operation fails. When permission to search a parent
+    /// directory of the target is denied, it fails with
+    /// [`ApplyErrorDetails::PermissionDenied`], naming the deepest directory
+    /// that could be inspected, and otherwise with the error of the
+    /// filesystem.
+    /// [`StatErrorPolicy::AssumeMissing`] attempts the rename anyway, which
+    /// never replaces the target: it fails if the target exists after all,
+    /// and on the platforms where the kernel cannot refuse to replace it.
+    /// [`StatErrorPolicy::Skip`] leaves the operation
+    /// [skipped](crate::RenameStatus::Skipped). Timeouts always fail.
+    ///
+    /// [`Plan::collisions`](crate::Plan::collisions) and
+    /// [`Plan::conflicts`](crate::Plan::conflicts) follow the
+    /// [plan policy](crate::PlanOptions::on_stat_error) instead. Defaults to
+    /// [`StatErrorPolicy::Fail`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, PlanOptions, Renamer, StatErrorPolicy};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// File::create(path("a.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    ///
+    /// let policy = StatErrorPolicy::Skip;
+    /// let plan_options = PlanOptions::new().on_stat_error(policy);
+    /// let plan = renamer.plan_with(&plan_options)?;
+    /// assert!(plan.collisions()?.is_empty());
+    /// let options = ApplyOptions::new().on_stat_error(policy);
+    /// plan.apply_with(&options).into_result()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn on_stat_error(mut self, policy: StatErrorPolicy) -> Self {
+        self.on_stat_error = policy;
+        self.explicit.insert("on_stat_error");
+        self
+    }
+
+    /// Sets what to do when a directory is renamed onto an existing
+    /// directory.
+    ///
+    /// With [`DirMerge::MergeInto`], every child of the source is renamed into
+    /// the target with the same options, merging the child directories
+    /// recursively, and the emptied source is then removed. The operation is
+    /// reported as [`RenameStatus::Merged`](crate::RenameStatus::Merged). A
+    /// child clashing with an entry of the target fails the operation with
+    /// [`ApplyErrorDetails::MergeFailed`]; the children moved until then stay
+    /// in the target. With [`DirMerge::Skip`], the operation is reported as
+    /// [`RenameStatus::Skipped`](crate::RenameStatus::Skipped). Symbolic links
+    /// to directories are not merged. Defaults to [`DirMerge::Error`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{ApplyOptions, DirMerge, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// fs::create_dir_all(path("new/photos"))?;
+    /// fs::create_dir_all(path("old/photos"))?;
+    /// fs::write(path("new/photos/a.jpg"), b"")?;
+    /// fs::write(path("old/photos/b.jpg"), b"")?;
+    /// fs::write(path("old/notes.txt"), b"")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("old"), path("new"));
+    ///
+    /// let options = ApplyOptions::new().dir_merge(DirMerge::MergeInto);
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// assert!(!path("old").exists());
+    /// assert!(path("new/photos/a.jpg").exists());
+    /// assert!(path("new/photos/b.jpg").exists());
+    /// assert!(path("new/notes.txt").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn dir_merge(mut self, dir_merge: DirMerge) -> Self {
+        self.dir_merge = dir_merge;
+        self.explicit.insert("dir_merge");
+        self
+    }
+
+    /// Sets what to do with an operation whose source changed since the plan
+    /// was made, for plans [stamping](PlanOptions::stamp_sources) their
+    /// sources.
+    ///
+    /// The source is checked just before it is renamed. A failed operation
+    /// stops the apply, unless [`continue_on_error`](Self::continue_on_error)
+    /// is set. Defaults to [`SourceChange::Fail`].
+    pub fn on_source_changed(mut self, on_source_changed: SourceChange) -> Self {
+        self.on_source_changed = on_source_changed;
+        self.explicit.insert("on_source_changed");
+        self
+    }
+
+    /// Sets a file to which the progress of the apply is written.
+    ///
+    /// The file is rewritten before every `every` operations, and once the
+    /// apply is over. It is replaced atomically and flushed to disk, so that
+    /// neither readers nor a crash ever leave a partial file. It holds a JSON
+    /// object with the following fields:
+    ///
+    /// - `version`: the format version, see
+    ///   [`JSON_VERSION`](crate::JSON_VERSION).
+    /// - `nominal_version`: the version of the crate that wrote it.
+    /// - `state`: `"running"`, or once the apply is over, `"succeeded"` or
+    ///   `"failed"`.
+    /// - `total`: the number of operations in the plan.
+    /// - `completed`: the number of operations attempted so far.
+    /// - `errors`: the number of failed operations so far.
+    /// - `current`: `null`, or while running, an object with the `source`
+    ///   and `target` of the operation about to be executed, encoded as in
+    ///   [`Plan::to_json_value`](crate::Plan::to_json_value).
+    /// - `timestamp`: the time of the update, in seconds since the Unix
+    ///   epoch.
+    ///
+    /// If the file cannot be written, it is no longer updated, and a
+    /// [`Warning::ProgressFile`](crate::Warning::ProgressFile) is reported in
+    /// the [`ApplyReport`](crate::ApplyReport).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    /// let progress_path = temp_dir.path().join("progress.json");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let options = ApplyOptions::new().progress_file(&progress_path, 100);
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    ///
+    /// let progress = std::fs::read(&progress_path)?;
+    /// let progress: serde_json::Value = serde_json::from_slice(&progress)?;
+    /// assert_eq!(progress["state"], "succeeded");
+    /// assert_eq!(progress["completed"], 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn progress_file(mut self, path: impl Into<PathBuf>, every: usize) -> Self {
+        self.progress_file = Some((path.into(), every));
+        self.explicit.insert("progress_file");
+        self
+    }
+
+    /// Sets a writer to which an audit log of the apply is appended.
+    ///
+    /// A line is written and flushed after every attempted operation,
+    /// whether it was applied or failed, including operations failing the
+    /// preflight checks. Operations that were not attempted are not logged.
+    /// Each line holds the following fields, separated by spaces:
+    ///
+    /// - the time the operation completed, as an RFC 3339 timestamp in UTC,
+    ///   such as `2024-07-01T12:34:56.789Z`;
+    /// - the status, `applied`, `merged`, `skipped`, `already_applied`,
+    ///   `deferred` or `failed`;
+    /// - the source and target paths, encoded as in
+    ///   [`Plan::to_json_value`](crate::Plan::to_json_value);
+    /// - the error message as a JSON string, or `null` if the operation did
+    ///   not fail.
+    ///
+    /// Since control characters are escaped in JSON strings, a file name
+    /// cannot span several lines of the log. Unlike an
+    /// [`UndoStack`](crate::UndoStack), the log is never read back. It is
+    /// only written by [`Plan::apply_with`](crate::Plan::apply_with).
+    ///
+    /// If the writer fails, the log is no longer written, and a
+    /// [`Warning::AuditLog`](crate::Warning::AuditLog) is reported in the
+    /// [`ApplyReport`](crate::ApplyReport).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{fs::{File, OpenOptions}, io::Read};
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    /// let log_path = temp_dir.path().join("audit.log");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let log = OpenOptions::new().create(true).append(true).open(&log_path)?;
+    /// let options = ApplyOptions::new().audit_log(log);
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    ///
+    /// let log = std::fs::read_to_string(&log_path)?;
+    /// assert_eq!(log.lines().count(), 1);
+    /// assert_eq!(log.split(' ').nth(1), Some("applied"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn audit_log<W>(mut self, writer: W) -> Self
+    where
+        W: io::Write + 'a,
+    {
+        self.audit_log = Some(RefCell::new(Box::new(writer)));
+        self.explicit.insert("audit_log");
+        self
+    }
+
+    /// Sets the generator of the temporary names of the files written aside
+    /// while applying, such as the symbolic links rewritten by
+    /// [`fix_symlinks_under`](Self::fix_symlinks_under)
+    #[cfg_attr(
+        feature = "serde",
+        doc = "and the [progress file](Self::progress_file)."
+    )]
+    #[cfg_attr(
+        not(feature = "serde"),
+        doc = "and, with the `serde` feature, the progress file."
+    )]
+    ///
+    /// Defaults to [`DefaultTempNamer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{cell::RefCell, ffi::{OsStr, OsString}};
+    /// # use nominal::{ApplyOptions, Renamer, TempNamer};
+    /// #[derive(Default)]
+    /// struct Recorder(RefCell<Vec<OsString>>);
+    ///
+    /// impl TempNamer for Recorder {
+    ///     fn temp_name(&self, name: &OsStr, attempt: usize) -> OsString {
+    ///         let mut temp_name = name.to_owned();
+    ///         temp_name.push(format!(".tmp{}", attempt));
+    ///         self.0.borrow_mut().push(temp_name.clone());
+    ///         temp_name
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(unix)] {
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// std::fs::write(path("a.txt"), "")?;
+    /// std::os::unix::fs::symlink("a.txt", path("link"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    /// let recorder = Recorder::default();
+    /// let options = ApplyOptions::new()
+    ///     .fix_symlinks_under(temp_dir.path())
+    ///     .temp_namer(&recorder);
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    /// assert_eq!(recorder.0.take(), ["link.tmp0"]);
+    /// # }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn temp_namer(mut self, namer: impl TempNamer + 'a) -> Self {
+        self.temp_namer = Some(Box::new(namer));
+        self.explicit.insert("temp_namer");
+        self
+    }
+
+    /// Returns the generator of temporary names.
+    pub(crate) fn namer(&self) -> &(dyn TempNamer + 'a) {
+        self.temp_namer.as_deref().unwrap_or(&DefaultTempNamer)
+    }
+
+    /// Sets a [staging directory](crate::Staging) where the sources of the
+    /// operations breaking cycles, and of the swaps, are moved aside, instead
+    /// of temporary paths next to them.
+    ///
+    /// Every moved source is recorded along with its original path, so that
+    /// the sources left behind by a crash are listed and restored with
+    /// [`Staging::recover`](crate::Staging::recover). The records are removed
+    /// once their source is moved on, and the directory once empty. The
+    /// directory should be on the same filesystem as the sources, for them
+    /// to be moved at all; see
+    /// [`Staging::under_common_root`](crate::Staging::under_common_root).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs;
+    /// # use nominal::{ApplyOptions, Renamer, Staging};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// fs::write(path("a.txt"), "a")?;
+    /// fs::write(path("b.txt"), "b")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    /// renamer.add(path("b.txt"), path("a.txt"));
+    /// let options = ApplyOptions::new().staging_dir(path(Staging::DIR_NAME));
+    /// renamer.plan()?.apply_with(&options).into_result()?;
+    ///
+    /// assert_eq!(fs::read_to_string(path("a.txt"))?, "b");
+    /// assert!(!path(Staging::DIR_NAME).exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn staging_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.staging_dir = Some(dir.into());
+        self.explicit.insert("staging_dir");
+        self
+    }
+
+    /// Sets the sink receiving the metrics of the apply.
+    ///
+    /// See [`MetricsSink`] for the metrics and when they are recorded. By
+    /// default, metrics are not recorded.
+    pub fn metrics(mut self, sink: impl MetricsSink + 'a) -> Self {
+        self.metrics = Some(Box::new(sink));
+        self.explicit.insert("metrics");
+        self
+    }
+
+    /// Records the metrics of an operation, once its status is known.
+    pub(crate) fn record_metrics(
+        &self,
+        status: &RenameStatus,
+        duration: Option<Duration>,
+        created_dirs: usize,
+    ) {
+        let Some(metrics) = &self.metrics else {
+            return;
+        };
+        match status {
+            RenameStatus::Applied | RenameStatus::Merged => metrics.operation_applied(),
+            RenameStatus::Failed(_) => metrics.operation_failed(),
+            _ => {}
+        }
+        if created_dirs > 0 {
+            metrics.dirs_created(created_dirs);
+        }
+        if let Some(duration) = duration {
+            metrics.operation_duration(duration);
+        }
+    }
+
+    /// Runs a filesystem call, the operation `op` on `path`, within the
+    /// operation timeout.
+    pub(crate) fn run<F, R>(&self, op: IoOp, path: &Path, f: F) -> Result<R, ApplyErrorDetails>
+    where
+        F: FnOnce() -> io::Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let fail = |error| ApplyErrorDetails::io(op, path, error);
+        let Some(timeout) = self.operation_timeout else {
+            return f().map_err(fail);
+        };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver is gone if the call timed out.
+            let _ = sender.send(f());
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result.map_err(fail),
+            Err(RecvTimeoutError::Timeout) => Err(ApplyErrorDetails::TimedOut(timeout)),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(fail(io::Error::other("filesystem call panicked")))
+            }
+        }
+    }
+
+    pub(crate) fn emit(&self, event: LogEvent<'_>) {
+        debug!("{}", event);
+        if let Some(log) = &self.log {
+            (log.borrow_mut())(event);
+        }
+    }
+}
+
+impl fmt::Debug for ApplyOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ApplyOptions");
+        debug
+            .field("log", &self.log.is_some())
+            .field("boundary", &self.boundary)
+            .field("continue_on_error", &self.continue_on_error)
+            .field("operation_timeout", &self.operation_timeout)
+            .field("throttle", &self.throttle)
+            .field("preserve_parent_mtimes", &self.preserve_parent_mtimes)
+            .field("fix_symlinks_under", &self.fix_symlinks_under)
+            .field("fix_renamed_symlinks", &self.fix_renamed_symlinks)
+            .field("dir_permissions", &self.dir_permissions)
+            .field("remove_unused_dirs", &self.remove_unused_dirs)
+            .field("deny_symlinked_parents", &self.deny_symlinked_parents)
+            .field("no_symlink_traversal", &self.no_symlink_traversal)
+            .field(
+                "allow_renaming_current_dir",
+                &self.allow_renaming_current_dir,
+            )
+            .field("idempotent", &self.idempotent)
+            .field("windows_defer_in_use", &self.windows_defer_in_use)
+            .field("copy_across_devices", &self.copy_across_devices)
+            .field("skip_free_space_check", &self.skip_free_space_check)
+            .field("lock", &self.lock)
+            .field("force_lock", &self.force_lock)
+            .field("expect", &self.expect)
+            .field("follow_symlinks", &self.follow_symlinks)
+            .field("on_stat_error", &self.on_stat_error)
+            .field("dir_merge", &self.dir_merge)
+            .field("on_source_changed", &self.on_source_changed)
+            .field("temp_namer", &self.temp_namer.is_some())
+            .field("staging_dir", &self.staging_dir)
+            .field("metrics", &self.metrics.is_some());
+        #[cfg(feature = "serde")]
+        debug
+            .field("progress_file", &self.progress_file)
+            .field("audit_log", &self.audit_log.is_some());
+        #[cfg(feature = "digest")]
+        debug.field("digest_max_size", &self.digest_max_size);
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::Path, thread, time::Duration};
+
+    use super::ApplyOptions;
+    use crate::error::{ApplyErrorDetails, IoOp};
+
+    #[test]
+    fn run_without_timeout() {
+        let options = ApplyOptions::new();
+        assert_eq!(
+            options.run(IoOp::Stat, Path::new("a"), || Ok(42)).unwrap(),
+            42
+        );
+
+        let result = options.run(IoOp::Remove, Path::new("a"), || {
+            Err::<(), _>(io::ErrorKind::NotFound.into())
+        });
+        assert!(matches!(
+            result,
+            Err(ApplyErrorDetails::Io { op: IoOp::Remove, path, error })
+                if path == Path::new("a") && error.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn run_with_timeout() {
+        let options = ApplyOptions::new().operation_timeout(Duration::from_millis(10));
+        assert_eq!(
+            options.run(IoOp::Stat, Path::new("a"), || Ok(42)).unwrap(),
+            42
+        );
+
+        let result = options.run(IoOp::Rename, Path::new("a"), || {
+            thread::sleep(Duration::from_secs(1));
+            Ok(())
+        });
+        assert!(matches!(
+            result,
+            Err(ApplyErrorDetails::TimedOut(timeout)) if timeout == Duration::from_millis(10)
+        ));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn merge_stamp_digests() {
+        use super::PlanOptions;
+
+        let options = PlanOptions::new().merge(PlanOptions::new().stamp_digests(1024));
+        assert_eq!(options.stamp_digest_max_size(), Some(1024));
+        let options = options.merge(PlanOptions::new());
+        assert_eq!(options.stamp_digest_max_size(), Some(1024));
+    }
+}
+
+/// Options for [`Plan::write_with`](crate::plan::Plan::write_with)
+#[cfg_attr(
+    feature = "ansi",
+    doc = "and [`Plan::write_colored_with`](crate::plan::Plan::write_colored_with)."
+)]
+#[cfg_attr(
+    not(feature = "ansi"),
+    doc = "and, with the `ansi` feature, `Plan::write_colored_with`."
+)]
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    pub(crate) arrow: String,
+    pub(crate) compress: bool,
+    null_terminated: bool,
+    max_width: Option<usize>,
+    #[cfg(feature = "ansi")]
+    pub(crate) tint_classes: bool,
+    pub(crate) messages: Messages,
+    applied_marker: String,
+    blocked_marker: String,
+    pending_marker: String,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            arrow: "=>".to_owned(),
+            compress: true,
+            null_terminated: false,
+            max_width: None,
+            #[cfg(feature = "ansi")]
+            tint_classes: false,
+            messages: Messages::default(),
+            applied_marker: "✓".to_owned(),
+            blocked_marker: "!".to_owned(),
+            pending_marker: "·".to_owned(),
+        }
+    }
+}
+
+impl WriteOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the arrow separating sources from targets.
+    ///
+    /// Defaults to `=>`.
+    pub fn arrow(mut self, arrow: impl Into<String>) -> Self {
+        self.arrow = arrow.into();
+        self
+    }
+
+    /// Sets whether the common ancestor of the source and target is factored
+    /// out, as in `dir/{old => new}`.
+    ///
+    /// When disabled, both full paths are always written. Enabled by default.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets whether lines are terminated with a NUL byte instead of a
+    /// newline.
+    ///
+    /// Disabled by default.
+    pub fn null_terminated(mut self, null_terminated: bool) -> Self {
+        self.null_terminated = null_terminated;
+        self
+    }
+
+    /// Sets the maximum display width of each written path.
+    ///
+    /// Longer paths are truncated with an ellipsis in the middle, preserving
+    /// the file name and as much of the leading directories as fits. Widths
+    /// are measured in terminal columns, so wide characters count double.
+    /// When the common ancestor is factored out, the ancestor and the
+    /// remaining parts are truncated separately.
+    ///
+    /// Truncation only affects the written output. By default, paths are not
+    /// truncated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Renamer, WriteOptions};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(
+    ///     "photos/2024/holidays/img_0001.jpg",
+    ///     "photos/2024/holidays/beach.jpg",
+    /// );
+    /// let plan = renamer.plan()?;
+    ///
+    /// let options = WriteOptions::new().compress(false).max_width(20);
+    /// let mut output = Vec::new();
+    /// plan.write_with(&mut output, &options)?;
+    /// assert_eq!(
+    ///     String::from_utf8(output)?,
+    ///     "photos…/img_0001.jpg => photos/20…/beach.jpg\n"
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Sets whether the colored writers tint the arrow of each rename
+    /// operation according to its [class](crate::RenameClass).
+    ///
+    /// Case-only and normalization-only renames stand out in bold, and moves
+    /// in blue, in bold when the file name also changes. Disabled by
+    /// default.
+    #[cfg(feature = "ansi")]
+    pub fn tint_classes(mut self, tint_classes: bool) -> Self {
+        self.tint_classes = tint_classes;
+        self
+    }
+
+    /// Sets the messages used by the writers, such as the summary template.
+    ///
+    /// Defaults to English messages.
+    pub fn messages(mut self, messages: Messages) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Sets the marker written before the operations of the given status by
+    /// [`Plan::write_annotated_with`](crate::Plan::write_annotated_with).
+    ///
+    /// Defaults to `✓` for applied operations, `!` for blocked ones and `·`
+    /// for pending ones.
+    pub fn status_marker(mut self, status: OperationStatus, marker: impl Into<String>) -> Self {
+        *match status {
+            OperationStatus::Applied => &mut self.applied_marker,
+            OperationStatus::Blocked => &mut self.blocked_marker,
+            OperationStatus::Pending => &mut self.pending_marker,
+        } = marker.into();
+        self
+    }
+
+    /// Returns the marker of a status.
+    pub(crate) fn marker(&self, status: OperationStatus) -> &str {
+        match status {
+            OperationStatus::Applied => &self.applied_marker,
+            OperationStatus::Blocked => &self.blocked_marker,
+            OperationStatus::Pending => &self.pending_marker,
+        }
+    }
+
+    /// Returns a path as displayed, truncated to the maximum width.
+    pub(crate) fn display<'a>(&self, path: &'a Path) -> Cow<'a, str> {
+        match (path.to_string_lossy(), self.max_width) {
+            (Cow::Borrowed(path), Some(max_width)) => truncate_path(path, max_width),
+            (Cow::Owned(path), Some(max_width)) => {
+                Cow::Owned(truncate_path(&path, max_width).into_owned())
+            }
+            (path, None) => path,
+        }
+    }
+
+    pub(crate) fn terminator(&self) -> &'static [u8] {
+        self.terminator_str().as_bytes()
+    }
+
+    pub(crate) fn terminator_str(&self) -> &'static str {
+        if self.null_terminated {
+            "\0"
+        } else {
+            "\n"
+        }
+    }
+}
+
+/// Options for [`Plan::confirm_with`](crate::plan::Plan::confirm_with).
+#[cfg(feature = "confirm")]
+#[derive(Debug, Clone, Default)]
+pub struct ConfirmOptions {
+    pub(crate) messages: Messages,
+}
+
+#[cfg(feature = "confirm")]
+impl ConfirmOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the messages of the prompt.
+    ///
+    /// Defaults to English messages.
+    pub fn messages(mut self, messages: Messages) -> Self {
+        self.messages = messages;
+        self
+    }
+}
+
+/// Options for
+/// [`Plan::confirm_with_preview`](crate::plan::Plan::confirm_with_preview) and
+/// [`Plan::write_preview`](crate::plan::Plan::write_preview).
+#[cfg(feature = "confirm")]
+#[derive(Debug, Clone)]
+pub struct PreviewOptions {
+    pub(crate) head: usize,
+    pub(crate) tail: usize,
+    pub(crate) pager: bool,
+    pub(crate) confirm: ConfirmOptions,
+    pub(crate) write: WriteOptions,
+}
+
+#[cfg(feature = "confirm")]
+impl PreviewOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of operations previewed from the start of the plan.
+    ///
+    /// Defaults to 10.
+    pub fn head(mut self, head: usize) -> Self {
+        self.head = head;
+        self
+    }
+
+    /// Sets the number of operations previewed from the end of the plan.
+    ///
+    /// Defaults to 5.
+    pub fn tail(mut self, tail: usize) -> Self {
+        self.tail = tail;
+        self
+    }
+
+    /// Sets whether the prompt also accepts `v`, to page through the whole
+    /// plan before answering.
+    ///
+    /// The plan is piped to the command in the `PAGER` environment
+    /// variable, or to `less`, and written to the standard error if neither
+    /// can be run. The [hint](Messages::pager_hint) lists the extra answer.
+    /// Disabled by default.
+    pub fn pager(mut self, pager: bool) -> Self {
+        self.pager = pager;
+        self
+    }
+
+    /// Sets the options of the prompt.
+    pub fn confirm_options(mut self, confirm: ConfirmOptions) -> Self {
+        self.confirm = confirm;
+        self
+    }
+
+    /// Sets the options the operations are written with, in the preview and
+    /// in the pager.
+    pub fn write_options(mut self, write: WriteOptions) -> Self {
+        self.write = write;
+        self
+    }
+}
+
+#[cfg(feature = "confirm")]
+impl Default for PreviewOptions {
+    fn default() -> Self {
+        Self {
+            head: 10,
+            tail: 5,
+            pager: false,
+            confirm: ConfirmOptions::default(),
+            write: WriteOptions::default(),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{inspect, options::FollowSymlinks};
+
+/// The target parent directories that do not exist yet.
+#[derive(Debug, Default)]
+pub(crate) struct MissingParents {
+    pending: HashSet<PathBuf>,
+}
+
+impl MissingParents {
+    /// Finds the parents of the given targets that do not exist.
+    pub(crate) fn find<'a, I>(targets: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = &'a Path>,
+    {
+        let mut checked = HashSet::new();
+        let mut pending = HashSet::new();
+        for target in targets {
+            let Some(parent) = target.parent() else {
+                continue;
+            };
+            if parent.as_os_str().is_empty() || !checked.insert(parent) {
+                continue;
+            }
+            // Parents are followed, as by the rename itself.
+            if !inspect::exists(parent, FollowSymlinks::Yes)? {
+                pending.insert(parent.to_owned());
+            }
+        }
+        Ok(Self { pending })
+    }
+
+    /// Returns `true` if the parent of a target must be created, and marks it
+    /// and its ancestors as created.
+    pub(crate) fn take(&mut self, target: &Path) -> bool {
+        let Some(parent) = target.parent() else {
+            return false;
+        };
+        if !self.pending.remove(parent) {
+            return false;
+        }
+        for ancestor in parent.ancestors().skip(1) {
+            self.pending.remove(ancestor);
+        }
+        true
+    }
+
+    /// Returns the deepest missing directories, i.e. those that are not an
+    /// ancestor of another missing directory.
+    pub(crate) fn deepest(&self) -> Vec<PathBuf> {
+        let ancestors: HashSet<&Path> = self
+            .pending
+            .iter()
+            .flat_map(|parent| parent.ancestors().skip(1))
+            .collect();
+        self.pending
+            .iter()
+            .filter(|parent| !ancestors.contains(parent.as_path()))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::Path};
+
+    use super::MissingParents;
+
+    #[test]
+    fn missing_parents() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name| temp_dir.path().join(name);
+        fs::create_dir(path("existing"))?;
+
+        let targets = [
+            path("existing/a.txt"),
+            path("out/2025/07/a.txt"),
+            path("out/2025/07/b.txt"),
+            path("out/2025/08/a.txt"),
+            path("out/2025/c.txt"),
+        ];
+        let mut parents = MissingParents::find(targets.iter().map(|t| t.as_path()))?;
+
+        let mut deepest = parents.deepest();
+        deepest.sort();
+        assert_eq!(deepest, [path("out/2025/07"), path("out/2025/08")]);
+
+        assert!(!parents.take(&path("existing/a.txt")));
+        assert!(parents.take(&path("out/2025/07/a.txt")));
+        assert!(!parents.take(&path("out/2025/07/b.txt")));
+        assert!(!parents.take(&path("out/2025/c.txt")));
+        assert!(parents.take(&path("out/2025/08/a.txt")));
+        assert!(!parents.take(Path::new("a.txt")));
+        Ok(())
+    }
+}
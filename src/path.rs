@@ -0,0 +1,504 @@
+//! Path utilities.
+//!
+//! These are the helpers this crate uses to display and check rename
+//! operations, exposed so that downstream tools agree with it on what, for
+//! instance, the common ancestor of two paths is.
+//!
+//! [`common_ancestor`], [`normalize`] and [`relative_to`] are lexical: they
+//! never access the filesystem, so they do not resolve symbolic links, and
+//! they compare components exactly, even on case-insensitive filesystems.
+//! [`path_exists`] and [`same_file`] query the filesystem.
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{inspect, options::FollowSymlinks};
+
+/// Returns the longest common ancestor of two paths.
+///
+/// Paths are compared component by component, so `/a/bc` is not an
+/// ancestor of `/a/b`. Returns [`None`] if the paths share no component,
+/// e.g. for relative paths starting with different components, or for
+/// paths on different Windows drives.
+///
+/// This function is lexical: normalize both paths first, since `a/../b`
+/// and `b` have no common ancestor, and canonicalize them first to compare
+/// paths reached through symbolic links. Components are compared exactly,
+/// including their case, whatever the platform.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use nominal::path::common_ancestor;
+/// assert_eq!(
+///     common_ancestor(Path::new("/a/b/c"), Path::new("/a/b/d/e")),
+///     Some(Path::new("/a/b"))
+/// );
+/// assert_eq!(
+///     common_ancestor(Path::new("/a/b"), Path::new("/x")),
+///     Some(Path::new("/"))
+/// );
+/// assert_eq!(common_ancestor(Path::new("a"), Path::new("b")), None);
+/// ```
+pub fn common_ancestor<'a>(path_1: &'a Path, path_2: &'a Path) -> Option<&'a Path> {
+    split_common(path_1, path_2).map(|(common, _, _)| common)
+}
+
+/// Returns the longest common ancestor of two paths, as in
+/// [`common_ancestor`], along with the rest of each path, as stripped of
+/// the ancestor with [`Path::strip_prefix`].
+///
+/// Both paths are walked once, component by component.
+pub(crate) fn split_common<'a>(
+    path_1: &'a Path,
+    path_2: &'a Path,
+) -> Option<(&'a Path, &'a Path, &'a Path)> {
+    let (mut rest_1, mut rest_2) = (path_1.components(), path_2.components());
+    let mut depth = 0;
+    loop {
+        let (before_1, before_2) = (rest_1.clone(), rest_2.clone());
+        match (rest_1.next(), rest_2.next()) {
+            (Some(component_1), Some(component_2)) if component_1 == component_2 => depth += 1,
+            _ => {
+                (rest_1, rest_2) = (before_1, before_2);
+                break;
+            }
+        }
+    }
+    if depth == 0 {
+        return None;
+    }
+    // Strip the remaining components from the back, as `Path::parent`
+    // does, leaving a path that is its own ancestor untouched.
+    let common = match rest_1.clone().count() {
+        0 => path_1,
+        remaining => {
+            let mut common = path_1.components();
+            for _ in 0..remaining {
+                common.next_back();
+            }
+            common.as_path()
+        }
+    };
+    Some((common, rest_1.as_path(), rest_2.as_path()))
+}
+
+/// Normalizes a path lexically.
+///
+/// This removes `.` components, and `..` components along with the preceding
+/// component, without accessing the filesystem. Leading `..` components of
+/// relative paths are kept, and `..` components at the root are dropped.
+///
+/// Since symbolic links are not resolved, the normalized path may not point
+/// to the same file: if `a` is a symbolic link to `x/y`, then `a/..` is
+/// `x`, while its normalized form is the current directory. Use
+/// [`Path::canonicalize`] to resolve symbolic links instead.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use nominal::path::normalize;
+/// assert_eq!(normalize(Path::new("/a/./b/../c")), Path::new("/a/c"));
+/// assert_eq!(normalize(Path::new("a/../../b")), Path::new("../b"));
+/// assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+/// ```
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                Some(Component::ParentDir | Component::CurDir) | None => {
+                    normalized.push(component);
+                }
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Returns a relative path leading from a base directory to a path.
+///
+/// Both paths should be normalized, and both absolute or both relative.
+/// Returns [`None`] if no such relative path exists, e.g. when the paths are
+/// on different Windows drives, or the base has leading `..` components that
+/// the path does not share.
+///
+/// Like [`normalize`], this function is lexical: the returned path only
+/// leads to the path if the components of the base that it climbs out of
+/// with `..` are not symbolic links.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::Path;
+/// # use nominal::path::relative_to;
+/// assert_eq!(
+///     relative_to(Path::new("/a/b"), Path::new("/a/c/d")),
+///     Some("../c/d".into())
+/// );
+/// assert_eq!(relative_to(Path::new("/a"), Path::new("/a")), Some(".".into()));
+/// assert_eq!(relative_to(Path::new("../a"), Path::new("b")), None);
+/// ```
+pub fn relative_to(base: &Path, path: &Path) -> Option<PathBuf> {
+    let mut base_components = base.components().peekable();
+    let mut path_components = path.components().peekable();
+    while let (Some(b), Some(p)) = (base_components.peek(), path_components.peek()) {
+        if b != p {
+            break;
+        }
+        base_components.next();
+        path_components.next();
+    }
+    let mut relative = PathBuf::new();
+    for component in base_components {
+        match component {
+            Component::Normal(_) => relative.push(".."),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    for component in path_components {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return None,
+            _ => relative.push(component),
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    Some(relative)
+}
+
+/// Tests whether a path exists.
+///
+/// Unlike [`Path::try_exists`], this function does not follow symbolic
+/// links: a dangling symbolic link exists. Errors other than a missing path,
+/// such as a permission denied on a parent directory, are returned rather
+/// than treated as a missing path.
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::path::path_exists;
+/// let temp_dir = tempfile::tempdir()?;
+/// assert!(path_exists(temp_dir.path())?);
+/// assert!(!path_exists(temp_dir.path().join("missing"))?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn path_exists<P>(path: P) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+{
+    inspect::exists(path.as_ref(), FollowSymlinks::No)
+}
+
+/// Tests whether two paths refer to the same file.
+///
+/// Symbolic links are followed, so a symbolic link is the same file as its
+/// target. On Unix, files are compared by device and inode numbers, so hard
+/// links to the same file are the same file. On Windows, files are compared
+/// by canonicalized path, so hard links are different files. Fails if
+/// either path does not exist.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs;
+/// # use nominal::path::same_file;
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = |name| temp_dir.path().join(name);
+///
+/// fs::create_dir(path("dir"))?;
+/// fs::write(path("a"), b"")?;
+/// fs::write(path("b"), b"")?;
+/// assert!(same_file(path("a"), path("dir/../a"))?);
+/// assert!(!same_file(path("a"), path("b"))?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn same_file<P, Q>(path_1: P, path_2: Q) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let (metadata_1, metadata_2) = (path_1.as_ref().metadata()?, path_2.as_ref().metadata()?);
+        Ok((metadata_1.dev(), metadata_1.ino()) == (metadata_2.dev(), metadata_2.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(path_1.as_ref().canonicalize()? == path_2.as_ref().canonicalize()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        path::{Component, Path, PathBuf},
+    };
+
+    /// Returns every path made of up to four components among `a`, `b`,
+    /// `.` and `..`, absolute or relative.
+    fn paths() -> Vec<PathBuf> {
+        let mut paths = vec![PathBuf::new()];
+        let mut last = paths.clone();
+        for _ in 0..4 {
+            last = last
+                .iter()
+                .flat_map(|path| ["a", "b", ".", ".."].map(|component| path.join(component)))
+                .collect();
+            paths.extend(last.iter().cloned());
+        }
+        let absolute: Vec<_> = paths.iter().map(|path| Path::new("/").join(path)).collect();
+        paths.extend(absolute);
+        paths
+    }
+
+    /// Returns the normalized paths, deduplicated.
+    fn normalized_paths() -> Vec<PathBuf> {
+        let mut paths: Vec<_> = paths().iter().map(|path| super::normalize(path)).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    #[test]
+    fn common_ancestor() {
+        let path_1 = Path::new("/a/b/c/d");
+        let path_2 = Path::new("/a/b/e/f");
+        assert_eq!(
+            super::common_ancestor(path_1, path_2),
+            Some(Path::new("/a/b"))
+        );
+
+        let path_1 = Path::new("/a/b/c/d");
+        let path_2 = Path::new("/a/b/c/d/e/f");
+        assert_eq!(
+            super::common_ancestor(path_1, path_2),
+            Some(Path::new("/a/b/c/d"))
+        );
+
+        let path_1 = Path::new("/a/b/c/d");
+        let path_2 = Path::new("/x/y/z");
+        assert_eq!(super::common_ancestor(path_1, path_2), Some(Path::new("/")));
+
+        let path_1 = Path::new("a/b/c/d");
+        let path_2 = Path::new("x/y/z");
+        assert_eq!(super::common_ancestor(path_1, path_2), None);
+    }
+
+    #[test]
+    fn split_common() {
+        // The scanning implementation, which the split must match byte for
+        // byte.
+        fn scan<'a>(path_1: &'a Path, path_2: &'a Path) -> Option<(&'a Path, &'a Path, &'a Path)> {
+            let common = path_1.ancestors().find(|&ancestor| {
+                !ancestor.as_os_str().is_empty() && path_2.starts_with(ancestor)
+            })?;
+            Some((
+                common,
+                path_1.strip_prefix(common).unwrap(),
+                path_2.strip_prefix(common).unwrap(),
+            ))
+        }
+
+        let mut paths = paths();
+        paths.extend(["a/", "a//b/", "./a/./b", "/a/b/.", "//a"].map(PathBuf::from));
+        let bytes = |split: Option<(&Path, &Path, &Path)>| {
+            split.map(|(common, rest_1, rest_2)| {
+                [common, rest_1, rest_2].map(|path| path.as_os_str().to_owned())
+            })
+        };
+        for path_1 in &paths {
+            for path_2 in &paths {
+                assert_eq!(
+                    bytes(super::split_common(path_1, path_2)),
+                    bytes(scan(path_1, path_2)),
+                    "{:?} and {:?}",
+                    path_1,
+                    path_2
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn common_ancestor_properties() {
+        let mut paths = normalized_paths();
+        paths.retain(|path| !path.as_os_str().is_empty());
+        for path_1 in &paths {
+            for path_2 in &paths {
+                let ancestor = super::common_ancestor(path_1, path_2);
+                assert_eq!(ancestor, super::common_ancestor(path_2, path_1));
+                let Some(ancestor) = ancestor else {
+                    assert_ne!(
+                        path_1.components().next(),
+                        path_2.components().next(),
+                        "{:?} and {:?} share a component",
+                        path_1,
+                        path_2
+                    );
+                    continue;
+                };
+                assert!(path_1.starts_with(ancestor) && path_2.starts_with(ancestor));
+                // No longer ancestor is shared.
+                let depth = ancestor.components().count();
+                let (next_1, next_2) = (
+                    path_1.components().nth(depth),
+                    path_2.components().nth(depth),
+                );
+                assert!(
+                    next_1.is_none() || next_1 != next_2,
+                    "{:?} and {:?} share a longer ancestor than {:?}",
+                    path_1,
+                    path_2,
+                    ancestor
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        let cases = [
+            ("/a/b/../c/./d", "/a/c/d"),
+            ("/../a", "/a"),
+            ("a/../../b", "../b"),
+            ("./a/b/..", "a"),
+            ("a/..", ""),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(super::normalize(Path::new(path)), Path::new(expected));
+        }
+    }
+
+    #[test]
+    fn normalize_properties() {
+        for path in paths() {
+            let normalized = super::normalize(&path);
+            assert_eq!(super::normalize(&normalized), normalized, "{:?}", path);
+            assert_eq!(normalized.has_root(), path.has_root(), "{:?}", path);
+            // `..` components only remain at the start of relative paths.
+            let mut components = normalized.components().skip_while(|component| {
+                !normalized.has_root() && *component == Component::ParentDir
+            });
+            assert!(
+                components.all(|component| !matches!(
+                    component,
+                    Component::CurDir | Component::ParentDir
+                )),
+                "{:?} is normalized to {:?}",
+                path,
+                normalized
+            );
+        }
+    }
+
+    #[test]
+    fn relative_to() {
+        let cases = [
+            ("/a/b", "/a/b/c", Some("c")),
+            ("/a/b", "/a/c/d", Some("../c/d")),
+            ("/a/b", "/a/b", Some(".")),
+            ("/a/b", "/x", Some("../../x")),
+            ("a", "b/c", Some("../b/c")),
+            ("../a", "b", None),
+        ];
+        for (base, path, expected) in cases {
+            assert_eq!(
+                super::relative_to(Path::new(base), Path::new(path)),
+                expected.map(PathBuf::from)
+            );
+        }
+    }
+
+    #[test]
+    fn relative_to_properties() {
+        let paths = normalized_paths();
+        for base in &paths {
+            for path in &paths {
+                if base.has_root() != path.has_root() {
+                    continue;
+                }
+                let relative = super::relative_to(base, path);
+                if base.has_root() {
+                    assert!(relative.is_some(), "{:?} from {:?}", path, base);
+                }
+                if let Some(relative) = relative {
+                    assert!(relative.is_relative(), "{:?} from {:?}", path, base);
+                    assert_eq!(
+                        super::normalize(&base.join(&relative)),
+                        *path,
+                        "{:?} from {:?} is {:?}",
+                        path,
+                        base,
+                        relative
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn path_exists() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let file_path = temp_dir.path().join("file.txt");
+        let link_path = temp_dir.path().join("link.txt");
+
+        // The file does not exist yet.
+        assert!(!super::path_exists(&file_path)?);
+
+        // Create the file, check that it exists.
+        fs::File::create(&file_path)?;
+        assert!(super::path_exists(&file_path)?);
+
+        // Create a symbolic link, check that it exists.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&file_path, &link_path)?;
+            assert!(super::path_exists(&link_path)?);
+        }
+        #[cfg(windows)]
+        {
+            std::os::windows::fs::symlink_file(&file_path, &link_path)?;
+        }
+        assert!(super::path_exists(&link_path)?);
+
+        // Remove the file, check that the symbolic link still exists.
+        fs::remove_file(&file_path)?;
+        assert!(super::path_exists(&link_path)?);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn same_file() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+        fs::write(path("b"), b"")?;
+        fs::hard_link(path("a"), path("hard"))?;
+        std::os::unix::fs::symlink(path("a"), path("soft"))?;
+
+        assert!(super::same_file(path("a"), path("a"))?);
+        assert!(super::same_file(path("a"), path("hard"))?);
+        assert!(super::same_file(path("soft"), path("a"))?);
+        assert!(!super::same_file(path("a"), path("b"))?);
+        assert!(super::same_file(path("a"), path("missing")).is_err());
+        Ok(())
+    }
+}
@@ -1,11 +1,177 @@
-use std::{io, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
-use crate::{error::ApplyError, operation::Rename};
+use crate::{
+    apply_iter::ApplyIter,
+    casefold::{fold_case, CaseInsensitiveDirs},
+    classify::RenameClass,
+    collate::{PathCollation, PathCollator, PlanOrdering},
+    cycles,
+    emptied::EmptiedDirs,
+    error::{ApplyError, ApplyErrorDetails, InvalidTargetReason, IoOp, PlanError},
+    fsutil::resolve_parent,
+    lock::ApplyLock,
+    log::LogEvent,
+    messages::format_template,
+    mtime::ParentMtimes,
+    operation::{Rename, RenameStrategy},
+    options::{ApplyOptions, PlanOptions, SourceChange, StatErrorPolicy, WriteOptions},
+    parents::MissingParents,
+    path::{normalize, path_exists},
+    preflight,
+    quote::write_shell_quoted,
+    relocate::MovedDirs,
+    renamer::{resolved_parent, Renamer},
+    report::{ApplyReport, OperationStatus, RenameStatus, ReportEntry},
+    stamp::SourceStamp,
+    symlinks,
+    tag::Tag,
+    throttle::Throttler,
+    warning::Warning,
+};
+
+#[cfg(feature = "ansi")]
+use crate::warning::{TracingSink, WarningSink};
+#[cfg(feature = "serde")]
+use crate::{audit::AuditLog, progress::ProgressFile};
+#[cfg(feature = "confirm")]
+use crate::{
+    messages::ConfirmTheme,
+    options::{ConfirmOptions, PreviewOptions},
+};
 
 /// A renaming plan.
 #[derive(Debug)]
 pub struct Plan<S, T> {
     pub(crate) renames: Vec<Rename<S, T>>,
+    /// The indices of the renames, in execution order.
+    pub(crate) execution: Vec<usize>,
+    /// The cycles of renames, as the index of the rename executed first in
+    /// each cycle, mapped to the index of the rename executed last, whose
+    /// source is moved aside before the first.
+    pub(crate) cycles: HashMap<usize, usize>,
+    pub(crate) stats: PlanStats,
+    pub(crate) collator: Arc<PathCollator>,
+    pub(crate) same_directory: bool,
+    /// What [`Plan::collisions`] does when a target cannot be inspected.
+    pub(crate) on_stat_error: StatErrorPolicy,
+    pub(crate) case_insensitive_dirs: CaseInsensitiveDirs,
+    pub(crate) skipped: Vec<SkippedEntry>,
+    /// The directory relative paths are relative to.
+    pub(crate) base_dir: Option<PathBuf>,
+    /// The stamps of the sources, by rename index.
+    pub(crate) stamps: HashMap<usize, SourceStamp>,
+    /// The indices of the renames touching hidden or system files.
+    pub(crate) hidden: HashSet<usize>,
+}
+
+/// How the relative paths of a plan are resolved when applying it.
+#[derive(Debug, Clone)]
+pub(crate) enum Anchor {
+    /// Against the current directory, which is the base directory, if any.
+    CurrentDir,
+    /// Against the given base directory.
+    BaseDir(PathBuf),
+    /// Nowhere, since the base directory does not exist.
+    Missing(PathBuf),
+}
+
+/// Statistics about how a [`Plan`] was built.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct PlanStats {
+    /// The number of entries dropped because their source and target were
+    /// equal.
+    pub noops: usize,
+    /// The number of entries merged into the previous link of their chain.
+    ///
+    /// See [`PlanOptions::coalesce_chains`].
+    pub coalesced: usize,
+    /// The number of entries dropped because they repeated an earlier entry.
+    pub duplicates: usize,
+    /// The number of cycles of operations, such as two swapped files, each
+    /// broken through a temporary path when applied, or exchanged for a
+    /// swap where supported.
+    pub cycles: usize,
+}
+
+/// A summary of a [`Plan`], as returned by [`Plan::summary`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct PlanSummary {
+    /// The number of rename operations.
+    pub renames: usize,
+    /// The number of entries dropped because their source and target were
+    /// equal.
+    pub noops: usize,
+    /// The number of entries dropped for any reason.
+    pub skipped: usize,
+    /// The number of rename operations touching hidden or system files.
+    ///
+    /// See [`Plan::is_hidden`].
+    pub hidden: usize,
+    classes: HashMap<RenameClass, usize>,
+}
+
+impl PlanSummary {
+    /// Returns the number of rename operations of the specified class.
+    pub fn count(&self, class: RenameClass) -> usize {
+        self.classes.get(&class).copied().unwrap_or(0)
+    }
+}
+
+/// An entry dropped while building a [`Plan`].
+///
+/// See [`Plan::skipped`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SkippedEntry {
+    /// The source path of the entry.
+    pub source: PathBuf,
+    /// The target path of the entry.
+    pub target: PathBuf,
+    /// Why the entry was dropped.
+    pub reason: SkipReason,
+    /// The tag of the entry, if any.
+    pub tag: Option<Tag>,
+}
+
+impl SkippedEntry {
+    pub(crate) fn new<S, T>(rename: &Rename<S, T>, reason: SkipReason) -> Self
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        Self {
+            source: rename.source.as_ref().to_owned(),
+            target: rename.target.as_ref().to_owned(),
+            reason,
+            tag: rename.tag.clone(),
+        }
+    }
+}
+
+/// The reason why an entry was dropped while building a [`Plan`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SkipReason {
+    /// The source and the target of the entry were equal.
+    NoOp,
+    /// The entry was merged into the previous link of its chain, which now
+    /// renames straight to the target of the entry.
+    ///
+    /// See [`PlanOptions::coalesce_chains`].
+    Coalesced,
+    /// The entry renamed the same source to the same target as an earlier
+    /// entry, once both paths are [normalized](crate::path::normalize).
+    Duplicate,
 }
 
 impl<S, T> Plan<S, T> {
@@ -35,9 +201,212 @@ impl<S, T> Plan<S, T> {
     /// let plan = renamer.plan()?;
     /// assert_eq!(plan.len(), 1);
     /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
     pub fn len(&self) -> usize {
         self.renames.len()
     }
+
+    /// Returns the rename operation at an index, in plan order.
+    ///
+    /// See [`ApplyError::rename_index`].
+    pub fn get(&self, index: usize) -> Option<&Rename<S, T>> {
+        self.renames.get(index)
+    }
+
+    /// Returns the stamp recorded for the source of the rename operation at
+    /// an index, in plan order.
+    ///
+    /// Sources are only stamped with
+    /// [`PlanOptions::stamp_sources`], and if they could be read when
+    /// planning.
+    pub fn source_stamp(&self, index: usize) -> Option<&SourceStamp> {
+        self.stamps.get(&index)
+    }
+
+    /// Returns the rename operations of the plan, in plan order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("b.txt", "d.txt");
+    /// renamer.add("a.txt", "c.txt");
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(plan.renames()[0].source, "a.txt");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn renames(&self) -> &[Rename<S, T>] {
+        &self.renames
+    }
+
+    /// Returns statistics about how the plan was built.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("old.txt", "new.txt");
+    /// renamer.add("same.txt", "same.txt");
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(plan.len(), 1);
+    /// assert_eq!(plan.stats().noops, 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn stats(&self) -> &PlanStats {
+        &self.stats
+    }
+
+    /// Returns the entries dropped while building the plan, with the reason
+    /// why, in the order they were dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Renamer, SkipReason};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("old.txt", "new.txt");
+    /// renamer.add("same.txt", "same.txt");
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(plan.skipped().len(), 1);
+    /// assert_eq!(plan.skipped()[0].source, std::path::Path::new("same.txt"));
+    /// assert_eq!(plan.skipped()[0].reason, SkipReason::NoOp);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn skipped(&self) -> &[SkippedEntry] {
+        &self.skipped
+    }
+
+    /// Returns the collation the plan is sorted with.
+    ///
+    /// Plans are sorted by target with the collation requested with
+    /// [`PlanOptions::ordering`](crate::PlanOptions::ordering), or with
+    /// [`PlanOrdering::Bytewise`] when [`PlanOptions::stable_bytewise_order`]
+    /// is enabled. Otherwise, they are sorted with the
+    /// [default](PlanOrdering::default) collation: `PlanOrdering::Unicode` when
+    /// the `unicode` feature is enabled, and `PlanOrdering::Bytewise`
+    /// otherwise. The same collation sorts the directories returned by
+    /// [`missing_parents`](Self::missing_parents). Whatever the collation, the
+    /// order is total: operations with equal targets are sorted by source,
+    /// paths equal for the collation are sorted bytewise, and only identical
+    /// operations keep their insertion order. Plans built from the same
+    /// operations are thus written the same way, whatever the order they were
+    /// added in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOrdering, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("old.txt", "new.txt");
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(plan.ordering(), PlanOrdering::default());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn ordering(&self) -> PlanOrdering {
+        self.collator.ordering()
+    }
+
+    /// Returns a handle on the collation the plan is sorted with, to sort
+    /// other paths the same way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::{Path, PathBuf};
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a", "b");
+    /// renamer.add("e", "e");
+    /// renamer.add("c", "c");
+    /// renamer.add("d", "a");
+    ///
+    /// let plan = renamer.plan()?;
+    /// let collation = plan.collation();
+    /// let mut skipped: Vec<&Path> = (plan.skipped().iter())
+    ///     .map(|entry| entry.target.as_path())
+    ///     .collect();
+    /// skipped.sort_by(|p1, p2| collation.compare(p1, p2));
+    /// assert_eq!(skipped, [Path::new("c"), Path::new("e")]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn collation(&self) -> PathCollation {
+        PathCollation::from_collator(Arc::clone(&self.collator))
+    }
+
+    /// Returns the directory the relative paths of the plan are relative to,
+    /// if it is recorded.
+    ///
+    /// Relative paths only make sense against the directory they were built in.
+    /// When a plan holds any, the canonical current directory is recorded as
+    /// its base directory when it is built. Applying the plan from another
+    /// directory then resolves its relative paths against the base directory,
+    /// and fails the operations holding one with
+    /// [`ApplyErrorDetails::MissingBaseDir`] if it no longer exists.
+    #[cfg_attr(
+        feature = "serde",
+        doc = "The base directory is also kept in the",
+        doc = "[JSON representation](Self::to_json_value) of the plan."
+    )]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("old.txt", "new.txt");
+    /// let plan = renamer.plan()?;
+    /// let current_dir = std::env::current_dir()?.canonicalize()?;
+    /// assert_eq!(plan.base_dir(), Some(current_dir.as_path()));
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("/old.txt", "/new.txt");
+    /// assert_eq!(renamer.plan()?.base_dir(), None);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.base_dir.as_deref()
+    }
+
+    /// Sets the directory the relative paths of the plan are relative to,
+    /// such as when they were built against another directory than the
+    /// current one.
+    ///
+    /// See [`base_dir`](Self::base_dir).
+    pub fn with_base_dir(mut self, base_dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    /// Moves the relative paths of the plan to another base directory, such
+    /// as after copying the tree they name, and returns the previous base
+    /// directory.
+    ///
+    /// Absolute paths are left unchanged. See [`base_dir`](Self::base_dir).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// std::fs::write(temp_dir.path().join("old.txt"), "old")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("old.txt", "new.txt");
+    /// let mut plan = renamer.plan()?;
+    /// plan.rebase(temp_dir.path());
+    /// plan.apply()?;
+    /// assert!(temp_dir.path().join("new.txt").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn rebase(&mut self, new_base: impl Into<PathBuf>) -> Option<PathBuf> {
+        self.base_dir.replace(new_base.into())
+    }
 }
 
 impl<S, T> Plan<S, T>
@@ -45,87 +414,1794 @@ where
     S: AsRef<Path>,
     T: AsRef<Path>,
 {
-    /// Writes the plan to the specified writer.
-    pub fn write_to<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::Write,
-    {
+    /// Creates a plan from pre-built rename operations, with the specified
+    /// options.
+    ///
+    /// This sorts and validates the operations as
+    /// [`Renamer::plan_with`](crate::Renamer::plan_with) does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Plan, PlanOptions, Rename};
+    /// let renames = vec![
+    ///     Rename::new("b.txt", "d.txt"),
+    ///     Rename::new("a.txt", "c.txt"),
+    /// ];
+    ///
+    /// let plan = Plan::from_renames(renames, &PlanOptions::new())?;
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"a.txt => c.txt\nb.txt => d.txt\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_renames(
+        renames: Vec<Rename<S, T>>,
+        options: &PlanOptions,
+    ) -> Result<Self, PlanError> {
+        Renamer { renames }.plan_with(options)
+    }
+
+    /// Checks the invariants every plan upholds, and panics with the first
+    /// one broken.
+    ///
+    /// This is meant for property-based tests and fuzz targets building plans
+    /// from generated operations. The invariants are:
+    ///
+    /// - The execution order is a permutation of the operations.
+    /// - Every operation is executed after the operations moving its target
+    ///   away, and after those renaming a directory to an ancestor of its
+    ///   target, unless they depend on it in turn, as in a cycle.
+    /// - The operations are sorted by target, then by source, as described
+    ///   in [`ordering`](Self::ordering).
+    /// - No operation has equal source and target, an empty source or an
+    ///   invalid target.
+    /// - No two operations have the same target, once normalized.
+    /// - With [`PlanOptions::same_directory`], no operation changes
+    ///   directory.
+    /// - The [stats](Self::stats) count the [skipped](Self::skipped)
+    ///   entries.
+    ///
+    /// Plans are built from operations with duplicate targets, which
+    /// [`conflicts`](Self::conflicts) reports, but they cannot be applied in
+    /// full: such plans are not valid. With the `arbitrary` feature, the
+    /// generated [`Renamer`]s only have distinct targets.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a", "b");
+    /// renamer.add("b", "a");
+    /// renamer.add("c", "c");
+    ///
+    /// let options = PlanOptions::new().coalesce_chains(true);
+    /// renamer.plan_with(&options)?.assert_valid();
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn assert_valid(&self) {
+        let mut executed = vec![false; self.renames.len()];
+        for &index in &self.execution {
+            assert!(
+                !std::mem::replace(&mut executed[index], true),
+                "operation {index} is executed twice"
+            );
+        }
+        assert!(
+            executed.iter().all(|&executed| executed),
+            "some operations are never executed"
+        );
+
+        let mut position = vec![0; self.renames.len()];
+        for (rank, &index) in self.execution.iter().enumerate() {
+            position[index] = rank;
+        }
+        // The operations each operation must be executed after.
+        let before: Vec<Vec<usize>> = (self.renames.iter())
+            .map(|rename| {
+                let target = rename.target.as_ref();
+                (0..self.renames.len())
+                    .filter(|&other| {
+                        let other = &self.renames[other];
+                        other.source.as_ref() == target
+                            || target
+                                .ancestors()
+                                .skip(1)
+                                .any(|a| a == other.target.as_ref())
+                    })
+                    .collect()
+            })
+            .collect();
+        let depends_on = |index: usize, other: usize| {
+            let mut seen = vec![false; self.renames.len()];
+            let mut stack = vec![index];
+            while let Some(current) = stack.pop() {
+                for &next in &before[current] {
+                    if next == other {
+                        return true;
+                    }
+                    if !std::mem::replace(&mut seen[next], true) {
+                        stack.push(next);
+                    }
+                }
+            }
+            false
+        };
+        for (index, before) in before.iter().enumerate() {
+            for &other in before {
+                assert!(
+                    other == index || position[other] < position[index] || depends_on(other, index),
+                    "operation {index} is executed before operation {other}, which it depends on"
+                );
+            }
+        }
+
+        for pair in self.renames.windows(2) {
+            let (first, second) = (&pair[0], &pair[1]);
+            let order = self
+                .collator
+                .compare_total(first.target.as_ref(), second.target.as_ref())
+                .then_with(|| {
+                    self.collator
+                        .compare_total(first.source.as_ref(), second.source.as_ref())
+                });
+            assert!(
+                order.is_le(),
+                "{:?} is sorted before {:?}",
+                first.target.as_ref(),
+                second.target.as_ref()
+            );
+        }
+
+        let mut targets = HashSet::with_capacity(self.renames.len());
         for rename in &self.renames {
-            rename.write_to(writer)?;
+            let (source, target) = (rename.source.as_ref(), rename.target.as_ref());
+            assert_ne!(source, target, "operation is a no-op");
+            assert!(
+                targets.insert(normalize(target)),
+                "duplicate target {target:?}"
+            );
+            assert!(!source.as_os_str().is_empty(), "source is empty");
+            assert_eq!(
+                InvalidTargetReason::of(target),
+                None,
+                "invalid target {target:?}"
+            );
+            if self.same_directory {
+                assert_eq!(
+                    resolved_parent(source),
+                    resolved_parent(target),
+                    "operation changes directory"
+                );
+            }
         }
-        Ok(())
+
+        let count = |reason| {
+            self.skipped
+                .iter()
+                .filter(|entry| entry.reason == reason)
+                .count()
+        };
+        assert_eq!(
+            self.stats.noops,
+            count(SkipReason::NoOp),
+            "wrong no-op count"
+        );
+        assert_eq!(
+            self.stats.coalesced,
+            count(SkipReason::Coalesced),
+            "wrong coalesced count"
+        );
+        assert_eq!(
+            self.stats.duplicates,
+            count(SkipReason::Duplicate),
+            "wrong duplicate count"
+        );
     }
 
-    #[cfg(feature = "ansi")]
-    /// Writes the plan to the specified writer, with ANSI colors.
-    pub fn write_colored_to<W>(&self, writer: &mut W) -> io::Result<()>
-    where
-        W: io::Write,
-    {
-        let ls_colors = lscolors::LsColors::from_env().unwrap_or_else(|| {
-            tracing::warn!("could not read LS_COLORS environment variable");
-            lscolors::LsColors::default()
-        });
+    /// Returns the rename operations whose target already exists.
+    ///
+    /// Each target is checked once, without following symbolic links.
+    /// Targets that are also the source of another operation in the plan are
+    /// not reported, since they will be vacated. Sources and targets are
+    /// compared once the symbolic links in their parents are resolved, so
+    /// that a target reached through a symbolic link to a directory still
+    /// matches the source it vacates. In the directories found to be
+    /// case-insensitive by [`PlanOptions::probe_case_sensitivity`], names are
+    /// compared once their case is folded.
+    ///
+    /// Fails if a path cannot be inspected, unless
+    /// [`PlanOptions::on_stat_error`] says otherwise: such a target is then
+    /// not a collision.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("a.txt"))?;
+    /// File::create(path("b.txt"))?;
+    /// File::create(path("c.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("b.txt"));
+    /// renamer.add(path("b.txt"), path("d.txt"));
+    /// renamer.add(path("d.txt"), path("c.txt"));
+    ///
+    /// let plan = renamer.plan()?;
+    /// let collisions = plan.collisions()?;
+    /// assert_eq!(collisions.len(), 1);
+    /// assert_eq!(collisions[0].target, path("c.txt"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn collisions(&self) -> io::Result<Vec<&Rename<S, T>>> {
+        let lenient = self.on_stat_error != StatErrorPolicy::Fail;
+        let sources: HashSet<PathBuf> = self
+            .renames
+            .iter()
+            .map(|r| match resolve_parent(r.source.as_ref()) {
+                Err(_) if lenient => Ok(normalize(r.source.as_ref())),
+                resolved => resolved,
+            })
+            .collect::<io::Result<_>>()?;
+        // In case-insensitive directories, a target is vacated by any source
+        // with the same folded name.
+        let folded_sources: HashSet<PathBuf> = if self.case_insensitive_dirs.is_empty() {
+            HashSet::new()
+        } else {
+            sources.iter().map(|source| fold_name(source)).collect()
+        };
+        let mut collisions = Vec::new();
         for rename in &self.renames {
-            rename.write_colored_to(&ls_colors, writer)?;
+            let target = rename.target.as_ref();
+            let resolved = match resolve_parent(target) {
+                Ok(resolved) => resolved,
+                // The target itself cannot be inspected either.
+                Err(_) if lenient => continue,
+                Err(err) => return Err(err),
+            };
+            let vacated = if self.case_insensitive_dirs.contains_parent(target) {
+                folded_sources.contains(&fold_name(&resolved))
+            } else {
+                sources.contains(&resolved)
+            };
+            if vacated {
+                continue;
+            }
+            match path_exists(target) {
+                Ok(true) => collisions.push(rename),
+                Ok(false) => {}
+                Err(_) if lenient => {}
+                Err(err) => return Err(err),
+            }
         }
-        Ok(())
+        Ok(collisions)
     }
 
-    /// Prompts the user to confirm the plan.
+    /// Returns the directories that applying the plan would create.
     ///
-    /// If the plan is empty, this returns [`None`]. Otherwise, it prompts the
-    /// user to confirm the plan and returns the user's response.
+    /// Only the deepest missing directories are returned: creating one of
+    /// them also creates its missing ancestors. The directories are sorted
+    /// with the same collation as the plan.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use nominal::{Plan, Renamer};
-    /// let plan: Plan<&str, &str> = Renamer::new().plan()?;
-    /// assert!(plan.confirm()?.is_none());
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.jpg"), path("2025/07/a.jpg"));
+    /// renamer.add(path("b.jpg"), path("2025/07/b.jpg"));
+    /// renamer.add(path("c.jpg"), path("2025/13/c.jpg"));
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(
+    ///     plan.missing_parents()?,
+    ///     [path("2025/07"), path("2025/13")]
+    /// );
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
-    #[cfg(feature = "confirm")]
-    pub fn confirm(&self) -> io::Result<Option<bool>> {
-        Ok(if self.is_empty() {
-            None
-        } else {
-            let prompt = dialoguer::Confirm::new()
-                .with_prompt("Proceed?")
-                .interact()
-                .map_err(|dialoguer::Error::IO(err)| err)?;
-            Some(prompt)
-        })
+    pub fn missing_parents(&self) -> io::Result<Vec<PathBuf>> {
+        if self.same_directory {
+            return Ok(Vec::new());
+        }
+        let mut parents =
+            MissingParents::find(self.renames.iter().map(|r| r.target.as_ref()))?.deepest();
+        parents.sort_by(|p1, p2| self.collator.compare(p1, p2));
+        Ok(parents)
     }
 
-    /// Executes the plan.
+    /// Returns the source directories that applying the plan would leave
+    /// empty, given the current state of the filesystem.
+    ///
+    /// A directory is emptied when each of its entries is either the source
+    /// of an operation, or itself an emptied directory, and when no target
+    /// is moved into it. Each directory holding a source is read once, and
+    /// paths are compared once the symbolic links in their parents are
+    /// resolved, so the returned paths are absolute. They are sorted deepest
+    /// first, so that they can be removed in order, and with the same
+    /// collation as the plan within each depth.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use std::fs::File;
-    /// # use nominal::{Plan, Renamer};
+    /// # use std::fs;
+    /// # use nominal::Renamer;
     /// let temp_dir = tempfile::tempdir()?;
-    /// let old_path = temp_dir.path().join("old.txt");
-    /// let new_path = temp_dir.path().join("new.txt");
+    /// let root = temp_dir.path().canonicalize()?;
+    /// fs::create_dir_all(root.join("inbox/2024"))?;
+    /// fs::write(root.join("inbox/2024/a.jpg"), b"")?;
     ///
-    /// File::create(&old_path)?;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(root.join("inbox/2024/a.jpg"), root.join("a.jpg"));
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(
+    ///     plan.dirs_emptied()?,
+    ///     [root.join("inbox/2024"), root.join("inbox")]
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn dirs_emptied(&self) -> io::Result<Vec<PathBuf>> {
+        let mut dirs = EmptiedDirs::find(
+            self.renames.iter().map(|r| r.source.as_ref()),
+            self.renames.iter().map(|r| r.target.as_ref()),
+        )?
+        .into_dirs();
+        dirs.sort_by(|dir_1, dir_2| {
+            let depth = |dir: &Path| dir.components().count();
+            depth(dir_2)
+                .cmp(&depth(dir_1))
+                .then_with(|| self.collator.compare(dir_1, dir_2))
+        });
+        Ok(dirs)
+    }
+
+    /// Classifies the rename operations of the plan, to highlight the risky
+    /// ones.
     ///
+    /// Returns the index of each operation, in plan order, along with its
+    /// class. Classes are computed lexically, without accessing the
+    /// filesystem. The operations touching hidden or system files are
+    /// flagged apart, by [`is_hidden`](Self::is_hidden).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{RenameClass, Renamer};
     /// let mut renamer = Renamer::new();
-    /// renamer.add(&old_path, &new_path);
+    /// renamer.add("README", "readme");
+    /// renamer.add("notes.txt", "notes.md");
+    /// renamer.add("a.txt", "b.txt");
     ///
     /// let plan = renamer.plan()?;
-    /// plan.apply()?;
+    /// assert_eq!(
+    ///     plan.classify(),
+    ///     [
+    ///         (0, RenameClass::RenameInPlace),
+    ///         (1, RenameClass::ExtensionOnly),
+    ///         (2, RenameClass::CaseOnly),
+    ///     ]
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn classify(&self) -> Vec<(usize, RenameClass)> {
+        self.renames.iter().map(Rename::class).enumerate().collect()
+    }
+
+    /// Tests whether the source or target of the rename operation at an
+    /// index, in plan order, is hidden, or is a system file.
     ///
-    /// assert!(!old_path.exists());
-    /// assert!(new_path.exists());
+    /// On Unix, these are the paths whose file name starts with a dot. On
+    /// Windows, they are the sources with the hidden or system attribute,
+    /// read along with their [stamps](PlanOptions::stamp_sources) when
+    /// planning.
+    pub fn is_hidden(&self, index: usize) -> bool {
+        self.hidden.contains(&index)
+    }
+
+    /// Returns a summary of the plan, counting its operations by
+    /// [class](Self::classify).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{RenameClass, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a/report.txt", "b/report.txt");
+    /// renamer.add("a/notes.txt", "b/old-notes.txt");
+    /// renamer.add("a/draft.txt", "a/final.txt");
+    /// renamer.add("a/same.txt", "a/same.txt");
+    ///
+    /// let summary = renamer.plan()?.summary();
+    /// assert_eq!((summary.renames, summary.noops), (3, 1));
+    /// assert_eq!(summary.count(RenameClass::Move), 1);
+    /// assert_eq!(summary.count(RenameClass::MoveAndRename), 1);
+    /// assert_eq!(summary.count(RenameClass::RenameInPlace), 1);
+    /// assert_eq!(summary.count(RenameClass::CaseOnly), 0);
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
-    pub fn apply(self) -> Result<(), ApplyError> {
-        // TODO: Multiple rounds to handle acyclic conflicts.
+    pub fn summary(&self) -> PlanSummary {
+        let mut classes = HashMap::new();
         for rename in &self.renames {
-            rename.apply()?;
+            *classes.entry(rename.class()).or_default() += 1;
+        }
+        PlanSummary {
+            renames: self.len(),
+            noops: self.stats.noops,
+            skipped: self.skipped.len(),
+            hidden: self.hidden.len(),
+            classes,
         }
+    }
+
+    /// Writes a summary of the plan to the specified writer, followed by a
+    /// line terminator.
+    ///
+    /// The summary is formatted with the [summary
+    /// template](crate::Messages::summary) of the options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Renamer, WriteOptions};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a.txt", "b.txt");
+    /// renamer.add("c.txt", "c.txt");
+    /// let plan = renamer.plan()?;
+    ///
+    /// let mut output = Vec::new();
+    /// plan.write_summary(&mut output, &WriteOptions::new())?;
+    /// assert_eq!(output, b"1 rename operation(s), 1 unchanged\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_summary<W>(&self, writer: &mut W, options: &WriteOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let summary = format_template(
+            &options.messages.summary,
+            &[&self.len(), &self.stats.noops, &self.skipped.len()],
+        );
+        writer.write_all(summary.as_bytes())?;
+        writer.write_all(options.terminator())
+    }
+
+    /// Writes the plan to the specified writer.
+    pub fn write_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.write_with(writer, &WriteOptions::default())
+    }
+
+    /// Writes the plan to the specified writer, with the specified options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Renamer, WriteOptions};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("dir/old.txt", "dir/new.txt");
+    /// let plan = renamer.plan()?;
+    ///
+    /// let mut output = Vec::new();
+    /// plan.write_to(&mut output)?;
+    /// assert_eq!(output, b"dir/{old.txt => new.txt}\n");
+    ///
+    /// let options = WriteOptions::new()
+    ///     .arrow("->")
+    ///     .compress(false)
+    ///     .null_terminated(true);
+    /// let mut output = Vec::new();
+    /// plan.write_with(&mut output, &options)?;
+    /// assert_eq!(output, b"dir/old.txt -> dir/new.txt\0");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_with<W>(&self, writer: &mut W, options: &WriteOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut line = String::new();
+        for rename in &self.renames {
+            line.clear();
+            rename.push_line(&mut line, options);
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the plan to the specified writer, with unambiguous quoting,
+    /// such as to paste it where whitespace is not preserved.
+    ///
+    /// Each operation is written on its own line, as the source and target
+    /// quoted for POSIX shells and separated by `=>`, then a comment holding
+    /// the index of the operation in the plan, as in
+    /// `'old name.txt' => 'new name.txt' # 0`. Paths are enclosed in single
+    /// quotes, each single quote being written as `'\''`, so that every line
+    /// can be split into words by a shell, or any parser following its
+    /// quoting rules, even if it holds a newline.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("it's.txt", "dir/it is.txt");
+    /// renamer.add("b", "a");
+    /// let plan = renamer.plan()?;
+    ///
+    /// let mut output = Vec::new();
+    /// plan.write_quoted_to(&mut output)?;
+    /// assert_eq!(
+    ///     String::from_utf8(output)?,
+    ///     "'b' => 'a' # 0\n'it'\\''s.txt' => 'dir/it is.txt' # 1\n"
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_quoted_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        for (index, rename) in self.renames.iter().enumerate() {
+            write_shell_quoted(writer, rename.source.as_ref())?;
+            writer.write_all(b" => ")?;
+            write_shell_quoted(writer, rename.target.as_ref())?;
+            writeln!(writer, " # {}", index)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the plan to the specified writer, prefixing every operation
+    /// with the marker of its status.
+    ///
+    /// `statuses` holds the status of every operation, in plan order. This
+    /// fails with [`io::ErrorKind::InvalidInput`] if it does not hold as
+    /// many statuses as there are operations, before anything is written.
+    /// Each marker is followed by a space; see
+    /// [`WriteOptions::status_marker`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{OperationStatus, Renamer, WriteOptions};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("a.txt", "b.txt");
+    /// renamer.add("c.txt", "d.txt");
+    /// let plan = renamer.plan()?;
+    ///
+    /// let statuses = [OperationStatus::Applied, OperationStatus::Pending];
+    /// let options = WriteOptions::new();
+    /// let mut output = Vec::new();
+    /// plan.write_annotated_with(&mut output, &statuses, &options)?;
+    /// assert_eq!(
+    ///     String::from_utf8(output)?,
+    ///     "✓ a.txt => b.txt\n· c.txt => d.txt\n"
+    /// );
+    ///
+    /// let err = plan
+    ///     .write_annotated_with(&mut Vec::new(), &statuses[..1], &options)
+    ///     .unwrap_err();
+    /// assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_annotated_with<W>(
+        &self,
+        writer: &mut W,
+        statuses: &[OperationStatus],
+        options: &WriteOptions,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.check_statuses(statuses)?;
+        let mut line = String::new();
+        for (rename, &status) in self.renames.iter().zip(statuses) {
+            line.clear();
+            let _ = write!(line, "{} ", options.marker(status));
+            rename.push_line(&mut line, options);
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the plan to the specified writer, prefixing every operation
+    /// with the marker of its status, with the default options.
+    ///
+    /// See [`write_annotated_with`](Self::write_annotated_with).
+    pub fn write_annotated_to<W>(
+        &self,
+        writer: &mut W,
+        statuses: &[OperationStatus],
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.write_annotated_with(writer, statuses, &WriteOptions::default())
+    }
+
+    #[cfg(feature = "ansi")]
+    /// Writes the plan to the specified writer, prefixing every operation
+    /// with the marker of its status, with ANSI colors and the specified
+    /// options.
+    ///
+    /// Markers are colored by status, and paths as in
+    /// [`write_colored_with`](Self::write_colored_with). Warnings are emitted
+    /// through [`TracingSink`].
+    pub fn write_annotated_colored_with<W>(
+        &self,
+        writer: &mut W,
+        statuses: &[OperationStatus],
+        options: &WriteOptions,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.check_statuses(statuses)?;
+        let ls_colors = ls_colors(&mut TracingSink);
+        let mut line = String::new();
+        for (rename, &status) in self.renames.iter().zip(statuses) {
+            line.clear();
+            let _ = write!(line, "{} ", status.style().paint(options.marker(status)));
+            rename.push_colored_line(&ls_colors, &mut line, options);
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Checks that there is a status for every operation.
+    fn check_statuses(&self, statuses: &[OperationStatus]) -> io::Result<()> {
+        if statuses.len() != self.renames.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "got {} status(es) for {} rename operation(s)",
+                    statuses.len(),
+                    self.renames.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "ansi")]
+    /// Writes the plan to the specified writer, with ANSI colors.
+    pub fn write_colored_to<W>(&self, writer: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.write_colored_with(writer, &WriteOptions::default())
+    }
+
+    #[cfg(feature = "ansi")]
+    /// Writes the plan to the specified writer, with ANSI colors and the
+    /// specified options.
+    ///
+    /// Warnings are emitted through [`TracingSink`].
+    pub fn write_colored_with<W>(&self, writer: &mut W, options: &WriteOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.write_colored_with_sink(writer, options, &mut TracingSink)
+    }
+
+    #[cfg(feature = "ansi")]
+    /// Writes the plan to the specified writer, with ANSI colors and the
+    /// specified options, reporting warnings to the specified sink.
+    ///
+    /// A [`Warning::LsColors`](crate::Warning::LsColors) is reported if the
+    /// `LS_COLORS` environment variable cannot be read.
+    pub fn write_colored_with_sink<W>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+        sink: &mut dyn WarningSink,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        self.write_colored_using(&ls_colors(sink), writer, options)
+    }
+
+    /// Writes the plan to the specified writer, with the given colors and
+    /// the specified options.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn write_colored_using<W>(
+        &self,
+        ls_colors: &lscolors::LsColors,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut line = String::new();
+        for rename in &self.renames {
+            line.clear();
+            rename.push_colored_line(ls_colors, &mut line, options);
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Prompts the user to confirm the plan.
+    ///
+    /// If the plan is empty, this returns [`None`]. Otherwise, it prompts the
+    /// user to confirm the plan and returns the user's response.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Plan, Renamer};
+    /// let plan: Plan<&str, &str> = Renamer::new().plan()?;
+    /// assert!(plan.confirm()?.is_none());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "confirm")]
+    pub fn confirm(&self) -> io::Result<Option<bool>> {
+        self.confirm_with(&ConfirmOptions::default())
+    }
+
+    /// Prompts the user to confirm the plan, with the specified options.
+    ///
+    /// The prompt and answers are taken from the
+    /// [messages](ConfirmOptions::messages) of the options.
+    #[cfg(feature = "confirm")]
+    pub fn confirm_with(&self, options: &ConfirmOptions) -> io::Result<Option<bool>> {
+        Ok(if self.is_empty() {
+            None
+        } else {
+            let messages = &options.messages;
+            let theme = ConfirmTheme(messages);
+            let prompt = dialoguer::Confirm::with_theme(&theme)
+                .with_prompt(format_template(&messages.prompt, &[&self.len()]))
+                .interact()
+                .map_err(|dialoguer::Error::IO(err)| err)?;
+            Some(prompt)
+        })
+    }
+
+    /// Writes the first and last operations of the plan to the specified
+    /// writer, with the specified options.
+    ///
+    /// The operations left out are replaced with a single line, from the
+    /// [`more`](crate::Messages::more) template of the write options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PreviewOptions, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// for index in 0..5 {
+    ///     renamer.add(format!("{index}.txt"), format!("{index}.bak"));
+    /// }
+    /// let plan = renamer.plan()?;
+    ///
+    /// let options = PreviewOptions::new().head(1).tail(2);
+    /// let mut output = Vec::new();
+    /// plan.write_preview(&mut output, &options)?;
+    /// assert_eq!(
+    ///     String::from_utf8(output)?,
+    ///     "0.txt => 0.bak\n... and 2 more\n3.txt => 3.bak\n4.txt => 4.bak\n"
+    /// );
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "confirm")]
+    pub fn write_preview<W>(&self, writer: &mut W, options: &PreviewOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let write = &options.write;
+        let len = self.renames.len();
+        if len <= options.head.saturating_add(options.tail) {
+            return self.write_with(writer, write);
+        }
+        for rename in &self.renames[..options.head] {
+            rename.write_to(writer, write)?;
+        }
+        let more = len - options.head - options.tail;
+        writer.write_all(format_template(&write.messages.more, &[&more]).as_bytes())?;
+        writer.write_all(write.terminator())?;
+        for rename in &self.renames[len - options.tail..] {
+            rename.write_to(writer, write)?;
+        }
+        Ok(())
+    }
+
+    /// Previews the plan on the standard error, then prompts the user to
+    /// confirm it, with the specified options.
+    ///
+    /// The preview is written as by [`write_preview`](Self::write_preview).
+    /// With a [pager](PreviewOptions::pager), the user may also page through
+    /// the whole plan before answering. As with
+    /// [`confirm_with`](Self::confirm_with), this returns [`None`] if the
+    /// plan is empty, without writing or prompting anything.
+    #[cfg(feature = "confirm")]
+    pub fn confirm_with_preview(&self, options: &PreviewOptions) -> io::Result<Option<bool>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.write_preview(&mut io::stderr().lock(), options)?;
+        if !options.pager {
+            return self.confirm_with(&options.confirm);
+        }
+
+        let messages = &options.confirm.messages;
+        let prompt = format_template(&messages.prompt, &[&self.len()]);
+        let term = dialoguer::console::Term::stderr();
+        loop {
+            term.write_str(&format!("{} {} ", prompt, messages.pager_hint))?;
+            let answer = term.read_char()?;
+            term.clear_line()?;
+            let answer = match answer.to_ascii_lowercase() {
+                'y' => true,
+                'n' => false,
+                'v' => {
+                    self.page(&options.write)?;
+                    continue;
+                }
+                _ => continue,
+            };
+            let selection = if answer { &messages.yes } else { &messages.no };
+            term.write_line(&format!("{} {}", prompt, selection))?;
+            return Ok(Some(answer));
+        }
+    }
+
+    /// Pipes the whole plan to the pager, or writes it to the standard error
+    /// if no pager can be run.
+    #[cfg(feature = "confirm")]
+    fn page(&self, options: &WriteOptions) -> io::Result<()> {
+        use std::process::{Command, Stdio};
+
+        let pager = std::env::var("PAGER")
+            .ok()
+            .filter(|pager| !pager.trim().is_empty())
+            .unwrap_or_else(|| "less".to_owned());
+        let mut words = pager.split_whitespace();
+        let mut command = Command::new(words.next().unwrap());
+        command.args(words).stdin(Stdio::piped());
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                debug!("could not run the pager {:?}: {}", pager, err);
+                return self.write_with(&mut io::stderr().lock(), options);
+            }
+        };
+        let mut stdin = child.stdin.take().unwrap();
+        match self.write_with(&mut stdin, options) {
+            // The pager was quit before the end of the plan.
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+        drop(stdin);
+        child.wait()?;
+        Ok(())
+    }
+
+    /// Executes the plan.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{Plan, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let plan = renamer.plan()?;
+    /// plan.apply()?;
+    ///
+    /// assert!(!old_path.exists());
+    /// assert!(new_path.exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply(self) -> Result<(), ApplyError> {
+        self.apply_with(&ApplyOptions::new()).into_result()
+    }
+
+    /// Executes the plan unless it is empty, and reports the outcome of every
+    /// rename operation.
+    ///
+    /// If the plan is empty, this returns [`None`],
+    #[cfg_attr(feature = "confirm", doc = "as [`confirm`](Self::confirm) does,")]
+    /// so that "nothing to do" can be told apart from a success. Otherwise,
+    /// this behaves like [`apply_with`](Self::apply_with) with the default
+    /// options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{Plan, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    ///
+    /// let plan: Plan<&str, &str> = Renamer::new().plan()?;
+    /// assert!(plan.apply_nonempty().is_none());
+    ///
+    /// File::create(&old_path)?;
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let report = renamer.plan()?.apply_nonempty().unwrap();
+    /// assert!(report.is_success());
+    /// assert_eq!(report.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_nonempty(self) -> Option<ApplyReport> {
+        (!self.is_empty()).then(|| self.apply_with(&ApplyOptions::new()))
+    }
+
+    /// Returns an iterator applying the plan lazily, one rename operation per
+    /// call to [`next`](Iterator::next).
+    ///
+    /// Each item is the outcome of the next rename operation, in execution
+    /// order. Unlike
+    /// [`apply`](Self::apply), no check is made up front, and the iterator
+    /// keeps going after a failure: progress reporting, cancellation and
+    /// error handling are left to the caller. Dropping the iterator leaves
+    /// the filesystem as it is, and the operations not attempted yet can be
+    /// retrieved with [`ApplyIter::into_remaining_plan`].
+    ///
+    /// Operations under a directory moved earlier are relocated as with
+    /// [`apply_with`](Self::apply_with), but the items hold the paths of the
+    /// plan.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// File::create(path("a.txt"))?;
+    /// File::create(path("b.txt"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a.txt"), path("a-new.txt"));
+    /// renamer.add(path("b.txt"), path("b-new.txt"));
+    /// let plan = renamer.plan()?;
+    ///
+    /// let mut iter = plan.apply_iter();
+    /// let rename = iter.next().unwrap()?;
+    /// assert_eq!(rename.target, path("a-new.txt"));
+    ///
+    /// let remaining = iter.into_remaining_plan();
+    /// assert_eq!(remaining.len(), 1);
+    /// remaining.apply()?;
+    /// assert!(path("b-new.txt").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_iter(&self) -> ApplyIter<'_, S, T> {
+        ApplyIter::new(self)
+    }
+
+    /// Executes the plan with the specified options, and reports the outcome
+    /// of every rename operation.
+    ///
+    /// Every operation is checked before anything is renamed; if any check
+    /// fails, nothing is renamed and the failed operations are reported.
+    /// Otherwise, unless [`ApplyOptions::continue_on_error`] is set, execution
+    /// stops at the first failure and the remaining operations are reported
+    /// as [`RenameStatus::Pending`].
+    ///
+    /// Once a directory is moved, the later operations whose source or
+    /// target lies under its old path are applied under its new path
+    /// instead, and reported as such, so that a plan renaming a directory
+    /// and some of its entries never acts on stale paths.
+    ///
+    /// Operations forming a cycle are applied by moving the source of one of
+    /// them to a temporary path in the same directory first, then renaming
+    /// it last; see [`PlanStats::cycles`]. If the cycle fails midway, the
+    /// source is moved back, or a [`Warning::ParkedSource`] names its
+    /// temporary path. Two swapped paths are exchanged in one system call
+    /// instead, where supported, as by [`Exchange`](crate::Exchange).
+    ///
+    /// Applying an empty plan reports a success without any entry: nothing
+    /// is locked or recorded by the [metrics sink](ApplyOptions::metrics).
+    #[cfg_attr(
+        feature = "serde",
+        doc = "Nor is anything logged to the [audit log](ApplyOptions::audit_log):",
+        doc = "only the [progress file](ApplyOptions::progress_file), if any, is",
+        doc = "written, so that its readers see the apply complete."
+    )]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let old_path = temp_dir.path().join("old.txt");
+    /// let new_path = temp_dir.path().join("new.txt");
+    ///
+    /// File::create(&old_path)?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(&old_path, &new_path);
+    ///
+    /// let report = renamer.plan()?.apply_with(&ApplyOptions::new());
+    /// assert!(report.is_success());
+    /// assert_eq!(report.applied(), 1);
+    ///
+    /// // Empty plans report no operation.
+    /// let report = Renamer::<&str, &str>::new()
+    ///     .plan()?
+    ///     .apply_with(&ApplyOptions::new());
+    /// assert!(report.is_success() && report.is_empty());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply_with(self, options: &ApplyOptions) -> ApplyReport {
+        let anchor = self.anchor();
+        if let Anchor::BaseDir(base_dir) = &anchor {
+            return self.anchored(base_dir).apply_with(options);
+        }
+        // The lock is released once the apply is over.
+        let _lock = match &options.lock {
+            Some(scope) if !self.renames.is_empty() => {
+                let paths = (self.renames.iter())
+                    .flat_map(|rename| [rename.source.as_ref(), rename.target.as_ref()]);
+                match ApplyLock::acquire(scope, paths, options.force_lock) {
+                    Ok(lock) => Some(lock),
+                    Err(details) => return self.locked_out(details, options),
+                }
+            }
+            _ => None,
+        };
+        let mut report = ApplyReport::default();
+        #[cfg(feature = "digest")]
+        {
+            report.digest_max_size = options.digest_max_size;
+        }
+        let mut preflight_failures = self.preflight(&anchor, options, &mut report.warnings);
+        let mut failed = !preflight_failures.is_empty();
+        // If the missing parents cannot be determined up front, each
+        // operation checks its own parent. Parents cannot be missing when
+        // targets stay in their source directory.
+        let mut missing_parents = if self.same_directory {
+            Some(MissingParents::default())
+        } else {
+            MissingParents::find(self.renames.iter().map(|r| r.target.as_ref())).ok()
+        };
+        let mut parent_mtimes = options.preserve_parent_mtimes.then(ParentMtimes::default);
+        let mut throttler = Throttler::new(options.throttle);
+        #[cfg(feature = "serde")]
+        let mut progress = options.progress_file.as_ref().map(|(path, every)| {
+            ProgressFile::new(path, *every, self.renames.len(), options.namer())
+        });
+        #[cfg(feature = "serde")]
+        let mut audit = options.audit_log.as_ref().map(AuditLog::new);
+        let source_links = if failed {
+            HashMap::new()
+        } else {
+            symlinks::source_links(self.renames.iter().map(|r| r.source.as_ref()))
+        };
+        let mut moved_dirs = MovedDirs::new(&self.renames);
+        // The sources moved aside to break cycles, with their temporary path.
+        let mut parked_sources: HashMap<usize, (PathBuf, PathBuf)> = HashMap::new();
+        // The swaps to exchange when their first operation runs, with their
+        // breaker, and the breakers exchanged, with the duration of the
+        // exchange.
+        let mut swaps = HashMap::new();
+        let mut exchanged = HashMap::new();
+        let mut renames: Vec<_> = self.renames.into_iter().map(Some).collect();
+        for index in self.execution {
+            if let Some(&breaker) = self.cycles.get(&index).filter(|_| !failed) {
+                let rename = renames[breaker].as_ref().expect("cycle broken twice");
+                let (source, target) = (rename.source.as_ref(), rename.target.as_ref());
+                let source = moved_dirs
+                    .relocate(source, target)
+                    .map_or_else(|| source.to_owned(), |(source, _)| source);
+                let first = renames[index].as_ref().expect("operation executed twice");
+                if target == first.source.as_ref() {
+                    swaps.insert(index, (breaker, source));
+                } else if !park_breaker(
+                    breaker,
+                    source,
+                    options,
+                    &mut parked_sources,
+                    &mut preflight_failures,
+                ) {
+                    failed = !options.continue_on_error;
+                }
+            }
+            let rename = renames[index].take().expect("operation executed twice");
+            let relocated = moved_dirs.relocate(rename.source.as_ref(), rename.target.as_ref());
+            let (source, target) = match &relocated {
+                Some((source, target)) => (source.as_path(), target.as_path()),
+                None => (rename.source.as_ref(), rename.target.as_ref()),
+            };
+            let parked = parked_sources.remove(&index);
+            // A parked source is renamed from its temporary path.
+            let current_source = parked.as_ref().map_or(source, |(temp_path, _)| temp_path);
+            let mut created_dirs = Vec::new();
+            let (status, duration) = if let Some(duration) = exchanged.remove(&index) {
+                // The breaker of a swap was exchanged with its first operation,
+                // whose paths are the same.
+                if let Some(parent_mtimes) = &mut parent_mtimes {
+                    parent_mtimes.record(source);
+                    parent_mtimes.record(target);
+                }
+                #[cfg(feature = "serde")]
+                if let Some(progress) = &mut progress {
+                    progress.start(source, target);
+                }
+                options.emit(LogEvent::Rename { source, target });
+                (RenameStatus::Applied, Some(duration))
+            } else if let Some(details) = preflight_failures.remove(&index) {
+                (RenameStatus::Failed(details), None)
+            } else if failed {
+                (RenameStatus::Pending, None)
+            } else if let Some(status) = check_source(
+                self.stamps.get(&index),
+                current_source,
+                options,
+                &mut report.warnings,
+            ) {
+                if matches!(status, RenameStatus::Failed(_)) {
+                    failed = !options.continue_on_error;
+                }
+                (status, None)
+            } else if relocated.is_some() && current_source == target {
+                // The directory moved earlier brought the entry to its target.
+                (RenameStatus::AlreadyApplied, None)
+            } else {
+                throttler.wait(options);
+                if let Some(parent_mtimes) = &mut parent_mtimes {
+                    parent_mtimes.record(source);
+                    parent_mtimes.record(target);
+                }
+                #[cfg(feature = "serde")]
+                if let Some(progress) = &mut progress {
+                    progress.start(source, target);
+                }
+                let start = Instant::now();
+                let same_file = (self.case_insensitive_dirs).same_file(source, target);
+                // Swaps are exchanged in one system call where supported,
+                // and otherwise broken through a temporary path.
+                let mut swapped = None;
+                if let Some((breaker, breaker_source)) = swaps.remove(&index) {
+                    let unchanged = (self.stamps.get(&breaker))
+                        .is_none_or(|stamp| matches!(stamp.changed(&breaker_source), Ok(None)));
+                    let current = Rename {
+                        source,
+                        target,
+                        tag: rename.tag.clone(),
+                        priority: rename.priority,
+                    };
+                    let stamp = self.stamps.get(&index);
+                    match unchanged.then(|| current.exchange_checked(options, stamp)) {
+                        Some(Some(Ok(strategy))) => {
+                            exchanged.insert(breaker, start.elapsed());
+                            swapped = Some(Ok(strategy));
+                        }
+                        Some(Some(Err(err))) => swapped = Some(Err(err)),
+                        _ => {
+                            if !park_breaker(
+                                breaker,
+                                breaker_source,
+                                options,
+                                &mut parked_sources,
+                                &mut preflight_failures,
+                            ) {
+                                failed = !options.continue_on_error;
+                            }
+                        }
+                    }
+                }
+                let result = if let Some(result) = swapped {
+                    result
+                } else if relocated.is_some() || parked.is_some() {
+                    let current = Rename {
+                        source: current_source,
+                        target,
+                        tag: rename.tag.clone(),
+                        priority: rename.priority,
+                    };
+                    // The parents of relocated targets were not known when
+                    // planning.
+                    let missing_parents = missing_parents.as_mut().filter(|_| relocated.is_none());
+                    current.apply_checked(
+                        options,
+                        same_file,
+                        self.stamps.get(&index),
+                        missing_parents,
+                        &mut created_dirs,
+                    )
+                } else {
+                    rename.apply_checked(
+                        options,
+                        same_file,
+                        self.stamps.get(&index),
+                        missing_parents.as_mut(),
+                        &mut created_dirs,
+                    )
+                };
+                let status = match result {
+                    Ok(RenameStrategy::Merged) => RenameStatus::Merged,
+                    Ok(RenameStrategy::Skipped) => RenameStatus::Skipped,
+                    Ok(RenameStrategy::AlreadyApplied) => RenameStatus::AlreadyApplied,
+                    Ok(RenameStrategy::Deferred) => RenameStatus::Deferred,
+                    Ok(_) => RenameStatus::Applied,
+                    Err(err) => {
+                        failed = !options.continue_on_error;
+                        RenameStatus::Failed(err.details)
+                    }
+                };
+                (status, Some(start.elapsed()))
+            };
+            #[cfg(feature = "serde")]
+            if let Some(progress) = &mut progress {
+                if !matches!(status, RenameStatus::Pending) {
+                    progress.record(matches!(status, RenameStatus::Failed(_)));
+                }
+            }
+            #[cfg(feature = "serde")]
+            if let Some(audit) = &mut audit {
+                audit.record(source, target, &status);
+            }
+            #[cfg(feature = "digest")]
+            let digest = match options.digest_max_size {
+                Some(max_size) if matches!(status, RenameStatus::Applied) => {
+                    crate::digest::digest_file(target, max_size).unwrap_or_else(|error| {
+                        report.warnings.push(Warning::Digest {
+                            path: target.to_owned(),
+                            error,
+                        });
+                        None
+                    })
+                }
+                _ => None,
+            };
+            if matches!(
+                status,
+                RenameStatus::Applied | RenameStatus::Merged | RenameStatus::AlreadyApplied
+            ) {
+                moved_dirs.record(index, source, target);
+            } else if let Some((temp_path, parked_source)) = parked {
+                if let Err(error) = cycles::unpark(&temp_path, &parked_source) {
+                    report.warnings.push(Warning::ParkedSource {
+                        path: parked_source,
+                        temp_path,
+                        error,
+                    });
+                }
+            }
+            options.record_metrics(&status, duration, created_dirs.len());
+            report.created_dirs.extend(created_dirs.iter().cloned());
+            report.entries.push(ReportEntry {
+                source: source.to_owned(),
+                target: target.to_owned(),
+                status,
+                duration,
+                tag: rename.tag,
+                index,
+                created_dirs,
+                #[cfg(feature = "digest")]
+                digest,
+            });
+        }
+        if !source_links.is_empty() || options.fix_symlinks_under.is_some() {
+            let renames = applied_renames(&report);
+            if let Some(root) = &options.fix_symlinks_under {
+                symlinks::fix_under(
+                    root,
+                    &renames,
+                    options.namer(),
+                    &mut report.rewritten_links,
+                    &mut report.warnings,
+                );
+            }
+            symlinks::fix_renamed(
+                &source_links,
+                &renames,
+                options.fix_renamed_symlinks,
+                options.namer(),
+                &mut report.rewritten_links,
+                &mut report.warnings,
+            );
+        }
+        if options.remove_unused_dirs && !report.is_success() {
+            remove_unused_dirs(&mut report);
+        }
+        if let Some(parent_mtimes) = parent_mtimes {
+            report.warnings.extend(parent_mtimes.restore());
+        }
+        #[cfg(feature = "serde")]
+        if let Some(progress) = progress {
+            report.warnings.extend(progress.finish(report.is_success()));
+        }
+        #[cfg(feature = "serde")]
+        if let Some(audit) = audit {
+            report.warnings.extend(audit.finish());
+        }
+        cycles::prune_staging(options);
+        report
+    }
+}
+
+impl<S, T> Plan<S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    /// Reports the first operation as failed since the lock could not be
+    /// taken, and the others as pending.
+    fn locked_out(self, details: ApplyErrorDetails, options: &ApplyOptions) -> ApplyReport {
+        let mut report = ApplyReport::default();
+        let mut details = Some(details);
+        let mut renames: Vec<_> = self.renames.into_iter().map(Some).collect();
+        for index in self.execution {
+            let rename = renames[index].take().expect("operation executed twice");
+            let status = match details.take() {
+                Some(details) => RenameStatus::Failed(details),
+                None => RenameStatus::Pending,
+            };
+            options.record_metrics(&status, None, 0);
+            report.entries.push(ReportEntry {
+                source: rename.source.as_ref().to_owned(),
+                target: rename.target.as_ref().to_owned(),
+                status,
+                duration: None,
+                tag: rename.tag,
+                index,
+                created_dirs: Vec::new(),
+                #[cfg(feature = "digest")]
+                digest: None,
+            });
+        }
+        report
+    }
+
+    /// Returns how the relative paths of the plan are resolved, from the
+    /// current directory.
+    pub(crate) fn anchor(&self) -> Anchor {
+        let Some(base_dir) = &self.base_dir else {
+            return Anchor::CurrentDir;
+        };
+        if !self.renames.iter().any(Rename::is_relative) {
+            return Anchor::CurrentDir;
+        }
+        let Ok(canonical) = base_dir.canonicalize() else {
+            return Anchor::Missing(base_dir.clone());
+        };
+        match env::current_dir().and_then(|dir| dir.canonicalize()) {
+            Ok(current_dir) if current_dir == canonical => Anchor::CurrentDir,
+            _ => Anchor::BaseDir(base_dir.clone()),
+        }
+    }
+
+    /// Returns the plan, with relative paths joined to a base directory.
+    pub(crate) fn anchored(&self, base_dir: &Path) -> Plan<PathBuf, PathBuf> {
+        let dirs = (self.case_insensitive_dirs.dirs.iter())
+            .map(|dir| base_dir.join(dir))
+            .collect();
+        Plan {
+            renames: (self.renames.iter())
+                .map(|rename| rename.anchored(base_dir))
+                .collect(),
+            execution: self.execution.clone(),
+            cycles: self.cycles.clone(),
+            stats: self.stats.clone(),
+            collator: Arc::clone(&self.collator),
+            same_directory: self.same_directory,
+            on_stat_error: self.on_stat_error,
+            case_insensitive_dirs: CaseInsensitiveDirs { dirs },
+            skipped: self.skipped.clone(),
+            base_dir: None,
+            stamps: self.stamps.clone(),
+            hidden: self.hidden.clone(),
+        }
+    }
+
+    /// Runs the preflight checks, also failing the operations holding a
+    /// relative path if the base directory is missing.
+    pub(crate) fn preflight(
+        &self,
+        anchor: &Anchor,
+        options: &ApplyOptions,
+        warnings: &mut Vec<Warning>,
+    ) -> HashMap<usize, ApplyErrorDetails> {
+        let mut failures = preflight::check(&self.renames, &self.stamps, options, warnings);
+        if let Anchor::Missing(base_dir) = anchor {
+            for (index, rename) in self.renames.iter().enumerate() {
+                if rename.is_relative() {
+                    failures.insert(index, ApplyErrorDetails::MissingBaseDir(base_dir.clone()));
+                }
+            }
+        }
+        failures
+    }
+}
+
+/// Returns the base directory of a plan holding the given operations: the
+/// canonical current directory if any path is relative.
+pub(crate) fn base_dir_of<S, T>(renames: &[Rename<S, T>]) -> Option<PathBuf>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    if !renames.iter().any(Rename::is_relative) {
+        return None;
+    }
+    env::current_dir().and_then(|dir| dir.canonicalize()).ok()
+}
+
+/// Checks the source of an operation against its stamp, if any, and returns
+/// the status of the operation if it changed or could not be checked.
+///
+/// Missing sources are left to the rename, which reports them.
+pub(crate) fn check_source(
+    stamp: Option<&SourceStamp>,
+    source: &Path,
+    options: &ApplyOptions,
+    warnings: &mut Vec<Warning>,
+) -> Option<RenameStatus> {
+    let field = match stamp?.changed(source) {
+        Ok(field) => field?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+        Err(error) => {
+            let details = ApplyErrorDetails::io(IoOp::Stat, source, error);
+            return Some(RenameStatus::Failed(details));
+        }
+    };
+    match options.on_source_changed {
+        SourceChange::Fail => Some(RenameStatus::Failed(ApplyErrorDetails::SourceChanged(
+            field,
+        ))),
+        SourceChange::Skip => {
+            warnings.push(Warning::SourceChanged {
+                path: source.to_owned(),
+                field,
+            });
+            Some(RenameStatus::Skipped)
+        }
+    }
+}
+
+/// Moves the source of a cycle breaker aside, before the first operation of
+/// its cycle, and returns whether it could be.
+///
+/// Otherwise, the breaker fails once reached.
+fn park_breaker(
+    breaker: usize,
+    source: PathBuf,
+    options: &ApplyOptions,
+    parked_sources: &mut HashMap<usize, (PathBuf, PathBuf)>,
+    preflight_failures: &mut HashMap<usize, ApplyErrorDetails>,
+) -> bool {
+    match cycles::park(&source, options) {
+        Ok(temp_path) => {
+            parked_sources.insert(breaker, (temp_path, source));
+            true
+        }
+        Err(err) => {
+            let details = ApplyErrorDetails::io(IoOp::Rename, &source, err);
+            preflight_failures.insert(breaker, details);
+            false
+        }
+    }
+}
+
+/// Folds the case of the file name of a path.
+fn fold_name(path: &Path) -> PathBuf {
+    match path.file_name() {
+        Some(name) => path.with_file_name(fold_case(name)),
+        None => path.to_owned(),
+    }
+}
+
+/// Returns the applied operations of a report, with absolute, normalized
+/// paths.
+fn applied_renames(report: &ApplyReport) -> HashMap<PathBuf, PathBuf> {
+    report
+        .entries
+        .iter()
+        .filter(|entry| matches!(entry.status, RenameStatus::Applied | RenameStatus::Merged))
+        .filter_map(|entry| {
+            let source = std::path::absolute(&entry.source).ok()?;
+            let target = std::path::absolute(&entry.target).ok()?;
+            Some((normalize(&source), normalize(&target)))
+        })
+        .collect()
+}
+
+/// Removes the created directories of a report that are still empty,
+/// deepest first.
+fn remove_unused_dirs(report: &mut ApplyReport) {
+    let mut kept = Vec::new();
+    let mut removed = HashSet::new();
+    // Directories are created outermost first.
+    while let Some(dir) = report.created_dirs.pop() {
+        match fs::remove_dir(&dir.path) {
+            Ok(()) => {
+                removed.insert(dir.path);
+            }
+            Err(err) if err.kind() == io::ErrorKind::DirectoryNotEmpty => kept.push(dir),
+            Err(error) => {
+                report.warnings.push(Warning::UnusedDir {
+                    path: dir.path.clone(),
+                    error,
+                });
+                kept.push(dir);
+            }
+        }
+    }
+    kept.reverse();
+    report.created_dirs = kept;
+    for entry in &mut report.entries {
+        entry
+            .created_dirs
+            .retain(|dir| !removed.contains(&dir.path));
+    }
+}
+
+/// Reads the colors from `LS_COLORS`, reporting a warning to the sink if the
+/// variable cannot be read.
+#[cfg(feature = "ansi")]
+pub(crate) fn ls_colors(sink: &mut dyn WarningSink) -> lscolors::LsColors {
+    lscolors::LsColors::from_env().unwrap_or_else(|| {
+        sink.warn(Warning::LsColors);
+        lscolors::LsColors::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use crate::{ApplyErrorDetails, ApplyOptions, PlanOptions, RenameStatus, Renamer, StampField};
+
+    #[test]
+    fn source_changed() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"a")?;
+        fs::write(path("b"), b"b")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("x"));
+        renamer.add(path("b"), path("y"));
+        renamer.add(path("missing"), path("z"));
+        let plan = (renamer.plan_with(&PlanOptions::new().stamp_sources(true)))
+            .map_err(io::Error::other)?;
+        assert_eq!(plan.source_stamp(0).map(|stamp| stamp.size), Some(1));
+        assert!(plan.source_stamp(2).is_none());
+        #[cfg(feature = "serde")]
+        assert_eq!(plan.to_json_value()["operations"][1]["stamp"]["size"], 1);
+
+        // The changed source fails, and the other operations are pending.
+        fs::write(path("a"), b"changed")?;
+        let report = plan.apply_with(&ApplyOptions::new());
+        assert!(matches!(
+            report.entries()[0].status,
+            RenameStatus::Failed(ApplyErrorDetails::SourceChanged(StampField::Size))
+        ));
+        assert!(matches!(report.entries()[1].status, RenameStatus::Pending));
+        assert!(report.warnings().is_empty());
+        assert!(path("a").exists() && path("b").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn stamped_dirs() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::create_dir(path("dir"))?;
+        fs::write(path("dir/a"), b"a")?;
+
+        // Moving the child changes its directory, which is not stamped.
+        let mut renamer = Renamer::new();
+        renamer.add(path("dir/a"), path("dir/b"));
+        renamer.add(path("dir"), path("dir2"));
+        let plan = (renamer.plan_with(&PlanOptions::new().stamp_sources(true)))
+            .map_err(io::Error::other)?;
+        assert!(plan.source_stamp(1).is_none());
+        let report = plan.apply_with(&ApplyOptions::new());
+        assert!(report.is_success(), "{:?}", report);
+        assert_eq!(fs::read(path("dir2/b"))?, b"a");
+        Ok(())
+    }
+
+    #[test]
+    fn remove_unused_dirs() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+
+        // The parents of the first operation are created, then the rename
+        // fails since its source is missing.
+        let mut renamer = Renamer::new();
+        renamer.add(path("missing"), path("out/fresh/deep/b"));
+        renamer.add(path("a"), path("out/kept/a"));
+        let options = ApplyOptions::new()
+            .continue_on_error(true)
+            .remove_unused_dirs(true);
+        let report = renamer.plan().unwrap().apply_with(&options);
+        assert_eq!(report.applied(), 1);
+        assert!(report.warnings().is_empty());
+        let created: Vec<_> = report.created_dirs().iter().map(|dir| &dir.path).collect();
+        assert_eq!(created, [&path("out"), &path("out/kept")]);
+        let entry_created: Vec<_> = (report.entries().iter())
+            .map(|entry| entry.created_dirs.len())
+            .collect();
+        // The failed operation keeps the directory still in use.
+        assert_eq!(entry_created, [1, 1]);
+        assert!(!path("out/fresh").exists());
+        assert!(path("out/kept/a").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn entry_created_dirs() -> io::Result<()> {
+        use std::cell::RefCell;
+
+        use crate::LogEvent;
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for name in ["a", "b", "c"] {
+            fs::write(path(name), b"")?;
+        }
+
+        // Shared parents are attributed to the first operation needing them.
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("out/2025/a"));
+        renamer.add(path("b"), path("out/2025/b"));
+        renamer.add(path("c"), path("out/2026/c"));
+        let events = RefCell::new(Vec::new());
+        let options = ApplyOptions::new().log(|event| {
+            if let LogEvent::ParentCreated { created, .. } = event {
+                events.borrow_mut().push(created.len());
+            }
+        });
+        let report = renamer.plan().unwrap().apply_with(&options);
+        drop(options);
+        assert!(report.is_success());
+        let created: Vec<Vec<_>> = (report.entries().iter())
+            .map(|entry| {
+                entry
+                    .created_dirs
+                    .iter()
+                    .map(|dir| dir.path.clone())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            created,
+            [
+                vec![path("out"), path("out/2025")],
+                vec![],
+                vec![path("out/2026")],
+            ]
+        );
+        assert_eq!(report.created_dirs().len(), 3);
+        assert_eq!(events.into_inner(), [2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn rename_index() -> io::Result<()> {
+        use crate::{ExecutionOrder, PlanOptions};
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("b"), b"")?;
+
+        // The operations are executed in reverse plan order.
+        let plan = || {
+            let mut renamer = Renamer::new();
+            renamer.add(path("b"), path("y"));
+            renamer.add(path("missing"), path("x"));
+            let options = PlanOptions::new().execution_order(ExecutionOrder::Unchanged);
+            renamer.plan_with(&options).unwrap()
+        };
+        let report = plan().apply_with(&ApplyOptions::new().continue_on_error(true));
+        let indices: Vec<_> = report.entries().iter().map(|entry| entry.index).collect();
+        assert_eq!(indices, [1, 0]);
+        assert_eq!(report.into_result().unwrap_err().rename_index(), Some(0));
+
+        fs::rename(path("y"), path("b"))?;
+        let plan = plan();
+        assert_eq!(plan.get(0).unwrap().source, path("missing"));
+        assert!(plan.get(2).is_none());
+        let err = plan.apply_iter().nth(1).unwrap().unwrap_err();
+        assert_eq!(err.rename_index(), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn base_dir() -> io::Result<()> {
+        use crate::{ApplyErrorDetails, RenameStatus};
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"a")?;
+        fs::write(path("b"), b"b")?;
+
+        // Relative paths are resolved against the base directory, wherever
+        // the plan is applied from.
+        let plan = |source: &str, target: &str| {
+            let mut renamer = Renamer::new();
+            renamer.add(source.to_owned(), target.to_owned());
+            renamer.plan().unwrap().with_base_dir(temp_dir.path())
+        };
+        plan("a", "c").apply().map_err(io::Error::other)?;
+        assert_eq!(fs::read(path("c"))?, b"a");
+        let plan_b = plan("b", "d");
+        let mut results = plan_b.apply_iter();
+        assert!(results.next().unwrap().is_ok());
+        assert_eq!(fs::read(path("d"))?, b"b");
+
+        // Relative paths are not resolved against a missing base directory.
+        let mut missing = plan("c", "e");
+        assert_eq!(
+            missing.rebase(path("missing")).as_deref(),
+            Some(temp_dir.path())
+        );
+        let report = missing.apply_with(&ApplyOptions::new());
+        assert!(matches!(
+            &report.entries()[0].status,
+            RenameStatus::Failed(ApplyErrorDetails::MissingBaseDir(dir)) if *dir == path("missing")
+        ));
+        let missing = plan("c", "e").with_base_dir(path("missing"));
+        let err = missing.apply_iter().next().unwrap().unwrap_err();
+        assert!(matches!(err.details, ApplyErrorDetails::MissingBaseDir(_)));
+        assert!(path("c").exists());
+
+        // Absolute paths need no base directory.
+        let mut renamer = Renamer::new();
+        renamer.add(path("c"), path("e"));
+        let plan = renamer.plan().unwrap();
+        assert_eq!(plan.base_dir(), None);
+        plan.with_base_dir(path("missing"))
+            .apply()
+            .map_err(io::Error::other)?;
+        assert!(path("e").exists());
+        Ok(())
+    }
+
+    #[cfg(feature = "confirm")]
+    #[test]
+    fn write_preview() -> io::Result<()> {
+        use crate::{Messages, PreviewOptions, WriteOptions};
+
+        let mut renamer = Renamer::new();
+        for index in 0..4 {
+            renamer.add(format!("{index}"), format!("{index}.bak"));
+        }
+        let plan = renamer.plan().map_err(io::Error::other)?;
+        let preview = |options: &PreviewOptions| -> io::Result<String> {
+            let mut output = Vec::new();
+            plan.write_preview(&mut output, options)?;
+            Ok(String::from_utf8(output).unwrap())
+        };
+
+        // Short plans are written whole.
+        let whole = "0 => 0.bak\n1 => 1.bak\n2 => 2.bak\n3 => 3.bak\n";
+        assert_eq!(preview(&PreviewOptions::new())?, whole);
+        assert_eq!(preview(&PreviewOptions::new().head(3).tail(1))?, whole);
+
+        let write = WriteOptions::new().messages(Messages::new().more("({0} de plus)"));
+        let options = PreviewOptions::new().head(0).tail(1).write_options(write);
+        assert_eq!(preview(&options)?, "(3 de plus)\n3 => 3.bak\n");
+        let options = PreviewOptions::new().head(1).tail(0);
+        assert_eq!(preview(&options)?, "0 => 0.bak\n... and 3 more\n");
         Ok(())
     }
 }
@@ -0,0 +1,254 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    casefold::is_case_insensitive,
+    conflicts::{Conflict, ConflictOptions},
+    error::PlanError,
+    fsutil,
+    options::PlanOptions,
+    plan::Plan,
+    renamer::Renamer,
+};
+
+/// A planner reusing its filesystem lookups across many small plans.
+///
+/// One-shot planning with [`Renamer::plan_with`] and [`Plan::conflicts`]
+/// lists the target directories, resolves their symbolic links and probes
+/// their case sensitivity every time. A planner keeps these results between
+/// calls, so that a long-running process planning batches into the same
+/// directories only pays for them once. The results are the same as with
+/// one-shot planning, as long as the cached directories do not change.
+///
+/// The planner does not watch the filesystem: directories changed since they
+/// were cached, including by applying a plan, must be
+/// [invalidated](Self::invalidate).
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs::File;
+/// # use nominal::{ConflictOptions, PlanOptions, Planner, Renamer};
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = |name| temp_dir.path().join(name);
+/// File::create(path("a.txt"))?;
+///
+/// let mut planner = Planner::new(PlanOptions::new());
+/// let mut renamer = Renamer::new();
+/// renamer.add(path("b.txt"), path("a.txt"));
+/// let plan = planner.plan(renamer)?;
+/// assert_eq!(planner.conflicts(&plan, &ConflictOptions::new())?.len(), 1);
+///
+/// // The directory changed behind the back of the planner.
+/// File::create(path("c.txt"))?;
+/// planner.invalidate(temp_dir.path());
+///
+/// let mut renamer = Renamer::new();
+/// renamer.add(path("d.txt"), path("c.txt"));
+/// let plan = planner.plan(renamer)?;
+/// assert_eq!(planner.conflicts(&plan, &ConflictOptions::new())?.len(), 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Planner {
+    options: PlanOptions,
+    cache: FsCache,
+}
+
+impl Planner {
+    /// Creates a planner building plans with the specified options.
+    pub fn new(options: PlanOptions) -> Self {
+        Self {
+            options,
+            cache: FsCache::default(),
+        }
+    }
+
+    /// Returns the options plans are built with.
+    pub fn options(&self) -> &PlanOptions {
+        &self.options
+    }
+
+    /// Consumes the renamer and returns a [`Plan`], as
+    /// [`Renamer::plan_with`] does.
+    pub fn plan<S, T>(&mut self, renamer: Renamer<S, T>) -> Result<Plan<S, T>, PlanError>
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        renamer.plan_cached(&self.options, &mut self.cache, None)
+    }
+
+    /// Returns the conflicts of a plan, as [`Plan::conflicts`] does.
+    pub fn conflicts<S, T>(
+        &mut self,
+        plan: &Plan<S, T>,
+        options: &ConflictOptions,
+    ) -> io::Result<Vec<Conflict>>
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        plan.conflicts_cached(options, &mut self.cache)
+    }
+
+    /// Forgets what is cached about a directory and everything under it.
+    ///
+    /// The directory is matched both as given and once canonicalized, if it
+    /// exists.
+    pub fn invalidate(&mut self, path: impl AsRef<Path>) {
+        self.cache.invalidate(path.as_ref());
+    }
+
+    /// Forgets everything cached.
+    pub fn clear(&mut self) {
+        self.cache = FsCache::default();
+    }
+}
+
+/// The filesystem lookups made while planning.
+#[derive(Debug, Default)]
+pub(crate) struct FsCache {
+    /// Whether each probed directory is case-insensitive.
+    case_insensitive: HashMap<PathBuf, bool>,
+    /// The parent directories, once their symbolic links are resolved.
+    parents: HashMap<PathBuf, PathBuf>,
+    /// The names of the entries of each directory.
+    listings: HashMap<PathBuf, Vec<OsString>>,
+    /// The entries of each directory, keyed by folded name, for each set of
+    /// conflict options.
+    indexes: HashMap<(PathBuf, ConflictOptions), HashMap<OsString, Vec<OsString>>>,
+}
+
+impl FsCache {
+    /// Tests whether an existing directory is case-insensitive.
+    pub(crate) fn is_case_insensitive(&mut self, dir: &Path) -> io::Result<bool> {
+        if let Some(&insensitive) = self.case_insensitive.get(dir) {
+            return Ok(insensitive);
+        }
+        let insensitive = is_case_insensitive(dir)?;
+        self.case_insensitive.insert(dir.to_owned(), insensitive);
+        Ok(insensitive)
+    }
+
+    /// Resolves the symbolic links in the parent of a path, as
+    /// [`fsutil::resolve_parent`] does.
+    pub(crate) fn resolve_parent(&mut self, path: &Path) -> io::Result<PathBuf> {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Ok(path.to_owned());
+        };
+        if let Some(resolved) = self.parents.get(parent) {
+            return Ok(resolved.join(name));
+        }
+        let resolved = fsutil::resolve_parent(path)?;
+        if let Some(resolved_parent) = resolved.parent() {
+            self.parents
+                .insert(parent.to_owned(), resolved_parent.to_owned());
+        }
+        Ok(resolved)
+    }
+
+    /// Returns the entries of a directory, keyed as by the conflict options.
+    ///
+    /// Missing directories have no entries.
+    pub(crate) fn dir_index(
+        &mut self,
+        dir: &Path,
+        options: &ConflictOptions,
+    ) -> io::Result<&HashMap<OsString, Vec<OsString>>> {
+        match self.indexes.entry((dir.to_owned(), options.clone())) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let listing = match self.listings.entry(dir.to_owned()) {
+                    Entry::Occupied(listing) => listing.into_mut(),
+                    Entry::Vacant(listing) => listing.insert(read_dir(dir)?),
+                };
+                let mut index: HashMap<_, Vec<_>> = HashMap::new();
+                for name in listing.iter() {
+                    index
+                        .entry(options.key(name))
+                        .or_default()
+                        .push(name.clone());
+                }
+                Ok(entry.insert(index))
+            }
+        }
+    }
+
+    /// Forgets what is cached about a directory and everything under it.
+    pub(crate) fn invalidate(&mut self, path: &Path) {
+        let canonical = path.canonicalize().ok();
+        let stale = |dir: &Path| {
+            dir.starts_with(path) || canonical.as_ref().is_some_and(|path| dir.starts_with(path))
+        };
+        self.case_insensitive.retain(|dir, _| !stale(dir));
+        self.parents
+            .retain(|parent, resolved| !stale(parent) && !stale(resolved));
+        self.listings.retain(|dir, _| !stale(dir));
+        self.indexes.retain(|(dir, _), _| !stale(dir));
+    }
+}
+
+/// Returns the names of the entries of a directory, or none if it does not
+/// exist.
+fn read_dir(dir: &Path) -> io::Result<Vec<OsString>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    entries
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::Planner;
+    use crate::{ConflictOptions, PlanOptions, Renamer};
+
+    #[test]
+    fn same_as_one_shot() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::create_dir(root.join("dir"))?;
+        fs::write(root.join("dir/README"), b"")?;
+        fs::write(root.join("a"), b"")?;
+
+        let renamer = || {
+            let mut renamer = Renamer::new();
+            renamer.add(root.join("a"), root.join("dir/readme"));
+            renamer.add(root.join("b"), root.join("dir/README"));
+            renamer.add(root.join("c"), root.join("missing/d"));
+            renamer
+        };
+        let options = PlanOptions::new().probe_case_sensitivity(true);
+        let conflict_options = ConflictOptions::new().case_fold(true);
+        let one_shot = renamer()
+            .plan_with(&options)
+            .unwrap()
+            .conflicts(&conflict_options)?;
+
+        let mut planner = Planner::new(options.clone());
+        for _ in 0..2 {
+            let plan = planner.plan(renamer()).unwrap();
+            assert_eq!(planner.conflicts(&plan, &conflict_options)?, one_shot);
+        }
+
+        // The cache is stale until the directory is invalidated.
+        fs::write(root.join("dir/readme.txt"), b"")?;
+        let mut renamer = Renamer::new();
+        renamer.add(root.join("e"), root.join("dir/readme.txt"));
+        let plan = planner.plan(renamer).unwrap();
+        assert!(planner.conflicts(&plan, &conflict_options)?.is_empty());
+        planner.invalidate(root.join("dir"));
+        assert_eq!(planner.conflicts(&plan, &conflict_options)?.len(), 1);
+        Ok(())
+    }
+}
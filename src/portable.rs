@@ -0,0 +1,316 @@
+use std::{
+    collections::HashSet,
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{casefold::fold_case, options::ValidationProfile, renamer::Renamer};
+
+/// The device names reserved by Windows, whatever their case and extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Why a file name breaks the rules of a [`ValidationProfile`].
+///
+/// See [`validation_profile`](crate::PlanOptions::validation_profile).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NameIssue {
+    /// The name ends with a space.
+    TrailingSpace,
+    /// The name ends with a dot.
+    TrailingDot,
+    /// The name holds a character reserved by Windows, such as `:` or `?`,
+    /// or a control character.
+    ReservedChar(char),
+    /// The name is a device name reserved by Windows, such as `CON` or
+    /// `nul.txt`.
+    ReservedName,
+}
+
+impl fmt::Display for NameIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameIssue::TrailingSpace => write!(f, "name ends with a space"),
+            NameIssue::TrailingDot => write!(f, "name ends with a dot"),
+            NameIssue::ReservedChar(c) => write!(f, "name holds the reserved character {:?}", c),
+            NameIssue::ReservedName => write!(f, "name is a reserved device name"),
+        }
+    }
+}
+
+/// A target rewritten by [`Renamer::sanitize_targets`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SanitizedTarget {
+    /// The index of the operation, in insertion order.
+    pub index: usize,
+    /// The target before it was rewritten.
+    pub original: PathBuf,
+    /// The rewritten target.
+    pub sanitized: PathBuf,
+    /// The issues of the original file name, in the order they were fixed.
+    pub issues: Vec<NameIssue>,
+    /// Whether a counter was added to the sanitized name, since it clashed
+    /// with another target.
+    pub deduplicated: bool,
+}
+
+impl ValidationProfile {
+    /// Returns the issues of a file name, under this profile.
+    pub(crate) fn issues(self, name: &OsStr) -> Vec<NameIssue> {
+        match self {
+            ValidationProfile::Native => Vec::new(),
+            ValidationProfile::SmbCompatible => windows_issues(name.as_encoded_bytes()),
+        }
+    }
+}
+
+/// Returns the issues of a file name under the Windows naming rules.
+///
+/// The rules only involve ASCII characters, so that they are checked on the
+/// bytes of the name, whether it is valid Unicode or not.
+fn windows_issues(name: &[u8]) -> Vec<NameIssue> {
+    let mut issues = Vec::new();
+    if let Some(&b) = name.iter().find(|&&b| is_reserved_byte(b)) {
+        issues.push(NameIssue::ReservedChar(char::from(b)));
+    }
+    if is_reserved_name(name) {
+        issues.push(NameIssue::ReservedName);
+    }
+    if name.ends_with(b" ") {
+        issues.push(NameIssue::TrailingSpace);
+    } else if name.ends_with(b".") {
+        issues.push(NameIssue::TrailingDot);
+    }
+    issues
+}
+
+fn is_reserved_byte(b: u8) -> bool {
+    b.is_ascii_control()
+        || matches!(
+            b,
+            b'<' | b'>' | b':' | b'"' | b'/' | b'\\' | b'|' | b'?' | b'*'
+        )
+}
+
+/// Tests whether a name is a reserved device name, with or without an
+/// extension.
+fn is_reserved_name(name: &[u8]) -> bool {
+    let stem = name.split(|&b| b == b'.').next().unwrap_or(name);
+    let stem = &stem[..stem.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1)];
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved.as_bytes()))
+}
+
+/// Rewrites a file name to follow the Windows naming rules.
+fn sanitize_name(name: &OsStr) -> OsString {
+    let mut name: Vec<u8> = (name.as_encoded_bytes().iter())
+        .map(|&b| if is_reserved_byte(b) { b'_' } else { b })
+        .collect();
+    name.truncate(
+        (name.iter())
+            .rposition(|&b| !matches!(b, b' ' | b'.'))
+            .map_or(0, |i| i + 1),
+    );
+    if name.is_empty() {
+        name.push(b'_');
+    }
+    if is_reserved_name(&name) {
+        let stem_len = name.iter().position(|&b| b == b'.').unwrap_or(name.len());
+        name.insert(stem_len, b'_');
+    }
+    // SAFETY: only ASCII bytes were replaced, removed or inserted, next to
+    // other ASCII bytes, so the bytes are still a valid encoding.
+    unsafe { OsString::from_encoded_bytes_unchecked(name) }
+}
+
+/// Returns a file name with a counter added to its stem.
+fn numbered_name(name: &OsStr, counter: usize) -> OsString {
+    let name = Path::new(name);
+    let mut numbered = name.file_stem().unwrap_or_default().to_owned();
+    numbered.push(format!(" ({})", counter));
+    if let Some(extension) = name.extension() {
+        numbered.push(".");
+        numbered.push(extension);
+    }
+    numbered
+}
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Rewrites the file names of the targets breaking the rules of a
+    /// validation profile, and returns what was changed.
+    ///
+    /// This is the sanitizing counterpart of
+    /// [`validation_profile`](crate::PlanOptions::validation_profile), which
+    /// makes planning fail on such targets instead. Under
+    /// [`ValidationProfile::SmbCompatible`], reserved and control characters
+    /// are replaced with `_`, trailing spaces and dots are removed, and `_`
+    /// is appended to reserved device names, as in `CON_.txt`. If the
+    /// sanitized target then clashes with another target of the renamer,
+    /// compared case-insensitively as Windows does, a counter is added to its
+    /// stem, as in `a (1).txt`. Only file names are checked, not the
+    /// directories leading to them. The rules only involve ASCII characters,
+    /// so that names that are not valid Unicode are sanitized as well.
+    ///
+    /// The rewritten targets are returned in insertion order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::PathBuf;
+    /// # use nominal::{Renamer, ValidationProfile};
+    /// let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+    /// renamer.add("a".into(), "share/report.".into());
+    /// renamer.add("b".into(), "share/report".into());
+    /// renamer.add("c".into(), "share/what?.txt".into());
+    ///
+    /// let profile = ValidationProfile::SmbCompatible;
+    /// let sanitized = renamer.sanitize_targets(profile);
+    /// assert_eq!(sanitized.len(), 2);
+    /// assert_eq!(sanitized[0].sanitized, PathBuf::from("share/report (1)"));
+    /// assert!(sanitized[0].deduplicated);
+    /// assert_eq!(sanitized[1].sanitized, PathBuf::from("share/what_.txt"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn sanitize_targets(&mut self, profile: ValidationProfile) -> Vec<SanitizedTarget> {
+        let key = |path: &Path| match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => parent.join(fold_case(name)),
+            _ => path.to_owned(),
+        };
+        let mut offending = Vec::new();
+        for (index, rename) in self.renames.iter().enumerate() {
+            let Some(name) = rename.target.file_name() else {
+                continue;
+            };
+            let issues = profile.issues(name);
+            if !issues.is_empty() {
+                offending.push((index, issues));
+            }
+        }
+        // The targets left unchanged keep their name.
+        let offending_indices: HashSet<usize> = offending.iter().map(|(index, _)| *index).collect();
+        let mut taken: HashSet<PathBuf> = (self.renames.iter().enumerate())
+            .filter(|(index, _)| !offending_indices.contains(index))
+            .map(|(_, rename)| key(&rename.target))
+            .collect();
+
+        let mut sanitized_targets = Vec::new();
+        for (index, issues) in offending {
+            let target = &mut self.renames[index].target;
+            let name = sanitize_name(target.file_name().unwrap());
+            let mut sanitized = target.with_file_name(&name);
+            let mut counter = 0;
+            while !taken.insert(key(&sanitized)) {
+                counter += 1;
+                sanitized = target.with_file_name(numbered_name(&name, counter));
+            }
+            let original = std::mem::replace(target, sanitized.clone());
+            sanitized_targets.push(SanitizedTarget {
+                index,
+                original,
+                sanitized,
+                issues,
+                deduplicated: counter > 0,
+            });
+        }
+        sanitized_targets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, path::PathBuf};
+
+    use super::{sanitize_name, NameIssue};
+    use crate::{PlanError, PlanOptions, Renamer, ValidationProfile};
+
+    #[test]
+    fn issues() {
+        let issues = |name: &str| ValidationProfile::SmbCompatible.issues(OsStr::new(name));
+        assert!(issues("report.txt").is_empty());
+        assert!(issues(".hidden").is_empty());
+        assert!(issues("console.txt").is_empty());
+        assert_eq!(issues("a "), [NameIssue::TrailingSpace]);
+        assert_eq!(issues("a. ."), [NameIssue::TrailingDot]);
+        assert_eq!(issues("a:b"), [NameIssue::ReservedChar(':')]);
+        assert_eq!(issues("tab\t"), [NameIssue::ReservedChar('\t')]);
+        assert_eq!(issues("nul.tar.gz"), [NameIssue::ReservedName]);
+        assert_eq!(
+            issues("Com1 ."),
+            [NameIssue::ReservedName, NameIssue::TrailingDot]
+        );
+        assert!(ValidationProfile::Native
+            .issues(OsStr::new("a "))
+            .is_empty());
+    }
+
+    #[test]
+    fn sanitize() {
+        let sanitize_name = |name: &str| sanitize_name(OsStr::new(name));
+        assert_eq!(sanitize_name("a "), "a");
+        assert_eq!(sanitize_name("a<b>.txt"), "a_b_.txt");
+        assert_eq!(sanitize_name(". ."), "_");
+        assert_eq!(sanitize_name("aux.txt"), "aux_.txt");
+        assert_eq!(sanitize_name("CON. "), "CON_");
+        assert_eq!(sanitize_name("caf\u{e9}?. "), "caf\u{e9}_");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"\xff:a ");
+        assert_eq!(
+            ValidationProfile::SmbCompatible.issues(name),
+            [NameIssue::ReservedChar(':'), NameIssue::TrailingSpace]
+        );
+        assert_eq!(sanitize_name(name), OsStr::from_bytes(b"\xff_a"));
+    }
+
+    #[test]
+    fn sanitize_targets() {
+        let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+        renamer.add("a".into(), "out/a.txt".into());
+        renamer.add("b".into(), "out/A.txt.".into());
+        renamer.add("c".into(), "out/a.txt ".into());
+        renamer.add("d".into(), "out/b".into());
+
+        let options = PlanOptions::new().validation_profile(ValidationProfile::SmbCompatible);
+        let plan = |renamer: &Renamer<PathBuf, PathBuf>| {
+            let renamer: Renamer<_, _> = (renamer.renames.iter())
+                .map(|rename| (rename.source.clone(), rename.target.clone()))
+                .collect();
+            renamer.plan_with(&options)
+        };
+        match plan(&renamer) {
+            Err(PlanError::NonPortableTargets(targets)) => assert_eq!(
+                targets,
+                [
+                    (PathBuf::from("out/A.txt."), NameIssue::TrailingDot),
+                    (PathBuf::from("out/a.txt "), NameIssue::TrailingSpace),
+                ]
+            ),
+            other => panic!("unexpected result: {:?}", other.map(|plan| plan.len())),
+        }
+
+        let sanitized = renamer.sanitize_targets(ValidationProfile::SmbCompatible);
+        let targets: Vec<_> = (sanitized.iter())
+            .map(|target| (target.index, target.sanitized.clone(), target.deduplicated))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                (1, PathBuf::from("out/A (1).txt"), true),
+                (2, PathBuf::from("out/a (2).txt"), true),
+            ]
+        );
+        assert_eq!(sanitized[1].original, PathBuf::from("out/a.txt "));
+        assert_eq!(plan(&renamer).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,479 @@
+use std::{
+    collections::HashMap,
+    env, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{ApplyErrorDetails, IoOp, SpaceShortage},
+    fsutil::{available_space, device_of, git_root, resolve_parent, symlinked_ancestor, tree_size},
+    inspect,
+    operation::Rename,
+    options::{ApplyOptions, Boundary, SourceKind},
+    stamp::SourceStamp,
+    warning::Warning,
+};
+
+/// Checks every rename operation before anything is renamed.
+///
+/// Returns the details of the failed checks, keyed by operation index.
+/// Non-fatal problems are appended to `warnings`.
+pub(crate) fn check<S, T>(
+    renames: &[Rename<S, T>],
+    stamps: &HashMap<usize, SourceStamp>,
+    options: &ApplyOptions,
+    warnings: &mut Vec<Warning>,
+) -> HashMap<usize, ApplyErrorDetails>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    let mut failures = HashMap::new();
+    let mut links = HashMap::new();
+    let current_dir = if options.allow_renaming_current_dir {
+        None
+    } else {
+        // Without a current directory, relative paths fail on their own.
+        env::current_dir().and_then(|dir| dir.canonicalize()).ok()
+    };
+    for (index, rename) in renames.iter().enumerate() {
+        let source = rename.source.as_ref();
+        let target = rename.target.as_ref();
+        // The checks would fail on the missing source, and the operation is
+        // found applied again when executed.
+        if options.idempotent
+            && inspect::exists(target, options.follow_symlinks).unwrap_or(false)
+            && rename
+                .already_applied(options, stamps.get(&index))
+                .unwrap_or(false)
+        {
+            continue;
+        }
+        if options.no_symlink_traversal {
+            if let Some(details) = traversal(source, &mut links)
+                .and_then(|()| traversal(target, &mut links))
+                .err()
+            {
+                failures.insert(index, details);
+                continue;
+            }
+        }
+        if let Some(current_dir) = &current_dir {
+            if contains(source, current_dir) {
+                failures.insert(
+                    index,
+                    ApplyErrorDetails::ContainsCurrentDir(current_dir.clone()),
+                );
+                continue;
+            }
+        }
+        if options.expect != SourceKind::Any {
+            match SourceKind::of(source, options.follow_symlinks) {
+                Ok(actual) if options.expect.matches(actual) => {}
+                Ok(actual) => {
+                    let expected = options.expect;
+                    failures.insert(index, ApplyErrorDetails::KindMismatch { expected, actual });
+                    continue;
+                }
+                Err(err) => {
+                    failures.insert(index, ApplyErrorDetails::io(IoOp::Stat, source, err));
+                    continue;
+                }
+            }
+        }
+        match crosses_boundary(source, target, options.boundary) {
+            Ok(false) => {}
+            Ok(true) => {
+                failures.insert(index, ApplyErrorDetails::CrossesBoundary(options.boundary));
+                continue;
+            }
+            Err(details) => {
+                failures.insert(index, details);
+                continue;
+            }
+        }
+        let Some(parent) = target.parent() else {
+            continue;
+        };
+        match symlinked_parent(parent, &mut links) {
+            Ok(None) => {}
+            Ok(Some(link)) if options.deny_symlinked_parents => {
+                failures.insert(index, ApplyErrorDetails::SymlinkedParent(link.clone()));
+            }
+            Ok(Some(link)) => warnings.push(Warning::SymlinkedParent {
+                target: target.to_owned(),
+                link: link.clone(),
+            }),
+            Err(err) if options.deny_symlinked_parents => {
+                // The error is shared by every target with the same parent.
+                let err = io::Error::new(err.kind(), err.to_string());
+                failures.insert(index, ApplyErrorDetails::io(IoOp::Stat, parent, err));
+            }
+            // The check is only advisory by default.
+            Err(_) => {}
+        }
+    }
+    if options.copy_across_devices && !options.skip_free_space_check {
+        let copies = cross_copies(renames, &failures);
+        failures.extend(shortages(&copies, available_space));
+    }
+    failures
+}
+
+/// A source copied to another filesystem, since it cannot be renamed there.
+struct CrossCopy<'a> {
+    index: usize,
+    device: u64,
+    target: &'a Path,
+    size: u64,
+}
+
+/// Returns the operations whose sources are on another filesystem than
+/// their targets, skipping the failed ones.
+///
+/// Paths that cannot be inspected are left out: their operations fail on
+/// their own when applied.
+fn cross_copies<'a, S, T>(
+    renames: &'a [Rename<S, T>],
+    failures: &HashMap<usize, ApplyErrorDetails>,
+) -> Vec<CrossCopy<'a>>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    let mut copies = Vec::new();
+    for (index, rename) in renames.iter().enumerate() {
+        if failures.contains_key(&index) {
+            continue;
+        }
+        let (source, target) = (rename.source.as_ref(), rename.target.as_ref());
+        let (Ok(source_device), Ok(device)) = (device_of(source), device_of(target)) else {
+            continue;
+        };
+        if source_device == device {
+            continue;
+        }
+        if let Ok(size) = tree_size(source) {
+            copies.push(CrossCopy {
+                index,
+                device,
+                target,
+                size,
+            });
+        }
+    }
+    copies
+}
+
+/// Fails every copy to a filesystem without enough available space for all
+/// the copies to it.
+///
+/// The space is looked up once per filesystem, at the first target copied
+/// to it. If it cannot be looked up, the copies are attempted anyway.
+fn shortages(
+    copies: &[CrossCopy<'_>],
+    available_space: impl Fn(&Path) -> io::Result<u64>,
+) -> HashMap<usize, ApplyErrorDetails> {
+    let mut devices: HashMap<u64, (&Path, u64)> = HashMap::new();
+    for copy in copies {
+        let (_, required) = devices.entry(copy.device).or_insert((copy.target, 0));
+        *required += copy.size;
+    }
+    let mut failures = HashMap::new();
+    for (device, (target, required)) in devices {
+        let Ok(available) = available_space(target) else {
+            continue;
+        };
+        if required <= available {
+            continue;
+        }
+        for copy in copies.iter().filter(|copy| copy.device == device) {
+            failures.insert(
+                copy.index,
+                ApplyErrorDetails::InsufficientSpace(Box::new(SpaceShortage {
+                    path: target.to_owned(),
+                    required,
+                    available,
+                })),
+            );
+        }
+    }
+    failures
+}
+
+/// The deepest symbolic link traversed by each parent directory.
+type Links<'a> = HashMap<&'a Path, io::Result<Option<PathBuf>>>;
+
+/// Returns the deepest symbolic link traversed by a parent directory, looked
+/// up once per directory.
+fn symlinked_parent<'a, 'l>(
+    parent: &'a Path,
+    links: &'l mut Links<'a>,
+) -> &'l io::Result<Option<PathBuf>> {
+    links
+        .entry(parent)
+        .or_insert_with(|| symlinked_ancestor(parent).map(|link| link.map(Path::to_owned)))
+}
+
+/// Fails if an ancestor of a path is a symbolic link.
+///
+/// The path itself may be a link, since renaming it does not follow it.
+fn traversal<'a>(path: &'a Path, links: &mut Links<'a>) -> Result<(), ApplyErrorDetails> {
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+    match symlinked_parent(parent, links) {
+        Ok(None) => Ok(()),
+        Ok(Some(link)) => Err(ApplyErrorDetails::SymlinkTraversal(link.clone())),
+        Err(err) => {
+            // The error is shared by every path with the same parent.
+            let err = io::Error::new(err.kind(), err.to_string());
+            Err(ApplyErrorDetails::io(IoOp::Stat, parent, err))
+        }
+    }
+}
+
+/// Tests whether a source is a canonical directory, or one of its
+/// ancestors.
+///
+/// Only the parent of the source is resolved, since renaming a symbolic link
+/// leaves its target in place. Sources that cannot be resolved contain
+/// nothing.
+fn contains(source: &Path, dir: &Path) -> bool {
+    let resolved = if source.file_name().is_some() {
+        resolve_parent(source)
+    } else {
+        // Paths such as `.` or `a/..` have no file name to keep.
+        source.canonicalize()
+    };
+    resolved.is_ok_and(|source: PathBuf| dir.starts_with(source))
+}
+
+fn crosses_boundary(
+    source: &Path,
+    target: &Path,
+    boundary: Boundary,
+) -> Result<bool, ApplyErrorDetails> {
+    let stat = |path: &Path| {
+        let path = path.to_owned();
+        move |error| ApplyErrorDetails::io(IoOp::Stat, path, error)
+    };
+    Ok(match boundary {
+        Boundary::SameFilesystem => {
+            device_of(source).map_err(stat(source))? != device_of(target).map_err(stat(target))?
+        }
+        Boundary::SameGitRepo => {
+            git_root(source.parent().unwrap_or(source)).map_err(stat(source))?
+                != git_root(target).map_err(stat(target))?
+        }
+        Boundary::None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, io, path::Path};
+
+    use crate::options::Boundary;
+
+    #[test]
+    fn crosses_git_repo() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo_1 = temp_dir.path().join("repo-1");
+        let repo_2 = temp_dir.path().join("repo-2");
+        fs::create_dir_all(repo_1.join(".git"))?;
+        fs::create_dir_all(repo_2.join(".git"))?;
+        let source = repo_1.join("file.txt");
+        fs::File::create(&source)?;
+
+        let crosses =
+            |target: &Path, boundary| super::crosses_boundary(&source, target, boundary).unwrap();
+        assert!(!crosses(&repo_1.join("sub/new.txt"), Boundary::SameGitRepo));
+        assert!(crosses(&repo_2.join("new.txt"), Boundary::SameGitRepo));
+        assert!(crosses(
+            &temp_dir.path().join("new.txt"),
+            Boundary::SameGitRepo
+        ));
+        assert!(!crosses(&repo_2.join("new.txt"), Boundary::None));
+        Ok(())
+    }
+
+    #[test]
+    fn contains_current_dir() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        let current_dir = root.join("a/b");
+        fs::create_dir_all(&current_dir)?;
+
+        assert!(super::contains(&root.join("a"), &current_dir));
+        assert!(super::contains(&current_dir, &current_dir));
+        assert!(super::contains(&root.join("a/b/.."), &current_dir));
+        assert!(!super::contains(&root.join("a/b/c"), &current_dir));
+        assert!(!super::contains(&root.join("b"), &current_dir));
+        assert!(!super::contains(&root.join("missing/.."), &current_dir));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("a", root.join("link"))?;
+            // Renaming the link itself leaves the directory in place.
+            assert!(!super::contains(&root.join("link"), &current_dir));
+            assert!(super::contains(&root.join("link/b"), &current_dir));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn source_kinds() -> io::Result<()> {
+        use crate::{
+            error::ApplyErrorDetails,
+            operation::Rename,
+            options::{ApplyOptions, SourceKind},
+        };
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        fs::write(root.join("file"), b"")?;
+        fs::create_dir(root.join("dir"))?;
+        let renames = [
+            Rename::new(root.join("file"), root.join("a")),
+            Rename::new(root.join("dir"), root.join("b")),
+            Rename::new(root.join("missing"), root.join("c")),
+        ];
+
+        let options = ApplyOptions::new().expect(SourceKind::Dir);
+        let failures = super::check(&renames, &HashMap::new(), &options, &mut Vec::new());
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(
+            failures[&0],
+            ApplyErrorDetails::KindMismatch {
+                expected: SourceKind::Dir,
+                actual: SourceKind::File,
+            }
+        ));
+        assert!(
+            matches!(&failures[&2], ApplyErrorDetails::Io { error, .. } if error.kind() == io::ErrorKind::NotFound)
+        );
+
+        let failures = super::check(
+            &renames,
+            &HashMap::new(),
+            &ApplyOptions::new(),
+            &mut Vec::new(),
+        );
+        assert!(failures.is_empty());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_parents() -> io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        use crate::{
+            error::ApplyErrorDetails, operation::Rename, options::ApplyOptions, warning::Warning,
+        };
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::create_dir(root.join("real"))?;
+        symlink("real", root.join("link"))?;
+        let renames = [
+            Rename::new(root.join("a.txt"), root.join("link/a.txt")),
+            Rename::new(root.join("b.txt"), root.join("link/sub/b.txt")),
+            Rename::new(root.join("c.txt"), root.join("real/c.txt")),
+        ];
+
+        let mut warnings = Vec::new();
+        let failures = super::check(
+            &renames,
+            &HashMap::new(),
+            &ApplyOptions::new(),
+            &mut warnings,
+        );
+        assert!(failures.is_empty());
+        assert_eq!(warnings.len(), 2);
+        assert!(matches!(
+            &warnings[1],
+            Warning::SymlinkedParent { target, link }
+                if *target == root.join("link/sub/b.txt") && *link == root.join("link")
+        ));
+
+        let options = ApplyOptions::new().deny_symlinked_parents(true);
+        let mut warnings = Vec::new();
+        let failures = super::check(&renames, &HashMap::new(), &options, &mut warnings);
+        assert!(warnings.is_empty());
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(
+            &failures[&0],
+            ApplyErrorDetails::SymlinkedParent(link) if *link == root.join("link")
+        ));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_traversal() -> io::Result<()> {
+        use std::os::unix::fs::symlink;
+
+        use crate::{error::ApplyErrorDetails, operation::Rename, options::ApplyOptions};
+
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+        fs::create_dir_all(root.join("real/sub"))?;
+        symlink("real", root.join("link"))?;
+        let renames = [
+            Rename::new(root.join("link/sub/a.txt"), root.join("a.txt")),
+            Rename::new(root.join("b.txt"), root.join("link/b.txt")),
+            Rename::new(root.join("real/sub/c.txt"), root.join("real/c.txt")),
+            // Links are renamed themselves.
+            Rename::new(root.join("link"), root.join("real/link")),
+        ];
+
+        let options = ApplyOptions::new().no_symlink_traversal(true);
+        let failures = super::check(&renames, &HashMap::new(), &options, &mut Vec::new());
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(
+            &failures[&0],
+            ApplyErrorDetails::SymlinkTraversal(link) if *link == root.join("link")
+        ));
+        assert!(matches!(
+            &failures[&1],
+            ApplyErrorDetails::SymlinkTraversal(link) if *link == root.join("link")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn free_space() {
+        use super::CrossCopy;
+        use crate::error::ApplyErrorDetails;
+
+        let copy = |index, device, target, size| CrossCopy {
+            index,
+            device,
+            target: Path::new(target),
+            size,
+        };
+        let copies = [
+            copy(0, 1, "/mnt/a/x", 600),
+            copy(1, 1, "/mnt/a/y", 500),
+            copy(2, 2, "/mnt/b/z", 900),
+            copy(3, 3, "/mnt/c/w", 100),
+        ];
+        let available = |target: &Path| match target.parent() {
+            Some(dir) if dir == Path::new("/mnt/c") => Err(io::ErrorKind::Other.into()),
+            _ => Ok(1000),
+        };
+
+        // The copies to a filesystem are summed, and the copies to a
+        // filesystem whose space is unknown are attempted.
+        let failures = super::shortages(&copies, available);
+        assert_eq!(failures.len(), 2);
+        for index in [0, 1] {
+            let ApplyErrorDetails::InsufficientSpace(shortage) = &failures[&index] else {
+                panic!("unexpected failure: {:?}", failures[&index]);
+            };
+            assert_eq!(shortage.path, Path::new("/mnt/a/x"));
+            assert_eq!((shortage.required, shortage.available), (1100, 1000));
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    fsutil::sync_parent,
+    json::{path_to_json, NOMINAL_VERSION},
+    tempname::{create_temp_sibling, TempNamer},
+    warning::Warning,
+    JSON_VERSION,
+};
+
+/// A progress file, rewritten while applying a plan.
+///
+/// See [`ApplyOptions::progress_file`](crate::ApplyOptions::progress_file).
+pub(crate) struct ProgressFile<'n> {
+    path: PathBuf,
+    namer: &'n dyn TempNamer,
+    every: usize,
+    total: usize,
+    completed: usize,
+    errors: usize,
+    /// The error that stopped the updates, if any.
+    error: Option<io::Error>,
+}
+
+impl<'n> ProgressFile<'n> {
+    pub(crate) fn new(path: &Path, every: usize, total: usize, namer: &'n dyn TempNamer) -> Self {
+        Self {
+            path: path.to_owned(),
+            namer,
+            every: every.max(1),
+            total,
+            completed: 0,
+            errors: 0,
+            error: None,
+        }
+    }
+
+    /// Updates the file before executing an operation, if it is due.
+    pub(crate) fn start(&mut self, source: &Path, target: &Path) {
+        if self.completed.is_multiple_of(self.every) {
+            let current = json!({
+                "source": path_to_json(source),
+                "target": path_to_json(target),
+            });
+            self.write("running", current);
+        }
+    }
+
+    /// Records the outcome of an operation.
+    pub(crate) fn record(&mut self, failed: bool) {
+        self.completed += 1;
+        if failed {
+            self.errors += 1;
+        }
+    }
+
+    /// Writes the final state of the file, and returns the warning raised
+    /// if it could not be updated.
+    pub(crate) fn finish(mut self, success: bool) -> Option<Warning> {
+        self.write(if success { "succeeded" } else { "failed" }, Value::Null);
+        self.error.map(|error| Warning::ProgressFile {
+            path: self.path,
+            error,
+        })
+    }
+
+    fn write(&mut self, state: &str, current: Value) {
+        if self.error.is_some() {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64());
+        let value = json!({
+            "version": JSON_VERSION,
+            "nominal_version": NOMINAL_VERSION,
+            "state": state,
+            "total": self.total,
+            "completed": self.completed,
+            "errors": self.errors,
+            "current": current,
+            "timestamp": timestamp,
+        });
+        if let Err(error) = replace_file(&self.path, value.to_string().as_bytes(), self.namer) {
+            warn!(
+                "could not update the progress file {}: {}",
+                self.path.display(),
+                error
+            );
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Atomically replaces the content of a file, through a temporary file
+/// named by `namer`.
+///
+/// The temporary file is flushed to disk before it replaces the file, and
+/// the replacement afterwards, so that a crash leaves either the old or the
+/// new content, never a partial one.
+pub(crate) fn replace_file(path: &Path, content: &[u8], namer: &dyn TempNamer) -> io::Result<()> {
+    let temp_path = create_temp_sibling(namer, path, |temp_path| {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)?;
+        (file.write_all(content))
+            .and_then(|()| file.sync_all())
+            .inspect_err(|_| {
+                let _ = fs::remove_file(temp_path);
+            })
+    })?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })?;
+    sync_parent(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::Path};
+
+    use serde_json::Value;
+
+    use super::ProgressFile;
+    use crate::tempname::DefaultTempNamer;
+
+    #[test]
+    fn progress_file() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("progress.json");
+        let read = || -> io::Result<Value> { Ok(serde_json::from_slice(&fs::read(&path)?)?) };
+
+        let mut progress = ProgressFile::new(&path, 2, 3, &DefaultTempNamer);
+        progress.start(Path::new("a"), Path::new("b"));
+        assert_eq!(read()?["state"], "running");
+        assert_eq!(read()?["current"]["source"], "a");
+        progress.record(false);
+
+        // Not due yet.
+        progress.start(Path::new("c"), Path::new("d"));
+        assert_eq!(read()?["current"]["source"], "a");
+        progress.record(true);
+
+        progress.start(Path::new("e"), Path::new("f"));
+        assert_eq!(read()?["completed"], 2);
+        progress.record(false);
+
+        assert!(progress.finish(false).is_none());
+        let value = read()?;
+        assert_eq!(value["state"], "failed");
+        assert_eq!(value["completed"], 3);
+        assert_eq!(value["errors"], 1);
+        assert!(value["current"].is_null());
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 1);
+        Ok(())
+    }
+}
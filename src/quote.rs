@@ -0,0 +1,49 @@
+use std::{io, path::Path};
+
+/// Writes a path quoted for POSIX shells.
+///
+/// The path is enclosed in single quotes, and each single quote it holds is
+/// written as `'\''`: nothing else is special between single quotes, so the
+/// other bytes of the path are written as is, including newlines and bytes
+/// that are not valid UTF-8. This is the only quoting routine of the crate,
+/// to be used by every writer producing shell words.
+pub(crate) fn write_shell_quoted<W>(writer: &mut W, path: &Path) -> io::Result<()>
+where
+    W: io::Write,
+{
+    writer.write_all(b"'")?;
+    let mut parts = path
+        .as_os_str()
+        .as_encoded_bytes()
+        .split(|&byte| byte == b'\'');
+    if let Some(first) = parts.next() {
+        writer.write_all(first)?;
+    }
+    for part in parts {
+        writer.write_all(b"'\\''")?;
+        writer.write_all(part)?;
+    }
+    writer.write_all(b"'")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::write_shell_quoted;
+
+    #[test]
+    fn shell_quoting() {
+        let quote = |path: &str| {
+            let mut output = Vec::new();
+            write_shell_quoted(&mut output, Path::new(path)).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b/c\td"), "'a b/c\td'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("''"), r"''\'''\'''");
+        assert_eq!(quote("$HOME `x` \"y\" \\n"), "'$HOME `x` \"y\" \\n'");
+        assert_eq!(quote("a\nb"), "'a\nb'");
+    }
+}
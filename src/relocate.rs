@@ -0,0 +1,192 @@
+//! The relocation of paths under the directories moved while applying a
+//! plan.
+//!
+//! The paths of a plan name the entries as they were when planning. Once an
+//! operation moves a directory, the later operations naming entries under
+//! it would act on stale paths: their sources and targets are relocated
+//! under the new path of the directory instead, as if the directory had
+//! been renamed last. An entry whose relocated source is its target was
+//! moved there with its directory, and is already applied.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::operation::Rename;
+
+/// The directories moved so far while applying a plan.
+#[derive(Debug, Default)]
+pub(crate) struct MovedDirs {
+    /// The indices of the operations whose source is an ancestor of a path
+    /// of another operation, the only ones whose moves are recorded.
+    containers: HashSet<usize>,
+    /// The applied moves of these sources, in execution order, with their
+    /// own paths relocated by the moves before them.
+    moves: Vec<(PathBuf, PathBuf)>,
+}
+
+impl MovedDirs {
+    /// Finds the operations of a plan whose source contains other paths of
+    /// the plan.
+    pub(crate) fn new<S, T>(renames: &[Rename<S, T>]) -> Self
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        let mut paths: Vec<&Path> = (renames.iter())
+            .flat_map(|rename| [rename.source.as_ref(), rename.target.as_ref()])
+            .collect();
+        paths.sort_unstable();
+        // Paths compare component by component, so that the paths under a
+        // directory come right after it.
+        let containers = (renames.iter().enumerate())
+            .filter(|(_, rename)| {
+                let source = rename.source.as_ref();
+                let next = paths.partition_point(|&path| path <= source);
+                paths.get(next).is_some_and(|path| path.starts_with(source))
+            })
+            .map(|(index, _)| index)
+            .collect();
+        Self {
+            containers,
+            moves: Vec::new(),
+        }
+    }
+
+    /// Returns the relocated source and target of an operation, or [`None`]
+    /// if neither is under a moved directory.
+    pub(crate) fn relocate(&self, source: &Path, target: &Path) -> Option<(PathBuf, PathBuf)> {
+        if self.moves.is_empty() {
+            return None;
+        }
+        match (self.relocate_path(source), self.relocate_path(target)) {
+            (None, None) => None,
+            (new_source, new_target) => Some((
+                new_source.unwrap_or_else(|| source.to_owned()),
+                new_target.unwrap_or_else(|| target.to_owned()),
+            )),
+        }
+    }
+
+    fn relocate_path(&self, path: &Path) -> Option<PathBuf> {
+        let mut relocated: Option<PathBuf> = None;
+        for (from, to) in &self.moves {
+            let current = relocated.as_deref().unwrap_or(path);
+            if let Ok(rest) = current.strip_prefix(from) {
+                if !rest.as_os_str().is_empty() {
+                    relocated = Some(to.join(rest));
+                }
+            }
+        }
+        relocated
+    }
+
+    /// Records that an operation moved its source to its target, given as
+    /// relocated.
+    pub(crate) fn record(&mut self, index: usize, source: &Path, target: &Path) {
+        if self.containers.contains(&index) {
+            self.moves.push((source.to_owned(), target.to_owned()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use crate::{ApplyOptions, RenameStatus, Renamer, UndoStack};
+
+    #[test]
+    fn renamed_ancestor() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::create_dir_all(path("data/in/sub"))?;
+        for file in ["data/in/a", "data/in/c", "data/in/sub/d"] {
+            fs::write(path(file), file)?;
+        }
+
+        let renames = [
+            ("data/in", "data/archive"),
+            ("data/in/a", "data/in/b"),
+            ("data/in/c", "out/c"),
+            ("data/in/sub", "data/in/sub2"),
+            ("data/in/sub/d", "data/in/sub/e"),
+        ];
+        let renamer: Renamer<PathBuf, PathBuf> = (renames.iter())
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        let plan = renamer.plan().unwrap();
+        // The directory is moved first.
+        assert_eq!(plan.execution[0], 0);
+        let report = plan.apply_with(&ApplyOptions::new());
+        assert!(report.is_success(), "{:?}", report);
+
+        for (file, content) in [
+            ("data/archive/b", "data/in/a"),
+            ("out/c", "data/in/c"),
+            ("data/archive/sub2/e", "data/in/sub/d"),
+        ] {
+            assert_eq!(fs::read_to_string(path(file))?, content);
+        }
+        assert!(!path("data/in").exists());
+        // The report holds the paths renamed.
+        let entries: Vec<_> = (report.entries().iter())
+            .map(|entry| (entry.source.clone(), entry.target.clone()))
+            .collect();
+        assert!(entries.contains(&(path("data/archive/a"), path("data/archive/b"))));
+        assert!(entries.contains(&(path("data/archive/sub/d"), path("data/archive/sub/e"))));
+
+        // Undoing renames everything back.
+        let mut undo_stack = UndoStack::new();
+        undo_stack.push(&report);
+        undo_stack.undo_last().unwrap();
+        for file in ["data/in/a", "data/in/c", "data/in/sub/d"] {
+            assert_eq!(fs::read_to_string(path(file))?, file);
+        }
+
+        // Iterating relocates as well.
+        let renamer: Renamer<PathBuf, PathBuf> = (renames.iter())
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        let plan = renamer.plan().unwrap();
+        assert!(plan.apply_iter().all(|result| result.is_ok()));
+        assert!(path("data/archive/sub2/e").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn relocated_onto_itself() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        let renames = [("d/f", "e/f"), ("d", "e")];
+        let renamer = || -> Renamer<PathBuf, PathBuf> {
+            (renames.iter())
+                .map(|(source, target)| (path(source), path(target)))
+                .collect()
+        };
+        fs::create_dir(path("d"))?;
+        fs::write(path("d/f"), "f")?;
+
+        // Moving the directory moves its entry to its target already.
+        let report = renamer().plan().unwrap().apply_with(&ApplyOptions::new());
+        assert!(report.is_success(), "{:?}", report);
+        assert!(matches!(
+            report.entries[1].status,
+            RenameStatus::AlreadyApplied
+        ));
+        assert_eq!(report.entries[1].source, path("e/f"));
+        assert_eq!(fs::read_to_string(path("e/f"))?, "f");
+
+        // Undoing moves the directory back.
+        let mut undo_stack = UndoStack::new();
+        undo_stack.push(&report);
+        undo_stack.undo_last().unwrap();
+        assert_eq!(fs::read_to_string(path("d/f"))?, "f");
+
+        let plan = renamer().plan().unwrap();
+        assert!(plan.apply_iter().all(|result| result.is_ok()));
+        assert_eq!(fs::read_to_string(path("e/f"))?, "f");
+        Ok(())
+    }
+}
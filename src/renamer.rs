@@ -1,11 +1,33 @@
-use std::path::Path;
+use std::{
+    any::Any,
+    cmp::Reverse,
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::{error::PlanError, operation::Rename, plan::Plan};
+use crate::{
+    casefold::CaseInsensitiveDirs,
+    collate::PathCollator,
+    cycles::break_cycles,
+    error::{InvalidTargetReason, PlanError},
+    fsutil::{entry_id, EntryId},
+    inspect,
+    noop::{is_noop, NoopOptions},
+    operation::Rename,
+    options::{ExecutionOrder, FollowSymlinks, PlanOptions},
+    path::{normalize, path_exists},
+    plan::{base_dir_of, Plan, PlanStats, SkipReason, SkippedEntry},
+    planner::FsCache,
+    stamp::SourceStamp,
+    tag::Tag,
+};
 
 /// Prepares a batch file renaming operation.
 #[derive(Debug)]
 pub struct Renamer<S, T> {
-    renames: Vec<Rename<S, T>>,
+    pub(crate) renames: Vec<Rename<S, T>>,
 }
 
 impl<S, T> Renamer<S, T> {
@@ -52,6 +74,92 @@ impl<S, T> Renamer<S, T> {
     pub fn add(&mut self, source: S, target: T) {
         self.renames.push(Rename::new(source, target));
     }
+
+    /// Adds a pre-built rename operation to the renamer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Rename, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add_rename(Rename::new("old.txt", "new.txt"));
+    /// ```
+    pub fn add_rename(&mut self, rename: Rename<S, T>) {
+        self.renames.push(rename);
+    }
+
+    /// Adds a rename operation to the renamer, with a user payload.
+    ///
+    /// The tag follows the operation through the plan, see [`Tag`].
+    #[cfg_attr(
+        feature = "serde",
+        doc = "To serialize it, attach a [`Tag::serializable`] tag with",
+        doc = "[`add_rename`](Self::add_rename) instead."
+    )]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add_tagged(path("missing.txt"), path("new.txt"), 42_u32);
+    ///
+    /// let err = renamer.plan()?.apply().unwrap_err();
+    /// assert_eq!(err.tag.unwrap().downcast_ref(), Some(&42_u32));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn add_tagged<U>(&mut self, source: S, target: T, tag: U)
+    where
+        U: Any + fmt::Debug + Send + Sync,
+    {
+        self.renames
+            .push(Rename::new(source, target).with_tag(Tag::new(tag)));
+    }
+
+    /// Adds a rename operation to the renamer, with a priority.
+    ///
+    /// Operations with a higher priority are executed first, such as to
+    /// attempt the important ones while there is still room for them, with
+    /// [`continue_on_error`](crate::ApplyOptions::continue_on_error).
+    /// Operations with the same priority, zero by default, are executed in
+    /// the [execution order](crate::PlanOptions::execution_order) of the
+    /// plan. Whatever their priorities, an operation whose target is the
+    /// source of another operation is still executed after it, and chained
+    /// operations merged by
+    /// [`PlanOptions::coalesce_chains`](crate::PlanOptions::coalesce_chains)
+    /// take the highest of their priorities. The plan is still displayed
+    /// sorted by target.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// File::create(path("a"))?;
+    /// File::create(path("b"))?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// renamer.add(path("a"), path("c"));
+    /// renamer.add_with_priority(path("b"), path("d"), 10);
+    ///
+    /// let report = renamer.plan()?.apply_with(&ApplyOptions::new());
+    /// let targets: Vec<_> = report
+    ///     .entries()
+    ///     .iter()
+    ///     .map(|entry| &entry.target)
+    ///     .collect();
+    /// assert_eq!(targets, [&path("d"), &path("c")]);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn add_with_priority(&mut self, source: S, target: T, priority: i32) {
+        self.renames
+            .push(Rename::new(source, target).with_priority(priority));
+    }
 }
 
 impl<S, T> Renamer<S, T>
@@ -61,46 +169,504 @@ where
 {
     /// Consumes the renamer and returns a [`Plan`].
     pub fn plan(self) -> Result<Plan<S, T>, PlanError> {
+        self.plan_with(&PlanOptions::new())
+    }
+
+    /// Consumes the renamer and returns a [`Plan`], with the specified
+    /// options.
+    ///
+    /// Planning fails with [`PlanError::InvalidTarget`] if a target does not
+    /// end with a file name, e.g. `dir/`, `..` or an empty path, and with
+    /// [`PlanError::EmptySource`] if a source is empty. Operations are
+    /// checked in insertion order, target first, before no-ops are skipped:
+    /// an operation renaming an empty path to itself is rejected too. An
+    /// operation repeating an earlier one, such as an operation added twice,
+    /// is skipped as [`SkipReason::Duplicate`]. A renamer without any
+    /// operation plans to an empty plan, which applies successfully without
+    /// touching the filesystem.
+    ///
+    /// The operations are ordered so that chains are applied from their end,
+    /// each operation after the one moving its target away, and cycles are
+    /// broken through a temporary path. An operation renaming a directory
+    /// runs before the ones moving entries into its new path, and the
+    /// operations on entries of a directory moved earlier are applied under
+    /// its new path, so that a directory and its children are renamed in
+    /// the same plan. Only operations sharing a source or
+    /// a target cannot all be applied, whatever the order: the later ones
+    /// fail when applied, and [`Plan::conflicts`] reports the shared targets
+    /// beforehand.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{PlanOptions, Renamer};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("photos/a.jpg", "photos/b.jpg");
+    ///
+    /// let plan = renamer.plan_with(&PlanOptions::new().same_directory(true))?;
+    /// assert_eq!(plan.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn plan_with(self, options: &PlanOptions) -> Result<Plan<S, T>, PlanError> {
+        self.plan_cached(options, &mut FsCache::default(), None)
+    }
+
+    /// Consumes the renamer and returns a [`Plan`], looking up the
+    /// filesystem through the given cache, and sorting with the given
+    /// collator or a new one.
+    pub(crate) fn plan_cached(
+        self,
+        options: &PlanOptions,
+        cache: &mut FsCache,
+        collator: Option<Arc<PathCollator>>,
+    ) -> Result<Plan<S, T>, PlanError> {
         let mut renames = self.renames;
-        renames.retain(|r| r.source.as_ref() != r.target.as_ref());
+        let mut stats = PlanStats::default();
 
-        // Sort the renames by target path.
-        #[cfg(feature = "unicode")]
-        {
-            use std::cmp::Ordering;
+        for rename in &renames {
+            let target = rename.target.as_ref();
+            if let Some(reason) = InvalidTargetReason::of(target) {
+                return Err(PlanError::InvalidTarget {
+                    target: target.to_owned(),
+                    reason,
+                });
+            }
+            if rename.source.as_ref().as_os_str().is_empty() {
+                return Err(PlanError::EmptySource {
+                    target: target.to_owned(),
+                });
+            }
+        }
 
-            use icu_collator::{Collator, CollatorOptions};
+        let non_portable: Vec<_> = (renames.iter())
+            .filter_map(|rename| {
+                let target = rename.target.as_ref();
+                let issue = *options
+                    .validation_profile
+                    .issues(target.file_name()?)
+                    .first()?;
+                Some((target.to_owned(), issue))
+            })
+            .collect();
+        if !non_portable.is_empty() {
+            return Err(PlanError::NonPortableTargets(non_portable));
+        }
 
-            let mut collator_opts = CollatorOptions::new();
-            collator_opts.numeric = Some(icu_collator::Numeric::On);
-            let collator = Collator::try_new(Default::default(), collator_opts)?;
+        let mut skipped = Vec::new();
+        skip_noops(&mut renames, &mut skipped);
+        skip_duplicates(&mut renames, &mut skipped);
+        if options.coalesce_chains {
+            renames = coalesce_chains(renames, &mut skipped);
+            // Chains may end where they started.
+            skip_noops(&mut renames, &mut skipped);
+        }
+        for entry in &skipped {
+            match entry.reason {
+                SkipReason::NoOp => stats.noops += 1,
+                SkipReason::Coalesced => stats.coalesced += 1,
+                SkipReason::Duplicate => stats.duplicates += 1,
+            }
+        }
 
-            #[cfg(unix)]
-            fn compare_paths(collator: &Collator, p1: &Path, p2: &Path) -> Ordering {
-                use std::os::unix::ffi::OsStrExt;
+        if options.same_directory {
+            let moved: Vec<_> = renames
+                .iter()
+                .map(|r| (r.source.as_ref(), r.target.as_ref()))
+                .filter(|(source, target)| resolved_parent(source) != resolved_parent(target))
+                .map(|(source, target)| (source.to_owned(), target.to_owned()))
+                .collect();
+            if !moved.is_empty() {
+                return Err(PlanError::ChangesDirectory(moved));
+            }
+        }
 
-                collator.compare_utf8(p1.as_os_str().as_bytes(), p2.as_os_str().as_bytes())
+        if options.resolve_identity {
+            let duplicates = duplicate_sources(&renames);
+            if !duplicates.is_empty() {
+                return Err(PlanError::DuplicateSources(duplicates));
             }
+        }
 
-            #[cfg(windows)]
-            fn compare_paths(collator: &Collator, p1: &Path, p2: &Path) -> Ordering {
-                use std::os::windows::ffi::OsStrExt;
+        let case_insensitive_dirs = if options.probe_case_sensitivity {
+            CaseInsensitiveDirs::probe(renames.iter().map(|r| r.target.as_ref()), cache)?
+        } else {
+            CaseInsensitiveDirs::default()
+        };
 
-                let p1: Vec<u16> = p1.as_os_str().collect();
-                let p2: Vec<u16> = p2.as_os_str().collect();
-                collator.compare_utf16(&p1, &p2)
+        // Sort the renames by target path, then by source path, keeping track
+        // of the insertion order.
+        let collator = match collator {
+            Some(collator) => collator,
+            None => PathCollator::shared(options.plan_ordering())?,
+        };
+        let compare = |r1: &Rename<S, T>, r2: &Rename<S, T>| {
+            collator
+                .compare_total(r1.target.as_ref(), r2.target.as_ref())
+                .then_with(|| collator.compare_total(r1.source.as_ref(), r2.source.as_ref()))
+        };
+        let mut indexed: Vec<_> = renames.into_iter().enumerate().collect();
+        // The sort is stable, so skipping it on sorted input changes nothing.
+        let sorted = options.assume_sorted
+            && indexed
+                .windows(2)
+                .all(|pair| compare(&pair[0].1, &pair[1].1).is_le());
+        if !sorted {
+            if options.assume_sorted {
+                warn!("the operations are not sorted, sorting them");
             }
+            indexed.sort_by(|(_, r1), (_, r2)| compare(r1, r2));
+        }
+        let (insertion, renames): (Vec<_>, Vec<_>) = indexed.into_iter().unzip();
+        let base_dir = base_dir_of(&renames);
 
-            renames
-                .sort_by(|r1, r2| compare_paths(&collator, r1.target.as_ref(), r2.target.as_ref()));
+        let mut execution: Vec<usize> = (0..renames.len()).collect();
+        match options.execution_order {
+            ExecutionOrder::TargetSorted => {}
+            ExecutionOrder::SourceDirGrouped => execution.sort_by(|&i, &j| {
+                let parent = |index: usize| {
+                    let source: &Path = renames[index].source.as_ref();
+                    source.parent().unwrap_or(Path::new(""))
+                };
+                collator.compare(parent(i), parent(j))
+            }),
+            ExecutionOrder::Unchanged => execution.sort_by_key(|&index| insertion[index]),
+        }
+        order_chains(&mut execution, &renames);
+        let cycles = break_cycles(&renames, &mut execution);
+        stats.cycles = cycles.len();
+
+        // The metadata of each source is read once, for its stamp and, on
+        // Windows, its attributes.
+        let mut stamps = HashMap::new();
+        let mut hidden = HashSet::new();
+        for (index, rename) in renames.iter().enumerate() {
+            let source = rename.source.as_ref();
+            let metadata = (options.stamp_sources || cfg!(windows))
+                .then(|| inspect::metadata(source, FollowSymlinks::No).ok())
+                .flatten();
+            if rename.is_hidden(metadata.as_ref()) {
+                hidden.insert(index);
+            }
+            if let (true, Some(metadata)) = (options.stamp_sources, &metadata) {
+                let digest_max_size = options.stamp_digest_max_size();
+                if let Ok(Some(stamp)) = SourceStamp::new(source, metadata, digest_max_size) {
+                    stamps.insert(index, stamp);
+                }
+            }
         }
-        #[cfg(not(feature = "unicode"))]
+
+        Ok(Plan {
+            renames,
+            execution,
+            cycles,
+            stats,
+            collator,
+            same_directory: options.same_directory,
+            on_stat_error: options.on_stat_error,
+            case_insensitive_dirs,
+            skipped,
+            base_dir,
+            stamps,
+            hidden,
+        })
+    }
+}
+
+/// Executes every operation after the ones moving its target away and the
+/// ones renaming a directory to an ancestor of its target, if any, and
+/// otherwise the operations with a higher priority first, so that the links
+/// of a chain run from its end.
+fn order_chains<S, T>(execution: &mut Vec<usize>, renames: &[Rename<S, T>])
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    // The sort is stable, so that ties keep their execution order.
+    execution.sort_by_key(|&index| Reverse(renames[index].priority));
+    let mut position = vec![0; renames.len()];
+    for (rank, &index) in execution.iter().enumerate() {
+        position[index] = rank;
+    }
+
+    let mut by_source: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (index, rename) in renames.iter().enumerate() {
+        by_source
+            .entry(rename.source.as_ref())
+            .or_default()
+            .push(index);
+    }
+    let mut by_target: HashMap<&Path, Vec<usize>> = HashMap::new();
+    for (index, rename) in renames.iter().enumerate() {
+        by_target
+            .entry(rename.target.as_ref())
+            .or_default()
+            .push(index);
+    }
+    let mut dependents = vec![Vec::new(); renames.len()];
+    let mut waits_on = vec![Vec::new(); renames.len()];
+    let mut blockers = vec![0usize; renames.len()];
+    for (index, rename) in renames.iter().enumerate() {
+        let target = rename.target.as_ref();
+        // An operation moving an entry into the new path of a directory
+        // waits for the directory, which would otherwise find its target
+        // created as a parent.
+        let containing = (target.ancestors().skip(1))
+            .filter_map(|ancestor| by_target.get(ancestor))
+            .flatten();
+        for &before in by_source
+            .get(target)
+            .into_iter()
+            .flatten()
+            .chain(containing)
         {
-            renames.sort_by(|r1, r2| r1.target.as_ref().cmp(r2.target.as_ref()));
+            if before != index {
+                dependents[before].push(index);
+                waits_on[index].push(before);
+                blockers[index] += 1;
+            }
         }
+    }
+
+    // The operations waiting on each other form a component, which is
+    // entered once the operations it waits on from outside are done.
+    let component = components(&dependents, &waits_on);
+    let mut outer_blockers = vec![0usize; renames.len()];
+    for (index, waits_on) in waits_on.iter().enumerate() {
+        outer_blockers[component[index]] += (waits_on.iter())
+            .filter(|&&before| component[before] != component[index])
+            .count();
+    }
 
-        Ok(Plan { renames })
+    let mut ready: BinaryHeap<_> = (0..renames.len())
+        .filter(|&index| blockers[index] == 0)
+        .map(|index| Reverse(position[index]))
+        .collect();
+    let mut ordered = Vec::with_capacity(renames.len());
+    let mut done = vec![false; renames.len()];
+    while ordered.len() < renames.len() {
+        let Some(Reverse(rank)) = ready.pop() else {
+            // The operations left form cycles, which no order can satisfy:
+            // the first one left in a component free to enter goes next.
+            let rank = (0..renames.len())
+                .find(|&rank| {
+                    let index = execution[rank];
+                    !done[index] && outer_blockers[component[index]] == 0
+                })
+                .unwrap();
+            ready.push(Reverse(rank));
+            blockers[execution[rank]] = 0;
+            continue;
+        };
+        let index = execution[rank];
+        if done[index] {
+            continue;
+        }
+        done[index] = true;
+        ordered.push(index);
+        for &dependent in &dependents[index] {
+            if component[dependent] != component[index] {
+                outer_blockers[component[dependent]] -= 1;
+            }
+            blockers[dependent] = blockers[dependent].saturating_sub(1);
+            if blockers[dependent] == 0 && !done[dependent] {
+                ready.push(Reverse(position[dependent]));
+            }
+        }
     }
+    *execution = ordered;
+}
+
+/// Returns the strongly connected component of each node of a graph, given
+/// the successors and the predecessors of each node.
+///
+/// Components are numbered from zero, with Kosaraju's algorithm.
+fn components(successors: &[Vec<usize>], predecessors: &[Vec<usize>]) -> Vec<usize> {
+    // The nodes, by increasing finish time of a depth-first search.
+    let mut finished = Vec::with_capacity(successors.len());
+    let mut visited = vec![false; successors.len()];
+    for root in 0..successors.len() {
+        if std::mem::replace(&mut visited[root], true) {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            match successors[*node].get(*next) {
+                Some(&successor) => {
+                    *next += 1;
+                    if !std::mem::replace(&mut visited[successor], true) {
+                        stack.push((successor, 0));
+                    }
+                }
+                None => {
+                    finished.push(*node);
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    let mut component = vec![usize::MAX; successors.len()];
+    let mut count = 0;
+    for &root in finished.iter().rev() {
+        if component[root] != usize::MAX {
+            continue;
+        }
+        component[root] = count;
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for &predecessor in &predecessors[node] {
+                if component[predecessor] == usize::MAX {
+                    component[predecessor] = count;
+                    stack.push(predecessor);
+                }
+            }
+        }
+        count += 1;
+    }
+    component
+}
+
+/// Returns the pairs of sources referring to the same directory entry.
+fn duplicate_sources<S, T>(renames: &[Rename<S, T>]) -> Vec<(PathBuf, PathBuf)>
+where
+    S: AsRef<Path>,
+{
+    #[derive(PartialEq, Eq, Hash)]
+    enum Key {
+        Entry(EntryId),
+        Path(PathBuf),
+    }
+
+    let mut seen: HashMap<Key, &Path> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for rename in renames {
+        let source = rename.source.as_ref();
+        let key = match entry_id(source) {
+            Ok(id) => Key::Entry(id),
+            Err(_) => Key::Path(normalize(
+                &std::path::absolute(source).unwrap_or_else(|_| source.to_owned()),
+            )),
+        };
+        match seen.entry(key) {
+            Entry::Occupied(entry) => {
+                duplicates.push((entry.get().to_path_buf(), source.to_owned()))
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(source);
+            }
+        }
+    }
+    duplicates
+}
+
+/// Drops the operations that would change nothing, see [`is_noop`].
+fn skip_noops<S, T>(renames: &mut Vec<Rename<S, T>>, skipped: &mut Vec<SkippedEntry>)
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    renames.retain(|rename| {
+        let noop = is_noop(&rename.source, &rename.target, &NoopOptions::default());
+        if noop {
+            skipped.push(SkippedEntry::new(rename, SkipReason::NoOp));
+        }
+        !noop
+    });
+}
+
+/// Drops the operations renaming the same source to the same target as an
+/// earlier one, once normalized. The first one is kept, with the highest
+/// priority of its duplicates.
+fn skip_duplicates<S, T>(renames: &mut Vec<Rename<S, T>>, skipped: &mut Vec<SkippedEntry>)
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    if renames.len() < 2 {
+        return;
+    }
+    let mut first: HashMap<(PathBuf, PathBuf), usize> = HashMap::with_capacity(renames.len());
+    let mut kept: Vec<Rename<S, T>> = Vec::with_capacity(renames.len());
+    for rename in renames.drain(..) {
+        let key = (
+            normalize(rename.source.as_ref()),
+            normalize(rename.target.as_ref()),
+        );
+        match first.entry(key) {
+            Entry::Occupied(entry) => {
+                let original = &mut kept[*entry.get()];
+                original.priority = original.priority.max(rename.priority);
+                skipped.push(SkippedEntry::new(&rename, SkipReason::Duplicate));
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(kept.len());
+                kept.push(rename);
+            }
+        }
+    }
+    *renames = kept;
+}
+
+/// Merges the chained operations whose intermediate path does not exist.
+fn coalesce_chains<S, T>(
+    renames: Vec<Rename<S, T>>,
+    skipped: &mut Vec<SkippedEntry>,
+) -> Vec<Rename<S, T>>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    // Intermediate paths must be the source and the target of a single
+    // operation each.
+    let mut sources: HashMap<PathBuf, Option<usize>> = HashMap::new();
+    let mut targets: HashMap<PathBuf, usize> = HashMap::new();
+    for (index, rename) in renames.iter().enumerate() {
+        sources
+            .entry(rename.source.as_ref().to_owned())
+            .and_modify(|next| *next = None)
+            .or_insert(Some(index));
+        *targets
+            .entry(rename.target.as_ref().to_owned())
+            .or_default() += 1;
+    }
+
+    let mut renames: Vec<_> = renames.into_iter().map(Some).collect();
+    for index in 0..renames.len() {
+        while let Some(rename) = &renames[index] {
+            let middle = rename.target.as_ref();
+            let next = sources
+                .get(middle)
+                .copied()
+                .flatten()
+                .filter(|&next| next != index && targets[middle] == 1)
+                .filter(|_| !path_exists(middle).unwrap_or(true));
+            let Some(next) = next else {
+                break;
+            };
+            let (Some(rename), Some(next_rename)) = (renames[index].take(), renames[next].take())
+            else {
+                unreachable!("merged operation still chained");
+            };
+            skipped.push(SkippedEntry::new(&next_rename, SkipReason::Coalesced));
+            renames[index] = Some(Rename {
+                source: rename.source,
+                target: next_rename.target,
+                tag: rename.tag,
+                // The merged operation is as urgent as any of its parts.
+                priority: rename.priority.max(next_rename.priority),
+            });
+        }
+    }
+    renames.into_iter().flatten().collect()
+}
+
+/// Returns the parent of a path, made absolute and normalized.
+pub(crate) fn resolved_parent(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    normalize(&std::path::absolute(parent).unwrap_or_else(|_| parent.to_owned()))
 }
 
 impl<S, T> Default for Renamer<S, T> {
@@ -128,3 +694,562 @@ impl<S, T> Extend<(S, T)> for Renamer<S, T> {
         self.renames.extend(iter.into_iter().map(Into::into));
     }
 }
+
+impl<S, T> FromIterator<Rename<S, T>> for Renamer<S, T> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Rename<S, T>>,
+    {
+        Self {
+            renames: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<S, T> Extend<Rename<S, T>> for Renamer<S, T> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Rename<S, T>>,
+    {
+        self.renames.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        path::{Path, PathBuf},
+    };
+
+    use super::Renamer;
+    use crate::{
+        collate::PlanOrdering,
+        error::{ApplyErrorDetails, InvalidTargetReason, PlanError},
+        operation::Rename,
+        options::{ApplyOptions, ExecutionOrder, PlanOptions, WriteOptions},
+        plan::SkipReason,
+    };
+
+    #[test]
+    fn invalid_paths() {
+        let cases = [
+            ("a", "", Some(InvalidTargetReason::Empty)),
+            ("a", "dir/", Some(InvalidTargetReason::TrailingSeparator)),
+            ("a", ".", Some(InvalidTargetReason::DotComponent)),
+            ("a", "dir/..", Some(InvalidTargetReason::DotComponent)),
+            ("a", "/", Some(InvalidTargetReason::TrailingSeparator)),
+            ("a", "dir/.b", None),
+            ("a", "..b", None),
+        ];
+        for (source, target, expected) in cases {
+            let mut renamer = Renamer::new();
+            renamer.add(source, target);
+            match (renamer.plan(), expected) {
+                (Ok(_), None) => {}
+                (Err(PlanError::InvalidTarget { target: t, reason }), Some(expected)) => {
+                    assert_eq!(t, Path::new(target));
+                    assert_eq!(reason, expected, "{target}");
+                }
+                (result, _) => panic!("unexpected result for {target:?}: {result:?}"),
+            }
+            if let Some(expected) = expected {
+                let err = Rename::new(source, target).apply().unwrap_err();
+                assert!(matches!(
+                    err.details,
+                    ApplyErrorDetails::InvalidTarget(reason) if reason == expected
+                ));
+            }
+        }
+
+        let mut renamer = Renamer::new();
+        renamer.add("", "a");
+        assert!(matches!(
+            renamer.plan(),
+            Err(PlanError::EmptySource { target }) if target == Path::new("a")
+        ));
+        assert!(matches!(
+            Rename::new("", "a").apply().unwrap_err().details,
+            ApplyErrorDetails::EmptySource
+        ));
+    }
+
+    #[test]
+    fn same_directory() {
+        let options = PlanOptions::new().same_directory(true);
+        let cwd = env::current_dir().unwrap();
+
+        let renamer: Renamer<PathBuf, PathBuf> = [
+            ("dir/a.txt", "dir/b.txt"),
+            ("dir/c.txt", "dir/./sub/../d.txt"),
+            ("e.txt", "f.txt"),
+            ("./g.txt", "h.txt"),
+        ]
+        .into_iter()
+        .map(|(source, target)| (source.into(), target.into()))
+        .chain([(cwd.join("i.txt"), "j.txt".into())])
+        .collect();
+        assert_eq!(renamer.plan_with(&options).unwrap().len(), 5);
+
+        // Bare file names are relative to the current directory, not to the
+        // source directory.
+        let mut renamer = Renamer::new();
+        renamer.add("dir/a.txt", "b.txt");
+        renamer.add("dir/c.txt", "dir/d.txt");
+        renamer.add("e.txt", "dir/f.txt");
+        match renamer.plan_with(&options) {
+            Err(PlanError::ChangesDirectory(pairs)) => assert_eq!(
+                pairs,
+                [
+                    ("dir/a.txt".into(), "b.txt".into()),
+                    ("e.txt".into(), "dir/f.txt".into()),
+                ]
+            ),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn execution_order() {
+        let execution = |execution_order| {
+            let renamer: Renamer<_, _> = [("x/1", "d"), ("y/2", "a"), ("x/3", "c"), ("y/4", "b")]
+                .into_iter()
+                .collect();
+            let options = PlanOptions::new().execution_order(execution_order);
+            let plan = renamer.plan_with(&options).unwrap();
+            plan.execution
+                .iter()
+                .map(|&index| plan.renames[index].source)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            execution(ExecutionOrder::TargetSorted),
+            ["y/2", "y/4", "x/3", "x/1"]
+        );
+        assert_eq!(
+            execution(ExecutionOrder::SourceDirGrouped),
+            ["x/3", "x/1", "y/2", "y/4"]
+        );
+        assert_eq!(
+            execution(ExecutionOrder::Unchanged),
+            ["x/1", "y/2", "x/3", "y/4"]
+        );
+    }
+
+    #[test]
+    fn cycles_waiting_on_cycles() {
+        // The second swap moves an entry into the new path of a directory
+        // of the first one, and waits on it, although it sorts first.
+        let renamer: Renamer<_, _> = [("x", "y"), ("y", "x"), ("a", "y/a"), ("y/a", "a")]
+            .into_iter()
+            .collect();
+        let plan = renamer.plan().unwrap();
+        plan.assert_valid();
+        let sources: Vec<_> = (plan.execution.iter())
+            .map(|&index| plan.renames[index].source)
+            .collect();
+        assert_eq!(sources, ["y", "x", "y/a", "a"]);
+    }
+
+    #[test]
+    fn chained_operations() -> std::io::Result<()> {
+        for execution_order in [
+            ExecutionOrder::TargetSorted,
+            ExecutionOrder::SourceDirGrouped,
+            ExecutionOrder::Unchanged,
+        ] {
+            let temp_dir = tempfile::tempdir()?;
+            let path = |name: &str| temp_dir.path().join(name);
+            std::fs::create_dir(path("x"))?;
+            for name in ["a", "x/b", "c"] {
+                std::fs::write(path(name), name)?;
+            }
+
+            // Every link targets the source of the next one.
+            let renamer: Renamer<_, _> = [("a", "x/b"), ("x/b", "c"), ("c", "d")]
+                .into_iter()
+                .map(|(source, target)| (path(source), path(target)))
+                .collect();
+            let options = PlanOptions::new().execution_order(execution_order);
+            let plan = renamer.plan_with(&options).unwrap();
+            let report = plan.apply_with(&ApplyOptions::new());
+            assert!(report.is_success(), "{:?}: {:?}", execution_order, report);
+            for (name, content) in [("x/b", "a"), ("c", "x/b"), ("d", "c")] {
+                assert_eq!(std::fs::read_to_string(path(name))?, content);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_dir_with_children() -> std::io::Result<()> {
+        for execution_order in [
+            ExecutionOrder::TargetSorted,
+            ExecutionOrder::SourceDirGrouped,
+            ExecutionOrder::Unchanged,
+        ] {
+            let temp_dir = tempfile::tempdir()?;
+            let path = |name: &str| temp_dir.path().join(name);
+            std::fs::create_dir(path("photos"))?;
+            for name in ["photos/a.jpg", "photos/b.jpg"] {
+                std::fs::write(path(name), name)?;
+            }
+
+            // The children come first, one of them into the new path of
+            // their directory.
+            let renamer: Renamer<_, _> = [
+                ("photos/a.jpg", "album/1.jpg"),
+                ("photos/b.jpg", "photos/2.jpg"),
+                ("photos", "album"),
+            ]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+            let options = PlanOptions::new().execution_order(execution_order);
+            let plan = renamer.plan_with(&options).unwrap();
+            let report = plan.apply_with(&ApplyOptions::new());
+            assert!(report.is_success(), "{:?}: {:?}", execution_order, report);
+            for (name, content) in [
+                ("album/1.jpg", "photos/a.jpg"),
+                ("album/2.jpg", "photos/b.jpg"),
+            ] {
+                assert_eq!(std::fs::read_to_string(path(name))?, content);
+            }
+            assert!(!path("photos").exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn priority() {
+        let execution = |renamer: Renamer<&'static str, &'static str>, options: &PlanOptions| {
+            let plan = renamer.plan_with(options).unwrap();
+            plan.execution
+                .iter()
+                .map(|&index| plan.renames[index].source)
+                .collect::<Vec<_>>()
+        };
+        let mut renamer = Renamer::new();
+        renamer.add("x/1", "d");
+        renamer.add_with_priority("y/2", "a", -1);
+        renamer.add_with_priority("x/3", "c", 5);
+        renamer.add("y/4", "b");
+        let options = PlanOptions::new().execution_order(ExecutionOrder::SourceDirGrouped);
+        // Ties keep the execution order, and the display order is unchanged.
+        let plan = renamer.plan_with(&options).unwrap();
+        let sources: Vec<_> = (plan.execution.iter())
+            .map(|&index| plan.renames[index].source)
+            .collect();
+        assert_eq!(sources, ["x/3", "x/1", "y/4", "y/2"]);
+        let targets: Vec<_> = plan.renames.iter().map(|rename| rename.target).collect();
+        assert_eq!(targets, ["a", "b", "c", "d"]);
+
+        // An operation waits for the one moving its target away, whatever
+        // their priorities, and cycles go last.
+        let mut renamer = Renamer::new();
+        renamer.add_with_priority("a", "b", 10);
+        renamer.add("b", "c");
+        renamer.add_with_priority("d", "e", 1);
+        renamer.add_with_priority("x", "y", 2);
+        renamer.add("y", "x");
+        assert_eq!(
+            execution(renamer, &PlanOptions::new()),
+            ["d", "b", "a", "x", "y"]
+        );
+
+        // Coalesced operations keep the highest priority.
+        let mut renamer = Renamer::new();
+        renamer.add("a", "b");
+        renamer.add_with_priority("b", "c", 3);
+        renamer.add_with_priority("d", "e", 1);
+        let options = PlanOptions::new().coalesce_chains(true);
+        assert_eq!(execution(renamer, &options), ["a", "d"]);
+    }
+
+    #[test]
+    fn reproducible_order() {
+        let entries = [
+            ("b", "same"),
+            ("a", "same"),
+            ("c", "other"),
+            ("caf\u{e9}", "same"),
+            ("cafe\u{301}", "same"),
+            ("d", "Same"),
+        ];
+        let write = |order: &[usize]| {
+            let renamer: Renamer<_, _> = order.iter().map(|&index| entries[index]).collect();
+            let mut output = Vec::new();
+            renamer.plan().unwrap().write_to(&mut output).unwrap();
+            output
+        };
+
+        // Every permutation of the entries is written the same way.
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        let expected = write(&order);
+        let mut count = 0;
+        loop {
+            assert_eq!(write(&order), expected, "order: {order:?}");
+            count += 1;
+            // Advance to the next permutation in lexicographic order.
+            let Some(pivot) = (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) else {
+                break;
+            };
+            let swap = (pivot..order.len())
+                .rev()
+                .find(|&i| order[i] > order[pivot - 1])
+                .unwrap();
+            order.swap(pivot - 1, swap);
+            order[pivot..].reverse();
+        }
+        assert_eq!(count, 720);
+    }
+
+    #[test]
+    fn stable_bytewise_order() {
+        // The expected output is the same whatever the features and the
+        // platform.
+        let renamer = || -> Renamer<_, _> {
+            [
+                ("1", "file10.txt"),
+                ("2", "file2.txt"),
+                ("3", "Zebra.txt"),
+                ("4", "apple.txt"),
+                ("5", "\u{e9}t\u{e9}.txt"),
+                ("6", "dir/b.txt"),
+                ("7", "dir-a.txt"),
+                ("8", "dir/A.txt"),
+            ]
+            .into_iter()
+            .collect()
+        };
+        for options in [
+            PlanOptions::new().stable_bytewise_order(true),
+            PlanOptions::new().ordering(PlanOrdering::Bytewise),
+        ] {
+            let plan = renamer().plan_with(&options).unwrap();
+            assert_eq!(plan.ordering(), PlanOrdering::Bytewise);
+
+            let mut output = Vec::new();
+            plan.write_with(&mut output, &WriteOptions::new().compress(false))
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "3 => Zebra.txt\n\
+                 4 => apple.txt\n\
+                 8 => dir/A.txt\n\
+                 6 => dir/b.txt\n\
+                 7 => dir-a.txt\n\
+                 1 => file10.txt\n\
+                 2 => file2.txt\n\
+                 5 => \u{e9}t\u{e9}.txt\n"
+            );
+        }
+
+        // A requested ordering takes precedence.
+        #[cfg(feature = "unicode")]
+        {
+            let options = PlanOptions::new()
+                .stable_bytewise_order(true)
+                .ordering(PlanOrdering::Unicode);
+            let plan = renamer().plan_with(&options).unwrap();
+            assert_eq!(plan.ordering(), PlanOrdering::Unicode);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_identity() -> std::io::Result<()> {
+        use std::{fs, os::unix::fs::symlink};
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name| temp_dir.path().join(name);
+        fs::create_dir(path("real"))?;
+        symlink("real", path("link"))?;
+        fs::write(path("real/a"), b"")?;
+        fs::hard_link(path("real/a"), path("real/b"))?;
+
+        let options = PlanOptions::new().resolve_identity(true);
+        let mut renamer = Renamer::new();
+        renamer.add(path("real/a"), path("a1"));
+        renamer.add(path("real/b"), path("b1"));
+        renamer.add(path("missing"), path("m1"));
+        assert_eq!(renamer.plan_with(&options).unwrap().len(), 3);
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("real/a"), path("a1"));
+        renamer.add(path("link/a"), path("a2"));
+        renamer.add(path("missing"), path("m1"));
+        renamer.add(path("./missing"), path("m2"));
+        match renamer.plan_with(&options) {
+            Err(PlanError::DuplicateSources(pairs)) => assert_eq!(
+                pairs,
+                [
+                    (path("real/a"), path("link/a")),
+                    (path("missing"), path("./missing")),
+                ]
+            ),
+            result => panic!("unexpected result: {result:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates() -> std::io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        std::fs::write(path("a"), b"")?;
+        std::fs::write(path("c"), b"")?;
+
+        let mut renamer: Renamer<PathBuf, PathBuf> = [
+            (path("a"), path("b")),
+            (path("c"), path("d")),
+            (path("a"), path("b")),
+        ]
+        .into_iter()
+        .collect();
+        // Equal once normalized.
+        renamer.extend([(path("./a"), path("sub/../b"))]);
+        renamer.add_with_priority(path("a"), path("b"), 5);
+
+        let plan = renamer.plan().unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan.stats().duplicates, 3);
+        let skipped: Vec<_> = (plan.skipped().iter())
+            .map(|entry| (entry.source.clone(), entry.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (path("a"), SkipReason::Duplicate),
+                (path("./a"), SkipReason::Duplicate),
+                (path("a"), SkipReason::Duplicate),
+            ]
+        );
+        // The kept operation is as urgent as its duplicates.
+        let kept = (plan.renames.iter())
+            .find(|rename| rename.source == path("a"))
+            .unwrap();
+        assert_eq!(kept.priority, 5);
+        plan.assert_valid();
+
+        // `a` is renamed once, instead of failing on its duplicates.
+        plan.apply().unwrap();
+        assert!(path("b").exists() && path("d").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn coalesce_chains() -> std::io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        std::fs::write(path("exists"), b"")?;
+
+        let mut renamer = Renamer::new();
+        // Merged into `a => d`, even though the links are out of order.
+        renamer.add(path("c"), path("d"));
+        renamer.add(path("a"), path("b"));
+        renamer.add(path("b"), path("c"));
+        // The intermediate path exists.
+        renamer.add(path("e"), path("exists"));
+        renamer.add(path("exists"), path("f"));
+        // The intermediate path is the target of two operations.
+        renamer.add(path("g"), path("h"));
+        renamer.add(path("i"), path("h"));
+        renamer.add(path("h"), path("j"));
+        // The chain ends where it started.
+        renamer.add(path("k"), path("l"));
+        renamer.add(path("l"), path("k"));
+
+        let options = PlanOptions::new().coalesce_chains(true);
+        let plan = renamer.plan_with(&options).unwrap();
+        let renames: Vec<_> = plan
+            .renames
+            .iter()
+            .map(|rename| (rename.source.clone(), rename.target.clone()))
+            .collect();
+        assert_eq!(
+            renames,
+            [
+                (path("a"), path("d")),
+                (path("e"), path("exists")),
+                (path("exists"), path("f")),
+                (path("g"), path("h")),
+                (path("i"), path("h")),
+                (path("h"), path("j")),
+            ]
+        );
+        assert_eq!(plan.stats().coalesced, 3);
+        assert_eq!(plan.stats().noops, 1);
+        let skipped: Vec<_> = plan
+            .skipped()
+            .iter()
+            .map(|entry| (entry.source.clone(), entry.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                (path("b"), SkipReason::Coalesced),
+                (path("c"), SkipReason::Coalesced),
+                (path("l"), SkipReason::Coalesced),
+                (path("k"), SkipReason::NoOp),
+            ]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "arbitrary")]
+    proptest::proptest! {
+        #[test]
+        fn generated_plans_are_valid(
+            data in proptest::collection::vec(proptest::num::u8::ANY, 0..256),
+            same_directory in proptest::bool::weighted(0.25),
+            coalesce_chains: bool,
+            stable_bytewise_order: bool,
+            execution_order in 0..3,
+        ) {
+            let renamer: Renamer<PathBuf, PathBuf> =
+                arbitrary::Unstructured::new(&data).arbitrary().unwrap();
+            let options = PlanOptions::new()
+                .same_directory(same_directory)
+                .coalesce_chains(coalesce_chains)
+                .stable_bytewise_order(stable_bytewise_order)
+                .execution_order(match execution_order {
+                    0 => ExecutionOrder::TargetSorted,
+                    1 => ExecutionOrder::SourceDirGrouped,
+                    _ => ExecutionOrder::Unchanged,
+                });
+            match renamer.plan_with(&options) {
+                Ok(plan) => plan.assert_valid(),
+                Err(err) => assert!(matches!(err, PlanError::ChangesDirectory(_)), "{err}"),
+            }
+        }
+    }
+
+    #[test]
+    fn assume_sorted() {
+        let entries = [("x", "a"), ("w", "a"), ("y", "B"), ("z", "b"), ("v", "c")];
+        let plan = |entries: &[(&'static str, &'static str)], assume_sorted| {
+            let mut renamer = Renamer::new();
+            for &(source, target) in entries {
+                renamer.add(source, target);
+            }
+            let options = PlanOptions::new().assume_sorted(assume_sorted);
+            renamer.plan_with(&options).unwrap()
+        };
+        let expected = plan(&entries, false);
+
+        // Sorted entries are kept as is, others are sorted anyway.
+        let sorted: Vec<_> = expected
+            .renames()
+            .iter()
+            .map(|rename| (rename.source, rename.target))
+            .collect();
+        let mut reversed = sorted.clone();
+        reversed.reverse();
+        for entries in [&sorted, &reversed] {
+            let plan = plan(entries, true);
+            assert_eq!(plan.renames(), expected.renames());
+            assert_eq!(plan.execution, expected.execution);
+        }
+    }
+}
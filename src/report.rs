@@ -0,0 +1,204 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    error::{ApplyError, ApplyErrorDetails},
+    symlinks::RewrittenLink,
+    tag::Tag,
+    warning::Warning,
+};
+
+/// The report returned from
+/// [`Plan::apply_with`](crate::plan::Plan::apply_with).
+#[derive(Debug, Default)]
+pub struct ApplyReport {
+    pub(crate) entries: Vec<ReportEntry>,
+    pub(crate) created_dirs: Vec<CreatedDir>,
+    pub(crate) rewritten_links: Vec<RewrittenLink>,
+    pub(crate) warnings: Vec<Warning>,
+    /// The maximum size of the files whose digest was recorded, if any.
+    #[cfg(feature = "digest")]
+    pub(crate) digest_max_size: Option<u64>,
+}
+
+/// The outcome of a single rename operation in an [`ApplyReport`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ReportEntry {
+    /// The source path of the rename operation.
+    pub source: PathBuf,
+    /// The target path of the rename operation.
+    pub target: PathBuf,
+    /// The status of the rename operation.
+    pub status: RenameStatus,
+    /// The time spent on the rename operation, or [`None`] if it was not
+    /// attempted.
+    pub duration: Option<Duration>,
+    /// The tag of the rename operation, if any.
+    pub tag: Option<Tag>,
+    /// The index of the rename operation, in plan order.
+    pub index: usize,
+    /// The directories created for the rename operation, in creation order.
+    ///
+    /// A directory shared by several operations is listed for the first one
+    /// renaming into it only, so that the directories of all entries add up
+    /// to the [created directories](ApplyReport::created_dirs) of the
+    /// report.
+    pub created_dirs: Vec<CreatedDir>,
+    /// The digest of the renamed file, if recorded.
+    ///
+    /// See [`record_digests`](crate::ApplyOptions::record_digests).
+    #[cfg(feature = "digest")]
+    pub digest: Option<crate::Digest>,
+}
+
+/// The status of a rename operation in an [`ApplyReport`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RenameStatus {
+    /// The rename operation was applied.
+    Applied,
+    /// The source directory was merged into the existing target directory.
+    ///
+    /// See [`DirMerge::MergeInto`](crate::DirMerge::MergeInto).
+    Merged,
+    /// The rename operation was skipped, since the target directory exists,
+    /// or the target could not be inspected.
+    ///
+    /// See [`DirMerge::Skip`](crate::DirMerge::Skip) and
+    /// [`StatErrorPolicy::Skip`](crate::StatErrorPolicy::Skip).
+    Skipped,
+    /// The rename operation was found applied already, by an earlier run.
+    ///
+    /// See [`ApplyOptions::idempotent`](crate::ApplyOptions::idempotent).
+    AlreadyApplied,
+    /// The rename operation could not be applied since the source is in use,
+    /// and was scheduled for the next reboot instead.
+    ///
+    /// See [`windows_defer_in_use`](crate::ApplyOptions::windows_defer_in_use).
+    Deferred,
+    /// The rename operation failed.
+    Failed(ApplyErrorDetails),
+    /// The rename operation was not attempted.
+    Pending,
+}
+
+/// The status of a rename operation, as annotated by
+/// [`Plan::write_annotated_with`](crate::Plan::write_annotated_with).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OperationStatus {
+    /// The rename operation was already applied.
+    Applied,
+    /// The rename operation cannot be applied.
+    Blocked,
+    /// The rename operation is still to be applied.
+    Pending,
+}
+
+impl OperationStatus {
+    /// Returns the style used to color the marker of this status.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn style(self) -> nu_ansi_term::Style {
+        use nu_ansi_term::Color;
+
+        match self {
+            Self::Applied => Color::Green.normal(),
+            Self::Blocked => Color::Red.bold(),
+            Self::Pending => nu_ansi_term::Style::new().dimmed(),
+        }
+    }
+}
+
+/// A directory created while applying a plan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CreatedDir {
+    /// The path of the directory.
+    pub path: PathBuf,
+    /// The mode set on the directory, or [`None`] if it was created with the
+    /// default permissions.
+    ///
+    /// See [`dir_permissions`](crate::ApplyOptions::dir_permissions).
+    pub mode: Option<u32>,
+}
+
+impl ApplyReport {
+    /// Returns the entries of the report, in execution order.
+    ///
+    /// See [`execution_order`](crate::PlanOptions::execution_order).
+    pub fn entries(&self) -> &[ReportEntry] {
+        &self.entries
+    }
+
+    /// Returns the directories created while applying the plan, in creation
+    /// order.
+    pub fn created_dirs(&self) -> &[CreatedDir] {
+        &self.created_dirs
+    }
+
+    /// Returns the symbolic links rewritten after applying the plan.
+    ///
+    /// See [`fix_symlinks_under`](crate::ApplyOptions::fix_symlinks_under).
+    pub fn rewritten_links(&self) -> &[RewrittenLink] {
+        &self.rewritten_links
+    }
+
+    /// Returns the warnings raised while applying the plan.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the number of rename operations in the applied plan, whatever
+    /// their status.
+    ///
+    /// This is zero if and only if the plan was empty.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the applied plan was empty.
+    ///
+    /// An empty report is also a success, see
+    /// [`is_success`](Self::is_success).
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the number of rename operations that were applied, including
+    /// merged directories, but not the ones found
+    /// [already applied](RenameStatus::AlreadyApplied) or
+    /// [deferred](RenameStatus::Deferred).
+    pub fn applied(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.status, RenameStatus::Applied | RenameStatus::Merged))
+            .count()
+    }
+
+    /// Returns `true` if every rename operation was applied, merged, skipped,
+    /// already applied or deferred.
+    pub fn is_success(&self) -> bool {
+        self.entries.iter().all(|entry| {
+            matches!(
+                entry.status,
+                RenameStatus::Applied
+                    | RenameStatus::Merged
+                    | RenameStatus::Skipped
+                    | RenameStatus::AlreadyApplied
+                    | RenameStatus::Deferred
+            )
+        })
+    }
+
+    /// Converts the report into a result, returning the first error if any.
+    pub fn into_result(self) -> Result<(), ApplyError> {
+        for entry in self.entries {
+            if let RenameStatus::Failed(details) = entry.status {
+                return Err(ApplyError::new(entry.source, entry.target, details)
+                    .with_tag(entry.tag)
+                    .with_index(Some(entry.index)));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+use std::{io, path::Path, sync::Arc};
+
+use crate::{
+    collate::PathCollator,
+    conflicts::{Conflict, ConflictOptions},
+    error::PlanError,
+    options::{ApplyOptions, PlanOptions},
+    plan::{Anchor, Plan},
+    planner::FsCache,
+    renamer::Renamer,
+    report::{ApplyReport, RenameStatus, ReportEntry},
+    warning::{TracingSink, WarningSink},
+};
+
+/// A renaming session, configured once and reusing its resources across
+/// plans.
+///
+/// A session owns the plan and apply options, and the sink receiving the
+/// warnings. It keeps what is costly to build between calls: the collator
+/// sorting the plans, the filesystem lookups made while planning, as a
+/// [`Planner`](crate::Planner) does, and with the `ansi` feature, the colors
+/// read from `LS_COLORS`. The one-shot methods, such as
+/// [`Renamer::plan_with`] and [`Plan::apply_with`], behave as a session
+/// with the same options, used once.
+///
+/// Applying a plan through the session invalidates the lookups of the
+/// directories it changed. Directories changed by anything else must be
+/// [invalidated](Self::invalidate).
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::{ApplyOptions, PlanOptions, Renamer, Session, Warning};
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = |name| temp_dir.path().join(name);
+/// std::fs::write(path("a.txt"), "a")?;
+///
+/// let mut warnings: Vec<Warning> = Vec::new();
+/// let mut session = Session::new(PlanOptions::new(), ApplyOptions::new())
+///     .warning_sink(&mut warnings);
+/// for (source, target) in [("a.txt", "b.txt"), ("b.txt", "c.txt")] {
+///     let mut renamer = Renamer::new();
+///     renamer.add(path(source), path(target));
+///     let plan = session.plan(renamer)?;
+///     assert!(session.apply(plan).is_success());
+/// }
+/// drop(session);
+/// assert!(path("c.txt").exists());
+/// assert!(warnings.is_empty());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Session<'a> {
+    plan_options: PlanOptions,
+    apply_options: ApplyOptions<'a>,
+    sink: Box<dyn WarningSink + 'a>,
+    cache: FsCache,
+    collator: Option<Arc<PathCollator>>,
+    #[cfg(feature = "ansi")]
+    ls_colors: Option<lscolors::LsColors>,
+}
+
+impl<'a> Session<'a> {
+    /// Creates a session building plans and applying them with the specified
+    /// options.
+    ///
+    /// Warnings are emitted through [`TracingSink`] until another sink is
+    /// set.
+    pub fn new(plan_options: PlanOptions, apply_options: ApplyOptions<'a>) -> Self {
+        Self {
+            plan_options,
+            apply_options,
+            sink: Box::new(TracingSink),
+            cache: FsCache::default(),
+            collator: None,
+            #[cfg(feature = "ansi")]
+            ls_colors: None,
+        }
+    }
+
+    /// Sets the sink receiving the warnings of the session.
+    ///
+    /// The warnings raised while applying a plan are moved from the
+    /// [`ApplyReport`] to the sink.
+    pub fn warning_sink(mut self, sink: impl WarningSink + 'a) -> Self {
+        self.sink = Box::new(sink);
+        self
+    }
+
+    /// Returns the options plans are built with.
+    pub fn plan_options(&self) -> &PlanOptions {
+        &self.plan_options
+    }
+
+    /// Returns the options plans are applied with.
+    pub fn apply_options(&self) -> &ApplyOptions<'a> {
+        &self.apply_options
+    }
+
+    /// Consumes the renamer and returns a [`Plan`], as
+    /// [`Renamer::plan_with`] does.
+    pub fn plan<S, T>(&mut self, renamer: Renamer<S, T>) -> Result<Plan<S, T>, PlanError>
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        let collator = match &self.collator {
+            Some(collator) => Arc::clone(collator),
+            None => {
+                let collator = PathCollator::shared(self.plan_options.plan_ordering())?;
+                self.collator.insert(collator).clone()
+            }
+        };
+        renamer.plan_cached(&self.plan_options, &mut self.cache, Some(collator))
+    }
+
+    /// Returns the conflicts of a plan, as [`Plan::conflicts`] does.
+    pub fn conflicts<S, T>(
+        &mut self,
+        plan: &Plan<S, T>,
+        options: &ConflictOptions,
+    ) -> io::Result<Vec<Conflict>>
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        plan.conflicts_cached(options, &mut self.cache)
+    }
+
+    /// Runs the checks made before applying a plan, without renaming
+    /// anything.
+    ///
+    /// The operations failing the checks are
+    /// [failed](RenameStatus::Failed) in the report, and the others
+    /// [pending](RenameStatus::Pending), in execution order. Only the checks
+    /// made before the first rename are run: an operation may still fail
+    /// once applied, such as because its source is missing.
+    pub fn dry_run<S, T>(&mut self, plan: &Plan<S, T>) -> ApplyReport
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        let anchor = plan.anchor();
+        if let Anchor::BaseDir(base_dir) = &anchor {
+            return self.dry_run(&plan.anchored(base_dir));
+        }
+        let mut report = ApplyReport::default();
+        let mut failures = plan.preflight(&anchor, &self.apply_options, &mut report.warnings);
+        for &index in &plan.execution {
+            let rename = &plan.renames[index];
+            let status = match failures.remove(&index) {
+                Some(details) => RenameStatus::Failed(details),
+                None => RenameStatus::Pending,
+            };
+            report.entries.push(ReportEntry {
+                source: rename.source.as_ref().to_owned(),
+                target: rename.target.as_ref().to_owned(),
+                status,
+                duration: None,
+                tag: rename.tag.clone(),
+                index,
+                created_dirs: Vec::new(),
+                #[cfg(feature = "digest")]
+                digest: None,
+            });
+        }
+        self.drain_warnings(&mut report);
+        report
+    }
+
+    /// Applies a plan, as [`Plan::apply_with`] does.
+    ///
+    /// The lookups of the directories holding the sources, targets and
+    /// created directories of the attempted operations are invalidated.
+    pub fn apply<S, T>(&mut self, plan: Plan<S, T>) -> ApplyReport
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        let mut report = plan.apply_with(&self.apply_options);
+        let attempted = (report.entries.iter())
+            .filter(|entry| !matches!(entry.status, RenameStatus::Pending))
+            .flat_map(|entry| [&entry.source, &entry.target]);
+        let created = report.created_dirs.iter().map(|dir| &dir.path);
+        for path in attempted.chain(created) {
+            if let Some(parent) = path.parent() {
+                self.cache.invalidate(parent);
+            }
+        }
+        self.drain_warnings(&mut report);
+        report
+    }
+
+    /// Writes a plan to the specified writer, with ANSI colors and the
+    /// specified options, as [`Plan::write_colored_with_sink`] does.
+    ///
+    /// `LS_COLORS` is read once per session.
+    #[cfg(feature = "ansi")]
+    pub fn write_colored<S, T, W>(
+        &mut self,
+        plan: &Plan<S, T>,
+        writer: &mut W,
+        options: &crate::WriteOptions,
+    ) -> io::Result<()>
+    where
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+        W: io::Write,
+    {
+        let ls_colors =
+            (self.ls_colors).get_or_insert_with(|| crate::plan::ls_colors(&mut *self.sink));
+        plan.write_colored_using(ls_colors, writer, options)
+    }
+
+    /// Forgets what is cached about a directory and everything under it, as
+    /// [`Planner::invalidate`](crate::Planner::invalidate) does.
+    pub fn invalidate(&mut self, path: impl AsRef<Path>) {
+        self.cache.invalidate(path.as_ref());
+    }
+
+    /// Moves the warnings of a report to the sink.
+    fn drain_warnings(&mut self, report: &mut ApplyReport) {
+        for warning in report.warnings.drain(..) {
+            self.sink.warn(warning);
+        }
+    }
+}
+
+impl Default for Session<'_> {
+    fn default() -> Self {
+        Self::new(PlanOptions::default(), ApplyOptions::default())
+    }
+}
+
+impl std::fmt::Debug for Session<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("plan_options", &self.plan_options)
+            .field("apply_options", &self.apply_options)
+            .field("cache", &self.cache)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::Session;
+    use crate::{
+        ApplyErrorDetails, ApplyOptions, PlanOptions, RenameStatus, Renamer, SourceKind, Warning,
+    };
+
+    #[test]
+    fn session() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"a")?;
+        fs::write(path("b"), b"b")?;
+
+        let mut warnings: Vec<Warning> = Vec::new();
+        let plan_options = PlanOptions::new().probe_case_sensitivity(true);
+        let apply_options = ApplyOptions::new().expect(SourceKind::File);
+        let mut session = Session::new(plan_options, apply_options).warning_sink(&mut warnings);
+
+        // Nothing is renamed by a dry run.
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("x"));
+        renamer.add(path("missing"), path("y"));
+        let plan = session.plan(renamer).map_err(io::Error::other)?;
+        let report = session.dry_run(&plan);
+        assert!(matches!(report.entries()[0].status, RenameStatus::Pending));
+        assert!(matches!(
+            report.entries()[1].status,
+            RenameStatus::Failed(ApplyErrorDetails::Io { .. })
+        ));
+        assert!(path("a").exists() && !path("x").exists());
+
+        // The cached listing of the directory is dropped once applied.
+        let mut renamer = Renamer::new();
+        renamer.add(path("b"), path("c"));
+        let plan = session.plan(renamer).map_err(io::Error::other)?;
+        assert!(session.apply(plan).is_success());
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("b"));
+        let plan = session.plan(renamer).map_err(io::Error::other)?;
+        let conflicts = session.conflicts(&plan, &Default::default())?;
+        assert!(conflicts.is_empty());
+        assert!(session.apply(plan).is_success());
+        drop(session);
+        assert_eq!(fs::read(path("b"))?, b"a");
+        assert_eq!(fs::read(path("c"))?, b"b");
+        assert!(warnings.is_empty());
+        Ok(())
+    }
+}
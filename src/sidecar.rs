@@ -0,0 +1,125 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{operation::Rename, path::path_exists, renamer::Renamer};
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Adds an operation for every sidecar of the sources added so far,
+    /// renaming it along with its primary file.
+    ///
+    /// A sidecar is a file next to the source, sharing its stem (the file
+    /// name without its extension) and having one of the given extensions,
+    /// such as `IMG_0001.xmp` for `IMG_0001.jpg`. It is renamed next to the
+    /// target, with the stem of the target, as in `2024-06-01.xmp`.
+    /// Extensions are given without their leading dot, and compared
+    /// case-sensitively.
+    ///
+    /// Sidecars that do not exist are skipped, and so are those already
+    /// added as the source of an operation, explicitly or as the sidecar of
+    /// an earlier source sharing the same stem. Sidecars are tagged as their
+    /// primary file. Returns the number of added operations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::{fs::File, path::PathBuf};
+    /// # use nominal::Renamer;
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// File::create(path("IMG_0001.jpg"))?;
+    /// File::create(path("IMG_0001.xmp"))?;
+    ///
+    /// let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+    /// renamer.add(path("IMG_0001.jpg"), path("2024-06-01.jpg"));
+    /// assert_eq!(renamer.expand_sidecars(&["xmp", "json"]), 1);
+    ///
+    /// renamer.plan()?.apply()?;
+    /// assert!(path("2024-06-01.xmp").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn expand_sidecars(&mut self, extensions: &[&str]) -> usize {
+        let mut sources: HashSet<PathBuf> = (self.renames.iter())
+            .map(|rename| rename.source.clone())
+            .collect();
+        let mut sidecars = Vec::new();
+        for rename in &self.renames {
+            let (Some(source_stem), Some(target_stem)) =
+                (rename.source.file_stem(), rename.target.file_stem())
+            else {
+                continue;
+            };
+            for extension in extensions {
+                let source = sibling(&rename.source, source_stem.into(), extension);
+                let target = sibling(&rename.target, target_stem.into(), extension);
+                if source == target
+                    || sources.contains(&source)
+                    || !path_exists(&source).unwrap_or(false)
+                {
+                    continue;
+                }
+                sources.insert(source.clone());
+                sidecars.push(Rename {
+                    source,
+                    target,
+                    tag: rename.tag.clone(),
+                    priority: rename.priority,
+                });
+            }
+        }
+        let count = sidecars.len();
+        self.renames.extend(sidecars);
+        count
+    }
+}
+
+/// Returns the path of the file next to `path`, with the given stem and
+/// extension.
+fn sibling(path: &Path, mut name: OsString, extension: &str) -> PathBuf {
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use crate::Renamer;
+
+    #[test]
+    fn expand_sidecars() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for file in [
+            "a.jpg", "a.raw", "a.xmp", "a.json", "b.mkv", "b.srt", "c.jpg", "c.xmp",
+        ] {
+            fs::write(path(file), file)?;
+        }
+
+        let mut renamer = Renamer::<PathBuf, PathBuf>::new();
+        renamer.add(path("a.jpg"), path("out/x.jpg"));
+        renamer.add(path("a.raw"), path("out/x.raw"));
+        renamer.add(path("b.mkv"), path("y.mkv"));
+        renamer.add(path("c.xmp"), path("z.xmp"));
+        renamer.add(path("c.jpg"), path("z.jpg"));
+        assert_eq!(renamer.expand_sidecars(&["xmp", "json", "srt"]), 3);
+        // Already expanded.
+        assert_eq!(renamer.expand_sidecars(&["xmp", "json", "srt"]), 0);
+
+        let renames: Vec<_> = (renamer.renames[5..].iter())
+            .map(|rename| (rename.source.clone(), rename.target.clone()))
+            .collect();
+        assert_eq!(
+            renames,
+            [
+                (path("a.xmp"), path("out/x.xmp")),
+                (path("a.json"), path("out/x.json")),
+                (path("b.srt"), path("y.srt")),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use crate::renamer::Renamer;
+
+/// An object-safe receiver of rename operations.
+///
+/// The builders adding operations from the filesystem, such as
+/// [`flatten_into`](crate::flatten_into), add them to any sink, so that code
+/// building operations, such as a plugin, does not need to name a concrete
+/// [`Renamer`] type. The builder methods of `Renamer<PathBuf, PathBuf>`,
+/// such as [`Renamer::flatten`], add them to the renamer itself.
+///
+/// # Examples
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use nominal::{RenameSink, Renamer};
+/// fn backup(sink: &mut dyn RenameSink, path: &Path) {
+///     sink.add(path, &path.with_extension("bak"));
+/// }
+///
+/// let mut renamer: Renamer<PathBuf, PathBuf> = Renamer::new();
+/// backup(&mut renamer, Path::new("a.txt"));
+/// let mut renames = Vec::new();
+/// backup(&mut renames, Path::new("a.txt"));
+///
+/// assert_eq!(renamer.plan()?.len(), 1);
+/// assert_eq!(renames, [(PathBuf::from("a.txt"), PathBuf::from("a.bak"))]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait RenameSink {
+    /// Receives a rename operation.
+    fn add(&mut self, source: &Path, target: &Path);
+}
+
+impl<R: RenameSink + ?Sized> RenameSink for &mut R {
+    fn add(&mut self, source: &Path, target: &Path) {
+        (**self).add(source, target);
+    }
+}
+
+impl RenameSink for Renamer<PathBuf, PathBuf> {
+    fn add(&mut self, source: &Path, target: &Path) {
+        Renamer::add(self, source.to_owned(), target.to_owned());
+    }
+}
+
+impl RenameSink for Vec<(PathBuf, PathBuf)> {
+    fn add(&mut self, source: &Path, target: &Path) {
+        self.push((source.to_owned(), target.to_owned()));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{generator::TargetGenerator, renamer::Renamer, sink::RenameSink};
+
+/// Alternative spellings of the extensions returned by [`infer`].
+///
+/// A file whose extension is listed here next to the detected one is not
+/// considered misnamed.
+const EXTENSION_ALIASES: &[(&str, &[&str])] = &[
+    ("aiff", &["aif"]),
+    ("heif", &["heic"]),
+    ("html", &["htm"]),
+    ("jpg", &["jpeg", "jpe"]),
+    ("mid", &["midi"]),
+    ("mpg", &["mpeg"]),
+    ("tif", &["tiff"]),
+];
+
+/// Options for [`Renamer::add_fix_extensions`].
+#[derive(Debug, Clone, Default)]
+pub struct FixExtensionOptions {
+    replace_wrong: bool,
+}
+
+impl FixExtensionOptions {
+    /// Creates the default options, which only add missing extensions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether extensions that do not match the detected file type are
+    /// replaced.
+    ///
+    /// When disabled (the default), only files without an extension are
+    /// renamed.
+    pub fn replace_wrong(mut self, replace_wrong: bool) -> Self {
+        self.replace_wrong = replace_wrong;
+        self
+    }
+}
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Adds rename operations fixing the extensions of the given files.
+    ///
+    /// The type of each file is detected from its first bytes. A rename is
+    /// added only if the current extension is missing or, when
+    /// [`FixExtensionOptions::replace_wrong`] is set, does not match the
+    /// detected type. Extensions are compared case-insensitively and common
+    /// alternative spellings (such as `jpeg` for `jpg`) are accepted.
+    ///
+    /// Files whose type cannot be detected are skipped, and returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{FixExtensionOptions, Renamer};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = temp_dir.path().join("image.jpg");
+    /// std::fs::write(&path, b"\x89PNG\r\n\x1a\n")?;
+    ///
+    /// let mut renamer = Renamer::new();
+    /// let options = FixExtensionOptions::new().replace_wrong(true);
+    /// let unknown = renamer.add_fix_extensions([&path], &options)?;
+    /// assert!(unknown.is_empty());
+    ///
+    /// let plan = renamer.plan()?;
+    /// assert_eq!(plan.len(), 1);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn add_fix_extensions<I, P>(
+        &mut self,
+        paths: I,
+        options: &FixExtensionOptions,
+    ) -> io::Result<Vec<PathBuf>>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        fix_extensions_into(self, paths, options)
+    }
+}
+
+/// Adds rename operations fixing the extensions of the given files, to any
+/// sink, as [`Renamer::add_fix_extensions`] does.
+pub fn fix_extensions_into<I, P>(
+    sink: &mut dyn RenameSink,
+    paths: I,
+    options: &FixExtensionOptions,
+) -> io::Result<Vec<PathBuf>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut unknown = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let Some(expected) = detect_extension(path)? else {
+            debug!("could not detect the type of {}", path.display());
+            unknown.push(path.to_owned());
+            continue;
+        };
+        if let Some(target) = options.fixed(path, expected) {
+            sink.add(path, &target);
+        }
+    }
+    Ok(unknown)
+}
+
+/// Fixes the extension of each source, as [`Renamer::add_fix_extensions`]
+/// does, one source at a time.
+///
+/// Sources whose type cannot be detected, or whose first bytes cannot be
+/// read, have no target.
+impl TargetGenerator for FixExtensionOptions {
+    fn target_for(&self, source: &Path, _index: usize) -> Option<PathBuf> {
+        let expected = detect_extension(source).unwrap_or_else(|err| {
+            debug!("could not read {}: {}", source.display(), err);
+            None
+        })?;
+        self.fixed(source, expected)
+    }
+}
+
+impl FixExtensionOptions {
+    /// Returns the path of a file with the extension of its detected type,
+    /// if it needs fixing.
+    fn fixed(&self, path: &Path, expected: &str) -> Option<PathBuf> {
+        let needs_fix = match path.extension() {
+            None => true,
+            Some(current) => {
+                self.replace_wrong
+                    && !current
+                        .to_str()
+                        .is_some_and(|current| extension_matches(current, expected))
+            }
+        };
+        needs_fix.then(|| path.with_extension(expected))
+    }
+}
+
+/// Returns the extension of the type of a file, detected from its first
+/// bytes, or [`None`] if it is not a file or its type is unknown.
+fn detect_extension(path: &Path) -> io::Result<Option<&'static str>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(infer::get_from_path(path)?.map(|kind| kind.extension()))
+}
+
+fn extension_matches(current: &str, expected: &str) -> bool {
+    current.eq_ignore_ascii_case(expected)
+        || EXTENSION_ALIASES
+            .iter()
+            .filter(|(canonical, _)| *canonical == expected)
+            .flat_map(|(_, aliases)| aliases.iter())
+            .any(|alias| current.eq_ignore_ascii_case(alias))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use super::FixExtensionOptions;
+    use crate::Renamer;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+    const JPG: &[u8] = b"\xff\xd8\xff\xe0";
+
+    fn targets(renamer: Renamer<PathBuf, PathBuf>) -> Vec<PathBuf> {
+        let plan = renamer.plan().unwrap();
+        plan.renames.into_iter().map(|r| r.target).collect()
+    }
+
+    #[test]
+    fn extension_matches() {
+        assert!(super::extension_matches("png", "png"));
+        assert!(super::extension_matches("PNG", "png"));
+        assert!(super::extension_matches("jpeg", "jpg"));
+        assert!(super::extension_matches("JPE", "jpg"));
+        assert!(!super::extension_matches("jpg", "png"));
+        assert!(!super::extension_matches("jpeg", "png"));
+    }
+
+    #[test]
+    fn add_missing_only() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let missing = temp_dir.path().join("missing");
+        let wrong = temp_dir.path().join("wrong.jpg");
+        let alias = temp_dir.path().join("alias.jpeg");
+        let unknown = temp_dir.path().join("unknown.txt");
+        fs::write(&missing, PNG)?;
+        fs::write(&wrong, PNG)?;
+        fs::write(&alias, JPG)?;
+        fs::write(&unknown, b"hello")?;
+
+        let mut renamer = Renamer::new();
+        let skipped = renamer.add_fix_extensions(
+            [&missing, &wrong, &alias, &unknown],
+            &FixExtensionOptions::new(),
+        )?;
+        assert_eq!(skipped, [unknown]);
+        assert_eq!(targets(renamer), [temp_dir.path().join("missing.png")]);
+        Ok(())
+    }
+
+    #[test]
+    fn replace_wrong() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let missing = temp_dir.path().join("missing");
+        let wrong = temp_dir.path().join("wrong.jpg");
+        let alias = temp_dir.path().join("alias.jpeg");
+        let dir = temp_dir.path().join("dir");
+        fs::write(&missing, PNG)?;
+        fs::write(&wrong, PNG)?;
+        fs::write(&alias, JPG)?;
+        fs::create_dir(&dir)?;
+
+        let options = FixExtensionOptions::new().replace_wrong(true);
+        let mut renamer = Renamer::new();
+        let skipped = renamer.add_fix_extensions([&missing, &wrong, &alias, &dir], &options)?;
+        assert_eq!(skipped, [dir.as_path()]);
+        let expected = [
+            temp_dir.path().join("missing.png"),
+            temp_dir.path().join("wrong.png"),
+        ];
+        assert_eq!(targets(renamer), expected);
+
+        // The options generate the same targets, one source at a time.
+        let renamer = Renamer::from_generator([&missing, &wrong, &alias, &dir], &options);
+        assert_eq!(targets(renamer), expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write as _},
+    path::{self, Path, PathBuf},
+};
+
+use crate::{
+    fsutil::sync_parent,
+    path::{common_ancestor, normalize, path_exists},
+};
+
+/// A directory holding files moved out of the way, so that they can be
+/// restored later.
+///
+/// Every staged path is moved into a numbered record of the staging
+/// directory, along with its original path. Records are written and flushed
+/// to disk before anything is moved, so that the files left behind by a
+/// crash can be listed and restored with [`recover`](Self::recover). Once they are no longer
+/// needed, staged files are deleted with [`finish`](Self::finish).
+///
+/// The staging directory is created with the first record, and removed once
+/// empty. It should be on the same filesystem as the staged paths, for them
+/// to be moved rather than copied; see
+/// [`under_common_root`](Self::under_common_root).
+///
+/// Applying a plan with
+/// [`ApplyOptions::staging_dir`](crate::ApplyOptions::staging_dir) stages
+/// the sources moved aside to break cycles, so that a crash leaves them
+/// there.
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::Staging;
+/// let temp_dir = tempfile::tempdir()?;
+/// let path = temp_dir.path().join("old.txt");
+/// let dir = temp_dir.path().join(Staging::DIR_NAME);
+/// std::fs::write(&path, "old")?;
+///
+/// let mut staging = Staging::new(&dir);
+/// staging.stage(&path)?;
+/// assert!(!path.exists());
+/// drop(staging);
+///
+/// // After a crash, the staged files are found again.
+/// let mut staging = Staging::recover(&dir)?;
+/// assert_eq!(staging.entries()[0].original(), path);
+/// staging.restore_all()?;
+/// assert_eq!(std::fs::read_to_string(&path)?, "old");
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct Staging {
+    dir: PathBuf,
+    entries: Vec<StagedEntry>,
+}
+
+/// A path moved into a [`Staging`] directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StagedEntry {
+    original: PathBuf,
+    record: PathBuf,
+}
+
+/// The name of the file holding the original path, in a record.
+const ORIGIN_NAME: &str = "origin";
+/// The name of the staged file, in a record.
+const ENTRY_NAME: &str = "entry";
+
+impl Staging {
+    /// The conventional name of a staging directory.
+    pub const DIR_NAME: &'static str = ".nominal-staging";
+
+    /// Creates a staging area in the given directory.
+    ///
+    /// Nothing is created until a path is staged. Records already in the
+    /// directory are kept, and new ones are numbered after them.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Creates a staging area in a [`DIR_NAME`](Self::DIR_NAME) directory,
+    /// under the closest common ancestor of the given paths.
+    ///
+    /// Paths are made absolute and normalized first. Fails with
+    /// [`io::ErrorKind::InvalidInput`] if they have no common ancestor, as
+    /// on different Windows drives, or if none is given.
+    pub fn under_common_root<I, P>(paths: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut root: Option<PathBuf> = None;
+        for path in paths {
+            let path = normalize(&path::absolute(path)?);
+            let ancestor = match &root {
+                None => path.parent().map(Path::to_owned),
+                Some(root) => common_ancestor(root, &path).map(Path::to_owned),
+            };
+            root = Some(ancestor.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "paths have no common ancestor")
+            })?);
+        }
+        let root =
+            root.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no path given"))?;
+        Ok(Self::new(root.join(Self::DIR_NAME)))
+    }
+
+    /// Lists the records left in a staging directory, such as after a crash.
+    ///
+    /// Records written before their path could be moved are removed. A
+    /// missing directory holds no records.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if a record holds a staged
+    /// path but not its original path, which cannot be restored. Such a
+    /// record is left in place, for its path to be recovered by hand.
+    pub fn recover(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut staging = Self::new(dir);
+        for (_, record) in staging.records()? {
+            let origin = match fs::read(record.join(ORIGIN_NAME)) {
+                Ok(origin) => Some(decode_path(origin)?),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err),
+            };
+            match (origin, path_exists(record.join(ENTRY_NAME))?) {
+                (Some(original), true) => {
+                    staging.entries.push(StagedEntry { original, record });
+                }
+                (None, true) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("staging record {:?} has no original path", record),
+                    ));
+                }
+                (_, false) => fs::remove_dir_all(&record)?,
+            }
+        }
+        Ok(staging)
+    }
+
+    /// Removes the records of a staging directory whose path was moved on,
+    /// and the directory once empty.
+    pub(crate) fn prune(dir: impl Into<PathBuf>) -> io::Result<()> {
+        Self::recover(dir)?.remove_dir()
+    }
+
+    /// Returns the staging directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the staged entries, in staging order.
+    pub fn entries(&self) -> &[StagedEntry] {
+        &self.entries
+    }
+
+    /// Moves a path into the staging directory, and returns the path it is
+    /// now at.
+    ///
+    /// The record is named after the next unused number, so that staging is
+    /// deterministic.
+    pub fn stage(&mut self, path: impl AsRef<Path>) -> io::Result<PathBuf> {
+        let original = normalize(&path::absolute(path)?);
+        fs::create_dir_all(&self.dir)?;
+        let mut number = self.records()?.last().map_or(0, |(number, _)| number + 1);
+        let record = loop {
+            let record = self.dir.join(number.to_string());
+            match fs::create_dir(&record) {
+                Ok(()) => break record,
+                // Created concurrently by someone else.
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => number += 1,
+                Err(err) => return Err(err),
+            }
+        };
+        let staged = record.join(ENTRY_NAME);
+        let moved = sync_parent(&record)
+            .and_then(|()| write_origin(&record.join(ORIGIN_NAME), &original))
+            .and_then(|()| fs::rename(&original, &staged));
+        if let Err(err) = moved {
+            let _ = fs::remove_dir_all(&record);
+            return Err(err);
+        }
+        self.entries.push(StagedEntry { original, record });
+        Ok(staged)
+    }
+
+    /// Moves every staged entry back to its original path, from the last
+    /// staged.
+    ///
+    /// Stops at the first entry that cannot be restored, such as because a
+    /// file now exists at its original path. The entries left are still
+    /// staged.
+    pub fn restore_all(&mut self) -> io::Result<()> {
+        while let Some(entry) = self.entries.last() {
+            entry.restore()?;
+            self.entries.pop();
+        }
+        self.remove_dir()
+    }
+
+    /// Deletes every staged entry, and the staging directory once empty.
+    ///
+    /// This is called once the staged files are no longer needed, such as
+    /// after a successful apply.
+    pub fn finish(mut self) -> io::Result<()> {
+        while let Some(entry) = self.entries.last() {
+            fs::remove_dir_all(&entry.record)?;
+            self.entries.pop();
+        }
+        self.remove_dir()
+    }
+
+    /// Returns the numbered records of the staging directory, in order.
+    fn records(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut records = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            // Entries not named by a number are not records.
+            if let Some(number) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            {
+                records.push((number, entry.path()));
+            }
+        }
+        records.sort();
+        Ok(records)
+    }
+
+    /// Removes the staging directory, if it is empty.
+    fn remove_dir(&self) -> io::Result<()> {
+        match fs::remove_dir(&self.dir) {
+            Err(err)
+                if !matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::DirectoryNotEmpty
+                ) =>
+            {
+                Err(err)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StagedEntry {
+    /// Returns the absolute path the entry was staged from.
+    pub fn original(&self) -> &Path {
+        &self.original
+    }
+
+    /// Returns the path the entry is staged at.
+    pub fn staged(&self) -> PathBuf {
+        self.record.join(ENTRY_NAME)
+    }
+
+    /// Moves the entry back to its original path, and removes its record.
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] if a file exists at the
+    /// original path.
+    pub fn restore(&self) -> io::Result<()> {
+        if path_exists(&self.original)? {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", self.original),
+            ));
+        }
+        fs::rename(self.staged(), &self.original)?;
+        fs::remove_dir_all(&self.record)
+    }
+}
+
+/// Writes the origin file of a record, and flushes it and the record to
+/// disk, so that it survives a crash whenever the staged path was moved.
+fn write_origin(path: &Path, original: &Path) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(&encode_path(original))?;
+    file.sync_all()?;
+    sync_parent(path)
+}
+
+/// Encodes a path as the bytes of an origin file.
+fn encode_path(path: &Path) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        path.as_os_str().as_bytes().to_owned()
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        path.as_os_str()
+            .encode_wide()
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+}
+
+/// Decodes a path encoded with [`encode_path`].
+fn decode_path(bytes: Vec<u8>) -> io::Result<PathBuf> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        Ok(OsString::from_vec(bytes).into())
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStringExt;
+
+        if bytes.len() % 2 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid staging record",
+            ));
+        }
+        let wide: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        Ok(OsString::from_wide(&wide).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::{decode_path, encode_path, Staging};
+    use crate::{cycles, ApplyOptions, Renamer};
+
+    #[test]
+    fn stage_and_recover() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path();
+        let staging_dir = root.join(Staging::DIR_NAME);
+        fs::write(root.join("a"), b"a")?;
+        fs::create_dir(root.join("b"))?;
+        fs::write(root.join("b/c"), b"c")?;
+
+        let mut staging = Staging::under_common_root([root.join("a"), root.join("b/c")])?;
+        assert_eq!(staging.dir(), staging_dir);
+        assert_eq!(staging.stage(root.join("a"))?, staging_dir.join("0/entry"));
+        assert_eq!(staging.stage(root.join("b"))?, staging_dir.join("1/entry"));
+        assert!(staging.stage(root.join("missing")).is_err());
+        // The record of a failed staging is removed.
+        assert!(!staging_dir.join("2").exists());
+
+        // Records written before a crash, without their entry, are dropped.
+        fs::create_dir(staging_dir.join("2"))?;
+        fs::write(staging_dir.join("2/origin"), b"/nowhere")?;
+        let mut recovered = Staging::recover(&staging_dir)?;
+        assert_eq!(recovered.entries(), staging.entries());
+        assert!(!staging_dir.join("2").exists());
+
+        // Records holding a staged path without its origin are kept.
+        fs::create_dir(staging_dir.join("2"))?;
+        fs::write(staging_dir.join("2/entry"), b"lost")?;
+        let err = Staging::recover(&staging_dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(staging_dir.join("2/entry"))?, b"lost");
+        fs::remove_dir_all(staging_dir.join("2"))?;
+
+        // Restoring stops at the first clash.
+        fs::write(root.join("a"), b"new")?;
+        assert!(recovered.restore_all().is_err());
+        assert_eq!(recovered.entries().len(), 1);
+        assert_eq!(fs::read(root.join("b/c"))?, b"c");
+
+        recovered.finish()?;
+        assert!(!staging_dir.exists());
+        assert!(Staging::recover(&staging_dir)?.entries().is_empty());
+        assert!(Staging::under_common_root::<_, &str>([]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parked_sources() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        let staging_dir = path(Staging::DIR_NAME);
+        for name in ["a", "b", "c"] {
+            fs::write(path(name), name)?;
+        }
+        let options = ApplyOptions::new().staging_dir(&staging_dir);
+
+        // The breaker of the cycle is parked in the staging directory, which
+        // is removed once done.
+        let renamer: Renamer<_, _> = [("a", "b"), ("b", "c"), ("c", "a")]
+            .into_iter()
+            .map(|(source, target)| (path(source), path(target)))
+            .collect();
+        renamer
+            .plan()
+            .unwrap()
+            .apply_with(&options)
+            .into_result()
+            .unwrap();
+        assert_eq!(fs::read(path("a"))?, b"c");
+        assert_eq!(fs::read(path("b"))?, b"a");
+        assert!(!staging_dir.exists());
+
+        // A source parked before a crash is restored.
+        let temp_path = cycles::park(&path("a"), &options)?;
+        assert!(temp_path.starts_with(&staging_dir));
+        let mut staging = Staging::recover(&staging_dir)?;
+        assert_eq!(staging.entries()[0].original(), path("a"));
+        staging.restore_all()?;
+        assert_eq!(fs::read(path("a"))?, b"c");
+        assert!(!staging_dir.exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_origins() -> io::Result<()> {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt, path::PathBuf};
+
+        let path = PathBuf::from(OsString::from_vec(b"/caf\xe9/\xff".to_vec()));
+        assert_eq!(encode_path(&path), b"/caf\xe9/\xff");
+        assert_eq!(decode_path(encode_path(&path))?, path);
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn unpaired_surrogate_origins() -> io::Result<()> {
+        use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf};
+
+        let path = PathBuf::from(OsString::from_wide(&[0x43, 0x3a, 0x5c, 0xd800, 0x61]));
+        assert_eq!(decode_path(encode_path(&path))?, path);
+        assert!(decode_path(vec![0x61]).is_err());
+        Ok(())
+    }
+}
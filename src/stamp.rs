@@ -0,0 +1,146 @@
+use std::{fmt, fs, io, path::Path, time::SystemTime};
+
+use crate::{inspect, options::FollowSymlinks};
+
+/// What was recorded about the source of a rename operation when planning
+/// it, to detect whether it changed before the plan is applied.
+///
+/// See [`PlanOptions::stamp_sources`](crate::PlanOptions::stamp_sources).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SourceStamp {
+    /// The size of the source, in bytes.
+    pub size: u64,
+    /// The last modification time of the source.
+    pub modified: SystemTime,
+    /// The digest of the content of the source, if recorded.
+    ///
+    /// See [`PlanOptions::stamp_digests`](crate::PlanOptions::stamp_digests).
+    #[cfg(feature = "digest")]
+    pub digest: Option<crate::Digest>,
+}
+
+/// A field of a [`SourceStamp`] found changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StampField {
+    /// The size of the source.
+    Size,
+    /// The last modification time of the source.
+    Modified,
+    /// The digest of the content of the source.
+    Digest,
+}
+
+impl fmt::Display for StampField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StampField::Size => write!(f, "size"),
+            StampField::Modified => write!(f, "modification time"),
+            StampField::Digest => write!(f, "content"),
+        }
+    }
+}
+
+impl SourceStamp {
+    /// Records the stamp of a source from its metadata, with the digest of
+    /// its content if it is a regular file of at most `digest_max_size`
+    /// bytes.
+    ///
+    /// Directories are not stamped, since the operations of the same plan
+    /// moving their children change them. Symbolic links are not followed,
+    /// so the metadata is that of the source itself. If the content cannot
+    /// be read, the stamp has no digest.
+    pub(crate) fn new(
+        path: &Path,
+        metadata: &fs::Metadata,
+        digest_max_size: Option<u64>,
+    ) -> io::Result<Option<Self>> {
+        if metadata.is_dir() {
+            return Ok(None);
+        }
+        #[cfg(not(feature = "digest"))]
+        let _ = (path, digest_max_size);
+        Ok(Some(Self {
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            #[cfg(feature = "digest")]
+            digest: digest_max_size.and_then(|max_size| {
+                crate::digest::digest_file(path, max_size).unwrap_or_else(|err| {
+                    debug!("could not digest {:?}: {}", path, err);
+                    None
+                })
+            }),
+        }))
+    }
+
+    /// Returns the first field of the stamp that no longer matches the
+    /// source, if any.
+    ///
+    /// The size and modification time are compared first, so that the
+    /// content is only read when they match.
+    pub(crate) fn changed(&self, path: &Path) -> io::Result<Option<StampField>> {
+        let metadata = inspect::metadata(path, FollowSymlinks::No)?;
+        if metadata.len() != self.size {
+            return Ok(Some(StampField::Size));
+        }
+        if metadata.modified()? != self.modified {
+            return Ok(Some(StampField::Modified));
+        }
+        #[cfg(feature = "digest")]
+        if let Some(digest) = &self.digest {
+            let current = crate::digest::digest_file(path, u64::MAX)?;
+            if current.as_ref() != Some(digest) {
+                return Ok(Some(StampField::Digest));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{SourceStamp, StampField};
+
+    #[test]
+    fn changed() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("a");
+        fs::write(&path, b"abc")?;
+        let stamp = SourceStamp::new(&path, &path.symlink_metadata()?, Some(1024))?.unwrap();
+        assert_eq!(stamp.size, 3);
+        assert_eq!(stamp.changed(&path)?, None);
+
+        fs::write(&path, b"abcd")?;
+        assert_eq!(stamp.changed(&path)?, Some(StampField::Size));
+
+        let file = fs::File::options().write(true).open(&path)?;
+        file.set_len(3)?;
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))?;
+        assert_eq!(stamp.changed(&path)?, Some(StampField::Modified));
+
+        // Same size and time, different content.
+        fs::write(&path, b"xyz")?;
+        file.set_modified(stamp.modified)?;
+        #[cfg(feature = "digest")]
+        assert_eq!(stamp.changed(&path)?, Some(StampField::Digest));
+        #[cfg(not(feature = "digest"))]
+        assert_eq!(stamp.changed(&path)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn dirs() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        assert_eq!(
+            SourceStamp::new(temp_dir.path(), &temp_dir.path().symlink_metadata()?, None)?,
+            None
+        );
+        Ok(())
+    }
+}
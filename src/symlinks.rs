@@ -0,0 +1,354 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    path::{normalize, relative_to},
+    tempname::{create_temp_sibling, TempNamer},
+    warning::Warning,
+};
+
+/// A symbolic link rewritten after applying a plan.
+///
+/// See [`fix_symlinks_under`](crate::ApplyOptions::fix_symlinks_under).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RewrittenLink {
+    /// The path of the symbolic link.
+    pub path: PathBuf,
+    /// The previous content of the link.
+    pub old_target: PathBuf,
+    /// The new content of the link.
+    pub new_target: PathBuf,
+}
+
+/// Rewrites the symbolic links under `root` pointing at a renamed source.
+///
+/// The renames must have absolute, normalized paths.
+pub(crate) fn fix_under(
+    root: &Path,
+    renames: &HashMap<PathBuf, PathBuf>,
+    namer: &dyn TempNamer,
+    links: &mut Vec<RewrittenLink>,
+    warnings: &mut Vec<Warning>,
+) {
+    // Links are replaced while scanning, so the entries are collected first.
+    let entries: Vec<_> = match fs::read_dir(root) {
+        Ok(entries) => entries.collect(),
+        Err(error) => {
+            warnings.push(Warning::SymlinkScan {
+                path: root.to_owned(),
+                error,
+            });
+            return;
+        }
+    };
+    for entry in entries {
+        let result = entry.and_then(|entry| {
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                if let Some(link) = fix_link(&entry.path(), renames, namer)? {
+                    debug!(
+                        "rewriting link {} to {}",
+                        link.path.display(),
+                        link.new_target.display()
+                    );
+                    links.push(link);
+                }
+            } else if file_type.is_dir() {
+                fix_under(&entry.path(), renames, namer, links, warnings);
+            }
+            Ok(())
+        });
+        if let Err(error) = result {
+            warnings.push(Warning::SymlinkScan {
+                path: root.to_owned(),
+                error,
+            });
+        }
+    }
+}
+
+fn fix_link(
+    path: &Path,
+    renames: &HashMap<PathBuf, PathBuf>,
+    namer: &dyn TempNamer,
+) -> io::Result<Option<RewrittenLink>> {
+    let old_target = fs::read_link(path)?;
+    let parent = absolute_parent(path)?;
+    let resolved = normalize(&parent.join(&old_target));
+
+    let Some(new_dest) = renamed(&resolved, renames) else {
+        return Ok(None);
+    };
+    let new_target = link_content(&old_target, &parent, new_dest);
+
+    replace_link(path, &new_target, namer)?;
+    Ok(Some(RewrittenLink {
+        path: path.to_owned(),
+        old_target,
+        new_target,
+    }))
+}
+
+/// A source of a plan that is a symbolic link pointing at or into another
+/// source, recorded before applying the plan.
+///
+/// See [`fix_renamed_symlinks`](crate::ApplyOptions::fix_renamed_symlinks).
+#[derive(Debug)]
+pub(crate) struct SourceLink {
+    /// The content of the link.
+    content: PathBuf,
+    /// The absolute, normalized path the link resolves to.
+    dest: PathBuf,
+}
+
+/// Returns the sources that are symbolic links pointing at or into another
+/// source, keyed by absolute, normalized path.
+///
+/// Sources that cannot be inspected are skipped.
+pub(crate) fn source_links<'a, I>(sources: I) -> HashMap<PathBuf, SourceLink>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let mut links = Vec::new();
+    let mut absolute_sources = HashSet::new();
+    for source in sources {
+        let Ok(absolute) = std::path::absolute(source).map(|path| normalize(&path)) else {
+            continue;
+        };
+        let is_symlink = source
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink());
+        if is_symlink {
+            let link = fs::read_link(source).and_then(|content| {
+                let dest = normalize(&absolute_parent(&absolute)?.join(&content));
+                Ok(SourceLink { content, dest })
+            });
+            if let Ok(link) = link {
+                links.push((absolute.clone(), link));
+            }
+        }
+        absolute_sources.insert(absolute);
+    }
+    links
+        .into_iter()
+        .filter(|(source, link)| {
+            link.dest
+                .ancestors()
+                .any(|ancestor| ancestor != source && absolute_sources.contains(ancestor))
+        })
+        .collect()
+}
+
+/// Rewrites the renamed source links whose destination was renamed too, or
+/// warns about them if `fix` is not set.
+///
+/// The renames must have absolute, normalized paths, and only hold the
+/// applied operations.
+pub(crate) fn fix_renamed(
+    source_links: &HashMap<PathBuf, SourceLink>,
+    renames: &HashMap<PathBuf, PathBuf>,
+    fix: bool,
+    namer: &dyn TempNamer,
+    links: &mut Vec<RewrittenLink>,
+    warnings: &mut Vec<Warning>,
+) {
+    for (source, link) in source_links {
+        let Some(path) = renames.get(source) else {
+            continue;
+        };
+        // The link may have been rewritten already, see `fix_under`.
+        if fs::read_link(path).ok().as_ref() != Some(&link.content) {
+            continue;
+        }
+        let Some(new_dest) = renamed(&link.dest, renames) else {
+            continue;
+        };
+        let Ok(parent) = absolute_parent(path) else {
+            continue;
+        };
+        // The link may still resolve to its destination from its new
+        // location.
+        if normalize(&parent.join(&link.content)) == new_dest {
+            continue;
+        }
+        let new_target = link_content(&link.content, &parent, new_dest);
+        if fix && replace_link(path, &new_target, namer).is_ok() {
+            debug!(
+                "rewriting link {} to {}",
+                path.display(),
+                new_target.display()
+            );
+            links.push(RewrittenLink {
+                path: path.clone(),
+                old_target: link.content.clone(),
+                new_target,
+            });
+        } else {
+            warnings.push(Warning::DanglingSymlink {
+                path: path.clone(),
+                old_target: link.content.clone(),
+            });
+        }
+    }
+}
+
+/// Returns the new path of a path, if it is or is inside a renamed source.
+fn renamed(path: &Path, renames: &HashMap<PathBuf, PathBuf>) -> Option<PathBuf> {
+    let (source, target) = path
+        .ancestors()
+        .find_map(|ancestor| renames.get_key_value(ancestor))?;
+    Some(match path.strip_prefix(source) {
+        Ok(rest) if !rest.as_os_str().is_empty() => target.join(rest),
+        _ => target.clone(),
+    })
+}
+
+/// Returns the content of a link in `parent` pointing at `dest`, relative
+/// if the old content was relative.
+fn link_content(old_content: &Path, parent: &Path, dest: PathBuf) -> PathBuf {
+    if old_content.is_relative() {
+        relative_to(parent, &dest).unwrap_or(dest)
+    } else {
+        dest
+    }
+}
+
+/// Returns the parent of a path, made absolute and normalized.
+fn absolute_parent(path: &Path) -> io::Result<PathBuf> {
+    Ok(normalize(&std::path::absolute(
+        path.parent().unwrap_or(Path::new("")),
+    )?))
+}
+
+/// Atomically replaces a symbolic link, through a temporary link named by
+/// `namer`.
+fn replace_link(path: &Path, new_target: &Path, namer: &dyn TempNamer) -> io::Result<()> {
+    let temp_path = create_temp_sibling(namer, path, |temp_path| {
+        #[cfg(unix)]
+        let result = std::os::unix::fs::symlink(new_target, temp_path);
+        #[cfg(windows)]
+        let result = if path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(new_target)
+            .is_dir()
+        {
+            std::os::windows::fs::symlink_dir(new_target, temp_path)
+        } else {
+            std::os::windows::fs::symlink_file(new_target, temp_path)
+        };
+        result
+    })?;
+    fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{collections::HashMap, fs, io, os::unix::fs::symlink, path::Path};
+
+    use crate::{path::normalize, ApplyOptions, DefaultTempNamer, Renamer, Warning};
+
+    #[test]
+    fn fix_under() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = normalize(&temp_dir.path().canonicalize()?);
+        fs::create_dir_all(root.join("links"))?;
+        fs::create_dir_all(root.join("new-dir"))?;
+        fs::write(root.join("new.txt"), b"")?;
+        fs::write(root.join("new-dir/file.txt"), b"")?;
+        fs::write(root.join("other.txt"), b"")?;
+
+        symlink("../old.txt", root.join("links/relative"))?;
+        symlink(root.join("old.txt"), root.join("links/absolute"))?;
+        symlink("../old-dir/file.txt", root.join("links/nested"))?;
+        symlink("../other.txt", root.join("links/other"))?;
+
+        let renames = HashMap::from([
+            (root.join("old.txt"), root.join("new.txt")),
+            (root.join("old-dir"), root.join("new-dir")),
+        ]);
+        let mut links = Vec::new();
+        let mut warnings = Vec::new();
+        super::fix_under(
+            &root,
+            &renames,
+            &DefaultTempNamer,
+            &mut links,
+            &mut warnings,
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(links.len(), 3);
+
+        let read = |name: &str| fs::read_link(root.join("links").join(name));
+        assert_eq!(read("relative")?, Path::new("../new.txt"));
+        assert_eq!(read("absolute")?, root.join("new.txt"));
+        assert_eq!(read("nested")?, Path::new("../new-dir/file.txt"));
+        assert_eq!(read("other")?, Path::new("../other.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn fix_renamed() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = normalize(&temp_dir.path().canonicalize()?);
+        fs::create_dir_all(root.join("links"))?;
+        fs::create_dir_all(root.join("moved"))?;
+        fs::write(root.join("data.txt"), b"")?;
+        fs::write(root.join("other.txt"), b"")?;
+        symlink("../data.txt", root.join("links/relative"))?;
+        symlink(root.join("data.txt"), root.join("links/absolute"))?;
+        symlink("../other.txt", root.join("links/other"))?;
+
+        let plan = |fix| {
+            let mut renamer = Renamer::new();
+            renamer.add(root.join("data.txt"), root.join("data-v1.txt"));
+            renamer.add(root.join("links/relative"), root.join("moved/relative"));
+            renamer.add(root.join("links/absolute"), root.join("links/absolute-1"));
+            renamer.add(root.join("links/other"), root.join("moved/other"));
+            let report = renamer
+                .plan()
+                .unwrap()
+                .apply_with(&ApplyOptions::new().fix_renamed_symlinks(fix));
+            assert!(report.is_success());
+            report
+        };
+
+        let report = plan(true);
+        assert!(report.warnings().is_empty());
+        assert_eq!(report.rewritten_links().len(), 2);
+        let read = |name: &str| fs::read_link(root.join(name));
+        assert_eq!(read("moved/relative")?, Path::new("../data-v1.txt"));
+        assert_eq!(read("links/absolute-1")?, root.join("data-v1.txt"));
+        // The destination of the link was not renamed.
+        assert_eq!(read("moved/other")?, Path::new("../other.txt"));
+
+        // Move everything back, and apply again without fixing the links.
+        fs::rename(root.join("data-v1.txt"), root.join("data.txt"))?;
+        symlink("../data.txt", root.join("links/relative"))?;
+        symlink(root.join("data.txt"), root.join("links/absolute"))?;
+        fs::rename(root.join("moved/other"), root.join("links/other"))?;
+        fs::remove_file(root.join("moved/relative"))?;
+        fs::remove_file(root.join("links/absolute-1"))?;
+        let report = plan(false);
+        assert!(report.rewritten_links().is_empty());
+        let mut dangling: Vec<_> = report
+            .warnings()
+            .iter()
+            .map(|warning| match warning {
+                Warning::DanglingSymlink { path, .. } => path.clone(),
+                warning => panic!("unexpected warning: {warning}"),
+            })
+            .collect();
+        dangling.sort();
+        assert_eq!(
+            dangling,
+            [root.join("links/absolute-1"), root.join("moved/relative")]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,520 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use crate::{plan::Plan, renamer::Renamer};
+
+/// Options for [`Renamer::from_table_reader`] and [`Plan::write_table_to`].
+///
+/// A rename table is a UTF-8 text file with one operation per line, its
+/// source and target separated by the delimiter, a tab by default, shown
+/// as `⇥` here:
+///
+/// ```text
+/// # Summer 2023
+/// IMG_0001.jpg⇥01 - Beach.jpg
+/// IMG_0002.jpg⇥02 - Sunset\tred.jpg
+/// ```
+///
+/// - Lines end with `\n` or `\r\n`, and a leading byte order mark is
+///   ignored.
+/// - Blank lines, and lines starting with `#`, are skipped.
+/// - Spaces and tabs around each path are trimmed.
+/// - Backslashes escape the characters that would otherwise be read
+///   differently: `\\` is a backslash, `\#` a `#`, `\s` a space, `\t` a tab,
+///   `\n` a newline and `\r` a carriage return. A backslash followed by the
+///   delimiter is the delimiter itself.
+/// - Paths that are not valid Unicode are escaped unit by unit: on Unix,
+///   `\xHH` is a byte that is not part of a valid UTF-8 sequence, such as
+///   a Latin-1 `é` written `\xe9`, and on Windows, `\uHHHH` is an unpaired
+///   surrogate. These escapes are only valid on their platform, and other
+///   escapes are invalid.
+///
+/// [`Plan::write_table_to`] escapes exactly what is needed for its output to
+/// be read back as the same paths: backslashes, newlines, carriage returns
+/// and delimiters, a `#` starting a line, spaces or tabs at either end of
+/// a path, and the units that are not valid Unicode.
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::{Renamer, TableOptions};
+/// let table = "# Album\nold 1.jpg;new;1.jpg\n";
+/// let options = TableOptions::new().delimiter(';');
+/// let err =
+///     Renamer::from_table_reader(table.as_bytes(), &options).unwrap_err();
+/// assert_eq!(err.to_string(), "line 2: expected one delimiter, found 2");
+///
+/// let table = "# Album\nold 1.jpg;new\\;1.jpg\n";
+/// let plan = Renamer::from_table_reader(table.as_bytes(), &options)?.plan()?;
+/// assert_eq!(plan.renames()[0].target.to_str(), Some("new;1.jpg"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    delimiter: char,
+}
+
+impl TableOptions {
+    /// Creates the default options, with a tab as delimiter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the delimiter between the source and the target.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delimiter is alphanumeric, a backslash, a `#`, a newline
+    /// or a carriage return.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        assert!(
+            !delimiter.is_alphanumeric() && !matches!(delimiter, '\\' | '#' | '\n' | '\r'),
+            "invalid table delimiter: {:?}",
+            delimiter
+        );
+        self.delimiter = delimiter;
+        self
+    }
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self { delimiter: '\t' }
+    }
+}
+
+impl Renamer<PathBuf, PathBuf> {
+    /// Creates a renamer from a rename table, in the format described in
+    /// [`TableOptions`].
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] on the first invalid line,
+    /// such as a line with no unescaped delimiter, or more than one; the
+    /// message holds the line number, from one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use nominal::{Renamer, TableOptions};
+    /// let table = "\u{feff}# Album\n\nIMG_0001.jpg\t01 - Beach.jpg  \r\n";
+    /// let options = TableOptions::new();
+    /// let renamer = Renamer::from_table_reader(table.as_bytes(), &options)?;
+    ///
+    /// let mut output = Vec::new();
+    /// renamer.plan()?.write_to(&mut output)?;
+    /// assert_eq!(output, b"IMG_0001.jpg => 01 - Beach.jpg\n");
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_table_reader<R>(reader: R, options: &TableOptions) -> io::Result<Self>
+    where
+        R: BufRead,
+    {
+        let mut renamer = Self::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = match index {
+                0 => line.strip_prefix('\u{feff}').unwrap_or(&line),
+                _ => &line,
+            };
+            let fail = |message| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", index + 1, message),
+                )
+            };
+            if let Some((source, target)) = parse_line(line, options.delimiter).map_err(fail)? {
+                renamer.add(source.into(), target.into());
+            }
+        }
+        Ok(renamer)
+    }
+}
+
+impl<S, T> Plan<S, T>
+where
+    S: AsRef<Path>,
+    T: AsRef<Path>,
+{
+    /// Writes the plan as a rename table, in the format described in
+    /// [`TableOptions`], which [`Renamer::from_table_reader`] reads back.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use nominal::{Renamer, TableOptions};
+    /// let mut renamer = Renamer::new();
+    /// renamer.add("#1.txt", " one\ttwo.txt");
+    /// let plan = renamer.plan()?;
+    ///
+    /// let options = TableOptions::new();
+    /// let mut output = Vec::new();
+    /// plan.write_table_to(&mut output, &options)?;
+    /// assert_eq!(output, b"\\#1.txt\t\\sone\\ttwo.txt\n");
+    ///
+    /// let plan = Renamer::from_table_reader(&output[..], &options)?.plan()?;
+    /// assert_eq!(plan.renames()[0].target, Path::new(" one\ttwo.txt"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn write_table_to<W>(&self, writer: &mut W, options: &TableOptions) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        for rename in &self.renames {
+            let line = format_line(
+                rename.source.as_ref().as_os_str(),
+                rename.target.as_ref().as_os_str(),
+                options.delimiter,
+            );
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// The raw unit of a path that is not valid Unicode: a byte on Unix, or an
+/// unpaired surrogate on Windows.
+#[cfg(unix)]
+type RawUnit = u8;
+#[cfg(windows)]
+type RawUnit = u16;
+
+/// A unit of a path: a character, or a raw unit where the path is not valid
+/// Unicode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Char(char),
+    Raw(RawUnit),
+}
+
+/// Splits a path into units.
+fn units(path: &OsStr) -> Vec<Unit> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut units = Vec::with_capacity(path.len());
+        for chunk in path.as_bytes().utf8_chunks() {
+            units.extend(chunk.valid().chars().map(Unit::Char));
+            units.extend(chunk.invalid().iter().copied().map(Unit::Raw));
+        }
+        units
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        char::decode_utf16(path.encode_wide())
+            .map(|unit| unit.map_or_else(|err| Unit::Raw(err.unpaired_surrogate()), Unit::Char))
+            .collect()
+    }
+}
+
+/// Joins units back into a path.
+fn from_units<I>(units: I) -> OsString
+where
+    I: IntoIterator<Item = Unit>,
+{
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        let mut bytes = Vec::new();
+        for unit in units {
+            match unit {
+                Unit::Char(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                Unit::Raw(byte) => bytes.push(byte),
+            }
+        }
+        OsString::from_vec(bytes)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStringExt;
+
+        let mut wide = Vec::new();
+        for unit in units {
+            match unit {
+                Unit::Char(c) => wide.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+                Unit::Raw(surrogate) => wide.push(surrogate),
+            }
+        }
+        OsString::from_wide(&wide)
+    }
+}
+
+/// Formats a line of a rename table, ending with a newline.
+fn format_line(source: &OsStr, target: &OsStr, delimiter: char) -> String {
+    let mut line = escape_field(source, delimiter, true);
+    line.push(delimiter);
+    line.push_str(&escape_field(target, delimiter, false));
+    line.push('\n');
+    line
+}
+
+/// Escapes a path for a rename table. The first field of a line also has a
+/// leading `#` escaped.
+fn escape_field(field: &OsStr, delimiter: char, first: bool) -> String {
+    let units = units(field);
+    let mut escaped = String::with_capacity(units.len());
+    for (index, &unit) in units.iter().enumerate() {
+        let c = match unit {
+            Unit::Char(c) => c,
+            #[cfg(unix)]
+            Unit::Raw(byte) => {
+                escaped.push_str(&format!("\\x{:02x}", byte));
+                continue;
+            }
+            #[cfg(windows)]
+            Unit::Raw(surrogate) => {
+                escaped.push_str(&format!("\\u{:04x}", surrogate));
+                continue;
+            }
+        };
+        let needs_escape = matches!(c, '\\' | '\n' | '\r')
+            || c == delimiter
+            || (first && index == 0 && c == '#')
+            || (matches!(c, ' ' | '\t') && (index == 0 || index + 1 == units.len()));
+        if !needs_escape {
+            escaped.push(c);
+            continue;
+        }
+        escaped.push('\\');
+        escaped.push(match c {
+            '\n' => 'n',
+            '\r' => 'r',
+            '\t' => 't',
+            ' ' => 's',
+            c => c,
+        });
+    }
+    escaped
+}
+
+/// Parses a line of a rename table, without its line ending, into its
+/// source and target, or none if it is blank or a comment.
+fn parse_line(line: &str, delimiter: char) -> Result<Option<(OsString, OsString)>, String> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if line.starts_with('#') || line.trim_matches([' ', '\t']).is_empty() {
+        return Ok(None);
+    }
+    // Each field is a list of units, with whether each was escaped.
+    let mut fields = vec![Vec::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("no field");
+        if c == delimiter {
+            fields.push(Vec::new());
+        } else if c == '\\' {
+            let unescaped = match chars.next() {
+                Some('\\') => '\\',
+                Some('#') => '#',
+                Some('s') => ' ',
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some(c) if c == delimiter => c,
+                #[cfg(unix)]
+                Some('x') => {
+                    field.push((Unit::Raw(parse_raw(&mut chars, 'x', 2)?), true));
+                    continue;
+                }
+                #[cfg(windows)]
+                Some('u') => {
+                    let surrogate = parse_raw(&mut chars, 'u', 4)?;
+                    if !(0xd800..=0xdfff).contains(&surrogate) {
+                        return Err(format!("\\u{:04x} is not a surrogate", surrogate));
+                    }
+                    field.push((Unit::Raw(surrogate), true));
+                    continue;
+                }
+                Some(c) => return Err(format!("invalid escape \\{}", c)),
+                None => return Err("backslash at end of line".to_owned()),
+            };
+            field.push((Unit::Char(unescaped), true));
+        } else {
+            field.push((Unit::Char(c), false));
+        }
+    }
+    if fields.len() != 2 {
+        return Err(format!(
+            "expected one delimiter, found {}",
+            fields.len() - 1
+        ));
+    }
+    let mut fields = fields.into_iter().map(|field| {
+        let is_trimmed =
+            |&(unit, escaped): &(Unit, bool)| !escaped && matches!(unit, Unit::Char(' ' | '\t'));
+        let start = field
+            .iter()
+            .position(|unit| !is_trimmed(unit))
+            .unwrap_or(field.len());
+        let end = field
+            .iter()
+            .rposition(|unit| !is_trimmed(unit))
+            .map_or(start, |end| end + 1);
+        from_units(field[start..end].iter().map(|&(unit, _)| unit))
+    });
+    let (source, target) = (fields.next().unwrap(), fields.next().unwrap());
+    if source.is_empty() || target.is_empty() {
+        return Err("empty path".to_owned());
+    }
+    Ok(Some((source, target)))
+}
+
+/// Parses the hexadecimal digits of a raw unit escape, following `\` and
+/// `letter`.
+fn parse_raw(chars: &mut std::str::Chars<'_>, letter: char, len: usize) -> Result<RawUnit, String> {
+    let digits: String = chars.take(len).collect();
+    if digits.len() != len || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid escape \\{}{}", letter, digits));
+    }
+    Ok(RawUnit::from_str_radix(&digits, 16).expect("invalid digits"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, io, path::PathBuf};
+
+    use super::{format_line, parse_line, TableOptions};
+    use crate::Renamer;
+
+    #[test]
+    fn roundtrip() -> io::Result<()> {
+        let hostile = [
+            "plain.txt",
+            "tab\tinside",
+            "\ttab first",
+            "tab last\t",
+            "  spaces  ",
+            "#hash",
+            "not#first",
+            "back\\slash\\",
+            "\\t",
+            "new\nline",
+            "carriage\rreturn\r\n",
+            "semi;colon",
+            "🦀 ünicode",
+            " ",
+        ];
+        for delimiter in ['\t', ';', ' ', '→'] {
+            for source in hostile {
+                for target in hostile {
+                    let line = format_line(OsStr::new(source), OsStr::new(target), delimiter);
+                    assert_eq!(line.matches('\n').count(), 1, "{:?}", line);
+                    let parsed = parse_line(line.strip_suffix('\n').unwrap(), delimiter);
+                    assert_eq!(
+                        parsed,
+                        Ok(Some((source.into(), target.into()))),
+                        "{:?}",
+                        line
+                    );
+                }
+            }
+        }
+
+        let mut renamer = Renamer::new();
+        for (index, source) in hostile.iter().enumerate() {
+            renamer.add(
+                PathBuf::from(source),
+                PathBuf::from(hostile[(index + 1) % hostile.len()]),
+            );
+        }
+        let plan = renamer.plan().unwrap();
+        let options = TableOptions::new();
+        let mut table = Vec::new();
+        plan.write_table_to(&mut table, &options)?;
+        let reread = Renamer::from_table_reader(&table[..], &options)?
+            .plan()
+            .unwrap();
+        assert_eq!(reread.renames(), plan.renames());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(parse_line("# a\tb", '\t'), Ok(None));
+        assert_eq!(parse_line(" \t ", ';'), Ok(None));
+        assert_eq!(
+            parse_line(" a \t b \r", '\t'),
+            Ok(Some(("a".into(), "b".into())))
+        );
+        for line in ["a", "a\tb\tc", "a\t", "\\#\t ", "a\\x\tb", "a\tb\\"] {
+            assert!(parse_line(line, '\t').is_err(), "{:?}", line);
+        }
+
+        let err =
+            Renamer::from_table_reader("a\tb\n\nc\n".as_bytes(), &TableOptions::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "line 3: expected one delimiter, found 0");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths() -> io::Result<()> {
+        use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+
+        // Latin-1 names, and text that only looks like an escape.
+        let source = OsString::from_vec(b"caf\xe9 \xff".to_vec());
+        let target = OsString::from_vec(b"\\xe9\t\xe9".to_vec());
+        let line = format_line(&source, &target, '\t');
+        assert_eq!(line, "caf\\xe9 \\xff\t\\\\xe9\\t\\xe9\n");
+        assert_eq!(
+            parse_line(line.trim_end_matches('\n'), '\t'),
+            Ok(Some((source.clone(), target.clone())))
+        );
+
+        let mut renamer = Renamer::new();
+        renamer.add(PathBuf::from(source), PathBuf::from(target));
+        let plan = renamer.plan().unwrap();
+        let mut table = Vec::new();
+        plan.write_table_to(&mut table, &TableOptions::new())?;
+        let reread = Renamer::from_table_reader(&table[..], &TableOptions::new())?
+            .plan()
+            .unwrap();
+        assert_eq!(reread.renames(), plan.renames());
+
+        for line in [
+            "a\\x\tb",
+            "a\\xe\tb",
+            "a\\xzz\tb",
+            "a\\x+f\tb",
+            "a\\ud800\tb",
+        ] {
+            assert!(parse_line(line, '\t').is_err(), "{:?}", line);
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn unpaired_surrogates() -> io::Result<()> {
+        use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+        let source = OsString::from_wide(&[0x61, 0xd800, 0x20]);
+        let target = OsString::from_wide(&[0xdfff, 0xd83e, 0xdd80]);
+        let line = format_line(&source, &target, '\t');
+        assert_eq!(line, "a\\ud800\\s\t\\udfff🦀\n");
+        assert_eq!(
+            parse_line(line.trim_end_matches('\n'), '\t'),
+            Ok(Some((source.clone(), target.clone())))
+        );
+
+        let mut renamer = Renamer::new();
+        renamer.add(PathBuf::from(source), PathBuf::from(target));
+        let plan = renamer.plan().unwrap();
+        let mut table = Vec::new();
+        plan.write_table_to(&mut table, &TableOptions::new())?;
+        let reread = Renamer::from_table_reader(&table[..], &TableOptions::new())?
+            .plan()
+            .unwrap();
+        assert_eq!(reread.renames(), plan.renames());
+
+        for line in ["a\\u0041\tb", "a\\ud80\tb", "a\\xe9\tb"] {
+            assert!(parse_line(line, '\t').is_err(), "{:?}", line);
+        }
+        Ok(())
+    }
+}
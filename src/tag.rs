@@ -0,0 +1,196 @@
+use std::{any::Any, fmt, sync::Arc};
+
+/// A user payload attached to a rename operation.
+///
+/// Tags are carried along with their operation: through the sorting and
+/// merging of the plan, into [conflicts](crate::Conflict), apply
+/// [reports](crate::ReportEntry), [errors](crate::ApplyError) and the
+/// [undo stack](crate::UndoStack). They are type-erased, and retrieved with
+/// [`downcast_ref`](Self::downcast_ref). Cloning a tag is cheap, and clones
+/// are equal to each other, but not to other tags holding an equal value.
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::Renamer;
+/// let mut renamer = Renamer::new();
+/// renamer.add_tagged("b.txt", "d.txt", 2_u64);
+/// renamer.add_tagged("a.txt", "c.txt", 1_u64);
+///
+/// let plan = renamer.plan()?;
+/// let ids: Vec<_> = plan
+///     .renames()
+///     .iter()
+///     .map(|rename| rename.tag.as_ref()?.downcast_ref::<u64>())
+///     .collect();
+/// assert_eq!(ids, [Some(&1), Some(&2)]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone)]
+pub struct Tag(Arc<dyn TagValue>);
+
+trait TagValue: Any + fmt::Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    #[cfg(feature = "serde")]
+    fn to_json(&self) -> Option<serde_json::Value>;
+}
+
+struct Opaque<U>(U);
+
+impl<U: fmt::Debug> fmt::Debug for Opaque<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<U> TagValue for Opaque<U>
+where
+    U: Any + fmt::Debug + Send + Sync,
+{
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    #[cfg(feature = "serde")]
+    fn to_json(&self) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+#[cfg(feature = "serde")]
+struct Serializable<U>(U);
+
+#[cfg(feature = "serde")]
+impl<U: fmt::Debug> fmt::Debug for Serializable<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<U> TagValue for Serializable<U>
+where
+    U: Any + fmt::Debug + Send + Sync + serde::Serialize,
+{
+    fn as_any(&self) -> &dyn Any {
+        &self.0
+    }
+
+    fn to_json(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(&self.0)
+            .map_err(|err| {
+                warn!(
+                    "leaving out tag {:?}, which cannot be serialized: {}",
+                    self.0, err
+                )
+            })
+            .ok()
+    }
+}
+
+impl Tag {
+    /// Creates a tag holding the given value.
+    ///
+    /// The tag is left out of the JSON representations.
+    #[cfg_attr(
+        feature = "serde",
+        doc = "See [`serializable`](Self::serializable) otherwise."
+    )]
+    pub fn new<U>(value: U) -> Self
+    where
+        U: Any + fmt::Debug + Send + Sync,
+    {
+        Self(Arc::new(Opaque(value)))
+    }
+
+    /// Creates a tag holding the given value, written as a `tag` field in the
+    /// JSON representations of plans, reports and undo stacks.
+    ///
+    /// Tags read back from an undo stack hold a [`serde_json::Value`]. A
+    /// value that fails to serialize, such as a map with non-string keys, is
+    /// left out with a warning.
+    #[cfg(feature = "serde")]
+    pub fn serializable<U>(value: U) -> Self
+    where
+        U: Any + fmt::Debug + Send + Sync + serde::Serialize,
+    {
+        Self(Arc::new(Serializable(value)))
+    }
+
+    /// Returns a reference to the value of the tag, if it is of type `U`.
+    pub fn downcast_ref<U>(&self) -> Option<&U>
+    where
+        U: Any,
+    {
+        self.0.as_any().downcast_ref()
+    }
+
+    /// Returns the JSON representation of the value, if it is serializable.
+    #[cfg(feature = "serde")]
+    pub(crate) fn to_json(&self) -> Option<serde_json::Value> {
+        self.0.to_json()
+    }
+}
+
+impl fmt::Debug for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Tag").field(&self.0).finish()
+    }
+}
+
+impl PartialEq for Tag {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Tag {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Tag;
+    use crate::{ApplyOptions, PlanOptions, Renamer};
+
+    #[test]
+    fn carried_through() -> std::io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("x"), b"")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add_tagged(path("x"), path("y"), 1);
+        renamer.add_tagged(path("y"), path("z"), 2);
+        renamer.add_tagged(path("a"), path("b"), 3);
+        let plan = renamer
+            .plan_with(&PlanOptions::new().coalesce_chains(true))
+            .unwrap();
+        let report = plan.apply_with(&ApplyOptions::new().continue_on_error(true));
+        // The merged operation keeps the tag of the first link.
+        let tags: Vec<_> = report
+            .entries()
+            .iter()
+            .map(|entry| entry.tag.as_ref().and_then(Tag::downcast_ref::<i32>))
+            .collect();
+        assert_eq!(tags, [Some(&3), Some(&1)]);
+        assert!(path("z").exists());
+
+        let tag = Tag::new(1);
+        assert_eq!(tag, tag.clone());
+        assert_ne!(tag, Tag::new(1));
+        assert_eq!(format!("{:?}", tag), "Tag(1)");
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn unserializable() {
+        use std::collections::HashMap;
+
+        let tag = Tag::serializable(HashMap::from([((1, 2), 3)]));
+        assert_eq!(tag.to_json(), None);
+        assert_eq!(Tag::serializable(1).to_json(), Some(serde_json::json!(1)));
+    }
+}
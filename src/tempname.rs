@@ -0,0 +1,175 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io,
+    path::{Component, Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A generator of temporary names, for the files written aside while
+/// applying a plan and then moved over the file they replace.
+///
+/// A temporary name is always joined to the directory of the file it
+/// shadows, so that moving it over the file is a rename within the same
+/// directory: names that are not a plain file name, such as ones holding a
+/// path separator, are rejected with [`io::ErrorKind::InvalidInput`]. When a
+/// name is already taken, another one is requested with the next attempt
+/// number, up to 16 times.
+///
+/// Names are set with
+/// [`ApplyOptions::temp_namer`](crate::ApplyOptions::temp_namer), and default
+/// to [`DefaultTempNamer`].
+///
+/// # Examples
+///
+/// ```
+/// # use std::ffi::{OsStr, OsString};
+/// # use nominal::TempNamer;
+/// struct Tilde;
+///
+/// impl TempNamer for Tilde {
+///     fn temp_name(&self, name: &OsStr, attempt: usize) -> OsString {
+///         let mut temp_name = name.to_owned();
+///         temp_name.push("~".repeat(attempt + 1));
+///         temp_name
+///     }
+/// }
+///
+/// assert_eq!(Tilde.temp_name(OsStr::new("a.txt"), 1), "a.txt~~");
+/// ```
+pub trait TempNamer {
+    /// Returns a temporary name for the file named `name`, for the given
+    /// attempt, from zero.
+    fn temp_name(&self, name: &OsStr, attempt: usize) -> OsString;
+}
+
+impl<N: TempNamer + ?Sized> TempNamer for &N {
+    fn temp_name(&self, name: &OsStr, attempt: usize) -> OsString {
+        (**self).temp_name(name, attempt)
+    }
+}
+
+/// The number of temporary names requested for a file before giving up.
+pub(crate) const MAX_ATTEMPTS: usize = 16;
+
+/// The default [`TempNamer`], producing names such as
+/// `.nominal-tmp-{pid}-{counter}-{name}`.
+///
+/// The counter is shared by the whole process, so that two names are never
+/// the same, whatever the attempt.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultTempNamer;
+
+impl TempNamer for DefaultTempNamer {
+    fn temp_name(&self, name: &OsStr, _attempt: usize) -> OsString {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut temp_name = OsString::from(format!(".nominal-tmp-{}-{}-", process::id(), count));
+        temp_name.push(name);
+        temp_name
+    }
+}
+
+/// Creates a temporary sibling of a path with `create`, and returns its
+/// path.
+///
+/// `create` must fail with [`io::ErrorKind::AlreadyExists`] if the temporary
+/// path is taken, for another name to be tried.
+pub(crate) fn create_temp_sibling<N, F>(
+    namer: &N,
+    path: &Path,
+    mut create: F,
+) -> io::Result<PathBuf>
+where
+    N: TempNamer + ?Sized,
+    F: FnMut(&Path) -> io::Result<()>,
+{
+    let name = path.file_name().unwrap_or_default();
+    for attempt in 0..MAX_ATTEMPTS {
+        let temp_name = namer.temp_name(name, attempt);
+        if !is_file_name(&temp_name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid temporary name {:?}", temp_name),
+            ));
+        }
+        let temp_path = path.with_file_name(temp_name);
+        match create(&temp_path) {
+            Ok(()) => return Ok(temp_path),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("no free temporary name for {:?}", path),
+    ))
+}
+
+/// Tests whether a name is a plain file name, naming an entry of the
+/// directory it is joined to.
+fn is_file_name(name: &OsStr) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(component)), None) if component == name
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{OsStr, OsString},
+        fs, io,
+    };
+
+    use super::{create_temp_sibling, DefaultTempNamer, TempNamer, MAX_ATTEMPTS};
+
+    struct Fixed(&'static str);
+
+    impl TempNamer for Fixed {
+        fn temp_name(&self, _name: &OsStr, _attempt: usize) -> OsString {
+            self.0.into()
+        }
+    }
+
+    #[test]
+    fn temp_siblings() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("a.txt");
+        let create = |temp_path: &std::path::Path| {
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(temp_path)
+                .map(drop)
+        };
+
+        let first = create_temp_sibling(&DefaultTempNamer, &path, create)?;
+        let second = create_temp_sibling(&DefaultTempNamer, &path, create)?;
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(temp_dir.path()));
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(&format!(".nominal-tmp-{}-", std::process::id())));
+        assert!(name.ends_with("-a.txt"));
+
+        // A name that is always taken is retried, then given up.
+        fs::write(temp_dir.path().join("taken"), b"")?;
+        let mut attempts = 0;
+        let err = create_temp_sibling(&Fixed("taken"), &path, |temp_path| {
+            attempts += 1;
+            create(temp_path)
+        })
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(attempts, MAX_ATTEMPTS);
+
+        // Temporary names never leave the directory.
+        for name in ["../escaped", "dir/name", "..", ".", ""] {
+            let err = create_temp_sibling(&Fixed(name), &path, create).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    log::LogEvent,
+    options::{ApplyOptions, Throttle},
+};
+
+/// Spaces the rename operations of an apply out, as set by a [`Throttle`].
+#[derive(Debug)]
+pub(crate) struct Throttler {
+    throttle: Throttle,
+    /// The number of operations started so far.
+    started: usize,
+    /// When the last operation started.
+    last_start: Option<Instant>,
+}
+
+impl Throttler {
+    pub(crate) fn new(throttle: Throttle) -> Self {
+        Self {
+            throttle,
+            started: 0,
+            last_start: None,
+        }
+    }
+
+    /// Waits until the next operation may start, logging the pause if any.
+    pub(crate) fn wait(&mut self, options: &ApplyOptions) {
+        let pause = self.pause(Instant::now());
+        if !pause.is_zero() {
+            options.emit(LogEvent::Pause { duration: pause });
+            thread::sleep(pause);
+        }
+    }
+
+    /// Returns how long to wait before starting the next operation, if it
+    /// is to start at `now`, and records it as started once waited for.
+    pub(crate) fn pause(&mut self, now: Instant) -> Duration {
+        let mut pause = Duration::ZERO;
+        if let (Some(max_ops_per_sec), Some(last_start)) =
+            (self.throttle.max_ops_per_sec, self.last_start)
+        {
+            let interval = Duration::from_secs(1) / max_ops_per_sec;
+            pause = (last_start + interval).saturating_duration_since(now);
+        }
+        if let Some((every, duration)) = self.throttle.pause_every {
+            if self.started > 0 && self.started.is_multiple_of(every) {
+                pause = pause.max(duration);
+            }
+        }
+        self.started += 1;
+        self.last_start = Some(now + pause);
+        pause
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Throttler;
+    use crate::options::Throttle;
+
+    #[test]
+    fn unthrottled() {
+        let mut throttler = Throttler::new(Throttle::default());
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(throttler.pause(now), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn max_ops_per_sec() {
+        let mut throttler = Throttler::new(Throttle {
+            max_ops_per_sec: Some(4),
+            pause_every: None,
+        });
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(throttler.pause(start), Duration::ZERO);
+        // Started too soon after the first one.
+        assert_eq!(throttler.pause(start + ms(100)), ms(150));
+        // The pause counts as elapsed time.
+        assert_eq!(throttler.pause(start + ms(250)), ms(250));
+        assert_eq!(throttler.pause(start + ms(2000)), Duration::ZERO);
+    }
+
+    #[test]
+    fn pause_every() {
+        let pause = Duration::from_secs(2);
+        let mut throttler = Throttler::new(Throttle {
+            max_ops_per_sec: Some(1),
+            pause_every: Some((3, pause)),
+        });
+        let mut now = Instant::now();
+        let mut pauses = Vec::new();
+        for _ in 0..7 {
+            let waited = throttler.pause(now);
+            pauses.push(waited.as_secs());
+            now += waited;
+        }
+        assert_eq!(pauses, [0, 1, 1, 2, 1, 1, 2]);
+    }
+}
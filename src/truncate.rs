@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const ELLIPSIS: &str = "…";
+
+/// Truncates a path to the specified display width, with an ellipsis in the
+/// middle.
+///
+/// The file name is preserved, along with as much of the leading directories
+/// as fits. If the file name alone does not fit, it is truncated in the
+/// middle instead.
+pub(crate) fn truncate_path(path: &str, max_width: usize) -> Cow<'_, str> {
+    if path.width() <= max_width {
+        return Cow::Borrowed(path);
+    }
+    let (dir, file_name) = match path.rfind(std::path::is_separator) {
+        Some(index) => path.split_at(index),
+        None => ("", path),
+    };
+    // The file name starts with the separator, if any.
+    let fixed_width = ELLIPSIS.width() + file_name.width();
+    if !dir.is_empty() && fixed_width <= max_width {
+        let head = take_width(dir.chars(), max_width - fixed_width);
+        return Cow::Owned(format!("{}{}{}", head, ELLIPSIS, file_name));
+    }
+    Cow::Owned(truncate_middle(path, max_width))
+}
+
+/// Truncates a string to the specified display width, keeping its start and
+/// end around an ellipsis.
+fn truncate_middle(s: &str, max_width: usize) -> String {
+    let Some(available) = max_width.checked_sub(ELLIPSIS.width()) else {
+        return String::new();
+    };
+    let tail_width = available / 2;
+    let head = take_width(s.chars(), available - tail_width);
+    let tail: String = take_width(s.chars().rev(), tail_width)
+        .chars()
+        .rev()
+        .collect();
+    format!("{}{}{}", head, ELLIPSIS, tail)
+}
+
+/// Collects characters until the specified display width is reached.
+fn take_width(chars: impl Iterator<Item = char>, max_width: usize) -> String {
+    let mut width = 0;
+    chars
+        .take_while(|c| {
+            width += c.width().unwrap_or(0);
+            width <= max_width
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use unicode_width::UnicodeWidthStr;
+
+    #[test]
+    fn truncate_path() {
+        let cases = [
+            ("a/b/c.txt", 20, "a/b/c.txt"),
+            ("abcdef/ghijkl/file.txt", 15, "abcde…/file.txt"),
+            ("abcdef/ghijkl/file.txt", 12, "ab…/file.txt"),
+            ("abcdef/ghijkl/file.txt", 9, "abcd….txt"),
+            ("very-long-file-name.txt", 10, "very-….txt"),
+            ("日本語/日本語/ファイル.txt", 18, "日本…/ファイル.txt"),
+            ("abc", 0, ""),
+        ];
+        for (path, max_width, expected) in cases {
+            let truncated = super::truncate_path(path, max_width);
+            assert_eq!(truncated, expected, "{path} truncated to {max_width}");
+            assert!(truncated.width() <= max_width);
+        }
+    }
+}
@@ -0,0 +1,645 @@
+use std::{
+    collections::{BinaryHeap, HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "digest")]
+use crate::digest::{digest_file, Digest};
+use crate::{
+    casefold::CaseInsensitiveDirs,
+    collate::{PathCollator, PlanOrdering},
+    cycles::break_cycles,
+    error::{ApplyError, ApplyErrorDetails, IoOp, PlanError, TargetKind, UndoError},
+    inspect,
+    operation::Rename,
+    options::{FollowSymlinks, StatErrorPolicy},
+    path::path_exists,
+    plan::{Plan, PlanStats},
+    report::{ApplyReport, RenameStatus},
+    tag::Tag,
+    tempname::{DefaultTempNamer, TempNamer},
+};
+
+/// A stack of applied plans, to undo them later.
+///
+/// Every applied plan is recorded with [`push`](Self::push), from its
+/// [`ApplyReport`]. Only the applied operations are recorded: failed and
+/// pending ones are left out, as well as skipped ones and merged
+/// directories, which a single rename cannot undo.
+///
+/// With the `serde` feature, the stack implements `Serialize` and
+/// `Deserialize`, so that it can be persisted between sessions.
+///
+/// With the `digest` feature, the
+#[cfg_attr(
+    feature = "digest",
+    doc = "[digests](crate::ApplyOptions::record_digests)"
+)]
+#[cfg_attr(not(feature = "digest"), doc = "digests")]
+/// of the renamed files are recorded too. An operation with a digest is only
+/// undone if its file still has the same content, so that a file replaced
+/// since is not renamed back in its place.
+///
+/// # Examples
+///
+/// ```
+/// # use std::fs::File;
+/// # use nominal::{ApplyOptions, Renamer, UndoStack};
+/// let temp_dir = tempfile::tempdir()?;
+/// let old_path = temp_dir.path().join("old.txt");
+/// let new_path = temp_dir.path().join("new.txt");
+///
+/// File::create(&old_path)?;
+///
+/// let mut renamer = Renamer::new();
+/// renamer.add(&old_path, &new_path);
+///
+/// let mut undo_stack = UndoStack::new();
+/// let report = renamer.plan()?.apply_with(&ApplyOptions::new());
+/// undo_stack.push(&report);
+/// assert!(new_path.exists());
+///
+/// undo_stack.undo_last()?;
+/// assert!(old_path.exists());
+/// assert!(undo_stack.is_empty());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UndoStack {
+    /// The applied operations of every plan, in apply order.
+    pub(crate) records: Vec<Vec<Rename<PathBuf, PathBuf>>>,
+    /// The digests of the renamed files, for every operation of `records`.
+    #[cfg(feature = "digest")]
+    pub(crate) digests: Vec<Vec<Option<RecordedDigest>>>,
+}
+
+/// The digest of a renamed file, as recorded in an [`UndoStack`].
+#[cfg(feature = "digest")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecordedDigest {
+    pub(crate) digest: Digest,
+    /// The maximum size of the files digested when it was recorded: a file
+    /// now larger has changed, and is not read.
+    pub(crate) max_size: u64,
+}
+
+impl UndoStack {
+    /// Creates an empty stack.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of plans in the stack.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns `true` if the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Records the operations applied in a report.
+    ///
+    /// Reports without any applied operation are not recorded.
+    pub fn push(&mut self, report: &ApplyReport) {
+        let entries =
+            (report.entries.iter()).filter(|entry| matches!(entry.status, RenameStatus::Applied));
+        let applied: Vec<_> = entries
+            .clone()
+            .map(|entry| Rename {
+                source: entry.source.clone(),
+                target: entry.target.clone(),
+                tag: entry.tag.clone(),
+                priority: 0,
+            })
+            .collect();
+        if !applied.is_empty() {
+            self.records.push(applied);
+            #[cfg(feature = "digest")]
+            self.digests.push(
+                entries
+                    .map(|entry| {
+                        Some(RecordedDigest {
+                            digest: entry.digest?,
+                            max_size: report.digest_max_size.unwrap_or(u64::MAX),
+                        })
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    /// Moves the plans recorded in another stack on top of this one.
+    #[cfg(feature = "serde")]
+    pub(crate) fn append(&mut self, other: &mut Self) {
+        self.records.append(&mut other.records);
+        #[cfg(feature = "digest")]
+        self.digests.append(&mut other.digests);
+    }
+
+    /// Undoes the last recorded plan, and returns the plan that was applied
+    /// to undo it, or [`None`] if the stack is empty.
+    ///
+    /// Every operation is checked first: if a recorded target no longer
+    /// exists, or a recorded source is occupied, nothing is renamed and
+    /// [`UndoError::Blocked`] lists the blocking operations. The plan then
+    /// stays in the stack. If an undo operation fails, the operations that
+    /// could not be undone stay in the stack.
+    ///
+    /// With the `digest` feature, a recorded target whose content no longer
+    /// matches its digest also blocks the undo, with
+    /// [`ApplyErrorDetails::ContentChanged`].
+    pub fn undo_last(&mut self) -> Result<Option<Plan<PathBuf, PathBuf>>, UndoError> {
+        let Some(record) = self.records.last() else {
+            return Ok(None);
+        };
+        let blocked = blockers(record);
+        #[cfg(feature = "digest")]
+        let blocked = {
+            let mut blocked = blocked;
+            blocked.extend(changed(record, self.digests.last().unwrap()));
+            blocked
+        };
+        if !blocked.is_empty() {
+            return Err(UndoError::Blocked(blocked));
+        }
+        let plan = undo_plan(record)?;
+        let mut record = self.records.pop().unwrap();
+        #[cfg(feature = "digest")]
+        let mut digests = self.digests.pop().unwrap();
+        // Cycles are undone out of order: the record keeps the operations
+        // not undone, wherever they are.
+        let mut undone = vec![false; record.len()];
+        for (&index, result) in plan.execution.iter().zip(plan.apply_iter()) {
+            match result {
+                Ok(_) => undone[record.len() - 1 - index] = true,
+                Err(err) => {
+                    let mut kept = undone.iter().map(|undone| !undone);
+                    record.retain(|_| kept.next().unwrap());
+                    self.records.push(record);
+                    #[cfg(feature = "digest")]
+                    {
+                        let mut kept = undone.iter().map(|undone| !undone);
+                        digests.retain(|_| kept.next().unwrap());
+                        self.digests.push(digests);
+                    }
+                    // The undo plan is not returned, so its indices are useless.
+                    return Err(err.with_index(None).into());
+                }
+            }
+        }
+        Ok(Some(plan))
+    }
+
+    /// Collapses the recorded plans into a single plan, with as few
+    /// operations as needed to undo them, and returns the number of
+    /// operations removed.
+    ///
+    /// A file renamed several times is recorded once, from its first path to
+    /// its last one, and a file renamed back to its first path is no longer
+    /// recorded. The tag and digest of the last operation on each file are
+    /// kept. The operations are ordered so that undoing them in reverse order
+    /// never renames a file over another recorded file: files moved in a
+    /// cycle, such as two swapped files, are renamed back through a temporary
+    /// path named by [`DefaultTempNamer`](crate::DefaultTempNamer), next to
+    /// their last path. A file later replaced by another recorded operation
+    /// can no longer be renamed back, and is no longer recorded either.
+    ///
+    /// Once compacted, the stack undoes all of its plans at once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::fs::File;
+    /// # use nominal::{ApplyOptions, Renamer, UndoStack};
+    /// let temp_dir = tempfile::tempdir()?;
+    /// let path = |name| temp_dir.path().join(name);
+    /// File::create(path("a.txt"))?;
+    ///
+    /// let mut undo_stack = UndoStack::new();
+    /// for (source, target) in [("a.txt", "b.txt"), ("b.txt", "c.txt")] {
+    ///     let mut renamer = Renamer::new();
+    ///     renamer.add(path(source), path(target));
+    ///     undo_stack.push(&renamer.plan()?.apply_with(&ApplyOptions::new()));
+    /// }
+    ///
+    /// assert_eq!(undo_stack.compact(), 1);
+    /// assert_eq!(undo_stack.undo_last()?.unwrap().len(), 1);
+    /// assert!(path("a.txt").exists());
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn compact(&mut self) -> usize {
+        let before: usize = self.records.iter().map(Vec::len).sum();
+        let operations = self.records.drain(..).flatten();
+        #[cfg(feature = "digest")]
+        let operations = operations.zip(self.digests.drain(..).flatten());
+
+        // Follows every file from its first path to its last one.
+        let mut chains: Vec<Chain> = Vec::new();
+        let mut current: HashMap<PathBuf, usize> = HashMap::new();
+        for (step, operation) in operations.enumerate() {
+            #[cfg(feature = "digest")]
+            let (rename, digest) = operation;
+            #[cfg(not(feature = "digest"))]
+            let rename = operation;
+            let index = current.remove(&rename.source).unwrap_or_else(|| {
+                chains.push(Chain {
+                    source: rename.source.clone(),
+                    target: PathBuf::new(),
+                    tag: None,
+                    #[cfg(feature = "digest")]
+                    digest: None,
+                    last: step,
+                    live: true,
+                });
+                chains.len() - 1
+            });
+            let chain = &mut chains[index];
+            chain.target = rename.target;
+            chain.tag = rename.tag;
+            #[cfg(feature = "digest")]
+            {
+                chain.digest = digest;
+            }
+            chain.last = step;
+            if chain.source == chain.target {
+                chain.live = false;
+            } else if let Some(replaced) = current.insert(chain.target.clone(), index) {
+                chains[replaced].live = false;
+            }
+        }
+        chains.retain(|chain| chain.live);
+
+        // A file is renamed back once the file renamed from its first path,
+        // if any, was renamed back: the last renamed files go first.
+        let by_target: HashMap<&Path, usize> = (chains.iter().enumerate())
+            .map(|(index, chain)| (chain.target.as_path(), index))
+            .collect();
+        let blockers: Vec<Option<usize>> = (chains.iter())
+            .map(|chain| by_target.get(chain.source.as_path()).copied())
+            .collect();
+        drop(by_target);
+        let mut waiting: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut ready = BinaryHeap::new();
+        for (index, blocker) in blockers.iter().enumerate() {
+            match blocker {
+                Some(blocker) => waiting.entry(*blocker).or_default().push(index),
+                None => ready.push((chains[index].last, index)),
+            }
+        }
+        let mut undo_order = Vec::with_capacity(chains.len());
+        #[cfg(feature = "digest")]
+        let mut undo_digests = Vec::with_capacity(chains.len());
+        loop {
+            while let Some((_, index)) = ready.pop() {
+                undo_order.push(Rename {
+                    source: chains[index].source.clone(),
+                    target: chains[index].target.clone(),
+                    tag: chains[index].tag.clone(),
+                    priority: 0,
+                });
+                #[cfg(feature = "digest")]
+                undo_digests.push(chains[index].digest);
+                for waiter in waiting.remove(&index).into_iter().flatten() {
+                    ready.push((chains[waiter].last, waiter));
+                }
+            }
+            // The files left are moved in cycles: one of them is renamed back
+            // through a temporary path, which frees its last path.
+            let Some(&start) = waiting.keys().next() else {
+                break;
+            };
+            let mut visited = HashSet::new();
+            let mut index = start;
+            while visited.insert(index) {
+                index = blockers[index].unwrap();
+            }
+            let chain = &mut chains[index];
+            let name = chain.target.file_name().unwrap_or_default();
+            let temp_path = chain
+                .target
+                .with_file_name(DefaultTempNamer.temp_name(name, 0));
+            undo_order.push(Rename {
+                source: temp_path.clone(),
+                target: std::mem::replace(&mut chain.target, temp_path),
+                tag: chain.tag.clone(),
+                priority: 0,
+            });
+            #[cfg(feature = "digest")]
+            undo_digests.push(chain.digest);
+            for waiter in waiting.remove(&index).into_iter().flatten() {
+                ready.push((chains[waiter].last, waiter));
+            }
+        }
+
+        let after = undo_order.len();
+        if after > 0 {
+            undo_order.reverse();
+            self.records.push(undo_order);
+            #[cfg(feature = "digest")]
+            {
+                undo_digests.reverse();
+                self.digests.push(undo_digests);
+            }
+        }
+        before - after
+    }
+
+    /// Undoes every recorded plan, from the last to the first, and returns
+    /// the plans that were applied to undo them.
+    ///
+    /// Stops at the first plan that cannot be undone, see
+    /// [`undo_last`](Self::undo_last).
+    pub fn undo_all(&mut self) -> Result<Vec<Plan<PathBuf, PathBuf>>, UndoError> {
+        let mut plans = Vec::with_capacity(self.records.len());
+        while let Some(plan) = self.undo_last()? {
+            plans.push(plan);
+        }
+        Ok(plans)
+    }
+}
+
+/// A file followed through the recorded operations, by
+/// [`UndoStack::compact`].
+struct Chain {
+    /// The first path of the file.
+    source: PathBuf,
+    /// The last path of the file.
+    target: PathBuf,
+    tag: Option<Tag>,
+    #[cfg(feature = "digest")]
+    digest: Option<RecordedDigest>,
+    /// The index of the last operation on the file, in apply order.
+    last: usize,
+    /// Whether the file still has to be renamed back.
+    live: bool,
+}
+
+/// Returns the plan undoing a record, renaming every target back to its
+/// source, in reverse order.
+pub(crate) fn undo_plan(
+    record: &[Rename<PathBuf, PathBuf>],
+) -> Result<Plan<PathBuf, PathBuf>, PlanError> {
+    let renames: Vec<_> = record
+        .iter()
+        .rev()
+        .map(|rename| Rename {
+            source: rename.target.clone(),
+            target: rename.source.clone(),
+            tag: rename.tag.clone(),
+            priority: rename.priority,
+        })
+        .collect();
+    // On Windows, the attributes of the sources are read, as when planning.
+    let hidden = (renames.iter().enumerate())
+        .filter(|(_, rename)| {
+            let metadata = cfg!(windows)
+                .then(|| inspect::metadata(&rename.source, FollowSymlinks::No).ok())
+                .flatten();
+            rename.is_hidden(metadata.as_ref())
+        })
+        .map(|(index, _)| index)
+        .collect();
+    // Undoing a cycle is a cycle too.
+    let mut execution = (0..record.len()).collect();
+    let cycles = break_cycles(&renames, &mut execution);
+    Ok(Plan {
+        renames,
+        execution,
+        stats: PlanStats {
+            cycles: cycles.len(),
+            ..PlanStats::default()
+        },
+        cycles,
+        collator: PathCollator::shared(PlanOrdering::default())?,
+        same_directory: false,
+        on_stat_error: StatErrorPolicy::default(),
+        case_insensitive_dirs: CaseInsensitiveDirs::default(),
+        skipped: Vec::new(),
+        // The paths are resolved as they were when applied.
+        base_dir: None,
+        stamps: HashMap::new(),
+        hidden,
+    })
+}
+
+/// Returns the errors of the operations of a record whose renamed file
+/// changed since it was recorded.
+///
+/// The size of a file is checked before reading it: a file larger than the
+/// files digested when it was recorded has changed.
+#[cfg(feature = "digest")]
+fn changed(
+    record: &[Rename<PathBuf, PathBuf>],
+    digests: &[Option<RecordedDigest>],
+) -> Vec<ApplyError> {
+    let mut changed = Vec::new();
+    for (rename, recorded) in record.iter().zip(digests) {
+        let Some(recorded) = recorded else {
+            continue;
+        };
+        let (source, target) = (&rename.target, &rename.source);
+        let details = match digest_file(source, recorded.max_size) {
+            Ok(Some(actual)) if actual == recorded.digest => continue,
+            Ok(_) => ApplyErrorDetails::ContentChanged,
+            // Missing files are reported by `blockers`.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => ApplyErrorDetails::io(IoOp::Stat, source, err),
+        };
+        changed.push(ApplyError::new(source, target, details).with_tag(rename.tag.clone()));
+    }
+    changed
+}
+
+/// Returns the errors of the operations of a record that cannot be undone.
+fn blockers(record: &[Rename<PathBuf, PathBuf>]) -> Vec<ApplyError> {
+    // A source may be occupied by the target of another operation, which is
+    // moved away by the undo, and a target may be missing, if it is the
+    // source of another operation, such as a temporary path of a compacted
+    // stack, or lies under one, such as an entry of a directory moved later,
+    // which is filled by the undo.
+    let targets: HashSet<_> = record.iter().map(|rename| &rename.target).collect();
+    let sources: HashSet<&Path> = record
+        .iter()
+        .map(|rename| rename.source.as_path())
+        .collect();
+    let mut blocked = Vec::new();
+    for rename in record {
+        let (source, target) = (&rename.target, &rename.source);
+        let result = path_exists(source)
+            .map_err(|err| (source, err))
+            .and_then(|source_exists| {
+                if !source_exists && !source.ancestors().any(|path| sources.contains(path)) {
+                    return Err((
+                        source,
+                        io::Error::new(io::ErrorKind::NotFound, "renamed path no longer exists"),
+                    ));
+                }
+                TargetKind::of(target, FollowSymlinks::No).map_err(|err| (target, err))
+            });
+        match result {
+            Ok(Some(kind)) if !targets.contains(target) => blocked
+                .push(ApplyError::target_exists(source, target, kind).with_tag(rename.tag.clone())),
+            Ok(_) => {}
+            Err((path, err)) => blocked.push(
+                ApplyError::new(source, target, ApplyErrorDetails::io(IoOp::Stat, path, err))
+                    .with_tag(rename.tag.clone()),
+            ),
+        }
+    }
+    blocked
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io};
+
+    use super::UndoStack;
+    use crate::{
+        error::{ApplyErrorDetails, TargetKind, UndoError},
+        options::ApplyOptions,
+        renamer::Renamer,
+    };
+
+    #[test]
+    fn undo() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"")?;
+        fs::write(path("b"), b"")?;
+
+        let mut undo_stack = UndoStack::new();
+        let apply = |undo_stack: &mut UndoStack, renames: &[(&str, &str)]| {
+            let renamer: Renamer<_, _> = renames
+                .iter()
+                .map(|(source, target)| (path(source), path(target)))
+                .collect();
+            let options = ApplyOptions::new().continue_on_error(true);
+            undo_stack.push(&renamer.plan().unwrap().apply_with(&options));
+        };
+        apply(
+            &mut undo_stack,
+            &[("a", "a1"), ("b", "b1"), ("missing", "m1")],
+        );
+        apply(&mut undo_stack, &[("a1", "a2")]);
+        apply(&mut undo_stack, &[("missing", "m2")]);
+        assert_eq!(undo_stack.len(), 2);
+        assert_eq!(undo_stack.records[0].len(), 2);
+
+        // A file now occupies a recorded source.
+        fs::write(path("a1"), b"")?;
+        match undo_stack.undo_last() {
+            Err(UndoError::Blocked(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].source, path("a2"));
+                assert!(matches!(
+                    errors[0].details,
+                    ApplyErrorDetails::TargetExists {
+                        kind: TargetKind::File
+                    }
+                ));
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+        assert_eq!(undo_stack.len(), 2);
+
+        fs::remove_file(path("a1"))?;
+        let plans = undo_stack.undo_all().unwrap();
+        assert_eq!(plans.len(), 2);
+        assert_eq!(plans[1].len(), 2);
+        assert!(undo_stack.is_empty());
+        assert!(path("a").exists());
+        assert!(path("b").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn compact() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        for name in ["a", "b", "c", "x"] {
+            fs::write(path(name), name)?;
+        }
+
+        let mut undo_stack = UndoStack::new();
+        let mut apply = |renames: &[(&str, &str)]| {
+            let renamer: Renamer<_, _> = renames
+                .iter()
+                .map(|(source, target)| (path(source), path(target)))
+                .collect();
+            undo_stack.push(&renamer.plan().unwrap().apply_with(&ApplyOptions::new()));
+        };
+        // A rotation through a temporary file, then a file moved to the
+        // temporary path.
+        apply(&[("a", "tmp")]);
+        apply(&[("c", "a")]);
+        apply(&[("b", "c")]);
+        apply(&[("tmp", "b")]);
+        apply(&[("x", "tmp")]);
+        apply(&[("tmp", "x1")]);
+
+        assert_eq!(undo_stack.compact(), 1);
+        assert_eq!(undo_stack.len(), 1);
+        assert_eq!(undo_stack.records[0].len(), 5);
+        assert!(undo_stack.undo_last().unwrap().is_some());
+        for name in ["a", "b", "c", "x"] {
+            assert_eq!(fs::read_to_string(path(name))?, name);
+        }
+        assert!(undo_stack.is_empty());
+        assert_eq!(undo_stack.compact(), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn digests() -> io::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = |name: &str| temp_dir.path().join(name);
+        fs::write(path("a"), b"a")?;
+        fs::write(path("b"), b"b")?;
+        fs::write(path("large"), b"large")?;
+
+        let mut renamer = Renamer::new();
+        renamer.add(path("a"), path("a1"));
+        renamer.add(path("b"), path("b1"));
+        renamer.add(path("large"), path("large1"));
+        let options = ApplyOptions::new().record_digests(1);
+        let report = renamer.plan().unwrap().apply_with(&options);
+        let mut undo_stack = UndoStack::new();
+        undo_stack.push(&report);
+        assert_eq!(undo_stack.digests[0].iter().flatten().count(), 2);
+
+        // A file replaced since is not renamed back.
+        fs::write(path("b1"), b"c")?;
+        fs::write(path("large1"), b"changed, but too large to tell")?;
+        match undo_stack.undo_last() {
+            Err(UndoError::Blocked(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].source, path("b1"));
+                assert!(matches!(
+                    errors[0].details,
+                    ApplyErrorDetails::ContentChanged
+                ));
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+        assert_eq!(undo_stack.len(), 1);
+
+        // A file grown beyond the size of the digested files is not read.
+        fs::write(path("b1"), b"b, grown")?;
+        match undo_stack.undo_last() {
+            Err(UndoError::Blocked(errors)) => assert!(matches!(
+                errors[0].details,
+                ApplyErrorDetails::ContentChanged
+            )),
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        fs::write(path("b1"), b"b")?;
+        assert!(undo_stack.undo_last().unwrap().is_some());
+        assert!(path("a").exists() && path("b").exists() && path("large").exists());
+        Ok(())
+    }
+}
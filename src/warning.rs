@@ -0,0 +1,265 @@
+use std::{fmt, io, path::PathBuf};
+
+use crate::stamp::StampField;
+
+/// A non-fatal problem encountered while writing or applying a plan.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The modification time of a parent directory could not be preserved.
+    ParentMtime {
+        /// The parent directory.
+        path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// The target parent of an operation is or traverses a symbolic link.
+    ///
+    /// See
+    /// [`deny_symlinked_parents`](crate::ApplyOptions::deny_symlinked_parents).
+    SymlinkedParent {
+        /// The target path of the operation.
+        target: PathBuf,
+        /// The symbolic link.
+        link: PathBuf,
+    },
+    /// The progress file could not be updated.
+    ///
+    #[cfg_attr(
+        feature = "serde",
+        doc = "See [`progress_file`](crate::ApplyOptions::progress_file)."
+    )]
+    #[cfg_attr(
+        not(feature = "serde"),
+        doc = "See `ApplyOptions::progress_file`, with the `serde` feature."
+    )]
+    ProgressFile {
+        /// The progress file.
+        path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// The audit log could not be written.
+    ///
+    #[cfg_attr(
+        feature = "serde",
+        doc = "See [`audit_log`](crate::ApplyOptions::audit_log)."
+    )]
+    #[cfg_attr(
+        not(feature = "serde"),
+        doc = "See `ApplyOptions::audit_log`, with the `serde` feature."
+    )]
+    AuditLog {
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// The `LS_COLORS` environment variable could not be read, and the
+    /// default colors were used.
+    #[cfg(feature = "ansi")]
+    LsColors,
+    /// A renamed source is a symbolic link pointing at another renamed
+    /// source, and was left dangling.
+    ///
+    /// See [`fix_renamed_symlinks`](crate::ApplyOptions::fix_renamed_symlinks).
+    DanglingSymlink {
+        /// The new path of the symbolic link.
+        path: PathBuf,
+        /// The content of the link, leading to the old path of its
+        /// destination.
+        old_target: PathBuf,
+    },
+    /// A directory could not be scanned for symbolic links to rewrite.
+    SymlinkScan {
+        /// The directory.
+        path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// The digest of a renamed file could not be computed.
+    ///
+    /// See [`record_digests`](crate::ApplyOptions::record_digests).
+    #[cfg(feature = "digest")]
+    Digest {
+        /// The renamed file.
+        path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// A directory created by a failed apply could not be removed.
+    ///
+    /// See [`remove_unused_dirs`](crate::ApplyOptions::remove_unused_dirs).
+    UnusedDir {
+        /// The directory.
+        path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+    /// An operation was skipped, since its source changed since the plan was
+    /// made.
+    ///
+    /// See [`on_source_changed`](crate::ApplyOptions::on_source_changed).
+    SourceChanged {
+        /// The source of the operation.
+        path: PathBuf,
+        /// The field of its stamp that no longer matches.
+        field: StampField,
+    },
+    /// The source of an operation renamed in a cycle was moved aside to
+    /// break the cycle, and could not be moved back once the operation did
+    /// not complete: it was left at a temporary path.
+    ParkedSource {
+        /// The source of the operation.
+        path: PathBuf,
+        /// The temporary path the source was left at.
+        temp_path: PathBuf,
+        /// The underlying error.
+        error: io::Error,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::ParentMtime { path, error } => write!(
+                f,
+                "could not preserve the modification time of {:?}: {}",
+                path, error
+            ),
+            Warning::SymlinkedParent { target, link } => write!(
+                f,
+                "the parent of {:?} traverses the symbolic link {:?}",
+                target, link
+            ),
+            Warning::ProgressFile { path, error } => {
+                write!(
+                    f,
+                    "could not update the progress file {:?}: {}",
+                    path, error
+                )
+            }
+            Warning::AuditLog { error } => {
+                write!(f, "could not write to the audit log: {}", error)
+            }
+            #[cfg(feature = "ansi")]
+            Warning::LsColors => write!(f, "could not read the LS_COLORS environment variable"),
+            Warning::DanglingSymlink { path, old_target } => write!(
+                f,
+                "the symbolic link {:?} still points at the renamed path {:?}",
+                path, old_target
+            ),
+            Warning::SymlinkScan { path, error } => {
+                write!(f, "could not scan {:?} for symbolic links: {}", path, error)
+            }
+            #[cfg(feature = "digest")]
+            Warning::Digest { path, error } => {
+                write!(f, "could not compute the digest of {:?}: {}", path, error)
+            }
+            Warning::UnusedDir { path, error } => {
+                write!(
+                    f,
+                    "could not remove the unused directory {:?}: {}",
+                    path, error
+                )
+            }
+            Warning::SourceChanged { path, field } => {
+                write!(
+                    f,
+                    "skipped {:?}, whose {} changed since the plan was made",
+                    path, field
+                )
+            }
+            Warning::ParkedSource {
+                path,
+                temp_path,
+                error,
+            } => write!(
+                f,
+                "could not move {:?} back from the temporary path {:?}: {}",
+                path, temp_path, error
+            ),
+        }
+    }
+}
+
+/// A receiver of the warnings raised outside of
+/// [`Plan::apply_with`](crate::Plan::apply_with), which reports its warnings
+/// in the [`ApplyReport`](crate::ApplyReport).
+///
+/// Vectors collect the warnings, so that they can be shown to users.
+/// Warnings are deduplicated by message, so that a warning raised every
+/// time a plan is written is collected once. [`TracingSink`] emits them
+/// through `tracing` instead.
+///
+/// # Examples
+///
+/// ```
+/// # use nominal::{Renamer, Warning, WriteOptions};
+/// let mut renamer = Renamer::new();
+/// renamer.add("old.txt", "new.txt");
+/// let plan = renamer.plan()?;
+///
+/// let mut warnings: Vec<Warning> = Vec::new();
+/// let mut output: Vec<u8> = Vec::new();
+/// # #[cfg(feature = "ansi")]
+/// plan.write_colored_with_sink(
+///     &mut output,
+///     &WriteOptions::new(),
+///     &mut warnings,
+/// )?;
+/// for warning in &warnings {
+///     eprintln!("warning: {}", warning);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait WarningSink {
+    /// Receives a warning.
+    fn warn(&mut self, warning: Warning);
+}
+
+impl<W: WarningSink + ?Sized> WarningSink for &mut W {
+    fn warn(&mut self, warning: Warning) {
+        (**self).warn(warning);
+    }
+}
+
+impl WarningSink for Vec<Warning> {
+    fn warn(&mut self, warning: Warning) {
+        let message = warning.to_string();
+        if !self.iter().any(|other| other.to_string() == message) {
+            self.push(warning);
+        }
+    }
+}
+
+/// A [`WarningSink`] emitting warnings through `tracing`, if the `tracing`
+/// feature is enabled, and dropping them otherwise.
+///
+/// This is the sink used by the methods that do not take one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSink;
+
+impl WarningSink for TracingSink {
+    fn warn(&mut self, warning: Warning) {
+        warn!("{}", warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::PathBuf};
+
+    use super::{Warning, WarningSink};
+
+    #[test]
+    fn deduplicated() {
+        let warning = |path: &str| Warning::SymlinkScan {
+            path: PathBuf::from(path),
+            error: io::Error::other("error"),
+        };
+        let mut warnings = Vec::new();
+        warnings.warn(warning("a"));
+        warnings.warn(warning("b"));
+        warnings.warn(warning("a"));
+        assert_eq!(warnings.len(), 2);
+    }
+}
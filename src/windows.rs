@@ -0,0 +1,124 @@
+use std::{
+    fs, io,
+    os::windows::{ffi::OsStrExt, fs::MetadataExt},
+    path::{self, Path},
+};
+
+use windows_sys::Win32::{
+    Foundation::{
+        CloseHandle, ERROR_ACCESS_DENIED, ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION,
+        STILL_ACTIVE,
+    },
+    Storage::FileSystem::{
+        GetDiskFreeSpaceExW, MoveFileExW, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM,
+        MOVEFILE_DELAY_UNTIL_REBOOT,
+    },
+    System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+};
+
+/// Tests whether an error means that the file is in use by another process.
+pub(crate) fn is_in_use(error: &io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(code) if code == ERROR_SHARING_VIOLATION as i32 || code == ERROR_LOCK_VIOLATION as i32
+    )
+}
+
+/// Tests whether a file has the hidden attribute, or with `system`, the
+/// system attribute.
+pub(crate) fn is_hidden(metadata: &fs::Metadata, system: bool) -> bool {
+    let mut mask = FILE_ATTRIBUTE_HIDDEN;
+    if system {
+        mask |= FILE_ATTRIBUTE_SYSTEM;
+    }
+    metadata.file_attributes() & mask != 0
+}
+
+/// Schedules a rename for the next reboot.
+///
+/// The paths are made absolute, since they are resolved at boot time. This
+/// usually requires administrator privileges.
+pub(crate) fn rename_on_reboot(source: &Path, target: &Path) -> io::Result<()> {
+    let wide = |path: &Path| -> io::Result<Vec<u16>> {
+        let path = path::absolute(path)?;
+        Ok(path.as_os_str().encode_wide().chain([0]).collect())
+    };
+    let (source, target) = (wide(source)?, wide(target)?);
+    // SAFETY: both paths are NUL-terminated, and outlive the call.
+    let moved = unsafe {
+        MoveFileExW(
+            source.as_ptr(),
+            target.as_ptr(),
+            MOVEFILE_DELAY_UNTIL_REBOOT,
+        )
+    };
+    if moved == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Renames a path, failing if the target exists.
+pub(crate) fn move_noreplace(source: &Path, target: &Path) -> io::Result<()> {
+    let wide = |path: &Path| -> Vec<u16> { path.as_os_str().encode_wide().chain([0]).collect() };
+    let (source, target) = (wide(source), wide(target));
+    // SAFETY: both paths are NUL-terminated, and outlive the call.
+    let moved = unsafe { MoveFileExW(source.as_ptr(), target.as_ptr(), 0) };
+    if moved == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes available to the current user on the volume
+/// of an existing path.
+pub(crate) fn available_space(path: &Path) -> io::Result<u64> {
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut available: u64 = 0;
+    // SAFETY: the path is NUL-terminated, and the other sizes are not
+    // requested.
+    let queried = unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if queried == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+/// Tests whether a process is running.
+///
+/// Processes that cannot be checked are assumed to be running.
+pub(crate) fn is_running(pid: u32) -> bool {
+    // SAFETY: the handle is checked, and closed once queried.
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED as i32);
+        }
+        let mut code: u32 = 0;
+        let queried = GetExitCodeProcess(handle, &mut code);
+        CloseHandle(handle);
+        queried == 0 || code == STILL_ACTIVE as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::is_in_use;
+
+    #[test]
+    fn in_use() {
+        assert!(is_in_use(&io::Error::from_raw_os_error(32)));
+        assert!(is_in_use(&io::Error::from_raw_os_error(33)));
+        assert!(!is_in_use(&io::Error::from_raw_os_error(5)));
+        assert!(!is_in_use(&io::Error::other("in use")));
+    }
+}
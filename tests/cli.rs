@@ -0,0 +1,228 @@
+//! Drives the reference command-line front end against temporary
+//! directories.
+
+use std::{
+    fs, io,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs the front end with the given arguments, recording its undo journal
+/// in `dir`.
+fn nominal(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nominal-cli"))
+        .arg("--journal")
+        .arg(dir.join("journal.json"))
+        .args(args)
+        .env("LS_COLORS", "di=01;34")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+/// Returns the sorted names of the entries of a directory.
+fn list(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn rename_then_undo() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let photos = temp_dir.path().join("photos");
+    fs::create_dir(&photos)?;
+    for name in ["b.jpg", "a.jpg", ".hidden", "notes.txt"] {
+        fs::write(photos.join(name), name)?;
+    }
+    let photos = photos.to_str().unwrap();
+
+    let output = nominal(
+        temp_dir.path(),
+        &["rename", "--yes", photos, "{n}-{stem}.{ext}"],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("renamed 3 entries"), "{}", stdout);
+    // The progress of every rename is reported.
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[3/3]"));
+    assert_eq!(
+        list(Path::new(photos))?,
+        [".hidden", "1-a.jpg", "2-b.jpg", "3-notes.txt"]
+    );
+    assert_eq!(
+        fs::read_to_string(Path::new(photos).join("2-b.jpg"))?,
+        "b.jpg"
+    );
+
+    let output = nominal(temp_dir.path(), &["undo"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        list(Path::new(photos))?,
+        [".hidden", "a.jpg", "b.jpg", "notes.txt"]
+    );
+    let output = nominal(temp_dir.path(), &["undo"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("nothing to undo"));
+    Ok(())
+}
+
+#[test]
+fn placeholders_in_names() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("dir");
+    fs::create_dir(&dir)?;
+    fs::write(dir.join("x{n}.txt"), "")?;
+    let dir = dir.to_str().unwrap();
+
+    let output = nominal(temp_dir.path(), &["rename", "--yes", dir, "{n}-{name}{"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(list(Path::new(dir))?, ["1-x{n}.txt{"]);
+    Ok(())
+}
+
+#[test]
+fn dry_run_and_progress() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("dir");
+    fs::create_dir(&dir)?;
+    fs::write(dir.join("a.txt"), "a")?;
+    let dir = dir.to_str().unwrap();
+
+    let output = nominal(temp_dir.path(), &["rename", "--dry-run", dir, "{name}.bak"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(list(Path::new(dir))?, ["a.txt"]);
+    assert!(!temp_dir.path().join("journal.json").exists());
+
+    let progress = temp_dir.path().join("progress.json");
+    let output = nominal(
+        temp_dir.path(),
+        &[
+            "rename",
+            "--yes",
+            "--progress",
+            progress.to_str().unwrap(),
+            dir,
+            "{name}.bak",
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(list(Path::new(dir))?, ["a.txt.bak"]);
+    assert!(fs::read_to_string(progress)?.contains("\"completed\""));
+    Ok(())
+}
+
+#[test]
+fn flatten_with_glob() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("root");
+    fs::create_dir_all(root.join("a/b"))?;
+    fs::write(root.join("a/b/c.txt"), "c")?;
+    fs::write(root.join("a/d.md"), "d")?;
+    let root = root.to_str().unwrap();
+
+    let output = nominal(
+        temp_dir.path(),
+        &["flatten", "--yes", "--glob", "*.txt", root],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("filtered 1 entries"));
+    assert_eq!(list(Path::new(root))?, ["a", "a__b__c.txt"]);
+    assert_eq!(list(&Path::new(root).join("a"))?, ["b", "d.md"]);
+    Ok(())
+}
+
+#[test]
+fn flatten_with_options() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("root");
+    fs::create_dir_all(root.join("a/.cache"))?;
+    fs::write(root.join("a/.cache/c"), "c")?;
+    fs::write(root.join("a/empty"), "")?;
+    fs::write(root.join("a/big"), "big")?;
+    let root = root.to_str().unwrap();
+
+    let output = nominal(
+        temp_dir.path(),
+        &[
+            "flatten",
+            "--yes",
+            "--min-size",
+            "1",
+            "--separator",
+            "-",
+            "--skip-hidden",
+            root,
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(list(Path::new(root))?, ["a", "a-big"]);
+    assert_eq!(list(&Path::new(root).join("a"))?, [".cache", "empty"]);
+
+    for args in [["--separator", "/"], ["--max-size", "big"]] {
+        let output = nominal(
+            temp_dir.path(),
+            &[&["flatten"], &args[..], &[root]].concat(),
+        );
+        assert!(!output.status.success(), "{:?}", args);
+    }
+    Ok(())
+}
+
+#[test]
+fn errors() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("dir");
+    fs::create_dir(&dir)?;
+    fs::write(dir.join("a"), "a")?;
+    fs::write(dir.join("b"), "b")?;
+    let dir = dir.to_str().unwrap();
+
+    // The first entry is renamed to the second, which is left as is.
+    let output = nominal(temp_dir.path(), &["rename", "--yes", dir, "b"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: 1 target(s) already exist"));
+    assert_eq!(list(Path::new(dir))?, ["a", "b"]);
+
+    for args in [&[][..], &["move"], &["rename", dir], &["undo", "--yes"]] {
+        let output = nominal(temp_dir.path(), args);
+        assert!(!output.status.success(), "{:?}", args);
+    }
+    Ok(())
+}
+
+#[test]
+fn undo_fails_midway() -> io::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("root");
+    fs::create_dir(&root)?;
+    fs::write(root.join("q"), "q")?;
+    fs::write(root.join("r"), "r")?;
+    let path = |name: &str| root.join(name).to_str().unwrap().to_owned();
+    // Undoing the second rename creates `p`, which blocks undoing the first.
+    fs::write(
+        temp_dir.path().join("journal.json"),
+        format!(
+            r#"{{"plans":[[{{"source":{:?},"target":{:?}}},{{"source":{:?},"target":{:?}}}]],"version":1}}"#,
+            path("p"),
+            path("q"),
+            path("p/f"),
+            path("r"),
+        ),
+    )?;
+
+    let output = nominal(temp_dir.path(), &["undo"]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert_eq!(list(&root)?, ["p", "q"]);
+
+    // The journal only keeps the rename not undone.
+    fs::rename(root.join("p/f"), root.join("f"))?;
+    fs::remove_dir(root.join("p"))?;
+    let output = nominal(temp_dir.path(), &["undo"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(list(&root)?, ["f", "p"]);
+    let output = nominal(temp_dir.path(), &["undo"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("nothing to undo"));
+    Ok(())
+}